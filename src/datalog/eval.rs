@@ -0,0 +1,227 @@
+//! Evaluation of rule bodies.
+use super::{Atom, Rule, Term, Tuple};
+use crate::layer::{Layer, ObjectType, StringTriple};
+use std::collections::{HashMap, HashSet};
+
+/// The facts of a derived relation.
+pub(super) type Relation = HashSet<Tuple>;
+
+type Bindings = HashMap<String, ObjectType>;
+
+fn resolve<'a>(term: &'a Term, bindings: &'a Bindings) -> Option<&'a ObjectType> {
+    match term {
+        Term::Variable(name) => bindings.get(name),
+        Term::Constant(constant) => Some(constant),
+    }
+}
+
+fn lookup_object(layer: &dyn Layer, object: &ObjectType) -> Option<u64> {
+    match object {
+        ObjectType::Node(node) => layer.object_node_id(node),
+        ObjectType::Value(value) => layer.object_value_id(value),
+    }
+}
+
+/// Bind the term to the given value, returning false if it is already bound to something else.
+fn unify(bindings: &mut Bindings, term: &Term, value: ObjectType) -> bool {
+    match term {
+        Term::Constant(constant) => *constant == value,
+        Term::Variable(name) => match bindings.get(name) {
+            Some(bound) => *bound == value,
+            None => {
+                bindings.insert(name.clone(), value);
+                true
+            }
+        },
+    }
+}
+
+/// Returns the given bindings, extended with each way in which the triple pattern matches the layer.
+fn match_triple(
+    layer: &dyn Layer,
+    subject: &Term,
+    predicate: &Term,
+    object: &Term,
+    bindings: &Bindings,
+) -> Vec<Bindings> {
+    // a bound term that is unknown to the layer can't be part of any of its triples
+    let subject_id = match resolve(subject, bindings) {
+        None => None,
+        Some(ObjectType::Node(node)) => match layer.subject_id(node) {
+            None => return Vec::new(),
+            id => id,
+        },
+        Some(ObjectType::Value(_)) => return Vec::new(),
+    };
+    let predicate_id = match resolve(predicate, bindings) {
+        None => None,
+        Some(ObjectType::Node(predicate)) => match layer.predicate_id(predicate) {
+            None => return Vec::new(),
+            id => id,
+        },
+        Some(ObjectType::Value(_)) => return Vec::new(),
+    };
+    let object_id = match resolve(object, bindings) {
+        None => None,
+        Some(object) => match lookup_object(layer, object) {
+            None => return Vec::new(),
+            id => id,
+        },
+    };
+
+    let triples = match (subject_id, predicate_id, object_id) {
+        (Some(s), Some(p), _) => layer.triples_sp(s, p),
+        (Some(s), None, _) => layer.triples_s(s),
+        (None, _, Some(o)) => layer.triples_o(o),
+        (None, Some(p), None) => layer.triples_p(p),
+        (None, None, None) => layer.triples(),
+    };
+
+    triples
+        .filter(|t| {
+            predicate_id.map_or(true, |p| t.predicate == p)
+                && object_id.map_or(true, |o| t.object == o)
+        })
+        .filter_map(|t| {
+            let mut extended = bindings.clone();
+            // the same variable may appear more than once in the pattern
+            if subject_id.is_none()
+                && !unify(
+                    &mut extended,
+                    subject,
+                    ObjectType::Node(layer.id_subject(t.subject)?),
+                )
+            {
+                return None;
+            }
+            if predicate_id.is_none()
+                && !unify(
+                    &mut extended,
+                    predicate,
+                    ObjectType::Node(layer.id_predicate(t.predicate)?),
+                )
+            {
+                return None;
+            }
+            if object_id.is_none() && !unify(&mut extended, object, layer.id_object(t.object)?) {
+                return None;
+            }
+
+            Some(extended)
+        })
+        .collect()
+}
+
+/// Returns the given bindings, extended with each fact of the relation that matches the terms.
+fn match_relation(
+    relation: Option<&Relation>,
+    terms: &[Term],
+    bindings: &Bindings,
+) -> Vec<Bindings> {
+    relation
+        .into_iter()
+        .flatten()
+        .filter_map(|tuple| {
+            let mut extended = bindings.clone();
+            if terms
+                .iter()
+                .zip(tuple)
+                .all(|(term, value)| unify(&mut extended, term, value.clone()))
+            {
+                Some(extended)
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// Returns whether the atom holds, given bindings for all of its variables.
+fn holds(
+    layer: &dyn Layer,
+    relations: &HashMap<String, Relation>,
+    atom: &Atom,
+    bindings: &Bindings,
+) -> bool {
+    let bound = |term| resolve(term, bindings).expect("negated variables are bound");
+    match atom {
+        Atom::Triple(subject, predicate, object) => match (bound(subject), bound(predicate)) {
+            (ObjectType::Node(subject), ObjectType::Node(predicate)) => {
+                layer.string_triple_exists(&StringTriple {
+                    subject: subject.clone(),
+                    predicate: predicate.clone(),
+                    object: bound(object).clone(),
+                })
+            }
+            _ => false,
+        },
+        Atom::Relation(name, terms) => {
+            let tuple: Tuple = terms.iter().map(|t| bound(t).clone()).collect();
+            relations
+                .get(name)
+                .is_some_and(|relation| relation.contains(&tuple))
+        }
+    }
+}
+
+/// Returns the head of the rule for every solution of its body.
+///
+/// If `delta` is given, the positive literal at that index is matched
+/// against the delta relations rather than against all relations.
+pub(super) fn fire(
+    rule: &Rule,
+    layer: &dyn Layer,
+    relations: &HashMap<String, Relation>,
+    delta: Option<(usize, &HashMap<String, Relation>)>,
+) -> Vec<Tuple> {
+    // start with the delta literal, as it is likely to have the fewest matches
+    let mut order: Vec<usize> = (0..rule.body.len())
+        .filter(|&i| !rule.body[i].negated)
+        .collect();
+    if let Some((index, _)) = delta {
+        order.retain(|&i| i != index);
+        order.insert(0, index);
+    }
+
+    let mut solutions = vec![Bindings::new()];
+    for index in order {
+        solutions = solutions
+            .iter()
+            .flat_map(|bindings| match &rule.body[index].atom {
+                Atom::Triple(subject, predicate, object) => {
+                    match_triple(layer, subject, predicate, object, bindings)
+                }
+                Atom::Relation(name, terms) => {
+                    let source = match delta {
+                        Some((i, delta)) if i == index => delta,
+                        _ => relations,
+                    };
+                    match_relation(source.get(name), terms, bindings)
+                }
+            })
+            .collect();
+        if solutions.is_empty() {
+            return Vec::new();
+        }
+    }
+
+    solutions
+        .into_iter()
+        .filter(|bindings| {
+            rule.body
+                .iter()
+                .filter(|literal| literal.negated)
+                .all(|literal| !holds(layer, relations, &literal.atom, bindings))
+        })
+        .map(|bindings| {
+            rule.head
+                .iter()
+                .map(|term| {
+                    resolve(term, &bindings)
+                        .expect("head variables are bound")
+                        .clone()
+                })
+                .collect()
+        })
+        .collect()
+}
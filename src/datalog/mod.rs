@@ -0,0 +1,699 @@
+//! A Datalog rule engine over layers.
+//!
+//! A `Program` is a set of rules, each deriving the facts of a named
+//! relation from a body of literals. A literal either matches a
+//! triple pattern against a layer, or matches the facts of another
+//! derived relation. Literals can be negated, so that a rule only
+//! applies if something is not present in the layer, or was not
+//! derived. Programs must be stratified: a relation can not depend on
+//! itself through negation.
+//!
+//! Programs are evaluated bottom-up, one stratum at a time, using
+//! semi-naive evaluation. Each round only considers solutions that
+//! use at least one fact that was derived in the previous round. The
+//! derived facts are returned as `Relations`, and relations of two or
+//! three columns can be materialized as triples into a child layer.
+mod eval;
+mod stratify;
+
+use self::eval::Relation;
+use crate::layer::{Layer, ObjectType, StringTriple};
+use crate::store::StoreLayer;
+use std::collections::{HashMap, HashSet};
+use std::{error, fmt, io};
+
+/// A row of a derived relation.
+pub type Tuple = Vec<ObjectType>;
+
+/// A variable or a constant in an atom.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Term {
+    /// A variable with the given name.
+    Variable(String),
+    /// A node or a value.
+    Constant(ObjectType),
+}
+
+impl Term {
+    /// Construct a variable with the given name.
+    pub fn var(name: &str) -> Term {
+        Term::Variable(name.to_owned())
+    }
+
+    /// Construct a node (or predicate) constant.
+    pub fn node(node: &str) -> Term {
+        Term::Constant(ObjectType::Node(node.to_owned()))
+    }
+
+    /// Construct a value constant.
+    pub fn value(value: &str) -> Term {
+        Term::Constant(ObjectType::Value(value.to_owned()))
+    }
+
+    /// Returns the name of this term if it is a variable.
+    pub fn variable(&self) -> Option<&str> {
+        match self {
+            Term::Variable(name) => Some(name),
+            Term::Constant(_) => None,
+        }
+    }
+}
+
+/// Something that can be true or false of its terms.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Atom {
+    /// A triple pattern of subject, predicate and object, that is matched against the layer.
+    Triple(Term, Term, Term),
+    /// A derived relation with the given name.
+    Relation(String, Vec<Term>),
+}
+
+impl Atom {
+    /// Construct a triple pattern.
+    pub fn triple(subject: Term, predicate: Term, object: Term) -> Atom {
+        Atom::Triple(subject, predicate, object)
+    }
+
+    /// Construct an atom of a derived relation.
+    pub fn relation(name: &str, terms: Vec<Term>) -> Atom {
+        Atom::Relation(name.to_owned(), terms)
+    }
+
+    fn terms(&self) -> Vec<&Term> {
+        match self {
+            Atom::Triple(subject, predicate, object) => vec![subject, predicate, object],
+            Atom::Relation(_, terms) => terms.iter().collect(),
+        }
+    }
+}
+
+/// An atom in the body of a rule, which is either required to hold or to not hold.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Literal {
+    atom: Atom,
+    negated: bool,
+}
+
+impl Literal {
+    /// Construct a literal that holds if the atom holds.
+    pub fn positive(atom: Atom) -> Literal {
+        Literal {
+            atom,
+            negated: false,
+        }
+    }
+
+    /// Construct a literal that holds if the atom does not hold.
+    pub fn negative(atom: Atom) -> Literal {
+        Literal {
+            atom,
+            negated: true,
+        }
+    }
+
+    /// Returns the atom of this literal.
+    pub fn atom(&self) -> &Atom {
+        &self.atom
+    }
+
+    /// Returns true if this literal holds when its atom does not.
+    pub fn is_negated(&self) -> bool {
+        self.negated
+    }
+}
+
+/// A rule, deriving a fact of the named relation for every solution of its body.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Rule {
+    name: String,
+    head: Vec<Term>,
+    body: Vec<Literal>,
+}
+
+impl Rule {
+    /// Construct a rule deriving `name(head...)` from the body.
+    pub fn new(name: &str, head: Vec<Term>, body: Vec<Literal>) -> Rule {
+        Rule {
+            name: name.to_owned(),
+            head,
+            body,
+        }
+    }
+
+    /// Returns the name of the relation this rule derives facts for.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the terms in the head of this rule.
+    pub fn head(&self) -> &[Term] {
+        &self.head
+    }
+
+    /// Returns the body of this rule.
+    pub fn body(&self) -> &[Literal] {
+        &self.body
+    }
+}
+
+/// An error in a Datalog program.
+#[derive(Debug, PartialEq, Eq)]
+pub enum DatalogError {
+    /// The variable (`.1`) in the rule with the given index (`.0`)
+    /// does not appear in a positive literal of its body.
+    UnsafeVariable(usize, String),
+    /// The relation (`.0`) is used with both `.1` and `.2` terms.
+    ArityMismatch(String, usize, usize),
+    /// The relation depends on itself through negation.
+    NegativeCycle(String),
+    /// The facts of the relation can't be stored as triples.
+    NotTriples(String),
+}
+
+impl fmt::Display for DatalogError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use DatalogError::*;
+        match self {
+            UnsafeVariable(rule, variable) => write!(
+                f,
+                "variable {} of rule {} does not appear in a positive literal",
+                variable, rule
+            ),
+            ArityMismatch(relation, expected, found) => write!(
+                f,
+                "relation {} is used with both {} and {} terms",
+                relation, expected, found
+            ),
+            NegativeCycle(relation) => {
+                write!(
+                    f,
+                    "relation {} depends on itself through negation",
+                    relation
+                )
+            }
+            NotTriples(relation) => {
+                write!(f, "the facts of relation {} are not triples", relation)
+            }
+        }
+    }
+}
+
+impl error::Error for DatalogError {}
+
+impl From<DatalogError> for io::Error {
+    fn from(err: DatalogError) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidInput, err)
+    }
+}
+
+/// The facts of the relations derived by a program.
+#[derive(Clone, Debug, Default)]
+pub struct Relations {
+    relations: HashMap<String, Relation>,
+}
+
+impl Relations {
+    /// Returns the facts of the named relation, or None if nothing was derived for it.
+    pub fn get(&self, name: &str) -> Option<&HashSet<Tuple>> {
+        self.relations.get(name)
+    }
+
+    /// Returns true if the given fact was derived for the named relation.
+    pub fn contains(&self, name: &str, tuple: &[ObjectType]) -> bool {
+        self.relations
+            .get(name)
+            .is_some_and(|relation| relation.contains(tuple))
+    }
+
+    /// Returns the names of the relations that facts were derived for.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.relations.keys().map(|name| name.as_str())
+    }
+
+    /// Convert the facts of the named relation to triples.
+    ///
+    /// A fact `name(s, o)` becomes the triple `s name o`, and a fact
+    /// `name(s, p, o)` becomes the triple `s p o`. Subjects and
+    /// predicates have to be nodes.
+    pub fn triples(&self, name: &str) -> Result<Vec<StringTriple>, DatalogError> {
+        let not_triples = || DatalogError::NotTriples(name.to_owned());
+        let node = |term: &ObjectType| match term {
+            ObjectType::Node(node) => Ok(node.clone()),
+            ObjectType::Value(_) => Err(not_triples()),
+        };
+
+        self.get(name)
+            .into_iter()
+            .flatten()
+            .map(|tuple| match tuple.as_slice() {
+                [subject, object] => Ok(StringTriple {
+                    subject: node(subject)?,
+                    predicate: name.to_owned(),
+                    object: object.clone(),
+                }),
+                [subject, predicate, object] => Ok(StringTriple {
+                    subject: node(subject)?,
+                    predicate: node(predicate)?,
+                    object: object.clone(),
+                }),
+                _ => Err(not_triples()),
+            })
+            .collect()
+    }
+}
+
+/// A stratified set of rules.
+#[derive(Clone, Debug)]
+pub struct Program {
+    rules: Vec<Rule>,
+    strata: Vec<Vec<usize>>,
+}
+
+impl Program {
+    /// Construct a program from the given rules.
+    ///
+    /// This returns an error if a variable in the head or in a
+    /// negated literal of a rule does not appear in a positive
+    /// literal, if a relation is used with different numbers of
+    /// terms, or if the rules can't be stratified.
+    pub fn new(rules: Vec<Rule>) -> Result<Program, DatalogError> {
+        check_safety(&rules)?;
+        check_arity(&rules)?;
+        let strata = stratify::stratify(&rules)?;
+
+        Ok(Program { rules, strata })
+    }
+
+    /// Returns the rules of this program.
+    pub fn rules(&self) -> &[Rule] {
+        &self.rules
+    }
+
+    /// Returns all facts that can be derived from the given layer.
+    pub fn evaluate(&self, layer: &dyn Layer) -> Relations {
+        let mut relations = HashMap::new();
+        for stratum in self.strata.iter() {
+            self.evaluate_stratum(layer, stratum, &mut relations);
+        }
+
+        Relations { relations }
+    }
+
+    /// Derive the facts of a single stratum, given the complete facts of all lower strata.
+    fn evaluate_stratum(
+        &self,
+        layer: &dyn Layer,
+        stratum: &[usize],
+        relations: &mut HashMap<String, Relation>,
+    ) {
+        let rules: Vec<&Rule> = stratum.iter().map(|&i| &self.rules[i]).collect();
+        let recursive: HashSet<&str> = rules.iter().map(|rule| rule.name.as_str()).collect();
+
+        let mut delta: HashMap<String, Relation> = HashMap::new();
+        for rule in rules.iter() {
+            for tuple in eval::fire(rule, layer, relations, None) {
+                delta.entry(rule.name.clone()).or_default().insert(tuple);
+            }
+        }
+
+        while !delta.is_empty() {
+            for (name, tuples) in delta.iter() {
+                relations
+                    .entry(name.clone())
+                    .or_default()
+                    .extend(tuples.iter().cloned());
+            }
+
+            // only solutions that use a fact from the last round can be new
+            let mut next: HashMap<String, Relation> = HashMap::new();
+            for rule in rules.iter() {
+                for (index, literal) in rule.body.iter().enumerate() {
+                    match &literal.atom {
+                        Atom::Relation(name, _)
+                            if !literal.negated && recursive.contains(name.as_str()) => {}
+                        _ => continue,
+                    }
+
+                    for tuple in eval::fire(rule, layer, relations, Some((index, &delta))) {
+                        // a rule may not have derived anything before this round
+                        let known = match relations.get(&rule.name) {
+                            Some(relation) => relation.contains(&tuple),
+                            None => false,
+                        };
+                        if !known {
+                            next.entry(rule.name.clone()).or_default().insert(tuple);
+                        }
+                    }
+                }
+            }
+            delta = next;
+        }
+    }
+
+    /// Materialize the facts of the given relations as triples.
+    ///
+    /// This returns a new child layer of the given layer, which adds
+    /// the triples for the facts that are not already in it. See
+    /// `Relations::triples` for how facts are converted to triples.
+    pub async fn materialize(
+        &self,
+        layer: &StoreLayer,
+        relations: &[&str],
+    ) -> io::Result<StoreLayer> {
+        let derived = self.evaluate(layer);
+        let mut triples = Vec::new();
+        for name in relations {
+            triples.extend(derived.triples(name)?);
+        }
+
+        let builder = layer.open_write().await?;
+        for triple in triples {
+            if !layer.string_triple_exists(&triple) {
+                builder.add_string_triple(triple)?;
+            }
+        }
+
+        builder.commit().await
+    }
+}
+
+/// Check that every variable of every rule is bound by a positive literal.
+fn check_safety(rules: &[Rule]) -> Result<(), DatalogError> {
+    for (index, rule) in rules.iter().enumerate() {
+        let bound: HashSet<&str> = rule
+            .body
+            .iter()
+            .filter(|literal| !literal.negated)
+            .flat_map(|literal| literal.atom.terms())
+            .filter_map(|term| term.variable())
+            .collect();
+        let unbound = rule
+            .head
+            .iter()
+            .chain(
+                rule.body
+                    .iter()
+                    .filter(|literal| literal.negated)
+                    .flat_map(|literal| literal.atom.terms()),
+            )
+            .filter_map(|term| term.variable())
+            .find(|variable| !bound.contains(variable));
+        if let Some(variable) = unbound {
+            return Err(DatalogError::UnsafeVariable(index, variable.to_owned()));
+        }
+    }
+
+    Ok(())
+}
+
+/// Check that every relation is always used with the same number of terms.
+fn check_arity(rules: &[Rule]) -> Result<(), DatalogError> {
+    let mut arities: HashMap<&str, usize> = HashMap::new();
+    let uses = rules.iter().flat_map(|rule| {
+        let body = rule.body.iter().filter_map(|literal| match &literal.atom {
+            Atom::Relation(name, terms) => Some((name.as_str(), terms.len())),
+            Atom::Triple(..) => None,
+        });
+        std::iter::once((rule.name.as_str(), rule.head.len())).chain(body)
+    });
+    for (name, arity) in uses {
+        let expected = *arities.entry(name).or_insert(arity);
+        if expected != arity {
+            return Err(DatalogError::ArityMismatch(
+                name.to_owned(),
+                expected,
+                arity,
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::sync::*;
+    use crate::store::*;
+    use tokio::runtime::Runtime;
+
+    fn example_layer() -> SyncStoreLayer {
+        let store = open_sync_memory_store();
+        let builder = store.create_base_layer().unwrap();
+        for (s, o) in [("a", "b"), ("b", "c"), ("c", "d"), ("x", "y")].iter() {
+            builder
+                .add_string_triple(StringTriple::new_node(s, "link", o))
+                .unwrap();
+        }
+        for person in ["a", "b", "x"].iter() {
+            builder
+                .add_string_triple(StringTriple::new_node(person, "type", "Person"))
+                .unwrap();
+        }
+        builder
+            .add_string_triple(StringTriple::new_value("a", "email", "a@example.com"))
+            .unwrap();
+
+        builder.commit().unwrap()
+    }
+
+    fn reachability() -> Vec<Rule> {
+        let (x, y, z) = (Term::var("x"), Term::var("y"), Term::var("z"));
+        vec![
+            Rule::new(
+                "reaches",
+                vec![x.clone(), y.clone()],
+                vec![Literal::positive(Atom::triple(
+                    x.clone(),
+                    Term::node("link"),
+                    y.clone(),
+                ))],
+            ),
+            Rule::new(
+                "reaches",
+                vec![x.clone(), z.clone()],
+                vec![
+                    Literal::positive(Atom::relation("reaches", vec![x, y.clone()])),
+                    Literal::positive(Atom::triple(y, Term::node("link"), z)),
+                ],
+            ),
+        ]
+    }
+
+    fn pair(a: &str, b: &str) -> Tuple {
+        vec![
+            ObjectType::Node(a.to_owned()),
+            ObjectType::Node(b.to_owned()),
+        ]
+    }
+
+    #[test]
+    fn derive_transitive_closure() {
+        let layer = example_layer();
+        let relations = Program::new(reachability()).unwrap().evaluate(&layer);
+
+        let expected: HashSet<Tuple> = vec![
+            pair("a", "b"),
+            pair("a", "c"),
+            pair("a", "d"),
+            pair("b", "c"),
+            pair("b", "d"),
+            pair("c", "d"),
+            pair("x", "y"),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(Some(&expected), relations.get("reaches"));
+        assert!(relations.contains("reaches", &pair("a", "d")));
+        assert!(!relations.contains("reaches", &pair("d", "a")));
+        assert_eq!(vec!["reaches"], relations.names().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn rules_can_depend_on_later_rules() {
+        let layer = example_layer();
+        let (x, y) = (Term::var("x"), Term::var("y"));
+        // `reach` comes first, so it derives nothing until `path` has facts
+        let rules = vec![
+            Rule::new(
+                "reach",
+                vec![x.clone(), y.clone()],
+                vec![Literal::positive(Atom::relation(
+                    "path",
+                    vec![x.clone(), y.clone()],
+                ))],
+            ),
+            Rule::new(
+                "path",
+                vec![x.clone(), y.clone()],
+                vec![Literal::positive(Atom::triple(x, Term::node("link"), y))],
+            ),
+        ];
+        let relations = Program::new(rules).unwrap().evaluate(&layer);
+
+        assert_eq!(relations.get("path"), relations.get("reach"));
+        assert!(relations.contains("reach", &pair("a", "b")));
+    }
+
+    #[test]
+    fn derive_with_stratified_negation() {
+        let layer = example_layer();
+        let (x, y, e) = (Term::var("x"), Term::var("y"), Term::var("e"));
+        let person = || {
+            Literal::positive(Atom::triple(
+                x.clone(),
+                Term::node("type"),
+                Term::node("Person"),
+            ))
+        };
+        let mut rules = reachability();
+        rules.push(Rule::new(
+            "has_email",
+            vec![x.clone()],
+            vec![Literal::positive(Atom::triple(
+                x.clone(),
+                Term::node("email"),
+                e,
+            ))],
+        ));
+        // people without an email address
+        rules.push(Rule::new(
+            "unreachable_by_mail",
+            vec![x.clone()],
+            vec![
+                person(),
+                Literal::negative(Atom::relation("has_email", vec![x.clone()])),
+            ],
+        ));
+        // people not linking to c, directly or indirectly
+        rules.push(Rule::new(
+            "isolated",
+            vec![x.clone()],
+            vec![
+                person(),
+                Literal::negative(Atom::relation("reaches", vec![x.clone(), Term::node("c")])),
+            ],
+        ));
+        // links that are not present in the opposite direction
+        rules.push(Rule::new(
+            "one_way",
+            vec![x.clone(), y.clone()],
+            vec![
+                Literal::positive(Atom::triple(x.clone(), Term::node("link"), y.clone())),
+                Literal::negative(Atom::triple(y, Term::node("link"), x)),
+            ],
+        ));
+
+        let relations = Program::new(rules).unwrap().evaluate(&layer);
+        let nodes = |names: &[&str]| -> HashSet<Tuple> {
+            names
+                .iter()
+                .map(|n| vec![ObjectType::Node(n.to_string())])
+                .collect()
+        };
+        assert_eq!(
+            Some(&nodes(&["b", "x"])),
+            relations.get("unreachable_by_mail")
+        );
+        assert_eq!(Some(&nodes(&["x"])), relations.get("isolated"));
+        assert_eq!(4, relations.get("one_way").unwrap().len());
+    }
+
+    #[test]
+    fn reject_invalid_programs() {
+        let (x, y) = (Term::var("x"), Term::var("y"));
+        let link = |s: &Term, o: &Term| {
+            Literal::positive(Atom::triple(s.clone(), Term::node("link"), o.clone()))
+        };
+
+        let unsafe_head = Rule::new("p", vec![x.clone(), y.clone()], vec![link(&x, &x)]);
+        assert_eq!(
+            DatalogError::UnsafeVariable(0, "y".to_owned()),
+            Program::new(vec![unsafe_head]).unwrap_err()
+        );
+
+        let unsafe_negation = Rule::new(
+            "p",
+            vec![x.clone()],
+            vec![
+                link(&x, &x),
+                Literal::negative(Atom::relation("q", vec![y.clone()])),
+            ],
+        );
+        assert_eq!(
+            DatalogError::UnsafeVariable(0, "y".to_owned()),
+            Program::new(vec![unsafe_negation]).unwrap_err()
+        );
+
+        let mismatch = vec![
+            Rule::new("p", vec![x.clone()], vec![link(&x, &y)]),
+            Rule::new("p", vec![x.clone(), y.clone()], vec![link(&x, &y)]),
+        ];
+        assert_eq!(
+            DatalogError::ArityMismatch("p".to_owned(), 1, 2),
+            Program::new(mismatch).unwrap_err()
+        );
+
+        // p depends on itself through q
+        let cycle = vec![
+            Rule::new(
+                "p",
+                vec![x.clone()],
+                vec![
+                    link(&x, &y),
+                    Literal::negative(Atom::relation("q", vec![x.clone()])),
+                ],
+            ),
+            Rule::new(
+                "q",
+                vec![x.clone()],
+                vec![Literal::positive(Atom::relation("p", vec![x]))],
+            ),
+        ];
+        assert!(matches!(
+            Program::new(cycle).unwrap_err(),
+            DatalogError::NegativeCycle(_)
+        ));
+    }
+
+    #[test]
+    fn materialize_derived_relation() {
+        let mut runtime = Runtime::new().unwrap();
+        let store = open_memory_store();
+        let builder = runtime.block_on(store.create_base_layer()).unwrap();
+        for (s, o) in [("a", "b"), ("b", "c")].iter() {
+            builder
+                .add_string_triple(StringTriple::new_node(s, "link", o))
+                .unwrap();
+        }
+        builder
+            .add_string_triple(StringTriple::new_node("a", "reaches", "b"))
+            .unwrap();
+        let layer = runtime.block_on(builder.commit()).unwrap();
+
+        let program = Program::new(reachability()).unwrap();
+        let child = runtime
+            .block_on(program.materialize(&layer, &["reaches"]))
+            .unwrap();
+
+        // a reaches b was already in the parent
+        assert_eq!(2, child.triple_layer_addition_count());
+        assert!(child.string_triple_exists(&StringTriple::new_node("a", "reaches", "c")));
+        assert!(child.string_triple_exists(&StringTriple::new_node("b", "reaches", "c")));
+
+        let values = Program::new(vec![Rule::new(
+            "linked",
+            vec![Term::value("yes"), Term::var("x")],
+            vec![Literal::positive(Atom::triple(
+                Term::var("x"),
+                Term::node("link"),
+                Term::var("y"),
+            ))],
+        )])
+        .unwrap();
+        match runtime.block_on(values.materialize(&layer, &["linked"])) {
+            Err(err) => assert_eq!(io::ErrorKind::InvalidInput, err.kind()),
+            Ok(_) => panic!("values can't be subjects"),
+        }
+    }
+}
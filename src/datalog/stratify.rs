@@ -0,0 +1,54 @@
+//! Stratification of programs with negation.
+use super::{Atom, DatalogError, Rule};
+use std::collections::HashMap;
+
+/// Assign every derived relation to a stratum, returning the indexes of the rules of each stratum, lowest first.
+///
+/// A relation is in a higher stratum than every relation it depends
+/// on through negation, and in the same or a higher stratum than
+/// every relation it depends on otherwise. This is impossible if a
+/// relation depends on itself through negation.
+pub(super) fn stratify(rules: &[Rule]) -> Result<Vec<Vec<usize>>, DatalogError> {
+    let mut strata: HashMap<&str, usize> = HashMap::new();
+    for rule in rules {
+        strata.insert(&rule.name, 0);
+        for literal in rule.body.iter() {
+            if let Atom::Relation(name, _) = &literal.atom {
+                strata.insert(name, 0);
+            }
+        }
+    }
+
+    // without a cycle through negation, no stratum exceeds the number of relations
+    let limit = strata.len();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for rule in rules {
+            for literal in rule.body.iter() {
+                if let Atom::Relation(name, _) = &literal.atom {
+                    let required = strata[name.as_str()] + literal.negated as usize;
+                    if strata[rule.name.as_str()] < required {
+                        if required > limit {
+                            return Err(DatalogError::NegativeCycle(rule.name.clone()));
+                        }
+                        strata.insert(&rule.name, required);
+                        changed = true;
+                    }
+                }
+            }
+        }
+    }
+
+    let mut result: Vec<Vec<usize>> = Vec::new();
+    for (index, rule) in rules.iter().enumerate() {
+        let stratum = strata[rule.name.as_str()];
+        if result.len() <= stratum {
+            result.resize(stratum + 1, Vec::new());
+        }
+        result[stratum].push(index);
+    }
+    result.retain(|rules| !rules.is_empty());
+
+    Ok(result)
+}
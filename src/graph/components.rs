@@ -0,0 +1,90 @@
+//! Weakly connected components.
+use super::{nodes_of, Graph};
+use std::collections::HashMap;
+
+/// A union-find structure over node indexes.
+struct DisjointSet {
+    parents: Vec<usize>,
+}
+
+impl DisjointSet {
+    fn new(size: usize) -> DisjointSet {
+        DisjointSet {
+            parents: (0..size).collect(),
+        }
+    }
+
+    fn find(&mut self, mut index: usize) -> usize {
+        while self.parents[index] != index {
+            // halve the path on the way up
+            self.parents[index] = self.parents[self.parents[index]];
+            index = self.parents[index];
+        }
+
+        index
+    }
+
+    /// Merge the sets of the two indexes, keeping the smallest index as the representative.
+    fn union(&mut self, left: usize, right: usize) {
+        let left = self.find(left);
+        let right = self.find(right);
+        if left < right {
+            self.parents[right] = left;
+        } else {
+            self.parents[left] = right;
+        }
+    }
+}
+
+impl Graph {
+    /// Find the weakly connected components of this graph, ignoring the direction of edges.
+    ///
+    /// Returns a map from every node with an edge to its component.
+    /// Components are identified by the smallest node id they contain.
+    pub fn weakly_connected_components(&self) -> HashMap<u64, u64> {
+        let edges = self.edges();
+        let nodes = nodes_of(&edges);
+        let index = |node: u64| nodes.binary_search(&node).unwrap();
+
+        let mut set = DisjointSet::new(nodes.len());
+        for &(subject, object) in edges.iter() {
+            set.union(index(subject), index(object));
+        }
+
+        (0..nodes.len())
+            .map(|i| (nodes[i], nodes[set.find(i)]))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::*;
+    use super::*;
+
+    #[test]
+    fn find_weakly_connected_components() {
+        let layer = example_graph_layer();
+        let graph = Graph::new(&layer, &["link"]);
+        let components = graph.weakly_connected_components();
+        assert_eq!(7, components.len());
+
+        let first = ids(&layer, &["a", "b", "c", "d"]);
+        let second = ids(&layer, &["x", "y", "z"]);
+        let first_component = *first.iter().min().unwrap();
+        let second_component = *second.iter().min().unwrap();
+        for node in first {
+            assert_eq!(first_component, components[&node]);
+        }
+        for node in second {
+            assert_eq!(second_component, components[&node]);
+        }
+
+        // edges against their direction also connect nodes
+        let graph = Graph::new(&layer, &["other"]);
+        let components = graph.weakly_connected_components();
+        let nodes = ids(&layer, &["d", "e", "f"]);
+        assert_eq!(3, components.len());
+        assert!(nodes.iter().all(|n| components[n] == nodes[0]));
+    }
+}
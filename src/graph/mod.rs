@@ -0,0 +1,196 @@
+//! Graph algorithms over layers.
+//!
+//! A `Graph` is a view of a layer as a directed graph. Its vertices
+//! are nodes, and its edges are the triples with one of a chosen set
+//! of predicates and a node as object. Triples with a value as object
+//! are ignored.
+//!
+//! Algorithms work directly on the layer, without exporting it
+//! first. All results are keyed by node id, which can be converted
+//! back to a string with `Layer::id_subject` or `Layer::id_object`.
+mod components;
+mod pagerank;
+mod traversal;
+
+pub use traversal::{Bfs, Dfs};
+
+use crate::layer::Layer;
+use rayon::prelude::*;
+
+/// A directed graph over the nodes of a layer, with an edge for every triple with one of the given predicates.
+pub struct Graph {
+    layer: Box<dyn Layer>,
+    predicates: Vec<u64>,
+}
+
+impl Graph {
+    /// Construct a graph from the given layer, with edges for the given predicates.
+    ///
+    /// Predicates that are unknown to the layer are ignored.
+    pub fn new(layer: &dyn Layer, predicates: &[&str]) -> Graph {
+        let predicates = predicates
+            .iter()
+            .filter_map(|p| layer.predicate_id(p))
+            .collect();
+
+        Graph::from_predicate_ids(layer, predicates)
+    }
+
+    /// Construct a graph from the given layer, with edges for the given predicate ids.
+    pub fn from_predicate_ids(layer: &dyn Layer, mut predicates: Vec<u64>) -> Graph {
+        predicates.sort_unstable();
+        predicates.dedup();
+
+        Graph {
+            layer: layer.clone_boxed(),
+            predicates,
+        }
+    }
+
+    /// Returns the layer this graph is a view of.
+    pub fn layer(&self) -> &dyn Layer {
+        &*self.layer
+    }
+
+    /// Returns the ids of the predicates whose triples are edges in this graph.
+    pub fn predicates(&self) -> &[u64] {
+        &self.predicates
+    }
+
+    fn is_node(&self, id: u64) -> bool {
+        self.layer.id_object_is_node(id) == Some(true)
+    }
+
+    /// Returns the nodes that the given node has an edge to, in ascending order.
+    pub fn successors(&self, node: u64) -> Vec<u64> {
+        let mut result: Vec<u64> = self
+            .predicates
+            .iter()
+            .flat_map(|&p| self.layer.triples_sp(node, p))
+            .map(|t| t.object)
+            .filter(|&o| self.is_node(o))
+            .collect();
+        result.sort_unstable();
+        result.dedup();
+
+        result
+    }
+
+    /// Returns the nodes that have an edge to the given node, in ascending order.
+    pub fn predecessors(&self, node: u64) -> Vec<u64> {
+        if !self.is_node(node) {
+            return Vec::new();
+        }
+
+        let mut result: Vec<u64> = self
+            .layer
+            .triples_o(node)
+            .filter(|t| self.predicates.binary_search(&t.predicate).is_ok())
+            .map(|t| t.subject)
+            .collect();
+        result.sort_unstable();
+        result.dedup();
+
+        result
+    }
+
+    /// Returns all edges of this graph, sorted and without duplicates.
+    ///
+    /// If a node has an edge to another node through several
+    /// predicates, this is a single edge in the graph.
+    pub fn edges(&self) -> Vec<(u64, u64)> {
+        let mut edges: Vec<(u64, u64)> = self
+            .predicates
+            .par_iter()
+            .map(|&p| {
+                self.layer
+                    .triples_p(p)
+                    .map(|t| (t.subject, t.object))
+                    .collect::<Vec<_>>()
+            })
+            .flatten()
+            .filter(|&(_, o)| self.is_node(o))
+            .collect();
+        edges.par_sort_unstable();
+        edges.dedup();
+
+        edges
+    }
+
+    /// Returns all nodes that have at least one edge, in ascending order.
+    pub fn nodes(&self) -> Vec<u64> {
+        nodes_of(&self.edges())
+    }
+}
+
+/// Returns all nodes in the given edges, in ascending order.
+fn nodes_of(edges: &[(u64, u64)]) -> Vec<u64> {
+    let mut nodes: Vec<u64> = edges.iter().flat_map(|&(s, o)| vec![s, o]).collect();
+    nodes.par_sort_unstable();
+    nodes.dedup();
+
+    nodes
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::layer::StringTriple;
+    use crate::store::sync::*;
+
+    /// A layer with two separate groups of nodes connected by `link`,
+    /// and some triples that are not part of the graph.
+    pub fn example_graph_layer() -> SyncStoreLayer {
+        let store = open_sync_memory_store();
+        let builder = store.create_base_layer().unwrap();
+        for (subject, predicate, object) in &[
+            ("a", "link", "b"),
+            ("a", "link", "c"),
+            ("b", "link", "d"),
+            ("c", "link", "d"),
+            ("d", "link", "a"),
+            ("d", "other", "e"),
+            ("e", "other", "f"),
+            ("x", "link", "y"),
+            ("y", "link", "z"),
+        ] {
+            builder
+                .add_string_triple(StringTriple::new_node(subject, predicate, object))
+                .unwrap();
+        }
+        builder
+            .add_string_triple(StringTriple::new_value("a", "link", "not a node"))
+            .unwrap();
+
+        builder.commit().unwrap()
+    }
+
+    pub fn ids(layer: &dyn Layer, nodes: &[&str]) -> Vec<u64> {
+        nodes
+            .iter()
+            .map(|n| layer.object_node_id(n).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn graph_edges_and_neighbours() {
+        let layer = example_graph_layer();
+        let graph = Graph::new(&layer, &["link", "unknown"]);
+        assert_eq!(1, graph.predicates().len());
+
+        let a = ids(&layer, &["a"])[0];
+        let mut expected = ids(&layer, &["b", "c"]);
+        expected.sort();
+        assert_eq!(expected, graph.successors(a));
+        assert_eq!(ids(&layer, &["d"]), graph.predecessors(a));
+
+        assert_eq!(7, graph.edges().len());
+        let mut expected = ids(&layer, &["a", "b", "c", "d", "x", "y", "z"]);
+        expected.sort();
+        assert_eq!(expected, graph.nodes());
+
+        let graph = Graph::new(&layer, &["link", "other"]);
+        assert_eq!(9, graph.edges().len());
+        assert_eq!(9, graph.nodes().len());
+    }
+}
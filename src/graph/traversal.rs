@@ -0,0 +1,163 @@
+//! Breadth-first and depth-first traversal, and unweighted shortest paths.
+use super::Graph;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+/// A breadth-first traversal of a graph, returning every reachable node together with its distance from the start.
+pub struct Bfs<'a> {
+    graph: &'a Graph,
+    queue: VecDeque<(u64, usize)>,
+    visited: HashSet<u64>,
+}
+
+impl<'a> Iterator for Bfs<'a> {
+    type Item = (u64, usize);
+
+    fn next(&mut self) -> Option<(u64, usize)> {
+        let (node, distance) = self.queue.pop_front()?;
+        for successor in self.graph.successors(node) {
+            if self.visited.insert(successor) {
+                self.queue.push_back((successor, distance + 1));
+            }
+        }
+
+        Some((node, distance))
+    }
+}
+
+/// A depth-first traversal of a graph, returning every reachable node in preorder.
+pub struct Dfs<'a> {
+    graph: &'a Graph,
+    stack: Vec<u64>,
+    visited: HashSet<u64>,
+}
+
+impl<'a> Iterator for Dfs<'a> {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<u64> {
+        while let Some(node) = self.stack.pop() {
+            if !self.visited.insert(node) {
+                continue;
+            }
+
+            // push in reverse so that the smallest successor is visited first
+            for successor in self.graph.successors(node).into_iter().rev() {
+                if !self.visited.contains(&successor) {
+                    self.stack.push(successor);
+                }
+            }
+
+            return Some(node);
+        }
+
+        None
+    }
+}
+
+impl Graph {
+    /// Traverse the graph breadth first, starting at the given node.
+    ///
+    /// The start node is returned first, with distance 0.
+    pub fn bfs(&self, start: u64) -> Bfs<'_> {
+        let mut queue = VecDeque::new();
+        queue.push_back((start, 0));
+        let mut visited = HashSet::new();
+        visited.insert(start);
+
+        Bfs {
+            graph: self,
+            queue,
+            visited,
+        }
+    }
+
+    /// Traverse the graph depth first, starting at the given node.
+    pub fn dfs(&self, start: u64) -> Dfs<'_> {
+        Dfs {
+            graph: self,
+            stack: vec![start],
+            visited: HashSet::new(),
+        }
+    }
+
+    /// Returns the distance from the given node to every node reachable from it.
+    pub fn distances(&self, start: u64) -> HashMap<u64, usize> {
+        self.bfs(start).collect()
+    }
+
+    /// Find a shortest path between two nodes, following edges in their direction.
+    ///
+    /// The path includes both the start and the end node. Returns
+    /// None if the end node can't be reached from the start node.
+    pub fn shortest_path(&self, start: u64, end: u64) -> Option<Vec<u64>> {
+        let mut parents: HashMap<u64, u64> = HashMap::new();
+        let mut queue = VecDeque::new();
+        queue.push_back(start);
+        let mut visited = HashSet::new();
+        visited.insert(start);
+
+        while let Some(node) = queue.pop_front() {
+            if node == end {
+                let mut path = vec![end];
+                let mut current = end;
+                while let Some(&parent) = parents.get(&current) {
+                    path.push(parent);
+                    current = parent;
+                }
+                path.reverse();
+
+                return Some(path);
+            }
+
+            for successor in self.successors(node) {
+                if visited.insert(successor) {
+                    parents.insert(successor, node);
+                    queue.push_back(successor);
+                }
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::*;
+    use super::*;
+
+    #[test]
+    fn traverse_breadth_and_depth_first() {
+        let layer = example_graph_layer();
+        let graph = Graph::new(&layer, &["link"]);
+        let n = ids(&layer, &["a", "b", "c", "d", "x"]);
+        let (a, b, c, d, x) = (n[0], n[1], n[2], n[3], n[4]);
+
+        let bfs: Vec<_> = graph.bfs(a).collect();
+        assert_eq!(4, bfs.len());
+        assert_eq!((a, 0), bfs[0]);
+        assert_eq!(Some(&2), graph.distances(a).get(&d));
+        assert_eq!(None, graph.distances(a).get(&x));
+
+        // the cycle back to a is not followed
+        let dfs: Vec<_> = graph.dfs(a).collect();
+        let (first, second) = if b < c { (b, c) } else { (c, b) };
+        assert_eq!(vec![a, first, d, second], dfs);
+    }
+
+    #[test]
+    fn find_shortest_paths() {
+        let layer = example_graph_layer();
+        let graph = Graph::new(&layer, &["link", "other"]);
+        let n = ids(&layer, &["a", "d", "f", "x", "z"]);
+        let (a, d, f, x, z) = (n[0], n[1], n[2], n[3], n[4]);
+
+        let path = graph.shortest_path(a, f).unwrap();
+        assert_eq!(5, path.len());
+        assert_eq!((a, d, f), (path[0], path[2], path[4]));
+        assert_eq!(Some(vec![a]), graph.shortest_path(a, a));
+        assert_eq!(3, graph.shortest_path(x, z).unwrap().len());
+        assert_eq!(None, graph.shortest_path(z, x));
+        assert_eq!(None, graph.shortest_path(a, x));
+    }
+}
@@ -3,11 +3,14 @@
 //! A base layer stores triple data without referring to a parent.
 use futures::stream::{Peekable, Stream, StreamExt};
 use futures::task::{Context, Poll};
+use once_cell::sync::OnceCell;
 
 use super::builder::*;
 use super::id_map::*;
 use super::internal::*;
 use super::layer::*;
+use super::simple_builder::LayerOptions;
+use super::statistics::*;
 use crate::storage::*;
 use crate::structure::*;
 
@@ -37,6 +40,11 @@ pub struct BaseLayer {
     o_ps_adjacency_list: AdjacencyList,
 
     predicate_wavelet_tree: WaveletTree,
+
+    search_index: Option<SuffixArray>,
+
+    statistics: Option<LayerStatistics>,
+    stack_statistics: OnceCell<Option<StackStatistics>>,
 }
 
 impl BaseLayer {
@@ -45,32 +53,32 @@ impl BaseLayer {
         files: &BaseLayerFiles<F>,
     ) -> io::Result<Self> {
         let maps = files.map_all().await?;
-        Ok(Self::load(name, maps))
+        Self::load(name, maps)
     }
 
-    pub fn load(name: [u32; 5], maps: BaseLayerMaps) -> BaseLayer {
+    /// Construct a base layer from the buffers of its files.
+    ///
+    /// This returns an error if any of the buffers is not a valid structure.
+    pub fn load(name: [u32; 5], maps: BaseLayerMaps) -> io::Result<BaseLayer> {
         let node_dictionary = PfcDict::parse(
             maps.node_dictionary_maps.blocks_map,
             maps.node_dictionary_maps.offsets_map,
-        )
-        .unwrap();
+        )?;
         let predicate_dictionary = PfcDict::parse(
             maps.predicate_dictionary_maps.blocks_map,
             maps.predicate_dictionary_maps.offsets_map,
-        )
-        .unwrap();
+        )?;
         let value_dictionary = PfcDict::parse(
             maps.value_dictionary_maps.blocks_map,
             maps.value_dictionary_maps.offsets_map,
-        )
-        .unwrap();
+        )?;
 
         let node_value_idmap = match maps.id_map_maps.node_value_idmap_maps {
             None => IdMap::default(),
             Some(maps) => IdMap::from_maps(
                 maps,
                 util::calculate_width((node_dictionary.len() + value_dictionary.len()) as u64),
-            ),
+            )?,
         };
 
         let predicate_idmap = match maps.id_map_maps.predicate_idmap_maps {
@@ -78,46 +86,54 @@ impl BaseLayer {
             Some(map) => IdMap::from_maps(
                 map,
                 util::calculate_width(predicate_dictionary.len() as u64),
-            ),
+            )?,
         };
 
-        let subjects = maps.subjects_map.map(|subjects_map| {
-            MonotonicLogArray::from_logarray(LogArray::parse(subjects_map).unwrap())
-        });
-        let objects = maps.objects_map.map(|objects_map| {
-            MonotonicLogArray::from_logarray(LogArray::parse(objects_map).unwrap())
-        });
+        let subjects = maps
+            .subjects_map
+            .map(MonotonicLogArray::parse)
+            .transpose()?;
+        let objects = maps.objects_map.map(MonotonicLogArray::parse).transpose()?;
 
         let s_p_adjacency_list = AdjacencyList::parse(
             maps.s_p_adjacency_list_maps.nums_map,
             maps.s_p_adjacency_list_maps.bitindex_maps.bits_map,
             maps.s_p_adjacency_list_maps.bitindex_maps.blocks_map,
             maps.s_p_adjacency_list_maps.bitindex_maps.sblocks_map,
-        );
+        )?
+        .with_select_map(maps.s_p_adjacency_list_maps.bitindex_maps.select_map)?;
         let sp_o_adjacency_list = AdjacencyList::parse(
             maps.sp_o_adjacency_list_maps.nums_map,
             maps.sp_o_adjacency_list_maps.bitindex_maps.bits_map,
             maps.sp_o_adjacency_list_maps.bitindex_maps.blocks_map,
             maps.sp_o_adjacency_list_maps.bitindex_maps.sblocks_map,
-        );
+        )?
+        .with_select_map(maps.sp_o_adjacency_list_maps.bitindex_maps.select_map)?;
         let o_ps_adjacency_list = AdjacencyList::parse(
             maps.o_ps_adjacency_list_maps.nums_map,
             maps.o_ps_adjacency_list_maps.bitindex_maps.bits_map,
             maps.o_ps_adjacency_list_maps.bitindex_maps.blocks_map,
             maps.o_ps_adjacency_list_maps.bitindex_maps.sblocks_map,
-        );
+        )?
+        .with_select_map(maps.o_ps_adjacency_list_maps.bitindex_maps.select_map)?;
 
         let predicate_wavelet_tree_width = s_p_adjacency_list.nums().width();
-        let predicate_wavelet_tree = WaveletTree::from_parts(
-            BitIndex::from_maps(
-                maps.predicate_wavelet_tree_maps.bits_map,
-                maps.predicate_wavelet_tree_maps.blocks_map,
-                maps.predicate_wavelet_tree_maps.sblocks_map,
-            ),
+        let predicate_wavelet_tree = WaveletTree::from_maps(
+            maps.predicate_wavelet_tree_maps,
             predicate_wavelet_tree_width,
-        );
+        )?;
+
+        let search_index = maps
+            .search_index_maps
+            .map(|maps| SuffixArray::parse(maps.text_map, maps.suffixes_map, maps.starts_map))
+            .transpose()?;
+
+        let statistics = maps
+            .statistics_map
+            .map(LayerStatistics::parse)
+            .transpose()?;
 
-        BaseLayer {
+        Ok(BaseLayer {
             name,
             node_dictionary,
             predicate_dictionary,
@@ -135,7 +151,12 @@ impl BaseLayer {
             o_ps_adjacency_list,
 
             predicate_wavelet_tree,
-        }
+
+            search_index,
+
+            statistics,
+            stack_statistics: OnceCell::new(),
+        })
     }
 }
 
@@ -231,6 +252,20 @@ impl InternalLayerImpl for BaseLayer {
     fn neg_objects(&self) -> Option<&MonotonicLogArray> {
         None
     }
+
+    fn search_index(&self) -> Option<&SuffixArray> {
+        self.search_index.as_ref()
+    }
+
+    fn statistics(&self) -> Option<&LayerStatistics> {
+        self.statistics.as_ref()
+    }
+
+    fn stack_statistics(&self) -> Option<&StackStatistics> {
+        self.stack_statistics
+            .get_or_init(|| StackStatistics::from_stack(self))
+            .as_ref()
+    }
 }
 
 /// A builder for a base layer.
@@ -243,20 +278,28 @@ pub struct BaseLayerFileBuilder<F: 'static + FileLoad + FileStore> {
     files: BaseLayerFiles<F>,
 
     builder: DictionarySetFileBuilder<F>,
+    options: LayerOptions,
 }
 
 impl<F: 'static + FileLoad + FileStore + Clone> BaseLayerFileBuilder<F> {
     /// Create the builder from the given files.
     pub fn from_files(files: &BaseLayerFiles<F>) -> Self {
+        Self::from_files_with_options(files, LayerOptions::default())
+    }
+
+    /// Create the builder from the given files, which will be built according to the given options.
+    pub fn from_files_with_options(files: &BaseLayerFiles<F>, options: LayerOptions) -> Self {
         let builder = DictionarySetFileBuilder::from_files(
             files.node_dictionary_files.clone(),
             files.predicate_dictionary_files.clone(),
             files.value_dictionary_files.clone(),
+            options,
         );
 
         BaseLayerFileBuilder {
             files: files.clone(),
             builder,
+            options,
         }
     }
 
@@ -337,7 +380,11 @@ impl<F: 'static + FileLoad + FileStore + Clone> BaseLayerFileBuilder<F> {
 
     /// Turn this builder into a phase 2 builder that will take triple data.
     pub async fn into_phase2(self) -> io::Result<BaseLayerFileBuilderPhase2<F>> {
-        let BaseLayerFileBuilder { files, builder } = self;
+        let BaseLayerFileBuilder {
+            files,
+            builder,
+            options,
+        } = self;
 
         builder.finalize().await?;
 
@@ -363,6 +410,7 @@ impl<F: 'static + FileLoad + FileStore + Clone> BaseLayerFileBuilder<F> {
             num_nodes,
             num_predicates,
             num_values,
+            options,
         ))
     }
 }
@@ -375,6 +423,8 @@ pub struct BaseLayerFileBuilderPhase2<F: 'static + FileLoad + FileStore> {
     files: BaseLayerFiles<F>,
 
     builder: TripleFileBuilder<F>,
+    statistics: StatisticsBuilder,
+    options: LayerOptions,
 }
 
 impl<F: 'static + FileLoad + FileStore> BaseLayerFileBuilderPhase2<F> {
@@ -384,6 +434,7 @@ impl<F: 'static + FileLoad + FileStore> BaseLayerFileBuilderPhase2<F> {
         num_nodes: usize,
         num_predicates: usize,
         num_values: usize,
+        options: LayerOptions,
     ) -> Self {
         let builder = TripleFileBuilder::new(
             files.s_p_adjacency_list_files.clone(),
@@ -392,9 +443,15 @@ impl<F: 'static + FileLoad + FileStore> BaseLayerFileBuilderPhase2<F> {
             num_predicates,
             num_values,
             None,
-        );
+        )
+        .with_options(options);
 
-        BaseLayerFileBuilderPhase2 { files, builder }
+        BaseLayerFileBuilderPhase2 {
+            files,
+            builder,
+            statistics: StatisticsBuilder::new(),
+            options,
+        }
     }
 
     /// Add the given subject, predicate and object.
@@ -406,6 +463,8 @@ impl<F: 'static + FileLoad + FileStore> BaseLayerFileBuilderPhase2<F> {
         predicate: u64,
         object: u64,
     ) -> io::Result<()> {
+        self.statistics
+            .add(IdTriple::new(subject, predicate, object));
         self.builder.add_triple(subject, predicate, object).await
     }
 
@@ -419,14 +478,19 @@ impl<F: 'static + FileLoad + FileStore> BaseLayerFileBuilderPhase2<F> {
     where
         <I as std::iter::IntoIterator>::IntoIter: Unpin + Send,
     {
-        self.builder.add_id_triples(triples).await
+        for triple in triples {
+            self.add_triple(triple.subject, triple.predicate, triple.object)
+                .await?;
+        }
+
+        Ok(())
     }
 
     pub async fn finalize(self) -> io::Result<()> {
-        let s_p_adjacency_list_files = self.files.s_p_adjacency_list_files;
-        let sp_o_adjacency_list_files = self.files.sp_o_adjacency_list_files;
-        let o_ps_adjacency_list_files = self.files.o_ps_adjacency_list_files;
-        let predicate_wavelet_tree_files = self.files.predicate_wavelet_tree_files;
+        let s_p_adjacency_list_files = self.files.s_p_adjacency_list_files.clone();
+        let sp_o_adjacency_list_files = self.files.sp_o_adjacency_list_files.clone();
+        let o_ps_adjacency_list_files = self.files.o_ps_adjacency_list_files.clone();
+        let predicate_wavelet_tree_files = self.files.predicate_wavelet_tree_files.clone();
 
         self.builder.finalize().await?;
 
@@ -436,8 +500,15 @@ impl<F: 'static + FileLoad + FileStore> BaseLayerFileBuilderPhase2<F> {
             o_ps_adjacency_list_files,
             None,
             predicate_wavelet_tree_files,
+            self.options,
         )
-        .await
+        .await?;
+
+        LayerStatistics::new(self.statistics.finish(), TripleStatistics::default())
+            .write(self.files.statistics_file.clone())
+            .await?;
+
+        self.files.write_checksums().await
     }
 }
 
@@ -4,6 +4,7 @@ use futures::stream::TryStreamExt;
 use rayon::prelude::*;
 
 use super::layer::*;
+use super::simple_builder::LayerOptions;
 use crate::storage::*;
 use crate::structure::util;
 use crate::structure::*;
@@ -19,19 +20,19 @@ impl<F: 'static + FileLoad + FileStore> DictionarySetFileBuilder<F> {
         node_files: DictionaryFiles<F>,
         predicate_files: DictionaryFiles<F>,
         value_files: DictionaryFiles<F>,
+        options: LayerOptions,
     ) -> Self {
-        let node_dictionary_builder = PfcDictFileBuilder::new(
-            node_files.blocks_file.open_write(),
-            node_files.offsets_file.open_write(),
-        );
-        let predicate_dictionary_builder = PfcDictFileBuilder::new(
-            predicate_files.blocks_file.open_write(),
-            predicate_files.offsets_file.open_write(),
-        );
-        let value_dictionary_builder = PfcDictFileBuilder::new(
-            value_files.blocks_file.open_write(),
-            value_files.offsets_file.open_write(),
-        );
+        let dictionary_builder = |files: DictionaryFiles<F>| {
+            PfcDictFileBuilder::with_block_size(
+                files.blocks_file.open_write(),
+                files.offsets_file.open_write(),
+                options.dictionary_block_size,
+            )
+            .with_elias_fano(options.elias_fano)
+        };
+        let node_dictionary_builder = dictionary_builder(node_files);
+        let predicate_dictionary_builder = dictionary_builder(predicate_files);
+        let value_dictionary_builder = dictionary_builder(value_files);
 
         Self {
             node_dictionary_builder,
@@ -136,6 +137,7 @@ impl<F: 'static + FileLoad + FileStore> DictionarySetFileBuilder<F> {
 pub struct TripleFileBuilder<F: 'static + FileLoad + FileStore> {
     subjects_file: Option<F>,
     subjects: Option<Vec<u64>>,
+    elias_fano: bool,
 
     s_p_adjacency_list_builder: AdjacencyListBuilder<F, F::Write, F::Write, F::Write>,
     sp_o_adjacency_list_builder: AdjacencyListBuilder<F, F::Write, F::Write, F::Write>,
@@ -191,6 +193,7 @@ impl<F: 'static + FileLoad + FileStore> TripleFileBuilder<F> {
         Self {
             subjects,
             subjects_file,
+            elias_fano: false,
             s_p_adjacency_list_builder,
             sp_o_adjacency_list_builder,
             last_subject: 0,
@@ -198,6 +201,23 @@ impl<F: 'static + FileLoad + FileStore> TripleFileBuilder<F> {
         }
     }
 
+    /// Build the files according to the given options.
+    ///
+    /// This decides whether the subjects file is written Elias-Fano
+    /// encoded, and how the bits of the adjacency lists are encoded.
+    /// It has to be called before any triples are added.
+    pub fn with_options(mut self, options: LayerOptions) -> Self {
+        self.elias_fano = options.elias_fano;
+        self.s_p_adjacency_list_builder = self
+            .s_p_adjacency_list_builder
+            .with_bit_index_encoding(options.bit_index_encoding);
+        self.sp_o_adjacency_list_builder = self
+            .sp_o_adjacency_list_builder
+            .with_bit_index_encoding(options.bit_index_encoding);
+
+        self
+    }
+
     /// Add the given subject, predicate and object.
     ///
     /// This will panic if a greater triple has already been added.
@@ -262,31 +282,39 @@ impl<F: 'static + FileLoad + FileStore> TripleFileBuilder<F> {
         self.sp_o_adjacency_list_builder.finalize().await?;
 
         if let Some(subjects) = self.subjects {
-            // isn't this just last_subject?
-            let max_subject = if subjects.is_empty() {
-                0
-            } else {
-                subjects[subjects.len() - 1]
-            };
-
-            let subjects_width = util::calculate_width(max_subject);
-            let mut subjects_logarray_builder =
-                LogArrayFileBuilder::new(self.subjects_file.unwrap().open_write(), subjects_width);
-
-            subjects_logarray_builder
-                .push_all(util::stream_iter_ok(subjects))
+            write_monotonic_sequence(self.subjects_file.unwrap(), subjects, self.elias_fano)
                 .await?;
-            subjects_logarray_builder.finalize().await?;
         };
 
         Ok(())
     }
 }
 
+/// Write an increasing list of ids, either as a log array or Elias-Fano encoded.
+async fn write_monotonic_sequence<F: 'static + FileStore>(
+    file: F,
+    elements: Vec<u64>,
+    elias_fano: bool,
+) -> io::Result<()> {
+    let max = elements.last().cloned().unwrap_or(0);
+    if elias_fano {
+        let mut builder = EliasFanoFileBuilder::new(file.open_write(), elements.len(), max);
+        builder.push_all(elements).await?;
+        builder.finalize().await?;
+    } else {
+        let mut builder = LogArrayFileBuilder::new(file.open_write(), util::calculate_width(max));
+        builder.push_all(util::stream_iter_ok(elements)).await?;
+        builder.finalize().await?;
+    }
+
+    Ok(())
+}
+
 pub async fn build_object_index<FLoad: 'static + FileLoad, F: 'static + FileLoad + FileStore>(
     sp_o_files: AdjacencyListFiles<FLoad>,
     o_ps_files: AdjacencyListFiles<F>,
     objects_file: Option<F>,
+    options: LayerOptions,
 ) -> io::Result<()> {
     let build_sparse_index = objects_file.is_some();
     let mut aj_stream =
@@ -307,7 +335,8 @@ pub async fn build_object_index<FLoad: 'static + FileLoad, F: 'static + FileLoad
         o_ps_files.bitindex_files.sblocks_file.open_write(),
         o_ps_files.nums_file.open_write(),
         aj_width,
-    );
+    )
+    .with_bit_index_encoding(options.bit_index_encoding);
 
     if build_sparse_index {
         // a sparse index compresses the adjacency list so that all objects in use are remapped to form a continuous range.
@@ -327,15 +356,9 @@ pub async fn build_object_index<FLoad: 'static + FileLoad, F: 'static + FileLoad
 
             o_ps_adjacency_list_builder.push(object_ix, sp).await?;
         }
-        let objects_width = util::calculate_width(last_object);
 
         // write out the object list
-        let mut objects_builder =
-            LogArrayFileBuilder::new(objects_file.unwrap().open_write(), objects_width);
-        objects_builder
-            .push_all(util::stream_iter_ok(objects))
-            .await?;
-        objects_builder.finalize().await?;
+        write_monotonic_sequence(objects_file.unwrap(), objects, options.elias_fano).await?;
     } else {
         o_ps_adjacency_list_builder
             .push_all(util::stream_iter_ok(pairs))
@@ -350,9 +373,11 @@ pub async fn build_predicate_index<FLoad: 'static + FileLoad, F: 'static + FileL
     destination_bits: F,
     destination_blocks: F,
     destination_sblocks: F,
+    options: LayerOptions,
 ) -> io::Result<()> {
     build_wavelet_tree_from_logarray(
         source,
+        options.bit_index_encoding,
         destination_bits,
         destination_blocks,
         destination_sblocks,
@@ -360,23 +385,54 @@ pub async fn build_predicate_index<FLoad: 'static + FileLoad, F: 'static + FileL
     .await
 }
 
-pub async fn build_indexes<FLoad: 'static + FileLoad, F: 'static + FileLoad + FileStore>(
+/// Build a select index for the bit index in the given files, unless its bits are RRR compressed.
+pub async fn build_select_index_file<F: 'static + FileLoad + FileStore>(
+    files: BitIndexFiles<F>,
+    sample_rate: u64,
+) -> io::Result<()> {
+    let maps = files.map_all().await?;
+    let index = BitIndex::from_maps(maps.bits_map, maps.blocks_map, maps.sblocks_map)?;
+    if index.is_rrr() {
+        return Ok(());
+    }
+
+    build_select_index(&index, sample_rate, files.select_file.open_write()).await
+}
+
+pub async fn build_indexes<
+    FLoad: 'static + FileLoad + FileStore,
+    F: 'static + FileLoad + FileStore,
+>(
     s_p_files: AdjacencyListFiles<FLoad>,
     sp_o_files: AdjacencyListFiles<FLoad>,
     o_ps_files: AdjacencyListFiles<F>,
     objects_file: Option<F>,
     wavelet_files: BitIndexFiles<F>,
+    options: LayerOptions,
 ) -> io::Result<()> {
-    let object_index_task = tokio::spawn(build_object_index(sp_o_files, o_ps_files, objects_file));
+    let object_index_task = tokio::spawn(build_object_index(
+        sp_o_files.clone(),
+        o_ps_files.clone(),
+        objects_file,
+        options,
+    ));
     let predicate_index_task = tokio::spawn(build_predicate_index(
         s_p_files.nums_file,
-        wavelet_files.bits_file,
-        wavelet_files.blocks_file,
-        wavelet_files.sblocks_file,
+        wavelet_files.bits_file.clone(),
+        wavelet_files.blocks_file.clone(),
+        wavelet_files.sblocks_file.clone(),
+        options,
     ));
 
     object_index_task.await??;
     predicate_index_task.await??;
 
+    if let Some(sample_rate) = options.select_sample_rate {
+        build_select_index_file(s_p_files.bitindex_files, sample_rate).await?;
+        build_select_index_file(sp_o_files.bitindex_files, sample_rate).await?;
+        build_select_index_file(o_ps_files.bitindex_files, sample_rate).await?;
+        build_select_index_file(wavelet_files, sample_rate).await?;
+    }
+
     Ok(())
 }
@@ -7,6 +7,8 @@ use super::builder::*;
 use super::id_map::*;
 use super::internal::*;
 use super::layer::*;
+use super::simple_builder::LayerOptions;
+use super::statistics::*;
 use crate::storage::*;
 use crate::structure::*;
 use rayon::prelude::*;
@@ -17,6 +19,7 @@ use std::sync::Arc;
 
 use futures::stream::{self, Stream, StreamExt};
 use futures::task::{Context, Poll};
+use once_cell::sync::OnceCell;
 
 /// A child layer.
 ///
@@ -50,6 +53,11 @@ pub struct ChildLayer {
 
     pos_predicate_wavelet_tree: WaveletTree,
     neg_predicate_wavelet_tree: WaveletTree,
+
+    search_index: Option<SuffixArray>,
+
+    statistics: Option<LayerStatistics>,
+    stack_statistics: OnceCell<Option<StackStatistics>>,
 }
 
 impl ChildLayer {
@@ -59,25 +67,29 @@ impl ChildLayer {
         files: &ChildLayerFiles<F>,
     ) -> io::Result<Self> {
         let maps = files.map_all().await?;
-        Ok(Self::load(name, parent, maps))
+        Self::load(name, parent, maps)
     }
 
-    pub fn load(name: [u32; 5], parent: Arc<InternalLayer>, maps: ChildLayerMaps) -> ChildLayer {
+    /// Construct a child layer from the buffers of its files.
+    ///
+    /// This returns an error if any of the buffers is not a valid structure.
+    pub fn load(
+        name: [u32; 5],
+        parent: Arc<InternalLayer>,
+        maps: ChildLayerMaps,
+    ) -> io::Result<ChildLayer> {
         let node_dictionary = PfcDict::parse(
             maps.node_dictionary_maps.blocks_map,
             maps.node_dictionary_maps.offsets_map,
-        )
-        .unwrap();
+        )?;
         let predicate_dictionary = PfcDict::parse(
             maps.predicate_dictionary_maps.blocks_map,
             maps.predicate_dictionary_maps.offsets_map,
-        )
-        .unwrap();
+        )?;
         let value_dictionary = PfcDict::parse(
             maps.value_dictionary_maps.blocks_map,
             maps.value_dictionary_maps.offsets_map,
-        )
-        .unwrap();
+        )?;
 
         let parent_node_value_count = parent.node_and_value_count();
         let parent_predicate_count = parent.predicate_count();
@@ -87,7 +99,7 @@ impl ChildLayer {
             Some(maps) => IdMap::from_maps(
                 maps,
                 util::calculate_width((node_dictionary.len() + value_dictionary.len()) as u64),
-            ),
+            )?,
         };
 
         let predicate_idmap = match maps.id_map_maps.predicate_idmap_maps {
@@ -95,76 +107,80 @@ impl ChildLayer {
             Some(map) => IdMap::from_maps(
                 map,
                 util::calculate_width(predicate_dictionary.len() as u64),
-            ),
+            )?,
         };
 
-        let pos_subjects =
-            MonotonicLogArray::from_logarray(LogArray::parse(maps.pos_subjects_map).unwrap());
-        let pos_objects =
-            MonotonicLogArray::from_logarray(LogArray::parse(maps.pos_objects_map).unwrap());
-        let neg_subjects =
-            MonotonicLogArray::from_logarray(LogArray::parse(maps.neg_subjects_map).unwrap());
-        let neg_objects =
-            MonotonicLogArray::from_logarray(LogArray::parse(maps.neg_objects_map).unwrap());
+        let pos_subjects = MonotonicLogArray::parse(maps.pos_subjects_map)?;
+        let pos_objects = MonotonicLogArray::parse(maps.pos_objects_map)?;
+        let neg_subjects = MonotonicLogArray::parse(maps.neg_subjects_map)?;
+        let neg_objects = MonotonicLogArray::parse(maps.neg_objects_map)?;
 
         let pos_s_p_adjacency_list = AdjacencyList::parse(
             maps.pos_s_p_adjacency_list_maps.nums_map,
             maps.pos_s_p_adjacency_list_maps.bitindex_maps.bits_map,
             maps.pos_s_p_adjacency_list_maps.bitindex_maps.blocks_map,
             maps.pos_s_p_adjacency_list_maps.bitindex_maps.sblocks_map,
-        );
+        )?
+        .with_select_map(maps.pos_s_p_adjacency_list_maps.bitindex_maps.select_map)?;
         let pos_sp_o_adjacency_list = AdjacencyList::parse(
             maps.pos_sp_o_adjacency_list_maps.nums_map,
             maps.pos_sp_o_adjacency_list_maps.bitindex_maps.bits_map,
             maps.pos_sp_o_adjacency_list_maps.bitindex_maps.blocks_map,
             maps.pos_sp_o_adjacency_list_maps.bitindex_maps.sblocks_map,
-        );
+        )?
+        .with_select_map(maps.pos_sp_o_adjacency_list_maps.bitindex_maps.select_map)?;
         let pos_o_ps_adjacency_list = AdjacencyList::parse(
             maps.pos_o_ps_adjacency_list_maps.nums_map,
             maps.pos_o_ps_adjacency_list_maps.bitindex_maps.bits_map,
             maps.pos_o_ps_adjacency_list_maps.bitindex_maps.blocks_map,
             maps.pos_o_ps_adjacency_list_maps.bitindex_maps.sblocks_map,
-        );
+        )?
+        .with_select_map(maps.pos_o_ps_adjacency_list_maps.bitindex_maps.select_map)?;
         let neg_s_p_adjacency_list = AdjacencyList::parse(
             maps.neg_s_p_adjacency_list_maps.nums_map,
             maps.neg_s_p_adjacency_list_maps.bitindex_maps.bits_map,
             maps.neg_s_p_adjacency_list_maps.bitindex_maps.blocks_map,
             maps.neg_s_p_adjacency_list_maps.bitindex_maps.sblocks_map,
-        );
+        )?
+        .with_select_map(maps.neg_s_p_adjacency_list_maps.bitindex_maps.select_map)?;
         let neg_sp_o_adjacency_list = AdjacencyList::parse(
             maps.neg_sp_o_adjacency_list_maps.nums_map,
             maps.neg_sp_o_adjacency_list_maps.bitindex_maps.bits_map,
             maps.neg_sp_o_adjacency_list_maps.bitindex_maps.blocks_map,
             maps.neg_sp_o_adjacency_list_maps.bitindex_maps.sblocks_map,
-        );
+        )?
+        .with_select_map(maps.neg_sp_o_adjacency_list_maps.bitindex_maps.select_map)?;
         let neg_o_ps_adjacency_list = AdjacencyList::parse(
             maps.neg_o_ps_adjacency_list_maps.nums_map,
             maps.neg_o_ps_adjacency_list_maps.bitindex_maps.bits_map,
             maps.neg_o_ps_adjacency_list_maps.bitindex_maps.blocks_map,
             maps.neg_o_ps_adjacency_list_maps.bitindex_maps.sblocks_map,
-        );
+        )?
+        .with_select_map(maps.neg_o_ps_adjacency_list_maps.bitindex_maps.select_map)?;
 
         let pos_predicate_wavelet_tree_width = pos_s_p_adjacency_list.nums().width();
-        let pos_predicate_wavelet_tree = WaveletTree::from_parts(
-            BitIndex::from_maps(
-                maps.pos_predicate_wavelet_tree_maps.bits_map,
-                maps.pos_predicate_wavelet_tree_maps.blocks_map,
-                maps.pos_predicate_wavelet_tree_maps.sblocks_map,
-            ),
+        let pos_predicate_wavelet_tree = WaveletTree::from_maps(
+            maps.pos_predicate_wavelet_tree_maps,
             pos_predicate_wavelet_tree_width,
-        );
+        )?;
 
         let neg_predicate_wavelet_tree_width = neg_s_p_adjacency_list.nums().width();
-        let neg_predicate_wavelet_tree = WaveletTree::from_parts(
-            BitIndex::from_maps(
-                maps.neg_predicate_wavelet_tree_maps.bits_map,
-                maps.neg_predicate_wavelet_tree_maps.blocks_map,
-                maps.neg_predicate_wavelet_tree_maps.sblocks_map,
-            ),
+        let neg_predicate_wavelet_tree = WaveletTree::from_maps(
+            maps.neg_predicate_wavelet_tree_maps,
             neg_predicate_wavelet_tree_width,
-        );
+        )?;
+
+        let search_index = maps
+            .search_index_maps
+            .map(|maps| SuffixArray::parse(maps.text_map, maps.suffixes_map, maps.starts_map))
+            .transpose()?;
 
-        ChildLayer {
+        let statistics = maps
+            .statistics_map
+            .map(LayerStatistics::parse)
+            .transpose()?;
+
+        Ok(ChildLayer {
             name,
             parent: parent,
 
@@ -193,7 +209,12 @@ impl ChildLayer {
 
             pos_predicate_wavelet_tree,
             neg_predicate_wavelet_tree,
-        }
+
+            search_index,
+
+            statistics,
+            stack_statistics: OnceCell::new(),
+        })
     }
 }
 
@@ -289,6 +310,20 @@ impl InternalLayerImpl for ChildLayer {
     fn neg_objects(&self) -> Option<&MonotonicLogArray> {
         Some(&self.neg_objects)
     }
+
+    fn search_index(&self) -> Option<&SuffixArray> {
+        self.search_index.as_ref()
+    }
+
+    fn statistics(&self) -> Option<&LayerStatistics> {
+        self.statistics.as_ref()
+    }
+
+    fn stack_statistics(&self) -> Option<&StackStatistics> {
+        self.stack_statistics
+            .get_or_init(|| StackStatistics::from_stack(self))
+            .as_ref()
+    }
 }
 
 /// A builder for a child layer.
@@ -301,21 +336,33 @@ pub struct ChildLayerFileBuilder<F: 'static + FileLoad + FileStore + Clone + Sen
     parent: Arc<dyn Layer>,
     files: ChildLayerFiles<F>,
     builder: DictionarySetFileBuilder<F>,
+    options: LayerOptions,
 }
 
 impl<F: 'static + FileLoad + FileStore + Clone + Send + Sync> ChildLayerFileBuilder<F> {
     /// Create the builder from the given files.
     pub fn from_files(parent: Arc<dyn Layer>, files: &ChildLayerFiles<F>) -> Self {
+        Self::from_files_with_options(parent, files, LayerOptions::default())
+    }
+
+    /// Create the builder from the given files, which will be built according to the given options.
+    pub fn from_files_with_options(
+        parent: Arc<dyn Layer>,
+        files: &ChildLayerFiles<F>,
+        options: LayerOptions,
+    ) -> Self {
         let builder = DictionarySetFileBuilder::from_files(
             files.node_dictionary_files.clone(),
             files.predicate_dictionary_files.clone(),
             files.value_dictionary_files.clone(),
+            options,
         );
 
         Self {
             parent,
             files: files.clone(),
             builder,
+            options,
         }
     }
 
@@ -430,6 +477,7 @@ impl<F: 'static + FileLoad + FileStore + Clone + Send + Sync> ChildLayerFileBuil
             parent,
             files,
             builder,
+            options,
         } = self;
 
         builder.finalize().await?;
@@ -457,6 +505,7 @@ impl<F: 'static + FileLoad + FileStore + Clone + Send + Sync> ChildLayerFileBuil
             num_nodes,
             num_predicates,
             num_values,
+            options,
         ))
     }
 }
@@ -472,6 +521,9 @@ pub struct ChildLayerFileBuilderPhase2<F: 'static + FileLoad + FileStore + Clone
 
     pos_builder: TripleFileBuilder<F>,
     neg_builder: TripleFileBuilder<F>,
+    additions: StatisticsBuilder,
+    removals: StatisticsBuilder,
+    options: LayerOptions,
 }
 
 impl<F: 'static + FileLoad + FileStore + Clone + Send + Sync> ChildLayerFileBuilderPhase2<F> {
@@ -482,6 +534,7 @@ impl<F: 'static + FileLoad + FileStore + Clone + Send + Sync> ChildLayerFileBuil
         num_nodes: usize,
         num_predicates: usize,
         num_values: usize,
+        options: LayerOptions,
     ) -> Self {
         let parent_counts = parent.all_counts();
         let pos_builder = TripleFileBuilder::new(
@@ -491,7 +544,8 @@ impl<F: 'static + FileLoad + FileStore + Clone + Send + Sync> ChildLayerFileBuil
             num_predicates + parent_counts.predicate_count,
             num_values + parent_counts.value_count,
             Some(files.pos_subjects_file.clone()),
-        );
+        )
+        .with_options(options);
 
         let neg_builder = TripleFileBuilder::new(
             files.neg_s_p_adjacency_list_files.clone(),
@@ -500,7 +554,8 @@ impl<F: 'static + FileLoad + FileStore + Clone + Send + Sync> ChildLayerFileBuil
             num_predicates + parent_counts.predicate_count,
             num_values + parent_counts.value_count,
             Some(files.neg_subjects_file.clone()),
-        );
+        )
+        .with_options(options);
 
         ChildLayerFileBuilderPhase2 {
             parent,
@@ -508,6 +563,9 @@ impl<F: 'static + FileLoad + FileStore + Clone + Send + Sync> ChildLayerFileBuil
 
             pos_builder,
             neg_builder,
+            additions: StatisticsBuilder::new(),
+            removals: StatisticsBuilder::new(),
+            options,
         }
     }
 
@@ -517,6 +575,8 @@ impl<F: 'static + FileLoad + FileStore + Clone + Send + Sync> ChildLayerFileBuil
         predicate: u64,
         object: u64,
     ) -> io::Result<()> {
+        self.additions
+            .add(IdTriple::new(subject, predicate, object));
         self.pos_builder
             .add_triple(subject, predicate, object)
             .await
@@ -545,6 +605,7 @@ impl<F: 'static + FileLoad + FileStore + Clone + Send + Sync> ChildLayerFileBuil
         predicate: u64,
         object: u64,
     ) -> io::Result<()> {
+        self.removals.add(IdTriple::new(subject, predicate, object));
         self.neg_builder
             .add_triple(subject, predicate, object)
             .await
@@ -619,24 +680,30 @@ impl<F: 'static + FileLoad + FileStore + Clone + Send + Sync> ChildLayerFileBuil
         neg_task.await??;
 
         let pos_indexes_task = tokio::spawn(build_indexes(
-            self.files.pos_s_p_adjacency_list_files,
-            self.files.pos_sp_o_adjacency_list_files,
-            self.files.pos_o_ps_adjacency_list_files,
-            Some(self.files.pos_objects_file),
-            self.files.pos_predicate_wavelet_tree_files,
+            self.files.pos_s_p_adjacency_list_files.clone(),
+            self.files.pos_sp_o_adjacency_list_files.clone(),
+            self.files.pos_o_ps_adjacency_list_files.clone(),
+            Some(self.files.pos_objects_file.clone()),
+            self.files.pos_predicate_wavelet_tree_files.clone(),
+            self.options,
         ));
         let neg_indexes_task = tokio::spawn(build_indexes(
-            self.files.neg_s_p_adjacency_list_files,
-            self.files.neg_sp_o_adjacency_list_files,
-            self.files.neg_o_ps_adjacency_list_files,
-            Some(self.files.neg_objects_file),
-            self.files.neg_predicate_wavelet_tree_files,
+            self.files.neg_s_p_adjacency_list_files.clone(),
+            self.files.neg_sp_o_adjacency_list_files.clone(),
+            self.files.neg_o_ps_adjacency_list_files.clone(),
+            Some(self.files.neg_objects_file.clone()),
+            self.files.neg_predicate_wavelet_tree_files.clone(),
+            self.options,
         ));
 
         pos_indexes_task.await??;
         neg_indexes_task.await??;
 
-        Ok(())
+        LayerStatistics::new(self.additions.finish(), self.removals.finish())
+            .write(self.files.statistics_file.clone())
+            .await?;
+
+        self.files.write_checksums().await
     }
 }
 
@@ -768,7 +835,7 @@ pub fn open_child_triple_stream<F: 'static + FileLoad + FileStore>(
     s_p_files: AdjacencyListFiles<F>,
     sp_o_files: AdjacencyListFiles<F>,
 ) -> impl Stream<Item = io::Result<(u64, u64, u64)>> + Unpin + Send {
-    let subjects_stream = logarray_stream_entries(subjects_file);
+    let subjects_stream = monotonic_logarray_stream_entries(subjects_file);
     let s_p_stream =
         adjacency_list_stream_pairs(s_p_files.bitindex_files.bits_file, s_p_files.nums_file);
     let sp_o_stream =
@@ -1161,6 +1228,8 @@ pub mod tests {
             ObjectType::Value("chicken".to_string()),
             child_layer.id_object(6).unwrap()
         );
+        assert_eq!(Some(true), child_layer.id_object_is_node(1));
+        assert_eq!(Some(false), child_layer.id_object_is_node(6));
     }
 
     #[test]
@@ -1200,6 +1269,10 @@ pub mod tests {
             ObjectType::Value("baz".to_string()),
             child_layer.id_object(12).unwrap()
         );
+        assert_eq!(Some(true), child_layer.id_object_is_node(11));
+        assert_eq!(Some(false), child_layer.id_object_is_node(12));
+        assert_eq!(None, child_layer.id_object_is_node(13));
+        assert_eq!(None, child_layer.id_object_is_node(0));
     }
 
     #[test]
@@ -3,8 +3,10 @@
 use std::io;
 
 use super::internal::*;
+use super::statistics::*;
 use crate::layer::builder::{build_indexes, TripleFileBuilder};
 use crate::layer::id_map::{construct_idmaps, construct_idmaps_upto};
+use crate::layer::simple_builder::LayerOptions;
 use crate::layer::Layer;
 use crate::storage::*;
 use crate::structure::*;
@@ -84,8 +86,18 @@ pub async fn delta_rollup<F: 'static + FileLoad + FileStore>(
         files.o_ps_adjacency_list_files.clone(),
         None,
         files.predicate_wavelet_tree_files.clone(),
+        LayerOptions::default(),
     )
-    .await
+    .await?;
+
+    LayerStatistics::new(
+        TripleStatistics::from_triples(layer.triples()),
+        TripleStatistics::default(),
+    )
+    .write(files.statistics_file.clone())
+    .await?;
+
+    files.write_checksums().await
 }
 
 pub async fn delta_rollup_upto<F: 'static + FileLoad + FileStore>(
@@ -103,7 +115,7 @@ pub async fn delta_rollup_upto<F: 'static + FileLoad + FileStore>(
         counts.node_count,
         counts.predicate_count,
         counts.value_count,
-        Some(files.pos_subjects_file),
+        Some(files.pos_subjects_file.clone()),
     );
 
     let mut neg_builder = TripleFileBuilder::new(
@@ -112,18 +124,22 @@ pub async fn delta_rollup_upto<F: 'static + FileLoad + FileStore>(
         counts.node_count,
         counts.predicate_count,
         counts.value_count,
-        Some(files.neg_subjects_file),
+        Some(files.neg_subjects_file.clone()),
     );
 
-    let additions = InternalTripleStackIterator::from_layer_stack(layer, upto)
-        .expect("upto not found")
-        .filter(|(sort, _)| *sort == TripleChange::Addition)
-        .map(|(_, t)| t);
+    let changes = |change| {
+        InternalTripleStackIterator::from_layer_stack(layer, upto)
+            .expect("upto not found")
+            .filter(move |(sort, _)| *sort == change)
+            .map(|(_, t)| t)
+    };
+    let statistics = LayerStatistics::new(
+        TripleStatistics::from_triples(changes(TripleChange::Addition)),
+        TripleStatistics::from_triples(changes(TripleChange::Removal)),
+    );
 
-    let removals = InternalTripleStackIterator::from_layer_stack(layer, upto)
-        .expect("upto not found")
-        .filter(|(sort, _)| *sort == TripleChange::Removal)
-        .map(|(_, t)| t);
+    let additions = changes(TripleChange::Addition);
+    let removals = changes(TripleChange::Removal);
 
     pos_builder.add_id_triples(additions).await?;
     pos_builder.finalize().await?;
@@ -137,6 +153,7 @@ pub async fn delta_rollup_upto<F: 'static + FileLoad + FileStore>(
         files.pos_o_ps_adjacency_list_files.clone(),
         Some(files.pos_objects_file.clone()),
         files.pos_predicate_wavelet_tree_files.clone(),
+        LayerOptions::default(),
     )
     .await?;
 
@@ -146,8 +163,13 @@ pub async fn delta_rollup_upto<F: 'static + FileLoad + FileStore>(
         files.neg_o_ps_adjacency_list_files.clone(),
         Some(files.neg_objects_file.clone()),
         files.neg_predicate_wavelet_tree_files.clone(),
+        LayerOptions::default(),
     )
-    .await
+    .await?;
+
+    statistics.write(files.statistics_file.clone()).await?;
+
+    files.write_checksums().await
 }
 
 #[cfg(test)]
@@ -18,17 +18,23 @@ impl Default for IdMap {
 }
 
 impl IdMap {
-    pub fn from_maps(maps: BitIndexMaps, width: u8) -> Self {
-        let bitindex = BitIndex::from_maps(maps.bits_map, maps.blocks_map, maps.sblocks_map);
-        let id_wtree = WaveletTree::from_parts(bitindex, width);
+    pub fn from_maps(maps: BitIndexMaps, width: u8) -> Result<Self, WaveletTreeError> {
+        let id_wtree = WaveletTree::from_maps(maps, width)?;
 
-        Self::from_parts(Some(id_wtree))
+        Ok(Self::from_parts(Some(id_wtree)))
     }
 
     pub fn from_parts(id_wtree: Option<WaveletTree>) -> Self {
         IdMap { id_wtree }
     }
 
+    pub fn size_in_bytes(&self) -> usize {
+        self.id_wtree
+            .as_ref()
+            .map(|wtree| wtree.size_in_bytes())
+            .unwrap_or(0)
+    }
+
     pub fn outer_to_inner(&self, id: u64) -> u64 {
         self.id_wtree
             .as_ref()
@@ -123,6 +129,7 @@ async fn construct_idmaps_from_slice<F: 'static + FileLoad + FileStore>(
     let node_value_build_task = tokio::spawn(build_wavelet_tree_from_iter(
         node_value_width,
         sorted_node_value_iter.map(move |(id, _)| id - node_value_offset as u64),
+        BitIndexEncoding::Plain,
         idmap_files.node_value_idmap_files.bits_file,
         idmap_files.node_value_idmap_files.blocks_file,
         idmap_files.node_value_idmap_files.sblocks_file,
@@ -132,6 +139,7 @@ async fn construct_idmaps_from_slice<F: 'static + FileLoad + FileStore>(
     let predicate_build_task = tokio::spawn(build_wavelet_tree_from_iter(
         predicate_width,
         sorted_predicate_iter.map(move |(id, _)| id - predicate_offset as u64),
+        BitIndexEncoding::Plain,
         idmap_files.predicate_idmap_files.bits_file,
         idmap_files.predicate_idmap_files.blocks_file,
         idmap_files.predicate_idmap_files.sblocks_file,
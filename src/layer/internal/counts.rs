@@ -0,0 +1,340 @@
+//! Triple counts that are computed from the layer structures, rather than by iterating over triples.
+use super::{external_id_to_internal, internal_id_to_external, InternalLayerImpl};
+use crate::structure::*;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::convert::TryInto;
+
+/// Returns the amount of sp_o entries for the s_p pairs at the given positions.
+fn object_count(sp_o: &AdjacencyList, first_pair: u64, last_pair: u64) -> u64 {
+    let start = sp_o.offset_for(first_pair + 1);
+    let end = sp_o.bits().select1(last_pair + 1).unwrap();
+
+    end - start + 1
+}
+
+/// Returns the first and last s_p positions of the subject at the given index, or None if it has no predicates.
+fn pairs_for_index(s_p: &AdjacencyList, index: u64) -> Option<(u64, u64)> {
+    if index > s_p.left_count() as u64 {
+        return None;
+    }
+
+    let first_pair = s_p.offset_for(index);
+    let last_pair = s_p.bits().select1(index).unwrap();
+    // a subject without triples has a single pair with predicate 0
+    if first_pair == last_pair && s_p.num_at_pos(first_pair) == 0 {
+        None
+    } else {
+        Some((first_pair, last_pair))
+    }
+}
+
+/// Add the triple counts of every subject in one half of a layer to `counts`, multiplied by `sign`.
+fn add_subject_counts(
+    subjects: Option<&MonotonicLogArray>,
+    s_p: &AdjacencyList,
+    sp_o: &AdjacencyList,
+    sign: i64,
+    counts: &mut HashMap<u64, i64>,
+) {
+    for index in 1..=s_p.left_count() as u64 {
+        let (first_pair, last_pair) = match pairs_for_index(s_p, index) {
+            Some(pairs) => pairs,
+            None => continue,
+        };
+
+        let subject = internal_id_to_external(subjects, index);
+        let count = object_count(sp_o, first_pair, last_pair) as i64;
+        *counts.entry(subject).or_default() += sign * count;
+    }
+}
+
+/// Add the triple counts of every predicate in one half of a layer to `counts`, multiplied by `sign`.
+fn add_predicate_counts(
+    predicates: &WaveletTree,
+    sp_o: &AdjacencyList,
+    sign: i64,
+    counts: &mut HashMap<u64, i64>,
+) {
+    for (predicate, _) in predicates.range_distinct(0..predicates.len()) {
+        if predicate == 0 {
+            continue;
+        }
+
+        let count: u64 = predicates
+            .lookup(predicate)
+            .unwrap()
+            .iter()
+            .map(|pair| object_count(sp_o, pair, pair))
+            .sum();
+        *counts.entry(predicate).or_default() += sign * count as i64;
+    }
+}
+
+/// Returns the non-zero counts, which are never negative once all layers have been taken into account.
+fn positive_counts(counts: HashMap<u64, i64>) -> HashMap<u64, usize> {
+    counts
+        .into_iter()
+        .filter(|&(_, count)| count > 0)
+        .map(|(id, count)| (id, count.try_into().unwrap()))
+        .collect()
+}
+
+pub(super) fn triple_count_by_subject(layer: &dyn InternalLayerImpl) -> HashMap<u64, usize> {
+    let mut counts = HashMap::new();
+    let mut current = Some(layer);
+    while let Some(layer) = current {
+        add_subject_counts(
+            layer.pos_subjects(),
+            layer.pos_s_p_adjacency_list(),
+            layer.pos_sp_o_adjacency_list(),
+            1,
+            &mut counts,
+        );
+        if let (Some(s_p), Some(sp_o)) = (
+            layer.neg_s_p_adjacency_list(),
+            layer.neg_sp_o_adjacency_list(),
+        ) {
+            add_subject_counts(layer.neg_subjects(), s_p, sp_o, -1, &mut counts);
+        }
+
+        current = layer
+            .immediate_parent()
+            .map(|p| p as &dyn InternalLayerImpl);
+    }
+
+    positive_counts(counts)
+}
+
+pub(super) fn triple_count_by_predicate(layer: &dyn InternalLayerImpl) -> HashMap<u64, usize> {
+    let mut counts = HashMap::new();
+    let mut current = Some(layer);
+    while let Some(layer) = current {
+        add_predicate_counts(
+            layer.pos_predicate_wavelet_tree(),
+            layer.pos_sp_o_adjacency_list(),
+            1,
+            &mut counts,
+        );
+        if let (Some(predicates), Some(sp_o)) = (
+            layer.neg_predicate_wavelet_tree(),
+            layer.neg_sp_o_adjacency_list(),
+        ) {
+            add_predicate_counts(predicates, sp_o, -1, &mut counts);
+        }
+
+        current = layer
+            .immediate_parent()
+            .map(|p| p as &dyn InternalLayerImpl);
+    }
+
+    positive_counts(counts)
+}
+
+/// The structures of either the additions or the removals of a layer.
+struct Half<'a> {
+    subjects: Option<&'a MonotonicLogArray>,
+    objects: Option<&'a MonotonicLogArray>,
+    s_p: &'a AdjacencyList,
+    sp_o: &'a AdjacencyList,
+    o_ps: &'a AdjacencyList,
+    predicates: &'a WaveletTree,
+}
+
+impl<'a> Half<'a> {
+    fn additions(layer: &'a dyn InternalLayerImpl) -> Half<'a> {
+        Half {
+            subjects: layer.pos_subjects(),
+            objects: layer.pos_objects(),
+            s_p: layer.pos_s_p_adjacency_list(),
+            sp_o: layer.pos_sp_o_adjacency_list(),
+            o_ps: layer.pos_o_ps_adjacency_list(),
+            predicates: layer.pos_predicate_wavelet_tree(),
+        }
+    }
+
+    fn removals(layer: &'a dyn InternalLayerImpl) -> Option<Half<'a>> {
+        match (
+            layer.neg_s_p_adjacency_list(),
+            layer.neg_sp_o_adjacency_list(),
+            layer.neg_o_ps_adjacency_list(),
+            layer.neg_predicate_wavelet_tree(),
+        ) {
+            (Some(s_p), Some(sp_o), Some(o_ps), Some(predicates)) => Some(Half {
+                subjects: layer.neg_subjects(),
+                objects: layer.neg_objects(),
+                s_p,
+                sp_o,
+                o_ps,
+                predicates,
+            }),
+            _ => None,
+        }
+    }
+
+    fn subject_pairs(&self, subject: u64) -> Option<(u64, u64)> {
+        let index = external_id_to_internal(self.subjects, subject)?;
+        pairs_for_index(self.s_p, index)
+    }
+
+    fn count_s(&self, subject: u64) -> u64 {
+        self.subject_pairs(subject)
+            .map(|(first_pair, last_pair)| object_count(self.sp_o, first_pair, last_pair))
+            .unwrap_or(0)
+    }
+
+    fn count_sp(&self, subject: u64, predicate: u64) -> u64 {
+        let (first_pair, last_pair) = match self.subject_pairs(subject) {
+            Some(pairs) => pairs,
+            None => return 0,
+        };
+
+        // the predicates of a subject are sorted, so the pair can be found through binary search
+        let (mut start, mut end) = (first_pair, last_pair + 1);
+        while start < end {
+            let mid = start + (end - start) / 2;
+            match self.s_p.num_at_pos(mid).cmp(&predicate) {
+                Ordering::Less => start = mid + 1,
+                Ordering::Greater => end = mid,
+                Ordering::Equal => return object_count(self.sp_o, mid, mid),
+            }
+        }
+
+        0
+    }
+
+    fn count_p(&self, predicate: u64) -> u64 {
+        if predicate == 0 {
+            return 0;
+        }
+
+        match self.predicates.lookup(predicate) {
+            Some(lookup) => lookup
+                .iter()
+                .map(|pair| object_count(self.sp_o, pair, pair))
+                .sum(),
+            None => 0,
+        }
+    }
+
+    fn count_o(&self, object: u64) -> u64 {
+        let index = match external_id_to_internal(self.objects, object) {
+            Some(index) if index <= self.o_ps.left_count() as u64 => index,
+            _ => return 0,
+        };
+
+        let first = self.o_ps.offset_for(index);
+        let last = self.o_ps.bits().select1(index).unwrap();
+        // an object without triples has a single entry 0
+        if first == last && self.o_ps.num_at_pos(first) == 0 {
+            0
+        } else {
+            last - first + 1
+        }
+    }
+}
+
+/// Returns the given count over the additions of every layer in the stack, minus the count over the removals.
+fn stack_count<C: Fn(&Half) -> u64>(layer: &dyn InternalLayerImpl, count: C) -> usize {
+    let mut total = 0_i64;
+    let mut current = Some(layer);
+    while let Some(layer) = current {
+        total += count(&Half::additions(layer)) as i64;
+        if let Some(removals) = Half::removals(layer) {
+            total -= count(&removals) as i64;
+        }
+
+        current = layer
+            .immediate_parent()
+            .map(|p| p as &dyn InternalLayerImpl);
+    }
+
+    total.try_into().unwrap()
+}
+
+pub(super) fn count_s(layer: &dyn InternalLayerImpl, subject: u64) -> usize {
+    stack_count(layer, |half| half.count_s(subject))
+}
+
+pub(super) fn count_sp(layer: &dyn InternalLayerImpl, subject: u64, predicate: u64) -> usize {
+    stack_count(layer, |half| half.count_sp(subject, predicate))
+}
+
+pub(super) fn count_p(layer: &dyn InternalLayerImpl, predicate: u64) -> usize {
+    stack_count(layer, |half| half.count_p(predicate))
+}
+
+pub(super) fn count_o(layer: &dyn InternalLayerImpl, object: u64) -> usize {
+    stack_count(layer, |half| half.count_o(object))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::layer::*;
+    use crate::store::sync::*;
+
+    #[test]
+    fn partial_pattern_counts_match_iteration() {
+        let store = open_sync_memory_store();
+        let builder = store.create_base_layer().unwrap();
+        let triples = vec![
+            StringTriple::new_node("cow", "likes", "duck"),
+            StringTriple::new_node("cow", "likes", "pig"),
+            StringTriple::new_value("cow", "says", "moo"),
+            StringTriple::new_node("duck", "likes", "pig"),
+            StringTriple::new_value("duck", "says", "quack"),
+            StringTriple::new_node("pig", "hates", "cow"),
+        ];
+        for triple in triples {
+            builder.add_string_triple(triple).unwrap();
+        }
+        let base = builder.commit().unwrap();
+
+        let builder = base.open_write().unwrap();
+        builder
+            .remove_string_triple(StringTriple::new_node("cow", "likes", "pig"))
+            .unwrap();
+        builder
+            .remove_string_triple(StringTriple::new_node("pig", "hates", "cow"))
+            .unwrap();
+        builder
+            .add_string_triple(StringTriple::new_node("horse", "likes", "cow"))
+            .unwrap();
+        builder
+            .add_string_triple(StringTriple::new_value("pig", "says", "oink"))
+            .unwrap();
+        let child = builder.commit().unwrap();
+
+        let builder = child.open_write().unwrap();
+        builder
+            .remove_string_triple(StringTriple::new_value("pig", "says", "oink"))
+            .unwrap();
+        builder
+            .add_string_triple(StringTriple::new_node("cow", "likes", "pig"))
+            .unwrap();
+        let layer = builder.commit().unwrap();
+
+        let node_count = layer.node_and_value_count() as u64;
+        let predicate_count = layer.predicate_count() as u64;
+        // include ids that are out of range
+        for id in 0..=node_count + 1 {
+            assert_eq!(layer.triples_s(id).count(), layer.count_s(id));
+            assert_eq!(layer.triples_o(id).count(), layer.count_o(id));
+            for predicate in 0..=predicate_count + 1 {
+                assert_eq!(
+                    layer.triples_sp(id, predicate).count(),
+                    layer.count_sp(id, predicate)
+                );
+            }
+        }
+        for predicate in 0..=predicate_count + 1 {
+            assert_eq!(layer.triples_p(predicate).count(), layer.count_p(predicate));
+        }
+
+        let cow = layer.subject_id("cow").unwrap();
+        let likes = layer.predicate_id("likes").unwrap();
+        assert_eq!(2, layer.count_sp(cow, likes));
+        assert_eq!(1, layer.count_o(layer.object_node_id("cow").unwrap()));
+        assert_eq!(0, layer.count_s(layer.subject_id("pig").unwrap()));
+    }
+}
@@ -1,18 +1,23 @@
+mod counts;
 mod object_iterator;
 mod predicate_iterator;
 mod subject_iterator;
+mod trie_iterator;
 
 use super::base::*;
 use super::child::*;
 use super::id_map::*;
 use super::layer::*;
+use super::statistics::{self, IdPattern, LayerStatistics, StackStatistics};
 use crate::structure::*;
+use std::collections::HashMap;
 use std::convert::TryInto;
 use std::ops::Deref;
 
 pub use object_iterator::*;
 pub use predicate_iterator::*;
 pub use subject_iterator::*;
+pub use trie_iterator::*;
 
 fn external_id_to_internal(array_option: Option<&MonotonicLogArray>, id: u64) -> Option<u64> {
     if id == 0 {
@@ -43,6 +48,79 @@ fn id_iter(
     }
 }
 
+/// Returns the ids of all nodes, and if `include_values` is set also
+/// all values, whose string starts with the given prefix, in any layer
+/// of the stack.
+fn node_value_ids_with_prefix(
+    layer: &dyn InternalLayerImpl,
+    prefix: &str,
+    include_values: bool,
+) -> Vec<u64> {
+    let mut result = Vec::new();
+    let mut current = Some(layer);
+    while let Some(layer) = current {
+        let offset = layer.parent_node_value_count() as u64 + 1;
+        let id_map = layer.node_value_id_map();
+        for id in layer.node_dictionary().prefix_range(prefix) {
+            result.push(offset + id_map.inner_to_outer(id));
+        }
+        if include_values {
+            let node_count = layer.node_dict_len() as u64;
+            for id in layer.value_dictionary().prefix_range(prefix) {
+                result.push(offset + id_map.inner_to_outer(id + node_count));
+            }
+        }
+
+        current = layer
+            .immediate_parent()
+            .map(|p| p as &dyn InternalLayerImpl);
+    }
+
+    result.sort_unstable();
+
+    result
+}
+
+/// Returns the ids of all nodes and values whose string matches the
+/// given pattern, in any layer of the stack.
+///
+/// Layers that were built with a search index are searched through
+/// that index. For the other layers, every dictionary entry is checked.
+fn node_value_ids_matching(layer: &dyn InternalLayerImpl, pattern: &SearchPattern) -> Vec<u64> {
+    let mut result = Vec::new();
+    let mut current = Some(layer);
+    while let Some(layer) = current {
+        let offset = layer.parent_node_value_count() as u64 + 1;
+        let id_map = layer.node_value_id_map();
+        match layer.search_index() {
+            Some(index) => {
+                for id in index.search(pattern) {
+                    result.push(offset + id_map.inner_to_outer(id));
+                }
+            }
+            None => {
+                let strings = layer
+                    .node_dictionary()
+                    .strings()
+                    .chain(layer.value_dictionary().strings());
+                for (id, s) in strings.enumerate() {
+                    if pattern.matches(&s) {
+                        result.push(offset + id_map.inner_to_outer(id as u64));
+                    }
+                }
+            }
+        }
+
+        current = layer
+            .immediate_parent()
+            .map(|p| p as &dyn InternalLayerImpl);
+    }
+
+    result.sort_unstable();
+
+    result
+}
+
 pub trait InternalLayerImpl {
     fn name(&self) -> [u32; 5];
     fn parent_name(&self) -> Option<[u32; 5]>;
@@ -74,6 +152,58 @@ pub trait InternalLayerImpl {
     fn pos_objects(&self) -> Option<&MonotonicLogArray>;
     fn neg_subjects(&self) -> Option<&MonotonicLogArray>;
     fn neg_objects(&self) -> Option<&MonotonicLogArray>;
+    /// The full-text search index over the node and value dictionaries of this layer, if one was built.
+    fn search_index(&self) -> Option<&SuffixArray>;
+    /// The statistics of this layer, if it was built with them.
+    fn statistics(&self) -> Option<&LayerStatistics>;
+    /// The statistics of this layer combined with those of its
+    /// ancestors, or None if any of them was built without statistics.
+    ///
+    /// These are combined on first use and kept with the layer.
+    fn stack_statistics(&self) -> Option<&StackStatistics>;
+
+    /// Returns the size in bytes of the structures of this layer, not including its ancestors.
+    fn size_in_bytes(&self) -> usize {
+        let mut size = self.node_dictionary().size_in_bytes()
+            + self.predicate_dictionary().size_in_bytes()
+            + self.value_dictionary().size_in_bytes()
+            + self.node_value_id_map().size_in_bytes()
+            + self.predicate_id_map().size_in_bytes()
+            + self.pos_s_p_adjacency_list().size_in_bytes()
+            + self.pos_sp_o_adjacency_list().size_in_bytes()
+            + self.pos_o_ps_adjacency_list().size_in_bytes()
+            + self.pos_predicate_wavelet_tree().size_in_bytes();
+
+        for adjacency_list in [
+            self.neg_s_p_adjacency_list(),
+            self.neg_sp_o_adjacency_list(),
+            self.neg_o_ps_adjacency_list(),
+        ]
+        .iter()
+        .flatten()
+        {
+            size += adjacency_list.size_in_bytes();
+        }
+        for array in [
+            self.pos_subjects(),
+            self.pos_objects(),
+            self.neg_subjects(),
+            self.neg_objects(),
+        ]
+        .iter()
+        .flatten()
+        {
+            size += array.size_in_bytes();
+        }
+        if let Some(wavelet_tree) = self.neg_predicate_wavelet_tree() {
+            size += wavelet_tree.size_in_bytes();
+        }
+        if let Some(search_index) = self.search_index() {
+            size += search_index.size_in_bytes();
+        }
+
+        size
+    }
 
     fn predicate_dict_get(&self, id: usize) -> Option<String> {
         self.predicate_dictionary().get(id)
@@ -267,6 +397,37 @@ pub trait InternalLayerImpl {
     }
 }
 
+/// Find the layer in which the given object id was introduced,
+/// returning that layer and the id of the object within its
+/// dictionaries, where values are numbered after nodes.
+fn object_id_to_layer_id(
+    layer: &dyn InternalLayerImpl,
+    id: u64,
+) -> Option<(&dyn InternalLayerImpl, u64)> {
+    if id == 0 {
+        return None;
+    }
+    let mut corrected_id = id - 1;
+    let mut current_layer = layer;
+    while let Some(parent) = current_layer.immediate_parent() {
+        let parent_count = current_layer.parent_node_value_count() as u64;
+        if corrected_id >= parent_count {
+            // object, if it exists, is in this layer
+            corrected_id -= parent_count;
+            break;
+        }
+
+        current_layer = parent;
+    }
+
+    Some((
+        current_layer,
+        current_layer
+            .node_value_id_map()
+            .outer_to_inner(corrected_id),
+    ))
+}
+
 impl<T: 'static + InternalLayerImpl + Send + Sync + Clone> Layer for T {
     fn name(&self) -> [u32; 5] {
         Self::name(self)
@@ -421,45 +582,30 @@ impl<T: 'static + InternalLayerImpl + Send + Sync + Clone> Layer for T {
     }
 
     fn id_object(&self, id: u64) -> Option<ObjectType> {
-        if id == 0 {
-            return None;
+        let (layer, corrected_id) = object_id_to_layer_id(self, id)?;
+        if corrected_id >= layer.node_dict_len() as u64 {
+            // object, if it exists, must be a value
+            let corrected_id = corrected_id - layer.node_dict_len() as u64;
+            layer
+                .value_dict_get(corrected_id.try_into().unwrap())
+                .map(ObjectType::Value)
+        } else {
+            layer
+                .node_dict_get(corrected_id.try_into().unwrap())
+                .map(ObjectType::Node)
         }
-        let mut corrected_id = id - 1;
-        let mut current_option: Option<&dyn InternalLayerImpl> = Some(self);
-        let mut parent_count = self.node_and_value_count() as u64;
-        while let Some(current_layer) = current_option {
-            if let Some(parent) = current_layer.immediate_parent() {
-                parent_count = parent_count
-                    - current_layer.node_dict_len() as u64
-                    - current_layer.value_dict_len() as u64;
-
-                if corrected_id >= parent_count {
-                    // object, if it exists, is in this layer
-                    corrected_id -= parent_count;
-                } else {
-                    current_option = Some(parent);
-                    continue;
-                }
-            }
-
-            corrected_id = current_layer
-                .node_value_id_map()
-                .outer_to_inner(corrected_id);
+    }
 
-            if corrected_id >= current_layer.node_dict_len() as u64 {
-                // object, if it exists, must be a value
-                corrected_id -= current_layer.node_dict_len() as u64;
-                return current_layer
-                    .value_dict_get(corrected_id.try_into().unwrap())
-                    .map(ObjectType::Value);
-            } else {
-                return current_layer
-                    .node_dict_get(corrected_id.try_into().unwrap())
-                    .map(ObjectType::Node);
-            }
+    fn id_object_is_node(&self, id: u64) -> Option<bool> {
+        let (layer, corrected_id) = object_id_to_layer_id(self, id)?;
+        let node_count = layer.node_dict_len() as u64;
+        if corrected_id < node_count {
+            Some(true)
+        } else if corrected_id < node_count + layer.value_dict_len() as u64 {
+            Some(false)
+        } else {
+            None
         }
-
-        None
     }
 
     fn subjects(&self) -> Box<dyn Iterator<Item = Box<dyn SubjectLookup>>> {
@@ -650,6 +796,33 @@ impl<T: 'static + InternalLayerImpl + Send + Sync + Clone> Layer for T {
         }
     }
 
+    fn subjects_with_prefix(
+        &self,
+        prefix: &str,
+    ) -> Box<dyn Iterator<Item = Box<dyn SubjectLookup>>> {
+        let layer = self.clone();
+        Box::new(
+            node_value_ids_with_prefix(self, prefix, false)
+                .into_iter()
+                .filter_map(move |id| layer.lookup_subject(id))
+                .filter(|lookup| lookup.triples().next().is_some()),
+        )
+    }
+
+    fn objects_with_prefix(&self, prefix: &str) -> Box<dyn Iterator<Item = Box<dyn ObjectLookup>>> {
+        let layer = self.clone();
+        Box::new(
+            node_value_ids_with_prefix(self, prefix, true)
+                .into_iter()
+                .filter_map(move |id| layer.lookup_object(id))
+                .filter(|lookup| lookup.subject_predicate_pairs().next().is_some()),
+        )
+    }
+
+    fn search_values(&self, pattern: &SearchPattern) -> Box<dyn Iterator<Item = u64>> {
+        Box::new(node_value_ids_matching(self, pattern).into_iter())
+    }
+
     fn objects(&self) -> Box<dyn Iterator<Item = Box<dyn ObjectLookup>>> {
         let mut layers = Vec::new();
         layers.push((
@@ -922,6 +1095,41 @@ impl<T: 'static + InternalLayerImpl + Send + Sync + Clone> Layer for T {
         removals
     }
 
+    fn triple_count_by_subject(&self) -> HashMap<u64, usize> {
+        counts::triple_count_by_subject(self)
+    }
+
+    fn triple_count_by_predicate(&self) -> HashMap<u64, usize> {
+        counts::triple_count_by_predicate(self)
+    }
+
+    fn count_s(&self, subject: u64) -> usize {
+        counts::count_s(self, subject)
+    }
+
+    fn count_sp(&self, subject: u64, predicate: u64) -> usize {
+        counts::count_sp(self, subject, predicate)
+    }
+
+    fn count_p(&self, predicate: u64) -> usize {
+        counts::count_p(self, predicate)
+    }
+
+    fn count_o(&self, object: u64) -> usize {
+        counts::count_o(self, object)
+    }
+
+    fn estimate(&self, pattern: &IdPattern) -> f64 {
+        if let Some(count) = statistics::exact_count(self, pattern) {
+            return count;
+        }
+
+        match self.stack_statistics() {
+            Some(statistics) => statistics.estimate(pattern),
+            None => statistics::estimate_from_counts(self, pattern),
+        }
+    }
+
     fn all_counts(&self) -> LayerCounts {
         let mut node_count = self.node_dict_len();
         let mut predicate_count = self.predicate_dict_len();
@@ -1105,6 +1313,10 @@ impl<T: 'static + InternalLayerImpl + Send + Sync + Clone> Layer for T {
                 .take_while(move |t| t.object == object),
         )
     }
+
+    fn triple_trie(&self, order: TrieOrder) -> Box<dyn TripleTrieIterator> {
+        Box::new(InternalTripleTrieIterator::from_layer(self, order))
+    }
 }
 
 #[derive(Clone)]
@@ -1265,6 +1477,15 @@ impl InternalLayerImpl for InternalLayer {
     fn neg_objects(&self) -> Option<&MonotonicLogArray> {
         (&**self).neg_objects()
     }
+    fn search_index(&self) -> Option<&SuffixArray> {
+        (**self).search_index()
+    }
+    fn statistics(&self) -> Option<&LayerStatistics> {
+        (**self).statistics()
+    }
+    fn stack_statistics(&self) -> Option<&StackStatistics> {
+        (**self).stack_statistics()
+    }
 }
 
 #[derive(Clone)]
@@ -1473,6 +1694,7 @@ fn layer_triple_exists(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::layer::LayerOptions;
     use crate::open_sync_memory_store;
     use crate::store::sync::*;
 
@@ -1520,6 +1742,156 @@ mod tests {
         assert_eq!(1, layer.triple_layer_removal_count());
     }
 
+    #[test]
+    fn subjects_and_objects_with_prefix_across_stack() {
+        let store = open_sync_memory_store();
+        let builder = store.create_base_layer().unwrap();
+        for (s, o) in &[
+            ("http://ex/Person/alice", "http://ex/Person/bob"),
+            ("http://ex/Person/bob", "http://ex/Pet/rex"),
+            ("http://ex/Pet/rex", "http://ex/Person/alice"),
+        ] {
+            builder
+                .add_string_triple(StringTriple::new_node(s, "knows", o))
+                .unwrap();
+        }
+        builder
+            .add_string_triple(StringTriple::new_value(
+                "http://ex/Person/alice",
+                "name",
+                "http://ex/Person/not-a-node",
+            ))
+            .unwrap();
+        let base_layer = builder.commit().unwrap();
+
+        let builder = base_layer.open_write().unwrap();
+        builder
+            .add_string_triple(StringTriple::new_node(
+                "http://ex/Person/carol",
+                "knows",
+                "http://ex/Person/dave",
+            ))
+            .unwrap();
+        builder
+            .remove_string_triple(StringTriple::new_node(
+                "http://ex/Person/bob",
+                "knows",
+                "http://ex/Pet/rex",
+            ))
+            .unwrap();
+        let layer = builder.commit().unwrap();
+
+        let subjects: Vec<_> = layer
+            .subjects_with_prefix("http://ex/Person/")
+            .map(|s| layer.id_subject(s.subject()).unwrap())
+            .collect();
+        let mut expected_subjects = vec!["http://ex/Person/alice", "http://ex/Person/carol"];
+        let mut sorted_subjects = subjects.clone();
+        sorted_subjects.sort();
+        expected_subjects.sort();
+        assert_eq!(expected_subjects, sorted_subjects);
+
+        let ids: Vec<_> = layer
+            .subjects_with_prefix("http://ex/")
+            .map(|s| s.subject())
+            .collect();
+        let mut sorted_ids = ids.clone();
+        sorted_ids.sort();
+        assert_eq!(sorted_ids, ids);
+        assert_eq!(3, ids.len());
+
+        let mut objects: Vec<_> = layer
+            .objects_with_prefix("http://ex/Person/")
+            .map(|o| layer.id_object(o.object()).unwrap())
+            .collect();
+        objects.sort();
+        assert_eq!(
+            vec![
+                ObjectType::Node("http://ex/Person/alice".to_string()),
+                ObjectType::Node("http://ex/Person/bob".to_string()),
+                ObjectType::Node("http://ex/Person/dave".to_string()),
+                ObjectType::Value("http://ex/Person/not-a-node".to_string()),
+            ],
+            objects
+        );
+
+        assert_eq!(0, layer.subjects_with_prefix("http://other/").count());
+        assert_eq!(0, layer.objects_with_prefix("http://ex/Pet/").count());
+    }
+
+    #[test]
+    fn search_values_across_indexed_and_unindexed_layers() {
+        let store = open_sync_memory_store();
+        let builder = store
+            .create_base_layer_with_options(LayerOptions {
+                search_index: true,
+                ..Default::default()
+            })
+            .unwrap();
+        builder
+            .add_string_triple(StringTriple::new_value("cow", "says", "moo"))
+            .unwrap();
+        builder
+            .add_string_triple(StringTriple::new_node("cow", "likes", "duck"))
+            .unwrap();
+        builder
+            .add_string_triple(StringTriple::new_value("duck", "says", "quack"))
+            .unwrap();
+        let base_layer = builder.commit().unwrap();
+
+        let builder = base_layer.open_write().unwrap();
+        builder
+            .add_string_triple(StringTriple::new_value("horse", "says", "neigh"))
+            .unwrap();
+        builder
+            .add_string_triple(StringTriple::new_node("horse", "likes", "cow"))
+            .unwrap();
+        let layer = builder.commit().unwrap();
+
+        let search = |pattern: SearchPattern| -> Vec<ObjectType> {
+            let ids: Vec<_> = layer.search_values(&pattern).collect();
+            let mut sorted_ids = ids.clone();
+            sorted_ids.sort();
+            assert_eq!(sorted_ids, ids);
+
+            let mut objects: Vec<_> = ids
+                .into_iter()
+                .map(|id| layer.id_object(id).unwrap())
+                .collect();
+            objects.sort();
+            objects
+        };
+
+        assert_eq!(
+            vec![
+                ObjectType::Node("cow".to_string()),
+                ObjectType::Node("horse".to_string()),
+                ObjectType::Value("moo".to_string()),
+            ],
+            search(SearchPattern::substring("o"))
+        );
+        assert_eq!(
+            vec![
+                ObjectType::Node("duck".to_string()),
+                ObjectType::Value("quack".to_string()),
+            ],
+            search(SearchPattern::substring("ck"))
+        );
+        assert_eq!(
+            vec![
+                ObjectType::Node("horse".to_string()),
+                ObjectType::Value("neigh".to_string()),
+            ],
+            search(SearchPattern::regex("(ho|ne)[a-z]+").unwrap())
+        );
+        assert_eq!(
+            vec![ObjectType::Value("moo".to_string())],
+            search(SearchPattern::regex("mo+").unwrap())
+        );
+        assert!(search(SearchPattern::regex("oo").unwrap()).is_empty());
+        assert!(search(SearchPattern::substring("zebra")).is_empty());
+    }
+
     use crate::layer::base::tests::*;
     use tokio::runtime::Runtime;
     #[test]
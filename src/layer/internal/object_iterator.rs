@@ -113,6 +113,7 @@ impl Iterator for InternalLayerTripleObjectIterator {
     }
 }
 
+#[derive(Clone)]
 pub struct OptInternalLayerTripleObjectIterator(pub Option<InternalLayerTripleObjectIterator>);
 
 impl OptInternalLayerTripleObjectIterator {
@@ -140,6 +141,7 @@ impl Iterator for OptInternalLayerTripleObjectIterator {
     }
 }
 
+#[derive(Clone)]
 pub struct InternalTripleObjectIterator {
     positives: Vec<OptInternalLayerTripleObjectIterator>,
     negatives: Vec<OptInternalLayerTripleObjectIterator>,
@@ -168,6 +170,12 @@ impl InternalTripleObjectIterator {
     }
 
     pub fn seek_object(mut self, object: u64) -> Self {
+        self.seek_object_ref(object);
+
+        self
+    }
+
+    pub fn seek_object_ref(&mut self, object: u64) {
         for p in self.positives.iter_mut() {
             p.seek_object_ref(object);
         }
@@ -175,8 +183,6 @@ impl InternalTripleObjectIterator {
         for n in self.negatives.iter_mut() {
             n.seek_object_ref(object);
         }
-
-        self
     }
 }
 
@@ -240,6 +240,12 @@ impl InternalTripleSubjectIterator {
     }
 
     pub fn seek_subject(mut self, subject: u64) -> Self {
+        self.seek_subject_ref(subject);
+
+        self
+    }
+
+    pub fn seek_subject_ref(&mut self, subject: u64) {
         for p in self.positives.iter_mut() {
             p.seek_subject_ref(subject);
         }
@@ -247,11 +253,15 @@ impl InternalTripleSubjectIterator {
         for n in self.negatives.iter_mut() {
             n.seek_subject_ref(subject);
         }
+    }
+
+    pub fn seek_subject_predicate(mut self, subject: u64, predicate: u64) -> Self {
+        self.seek_subject_predicate_ref(subject, predicate);
 
         self
     }
 
-    pub fn seek_subject_predicate(mut self, subject: u64, predicate: u64) -> Self {
+    pub fn seek_subject_predicate_ref(&mut self, subject: u64, predicate: u64) {
         for p in self.positives.iter_mut() {
             p.seek_subject_predicate_ref(subject, predicate);
         }
@@ -259,8 +269,6 @@ impl InternalTripleSubjectIterator {
         for n in self.negatives.iter_mut() {
             n.seek_subject_predicate_ref(subject, predicate);
         }
-
-        self
     }
 }
 
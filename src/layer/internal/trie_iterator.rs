@@ -0,0 +1,529 @@
+use super::super::layer::*;
+use super::object_iterator::*;
+use super::subject_iterator::*;
+use super::InternalLayerImpl;
+
+/// Visits the triples with the same object ordered by predicate and
+/// subject, rather than by subject and predicate like the object
+/// index does.
+///
+/// The triples of one object are buffered at a time.
+#[derive(Clone)]
+struct ObjectPredicateCursor {
+    iter: InternalTripleObjectIterator,
+    run: Vec<IdTriple>,
+    index: usize,
+    peeked: Option<IdTriple>,
+}
+
+impl ObjectPredicateCursor {
+    fn new(iter: InternalTripleObjectIterator) -> Self {
+        Self {
+            iter,
+            run: Vec::new(),
+            index: 0,
+            peeked: None,
+        }
+    }
+
+    fn seek_object_ref(&mut self, object: u64) {
+        self.iter.seek_object_ref(object);
+        self.run.clear();
+        self.index = 0;
+        self.peeked = None;
+    }
+
+    fn next(&mut self) -> Option<IdTriple> {
+        if self.index == self.run.len() {
+            let first = self.peeked.take().or_else(|| self.iter.next())?;
+            self.run.clear();
+            self.run.push(first);
+            for triple in &mut self.iter {
+                if triple.object != first.object {
+                    self.peeked = Some(triple);
+                    break;
+                }
+                self.run.push(triple);
+            }
+            self.run.sort_by_key(|t| (t.predicate, t.subject));
+            self.index = 0;
+        }
+
+        self.index += 1;
+        Some(self.run[self.index - 1])
+    }
+}
+
+#[derive(Clone)]
+enum TrieCursor {
+    Subject(InternalTripleSubjectIterator),
+    Object(InternalTripleObjectIterator),
+    ObjectPredicate(ObjectPredicateCursor),
+}
+
+impl TrieCursor {
+    /// The amount of levels that can be sought directly, rather than by scanning.
+    fn seek_depth(&self) -> usize {
+        match self {
+            TrieCursor::Subject(_) => 2,
+            TrieCursor::Object(_) | TrieCursor::ObjectPredicate(_) => 1,
+        }
+    }
+
+    /// Move to a position at or before the first triple whose key is at least the given prefix.
+    fn seek(&mut self, prefix: &[u64]) {
+        match self {
+            TrieCursor::Subject(iter) => match prefix.len() {
+                1 => iter.seek_subject_ref(prefix[0]),
+                _ => iter.seek_subject_predicate_ref(prefix[0], prefix[1]),
+            },
+            TrieCursor::Object(iter) => iter.seek_object_ref(prefix[0]),
+            TrieCursor::ObjectPredicate(cursor) => cursor.seek_object_ref(prefix[0]),
+        }
+    }
+
+    fn next(&mut self) -> Option<IdTriple> {
+        match self {
+            TrieCursor::Subject(iter) => iter.next(),
+            TrieCursor::Object(iter) => iter.next(),
+            TrieCursor::ObjectPredicate(cursor) => cursor.next(),
+        }
+    }
+}
+
+/// A trie iterator over all triples in a layer stack.
+///
+/// The trie is walked with a single cursor over the triples in trie
+/// order, which only ever moves forward, except when a level is
+/// opened again after moving up from it.
+#[derive(Clone)]
+pub struct InternalTripleTrieIterator {
+    order: TrieOrder,
+    cursor: TrieCursor,
+    depth: usize,
+    /// The keys of the levels down to and including the current one.
+    prefix: [u64; 3],
+    /// Whether the current level is at a key rather than at its end.
+    valid: bool,
+    /// The key of the last triple taken from the cursor. Every
+    /// triple before it has been passed over.
+    head: Option<[u64; 3]>,
+    /// Whether `head` is the first triple with the current prefix,
+    /// which stops being the case after moving up from the level
+    /// below.
+    at_run_start: bool,
+}
+
+impl InternalTripleTrieIterator {
+    pub fn from_layer<T: 'static + InternalLayerImpl>(layer: &T, order: TrieOrder) -> Self {
+        let cursor = match order {
+            TrieOrder::SubjectPredicateObject => {
+                TrieCursor::Subject(InternalTripleSubjectIterator::from_layer(layer))
+            }
+            TrieOrder::ObjectSubjectPredicate => {
+                TrieCursor::Object(InternalTripleObjectIterator::from_layer(layer))
+            }
+            TrieOrder::ObjectPredicateSubject => TrieCursor::ObjectPredicate(
+                ObjectPredicateCursor::new(InternalTripleObjectIterator::from_layer(layer)),
+            ),
+        };
+
+        Self {
+            order,
+            cursor,
+            depth: 0,
+            prefix: [0; 3],
+            valid: false,
+            head: None,
+            at_run_start: false,
+        }
+    }
+
+    fn pull(&mut self) {
+        let order = self.order;
+        self.head = self.cursor.next().map(|t| order.key(&t));
+    }
+
+    /// Skip over all triples whose key up to the current depth is smaller than the given one.
+    fn skip_to(&mut self, target: &[u64]) {
+        while let Some(head) = self.head {
+            if head[..self.depth] >= *target {
+                break;
+            }
+            self.pull();
+        }
+
+        let parent_len = self.depth - 1;
+        self.valid = match self.head {
+            Some(head) if head[..parent_len] == target[..parent_len] => {
+                self.prefix[parent_len] = head[parent_len];
+                true
+            }
+            _ => false,
+        };
+        self.at_run_start = true;
+    }
+
+    /// Move to the first triple whose key is at least the given one,
+    /// starting from the current position of the cursor.
+    fn advance_to(&mut self, target: &[u64]) {
+        let behind = match self.head {
+            Some(head) => head[..self.depth] < *target,
+            None => false,
+        };
+        if behind && self.depth <= self.cursor.seek_depth() {
+            self.cursor.seek(target);
+            self.pull();
+        }
+
+        self.skip_to(target);
+    }
+
+    /// Move the cursor back to the first triple whose key is at least the prefix up to the current depth.
+    fn seek_prefix(&mut self) {
+        let prefix = self.prefix;
+        let seek_len = self.depth.min(self.cursor.seek_depth());
+        self.cursor.seek(&prefix[..seek_len]);
+        self.pull();
+        self.skip_to(&prefix[..self.depth]);
+    }
+}
+
+impl TripleTrieIterator for InternalTripleTrieIterator {
+    fn order(&self) -> TrieOrder {
+        self.order
+    }
+
+    fn depth(&self) -> usize {
+        self.depth
+    }
+
+    fn open(&mut self) {
+        assert!(self.depth < 3, "cannot open a trie iterator at depth 3");
+        if self.depth == 0 {
+            self.depth = 1;
+            self.prefix[0] = 0;
+            self.seek_prefix();
+        } else {
+            assert!(
+                self.valid,
+                "cannot open a trie iterator at the end of a level"
+            );
+            if !self.at_run_start {
+                self.seek_prefix();
+            }
+            // the current triple is the first with this key, so it is
+            // also the first of the level below.
+            let head = self.head.expect("a valid level should have a triple");
+            self.prefix[self.depth] = head[self.depth];
+            self.depth += 1;
+        }
+    }
+
+    fn up(&mut self) {
+        assert!(
+            self.depth > 0,
+            "cannot move up from the root of a trie iterator"
+        );
+        self.depth -= 1;
+        if self.depth > 0 {
+            // the key of this level is still in the prefix, but the
+            // cursor may have moved past the start of its triples
+            self.valid = true;
+            self.at_run_start = false;
+        }
+    }
+
+    fn key(&self) -> Option<u64> {
+        if self.depth == 0 || !self.valid {
+            return None;
+        }
+
+        Some(self.prefix[self.depth - 1])
+    }
+
+    fn next(&mut self) {
+        if let Some(key) = self.key() {
+            match key.checked_add(1) {
+                Some(next) => {
+                    let mut target = self.prefix;
+                    target[self.depth - 1] = next;
+                    self.advance_to(&target[..self.depth]);
+                }
+                None => self.valid = false,
+            }
+        }
+    }
+
+    fn seek(&mut self, key: u64) {
+        if let Some(current) = self.key() {
+            if key > current {
+                let mut target = self.prefix;
+                target[self.depth - 1] = key;
+                self.advance_to(&target[..self.depth]);
+            }
+        }
+    }
+}
+
+/// A trie iterator over triples collected in memory.
+///
+/// This is used by layers that have no index to walk the trie with.
+/// The triples are sorted into trie order up front, after which each
+/// level is a range of them that is moved through by binary search.
+#[derive(Clone)]
+pub struct SortedTripleTrieIterator {
+    order: TrieOrder,
+    keys: Vec<[u64; 3]>,
+    depth: usize,
+    /// The start of the run of the current key.
+    pos: usize,
+    /// The range of keys of every level down to and including the current one.
+    levels: Vec<(usize, usize)>,
+}
+
+impl SortedTripleTrieIterator {
+    pub fn new<I: IntoIterator<Item = IdTriple>>(triples: I, order: TrieOrder) -> Self {
+        let mut keys: Vec<_> = triples.into_iter().map(|t| order.key(&t)).collect();
+        keys.sort_unstable();
+        keys.dedup();
+
+        Self {
+            order,
+            keys,
+            depth: 0,
+            pos: 0,
+            levels: Vec::with_capacity(3),
+        }
+    }
+
+    /// The end of the range of the current level.
+    fn level_end(&self) -> usize {
+        self.levels.last().map(|(_, end)| *end).unwrap_or(0)
+    }
+
+    /// The first position at or after the current one whose key is not smaller than the given one.
+    fn position_of(&self, key: u64) -> usize {
+        let part = self.depth - 1;
+        let end = self.level_end();
+        self.pos + self.keys[self.pos..end].partition_point(|k| k[part] < key)
+    }
+
+    /// The first position after the run of the given key.
+    fn run_end(&self, key: u64) -> usize {
+        match key.checked_add(1) {
+            Some(next) => self.position_of(next),
+            None => self.level_end(),
+        }
+    }
+}
+
+impl TripleTrieIterator for SortedTripleTrieIterator {
+    fn order(&self) -> TrieOrder {
+        self.order
+    }
+
+    fn depth(&self) -> usize {
+        self.depth
+    }
+
+    fn open(&mut self) {
+        assert!(self.depth < 3, "cannot open a trie iterator at depth 3");
+        let end = if self.depth == 0 {
+            self.keys.len()
+        } else {
+            let key = self
+                .key()
+                .expect("cannot open a trie iterator at the end of a level");
+            self.run_end(key)
+        };
+        self.levels.push((self.pos, end));
+        self.depth += 1;
+    }
+
+    fn up(&mut self) {
+        assert!(
+            self.depth > 0,
+            "cannot move up from the root of a trie iterator"
+        );
+        let (start, _) = self.levels.pop().unwrap();
+        self.pos = start;
+        self.depth -= 1;
+    }
+
+    fn key(&self) -> Option<u64> {
+        if self.depth == 0 || self.pos >= self.level_end() {
+            return None;
+        }
+
+        Some(self.keys[self.pos][self.depth - 1])
+    }
+
+    fn next(&mut self) {
+        if let Some(key) = self.key() {
+            self.pos = self.run_end(key);
+        }
+    }
+
+    fn seek(&mut self, key: u64) {
+        if let Some(current) = self.key() {
+            if key > current {
+                self.pos = self.position_of(key);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layer::base::tests::*;
+    use crate::layer::child::tests::*;
+    use crate::layer::*;
+    use std::sync::Arc;
+    use tokio::runtime::Runtime;
+
+    /// Collect all triples in a trie by walking it depth first.
+    fn walk(trie: &mut dyn TripleTrieIterator) -> Vec<IdTriple> {
+        let mut result = Vec::new();
+        let mut key = [0; 3];
+        trie.open();
+        while let Some(k0) = trie.key() {
+            key[0] = k0;
+            trie.open();
+            while let Some(k1) = trie.key() {
+                key[1] = k1;
+                trie.open();
+                while let Some(k2) = trie.key() {
+                    key[2] = k2;
+                    result.push(trie.order().triple(key));
+                    trie.next();
+                }
+                trie.up();
+                trie.next();
+            }
+            trie.up();
+            trie.next();
+        }
+        trie.up();
+
+        result
+    }
+
+    #[test]
+    fn walk_trie_in_all_orders() {
+        let runtime = Runtime::new().unwrap();
+        let base_layer = example_base_layer(runtime.handle());
+
+        let mut expected: Vec<_> = base_layer.triples().collect();
+        let mut spo = base_layer.triple_trie(TrieOrder::SubjectPredicateObject);
+        assert_eq!(expected, walk(&mut *spo));
+
+        expected.sort_by_key(|t| (t.object, t.subject, t.predicate));
+        let mut osp = base_layer.triple_trie(TrieOrder::ObjectSubjectPredicate);
+        assert_eq!(expected, walk(&mut *osp));
+        assert_eq!(0, osp.depth());
+
+        expected.sort_by_key(|t| (t.object, t.predicate, t.subject));
+        let mut ops = base_layer.triple_trie(TrieOrder::ObjectPredicateSubject);
+        assert_eq!(expected, walk(&mut *ops));
+    }
+
+    #[test]
+    fn seek_in_trie() {
+        let runtime = Runtime::new().unwrap();
+        let base_layer = example_base_layer(runtime.handle());
+        let triples: Vec<_> = base_layer.triples().collect();
+        let mut trie = base_layer.triple_trie(TrieOrder::SubjectPredicateObject);
+
+        trie.open();
+        trie.seek(2);
+        assert_eq!(Some(2), trie.key());
+        trie.seek(1);
+        assert_eq!(Some(2), trie.key());
+
+        trie.open();
+        let predicates: Vec<_> = triples
+            .iter()
+            .filter(|t| t.subject == 2)
+            .map(|t| t.predicate)
+            .collect();
+        assert_eq!(Some(predicates[0]), trie.key());
+        trie.seek(predicates[predicates.len() - 1] + 1);
+        assert!(trie.at_end());
+
+        // moving up restores the key the level was opened from
+        trie.up();
+        assert_eq!(Some(2), trie.key());
+        // and the level below can be opened again
+        trie.open();
+        assert_eq!(Some(predicates[0]), trie.key());
+        trie.up();
+        trie.seek(u64::MAX);
+        assert!(trie.at_end());
+    }
+
+    #[test]
+    fn trie_over_child_layer() {
+        let mut runtime = Runtime::new().unwrap();
+        let base_layer: Arc<InternalLayer> = Arc::new(example_base_layer(runtime.handle()).into());
+        let files = child_layer_files();
+        let child_layer = runtime
+            .block_on(async {
+                let builder = ChildLayerFileBuilder::from_files(base_layer.clone(), &files);
+                let mut builder = builder.into_phase2().await?;
+                builder.add_triple(1, 1, 6).await?;
+                builder.add_triple(3, 3, 3).await?;
+                builder.remove_triple(2, 1, 1).await?;
+                builder.remove_triple(3, 2, 5).await?;
+                builder.finalize().await?;
+
+                ChildLayer::load_from_files([5, 4, 3, 2, 1], base_layer, &files).await
+            })
+            .unwrap();
+
+        let mut expected: Vec<_> = child_layer.triples().collect();
+        let mut spo = child_layer.triple_trie(TrieOrder::SubjectPredicateObject);
+        assert_eq!(expected, walk(&mut *spo));
+
+        expected.sort_by_key(|t| (t.object, t.subject, t.predicate));
+        let mut osp = child_layer.triple_trie(TrieOrder::ObjectSubjectPredicate);
+        assert_eq!(expected, walk(&mut *osp));
+
+        expected.sort_by_key(|t| (t.object, t.predicate, t.subject));
+        let mut ops = child_layer.triple_trie(TrieOrder::ObjectPredicateSubject);
+        assert_eq!(expected, walk(&mut *ops));
+    }
+
+    #[test]
+    fn sorted_trie_matches_layer_trie() {
+        let runtime = Runtime::new().unwrap();
+        let base_layer = example_base_layer(runtime.handle());
+
+        for order in [
+            TrieOrder::SubjectPredicateObject,
+            TrieOrder::ObjectSubjectPredicate,
+            TrieOrder::ObjectPredicateSubject,
+        ]
+        .iter()
+        {
+            let mut sorted = SortedTripleTrieIterator::new(base_layer.triples(), *order);
+            let mut trie = base_layer.triple_trie(*order);
+            assert_eq!(walk(&mut *trie), walk(&mut sorted));
+        }
+
+        let mut trie =
+            SortedTripleTrieIterator::new(base_layer.triples(), TrieOrder::SubjectPredicateObject);
+        trie.open();
+        trie.seek(2);
+        assert_eq!(Some(2), trie.key());
+        trie.open();
+        let first_predicate = trie.key();
+        trie.seek(u64::MAX);
+        assert!(trie.at_end());
+        trie.up();
+        assert_eq!(Some(2), trie.key());
+        trie.open();
+        assert_eq!(first_predicate, trie.key());
+    }
+}
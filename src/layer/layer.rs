@@ -1,4 +1,7 @@
 //! Common data structures and traits for all layer types.
+use super::internal::SortedTripleTrieIterator;
+use super::statistics::{self, IdPattern};
+use crate::structure::SearchPattern;
 use std::collections::HashMap;
 use std::hash::Hash;
 use std::iter::Peekable;
@@ -34,6 +37,14 @@ pub trait Layer: Send + Sync {
     fn id_predicate(&self, id: u64) -> Option<String>;
     /// The object corresponding to a numerical id, or None if it cannot be found.
     fn id_object(&self, id: u64) -> Option<ObjectType>;
+    /// Whether the object corresponding to a numerical id is a node, or None if it cannot be found.
+    ///
+    /// Layers in a store answer this without looking up the string,
+    /// unlike the default implementation, which uses `id_object`.
+    fn id_object_is_node(&self, id: u64) -> Option<bool> {
+        self.id_object(id)
+            .map(|object| matches!(object, ObjectType::Node(_)))
+    }
 
     /// Returns an iterator over all triple data known to this layer.
     ///
@@ -43,6 +54,28 @@ pub trait Layer: Send + Sync {
     /// predicate-object pair.
     fn subjects(&self) -> Box<dyn Iterator<Item = Box<dyn SubjectLookup>>>;
 
+    /// Returns an iterator over all subjects known to this layer whose name starts with the given prefix.
+    ///
+    /// Layers in a store look subjects up through a range query on
+    /// the node dictionary of each layer in the stack, which is much
+    /// cheaper than the default implementation of filtering
+    /// `subjects` when only a small part of the subjects share the
+    /// prefix. Subjects are returned in id order.
+    fn subjects_with_prefix(
+        &self,
+        prefix: &str,
+    ) -> Box<dyn Iterator<Item = Box<dyn SubjectLookup>>> {
+        let layer = self.clone_boxed();
+        let prefix = prefix.to_string();
+        Box::new(
+            self.subjects()
+                .filter(move |lookup| match layer.id_subject(lookup.subject()) {
+                    Some(subject) => subject.starts_with(&prefix),
+                    None => false,
+                }),
+        )
+    }
+
     /// Returns an iterator over all triple data added by this layer.
     ///
     /// This data is returned by
@@ -130,6 +163,41 @@ pub trait Layer: Send + Sync {
     /// object.
     fn objects(&self) -> Box<dyn Iterator<Item = Box<dyn ObjectLookup>>>;
 
+    /// Returns an iterator over all objects known to this layer whose node or value string starts with the given prefix.
+    ///
+    /// Objects are returned in id order. The default implementation
+    /// filters `objects`.
+    fn objects_with_prefix(&self, prefix: &str) -> Box<dyn Iterator<Item = Box<dyn ObjectLookup>>> {
+        let layer = self.clone_boxed();
+        let prefix = prefix.to_string();
+        Box::new(
+            self.objects()
+                .filter(move |lookup| match layer.id_object(lookup.object()) {
+                    Some(ObjectType::Node(s)) | Some(ObjectType::Value(s)) => {
+                        s.starts_with(&prefix)
+                    }
+                    None => false,
+                }),
+        )
+    }
+
+    /// Returns the ids of all nodes and values known to this layer whose string matches the given pattern.
+    ///
+    /// Layers in the stack that were built with a search index (see
+    /// `LayerOptions`) answer this through their index. Other layers
+    /// fall back to scanning their dictionaries, as does the default
+    /// implementation. Ids are returned in order.
+    fn search_values(&self, pattern: &SearchPattern) -> Box<dyn Iterator<Item = u64>> {
+        let layer = self.clone_boxed();
+        let pattern = pattern.clone();
+        Box::new((1..=self.node_and_value_count() as u64).filter(move |id| {
+            match layer.id_object(*id) {
+                Some(ObjectType::Node(s)) | Some(ObjectType::Value(s)) => pattern.matches(&s),
+                None => false,
+            }
+        }))
+    }
+
     /// Returns an iterator over all objects added by this layer.
     ///
     /// Objects are returned as an `ObjectLookup`, an object that can
@@ -381,6 +449,168 @@ pub trait Layer: Send + Sync {
     fn triple_layer_addition_count(&self) -> usize;
     /// Returns the amount of triples that this layer removes.
     fn triple_layer_removal_count(&self) -> usize;
+
+    /// Returns the amount of triples of every subject that has any.
+    ///
+    /// Layers in a store compute this from the adjacency lists of each
+    /// layer in the stack. The default implementation iterates over
+    /// all triples.
+    fn triple_count_by_subject(&self) -> HashMap<u64, usize> {
+        let mut counts = HashMap::new();
+        for triple in self.triples() {
+            *counts.entry(triple.subject).or_insert(0) += 1;
+        }
+
+        counts
+    }
+
+    /// Returns the amount of triples of every predicate that has any.
+    ///
+    /// Layers in a store compute this from the predicate wavelet tree
+    /// of each layer in the stack. The default implementation iterates
+    /// over all triples.
+    fn triple_count_by_predicate(&self) -> HashMap<u64, usize> {
+        let mut counts = HashMap::new();
+        for triple in self.triples() {
+            *counts.entry(triple.predicate).or_insert(0) += 1;
+        }
+
+        counts
+    }
+
+    /// Returns the amount of triples with the given subject.
+    ///
+    /// Layers in a store compute this and the other `count_` methods
+    /// from the adjacency lists and predicate wavelet tree of each
+    /// layer in the stack, without iterating over the triples. The
+    /// default implementations count the matching triples.
+    fn count_s(&self, subject: u64) -> usize {
+        self.triples_s(subject).count()
+    }
+
+    /// Returns the amount of triples with the given subject and predicate.
+    fn count_sp(&self, subject: u64, predicate: u64) -> usize {
+        self.triples_sp(subject, predicate).count()
+    }
+
+    /// Returns the amount of triples with the given predicate.
+    fn count_p(&self, predicate: u64) -> usize {
+        self.triples_p(predicate).count()
+    }
+
+    /// Returns the amount of triples with the given object.
+    fn count_o(&self, object: u64) -> usize {
+        self.triples_o(object).count()
+    }
+
+    /// Estimate the amount of triples that match the given pattern.
+    ///
+    /// Fully bound patterns, the pattern matching everything, and
+    /// patterns that only fix a subject, a subject and predicate, a
+    /// predicate or an object are counted exactly. All other estimates
+    /// are computed from the statistics stored with each layer in the
+    /// stack, or from exact counts of part of the pattern if some layer
+    /// was built without statistics. The default implementation always
+    /// uses the exact counts.
+    fn estimate(&self, pattern: &IdPattern) -> f64 {
+        match statistics::exact_count(self, pattern) {
+            Some(count) => count,
+            None => statistics::estimate_from_counts(self, pattern),
+        }
+    }
+
+    /// Returns a trie iterator over all triples known to this layer, visiting the parts of each triple in the given order.
+    ///
+    /// The default implementation collects all triples into memory
+    /// and sorts them. Layers in a store walk their indexes instead.
+    fn triple_trie(&self, order: TrieOrder) -> Box<dyn TripleTrieIterator> {
+        Box::new(SortedTripleTrieIterator::new(self.triples(), order))
+    }
+}
+
+/// The order in which a `TripleTrieIterator` visits the parts of a triple.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TrieOrder {
+    /// Subjects, then predicates, then objects.
+    ///
+    /// Subjects and predicates can be sought directly, while objects
+    /// are sought by scanning the objects of a subject-predicate pair.
+    SubjectPredicateObject,
+    /// Objects, then subjects, then predicates.
+    ///
+    /// This is the order of the object index. Objects can be sought
+    /// directly, while subjects and predicates are sought by scanning
+    /// the triples of an object.
+    ObjectSubjectPredicate,
+    /// Objects, then predicates, then subjects.
+    ///
+    /// Objects can be sought directly. The triples of an object are
+    /// sorted by predicate and subject as the object is reached, after
+    /// which predicates and subjects are sought by scanning them.
+    ObjectPredicateSubject,
+}
+
+impl TrieOrder {
+    /// Returns the parts of the given triple, in this order.
+    pub fn key(&self, triple: &IdTriple) -> [u64; 3] {
+        match self {
+            TrieOrder::SubjectPredicateObject => [triple.subject, triple.predicate, triple.object],
+            TrieOrder::ObjectSubjectPredicate => [triple.object, triple.subject, triple.predicate],
+            TrieOrder::ObjectPredicateSubject => [triple.object, triple.predicate, triple.subject],
+        }
+    }
+
+    /// Construct a triple from its parts, given in this order.
+    pub fn triple(&self, key: [u64; 3]) -> IdTriple {
+        match self {
+            TrieOrder::SubjectPredicateObject => IdTriple::new(key[0], key[1], key[2]),
+            TrieOrder::ObjectSubjectPredicate => IdTriple::new(key[1], key[2], key[0]),
+            TrieOrder::ObjectPredicateSubject => IdTriple::new(key[2], key[1], key[0]),
+        }
+    }
+}
+
+/// An iterator over the triples of a layer, viewed as a trie of depth 3.
+///
+/// Each level of the trie holds one part of a triple, in the order
+/// given by `order`. A trie iterator starts at the root, at depth 0.
+/// `open` moves it to the first key of the level below, and `up`
+/// moves it back to the key it was opened from. Within a level,
+/// keys are visited in ascending order, and only the keys sharing
+/// the keys of the levels above are visited.
+///
+/// This is the interface needed for worst-case optimal joins like
+/// leapfrog triejoin.
+pub trait TripleTrieIterator: Send {
+    /// The order in which the parts of a triple are visited.
+    fn order(&self) -> TrieOrder;
+
+    /// The current depth, 0 at the root and 3 at the last part of a triple.
+    fn depth(&self) -> usize;
+
+    /// Move to the first key of the level below the current key.
+    ///
+    /// This should not be called at depth 3, or when at the end of a level.
+    fn open(&mut self);
+
+    /// Move back to the key of the level above.
+    fn up(&mut self);
+
+    /// Returns the current key, or None if the end of the level was reached.
+    fn key(&self) -> Option<u64>;
+
+    /// Move to the next key of this level.
+    fn next(&mut self);
+
+    /// Move to the first key of this level that is not smaller than the given key.
+    ///
+    /// If the current key is already at least the given key, this does nothing.
+    fn seek(&mut self, key: u64);
+
+    /// Returns true if the end of the current level was reached.
+    fn at_end(&self) -> bool {
+        self.key().is_none()
+    }
 }
 
 pub struct LayerCounts {
@@ -390,7 +620,7 @@ pub struct LayerCounts {
 }
 
 /// The type of a layer - either base or child.
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum LayerType {
     Base,
     Child,
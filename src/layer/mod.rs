@@ -11,6 +11,7 @@ mod id_map;
 mod internal;
 mod layer;
 mod simple_builder;
+mod statistics;
 
 pub use base::*;
 pub use child::*;
@@ -18,3 +19,4 @@ pub use id_map::*;
 pub use internal::*;
 pub use layer::*;
 pub use simple_builder::*;
+pub use statistics::*;
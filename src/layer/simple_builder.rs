@@ -13,6 +13,9 @@ use super::base::*;
 use super::child::*;
 use super::layer::*;
 use crate::storage::*;
+use crate::structure::build_suffix_array;
+use crate::structure::pfc::{DEFAULT_BLOCK_SIZE, MAX_BLOCK_SIZE};
+use crate::structure::BitIndexEncoding;
 use std::collections::{HashMap, HashSet};
 use std::io;
 use std::pin::Pin;
@@ -46,6 +49,59 @@ pub trait LayerBuilder: Send + Sync {
     fn commit_boxed(self: Box<Self>) -> Pin<Box<dyn Future<Output = io::Result<()>> + Send>>;
 }
 
+/// Options that control how a layer is built.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LayerOptions {
+    /// Whether to build a full-text search index over the node and value dictionaries.
+    ///
+    /// This allows substring and regular expression searches through
+    /// `Layer::search_values` without decoding every dictionary
+    /// entry, at the cost of extra storage.
+    pub search_index: bool,
+    /// The amount of strings per block in the dictionaries of this layer.
+    ///
+    /// Larger blocks compress long strings with shared prefixes, such
+    /// as IRIs, better, but make lookups slower. This has to be
+    /// between 1 and `MAX_BLOCK_SIZE`.
+    pub dictionary_block_size: usize,
+    /// Whether to Elias-Fano encode the increasing lists of this layer.
+    ///
+    /// These are the block offsets of the dictionaries and, in child
+    /// layers, the subject and object lists. Child layers that only
+    /// touch a few of the subjects and objects known to their parents
+    /// store those as sparse lists, and the block offsets of a large
+    /// dictionary grow with the size of its strings. Both take up
+    /// much less space this way. Lookups in them become slightly
+    /// slower.
+    pub elias_fano: bool,
+    /// How the bits of the adjacency lists and wavelet trees of this layer are encoded.
+    ///
+    /// RRR compression makes very sparse or very dense bits, which
+    /// are common in large child layers with few changes, much
+    /// smaller, at the cost of slower rank and select queries.
+    pub bit_index_encoding: BitIndexEncoding,
+    /// Whether to store a sampled select index for the bit indexes of this layer, and how often to sample.
+    ///
+    /// When set to `Some(k)`, the position of every k-th 1-bit and
+    /// 0-bit is stored alongside each plain bit index, which makes
+    /// select queries, and therefore most triple lookups, faster.
+    /// Smaller sample rates make select faster but take more space.
+    /// This has to be larger than 0.
+    pub select_sample_rate: Option<u64>,
+}
+
+impl Default for LayerOptions {
+    fn default() -> Self {
+        LayerOptions {
+            search_index: false,
+            dictionary_block_size: DEFAULT_BLOCK_SIZE,
+            elias_fano: false,
+            bit_index_encoding: BitIndexEncoding::Plain,
+            select_sample_rate: None,
+        }
+    }
+}
+
 /// A layer builder
 ///
 /// `SimpleLayerBuilder` provides methods for adding and removing
@@ -55,6 +111,7 @@ pub struct SimpleLayerBuilder<F: 'static + FileLoad + FileStore + Clone> {
     name: [u32; 5],
     parent: Option<Arc<dyn Layer>>,
     files: LayerFiles<F>,
+    options: LayerOptions,
     additions: Vec<StringTriple>,
     id_additions: Vec<IdTriple>,
     removals: Vec<StringTriple>,
@@ -68,6 +125,7 @@ impl<F: 'static + FileLoad + FileStore + Clone> SimpleLayerBuilder<F> {
             name,
             parent: None,
             files: LayerFiles::Base(files),
+            options: LayerOptions::default(),
             additions: Vec::new(),
             id_additions: Vec::with_capacity(0),
             removals: Vec::new(),
@@ -81,12 +139,19 @@ impl<F: 'static + FileLoad + FileStore + Clone> SimpleLayerBuilder<F> {
             name,
             parent: Some(parent),
             files: LayerFiles::Child(files),
+            options: LayerOptions::default(),
             additions: Vec::new(),
             id_additions: Vec::new(),
             removals: Vec::new(),
             id_removals: Vec::new(),
         }
     }
+
+    /// Set the options to build this layer with.
+    pub fn with_options(mut self, options: LayerOptions) -> Self {
+        self.options = options;
+        self
+    }
 }
 
 impl<F: 'static + FileLoad + FileStore + Clone> LayerBuilder for SimpleLayerBuilder<F> {
@@ -119,6 +184,7 @@ impl<F: 'static + FileLoad + FileStore + Clone> LayerBuilder for SimpleLayerBuil
             name: _,
             parent,
             files,
+            options,
             additions,
             id_additions,
             removals,
@@ -188,11 +254,29 @@ impl<F: 'static + FileLoad + FileStore + Clone> LayerBuilder for SimpleLayerBuil
             collect_unresolved_strings(&additions);
 
         // time to build things
-        Box::pin(async {
+        Box::pin(async move {
+            let block_size = options.dictionary_block_size;
+            if block_size == 0 || block_size > MAX_BLOCK_SIZE {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("invalid dictionary block size {}", block_size),
+                ));
+            }
+            if options.select_sample_rate == Some(0) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "invalid select sample rate 0",
+                ));
+            }
+
             match parent {
                 Some(parent) => {
                     let files = files.into_child();
-                    let mut builder = ChildLayerFileBuilder::from_files(parent.clone(), &files);
+                    let mut builder = ChildLayerFileBuilder::from_files_with_options(
+                        parent.clone(),
+                        &files,
+                        options,
+                    );
 
                     let node_ids = builder.add_nodes(unresolved_nodes.clone()).await?;
                     let predicate_ids = builder
@@ -202,6 +286,19 @@ impl<F: 'static + FileLoad + FileStore + Clone> LayerBuilder for SimpleLayerBuil
 
                     let mut builder = builder.into_phase2().await?;
 
+                    if options.search_index {
+                        build_suffix_array(
+                            unresolved_nodes
+                                .iter()
+                                .chain(unresolved_values.iter())
+                                .map(|s| s.as_str()),
+                            files.search_index_files.text_file.clone(),
+                            files.search_index_files.suffixes_file.clone(),
+                            files.search_index_files.starts_file.clone(),
+                        )
+                        .await?;
+                    }
+
                     let counts = parent.all_counts();
                     let parent_node_offset = counts.node_count as u64 + counts.value_count as u64;
                     let parent_predicate_offset = counts.predicate_count as u64;
@@ -239,7 +336,8 @@ impl<F: 'static + FileLoad + FileStore + Clone> LayerBuilder for SimpleLayerBuil
                 None => {
                     // TODO almost same as above, should be more generic
                     let files = files.into_base();
-                    let mut builder = BaseLayerFileBuilder::from_files(&files);
+                    let mut builder =
+                        BaseLayerFileBuilder::from_files_with_options(&files, options);
 
                     let node_ids = builder.add_nodes(unresolved_nodes.clone()).await?;
                     let predicate_ids = builder
@@ -249,6 +347,19 @@ impl<F: 'static + FileLoad + FileStore + Clone> LayerBuilder for SimpleLayerBuil
 
                     let mut builder = builder.into_phase2().await?;
 
+                    if options.search_index {
+                        build_suffix_array(
+                            unresolved_nodes
+                                .iter()
+                                .chain(unresolved_values.iter())
+                                .map(|s| s.as_str()),
+                            files.search_index_files.text_file.clone(),
+                            files.search_index_files.suffixes_file.clone(),
+                            files.search_index_files.starts_file.clone(),
+                        )
+                        .await?;
+                    }
+
                     let mut node_map = HashMap::new();
                     for (node, id) in unresolved_nodes.into_iter().zip(node_ids) {
                         node_map.insert(node, id);
@@ -0,0 +1,623 @@
+//! Cardinality statistics of layers.
+//!
+//! Every layer stores, for its additions and for its removals, the
+//! amount of triples, distinct subjects and distinct objects per
+//! predicate, along with the most frequent objects of each
+//! predicate. These are written when the layer is built, and are
+//! combined across a layer stack to estimate how many triples a
+//! pattern matches, which is what query planners need to order joins.
+use super::internal::InternalLayerImpl;
+use super::layer::{IdTriple, Layer};
+use crate::storage::FileStore;
+use byteorder::{BigEndian, ByteOrder};
+use bytes::Bytes;
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use tokio::io::AsyncWriteExt;
+
+/// The amount of most frequent objects that is kept for every predicate.
+pub const TOP_OBJECTS: usize = 16;
+
+/// Statistics about the triples with a single predicate.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PredicateStatistics {
+    pub triple_count: u64,
+    pub subject_count: u64,
+    pub object_count: u64,
+    /// The most frequent objects with their triple counts, most frequent first.
+    pub top_objects: Vec<(u64, u64)>,
+}
+
+/// Statistics about a set of triples, per predicate.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TripleStatistics {
+    predicates: BTreeMap<u64, PredicateStatistics>,
+}
+
+impl TripleStatistics {
+    /// Compute the statistics of the given triples, which have to be sorted.
+    pub fn from_triples<I: IntoIterator<Item = IdTriple>>(triples: I) -> TripleStatistics {
+        let mut builder = StatisticsBuilder::new();
+        for triple in triples {
+            builder.add(triple);
+        }
+
+        builder.finish()
+    }
+
+    /// Returns the statistics of the given predicate, if there are triples with this predicate.
+    pub fn predicate(&self, predicate: u64) -> Option<&PredicateStatistics> {
+        self.predicates.get(&predicate)
+    }
+
+    /// Returns the statistics of every predicate, in predicate order.
+    pub fn predicates(&self) -> impl Iterator<Item = (u64, &PredicateStatistics)> {
+        self.predicates.iter().map(|(p, s)| (*p, s))
+    }
+
+    /// Returns the total amount of triples.
+    pub fn triple_count(&self) -> u64 {
+        self.predicates.values().map(|s| s.triple_count).sum()
+    }
+
+    fn write_to(&self, buf: &mut Vec<u8>) {
+        push_u64(buf, self.predicates.len() as u64);
+        for (predicate, statistics) in self.predicates.iter() {
+            push_u64(buf, *predicate);
+            push_u64(buf, statistics.triple_count);
+            push_u64(buf, statistics.subject_count);
+            push_u64(buf, statistics.object_count);
+            push_u64(buf, statistics.top_objects.len() as u64);
+            for (object, count) in statistics.top_objects.iter() {
+                push_u64(buf, *object);
+                push_u64(buf, *count);
+            }
+        }
+    }
+
+    fn read_from(reader: &mut Reader) -> io::Result<TripleStatistics> {
+        let mut predicates = BTreeMap::new();
+        for _ in 0..reader.next()? {
+            let predicate = reader.next()?;
+            let triple_count = reader.next()?;
+            let subject_count = reader.next()?;
+            let object_count = reader.next()?;
+            let mut top_objects = Vec::new();
+            for _ in 0..reader.next()? {
+                top_objects.push((reader.next()?, reader.next()?));
+            }
+
+            predicates.insert(
+                predicate,
+                PredicateStatistics {
+                    triple_count,
+                    subject_count,
+                    object_count,
+                    top_objects,
+                },
+            );
+        }
+
+        Ok(TripleStatistics { predicates })
+    }
+}
+
+/// Collects statistics from triples as they are added to a layer.
+///
+/// Triples have to be added in sorted order, which is the order in
+/// which the layer builders take them.
+#[derive(Default)]
+pub struct StatisticsBuilder {
+    /// Per predicate, the last subject seen, the amount of distinct subjects, and the triple count per object.
+    predicates: HashMap<u64, (u64, u64, HashMap<u64, u64>)>,
+}
+
+impl StatisticsBuilder {
+    pub fn new() -> StatisticsBuilder {
+        StatisticsBuilder::default()
+    }
+
+    /// Add a triple. Triples with a zero id are ignored, just like the layer builders do.
+    pub fn add(&mut self, triple: IdTriple) {
+        if triple.subject == 0 || triple.predicate == 0 || triple.object == 0 {
+            return;
+        }
+
+        let (last_subject, subject_count, objects) =
+            self.predicates.entry(triple.predicate).or_default();
+        // triples come in subject order, so a subject is new for a predicate if it differs from the last one
+        if *last_subject != triple.subject {
+            *last_subject = triple.subject;
+            *subject_count += 1;
+        }
+        *objects.entry(triple.object).or_default() += 1;
+    }
+
+    pub fn finish(self) -> TripleStatistics {
+        let predicates = self
+            .predicates
+            .into_iter()
+            .map(|(predicate, (_, subject_count, objects))| {
+                let mut top_objects: Vec<_> = objects.iter().map(|(o, c)| (*o, *c)).collect();
+                top_objects.sort_unstable_by(|(o1, c1), (o2, c2)| c2.cmp(c1).then(o1.cmp(o2)));
+                top_objects.truncate(TOP_OBJECTS);
+
+                let statistics = PredicateStatistics {
+                    triple_count: objects.values().sum(),
+                    subject_count,
+                    object_count: objects.len() as u64,
+                    top_objects,
+                };
+
+                (predicate, statistics)
+            })
+            .collect();
+
+        TripleStatistics { predicates }
+    }
+}
+
+/// The statistics of the additions and removals of a single layer.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LayerStatistics {
+    additions: TripleStatistics,
+    removals: TripleStatistics,
+}
+
+impl LayerStatistics {
+    pub fn new(additions: TripleStatistics, removals: TripleStatistics) -> LayerStatistics {
+        LayerStatistics {
+            additions,
+            removals,
+        }
+    }
+
+    /// Compute the statistics of a layer by iterating over its triples.
+    ///
+    /// This is only needed for layers that were built without statistics.
+    pub fn from_layer(layer: &dyn InternalLayerImpl) -> LayerStatistics {
+        LayerStatistics::new(
+            TripleStatistics::from_triples(layer.internal_triple_additions()),
+            TripleStatistics::from_triples(layer.internal_triple_removals()),
+        )
+    }
+
+    pub fn additions(&self) -> &TripleStatistics {
+        &self.additions
+    }
+
+    pub fn removals(&self) -> &TripleStatistics {
+        &self.removals
+    }
+
+    /// Parse statistics as written by `write`.
+    pub fn parse(bytes: Bytes) -> io::Result<LayerStatistics> {
+        let mut reader = Reader { bytes: &bytes };
+        let additions = TripleStatistics::read_from(&mut reader)?;
+        let removals = TripleStatistics::read_from(&mut reader)?;
+        if !reader.bytes.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "trailing data after layer statistics",
+            ));
+        }
+
+        Ok(LayerStatistics::new(additions, removals))
+    }
+
+    /// Serialize these statistics as a sequence of big-endian 64 bit numbers.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        self.additions.write_to(&mut buf);
+        self.removals.write_to(&mut buf);
+
+        buf
+    }
+
+    pub async fn write<F: FileStore>(&self, file: F) -> io::Result<()> {
+        let mut writer = file.open_write();
+        writer.write_all(&self.to_bytes()).await?;
+        writer.flush().await
+    }
+}
+
+fn push_u64(buf: &mut Vec<u8>, num: u64) {
+    let mut bytes = [0; 8];
+    BigEndian::write_u64(&mut bytes, num);
+    buf.extend_from_slice(&bytes);
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn next(&mut self) -> io::Result<u64> {
+        if self.bytes.len() < 8 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "layer statistics are truncated",
+            ));
+        }
+        let num = BigEndian::read_u64(self.bytes);
+        self.bytes = &self.bytes[8..];
+
+        Ok(num)
+    }
+}
+
+/// A subject, predicate or object in a pattern to estimate.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PatternTerm {
+    /// Matches anything.
+    Any,
+    /// Matches a single id that is not known yet, like a variable that
+    /// is bound by an earlier join.
+    Bound,
+    /// Matches the given id.
+    Id(u64),
+}
+
+/// A triple pattern over ids, for estimating how many triples match it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct IdPattern {
+    pub subject: PatternTerm,
+    pub predicate: PatternTerm,
+    pub object: PatternTerm,
+}
+
+impl IdPattern {
+    pub fn new(subject: PatternTerm, predicate: PatternTerm, object: PatternTerm) -> IdPattern {
+        IdPattern {
+            subject,
+            predicate,
+            object,
+        }
+    }
+}
+
+/// The statistics of a predicate, combined over a layer stack.
+#[derive(Clone, Default)]
+struct Combined {
+    triple_count: f64,
+    subject_count: f64,
+    object_count: f64,
+    top_objects: HashMap<u64, f64>,
+}
+
+impl Combined {
+    fn add(&mut self, statistics: &PredicateStatistics, sign: f64) {
+        self.triple_count += sign * statistics.triple_count as f64;
+        // removals rarely remove a subject or object entirely, so distinct counts only ever go up
+        if sign > 0.0 {
+            self.subject_count += statistics.subject_count as f64;
+            self.object_count += statistics.object_count as f64;
+        }
+        for (object, count) in statistics.top_objects.iter() {
+            *self.top_objects.entry(*object).or_default() += sign * *count as f64;
+        }
+    }
+
+    fn estimate(&self, subject: PatternTerm, object: PatternTerm) -> f64 {
+        let triple_count = self.triple_count;
+        if triple_count <= 0.0 {
+            return 0.0;
+        }
+        let subject_count = self.subject_count.min(triple_count).max(1.0);
+        let object_count = self.object_count.min(triple_count).max(1.0);
+
+        let mut estimate = triple_count;
+        if subject != PatternTerm::Any {
+            estimate /= subject_count;
+        }
+        match object {
+            PatternTerm::Any => {}
+            PatternTerm::Bound => estimate /= object_count,
+            PatternTerm::Id(object) => {
+                let frequency = match self.top_objects.get(&object) {
+                    Some(count) => count.max(0.0),
+                    None => {
+                        // spread the triples that don't have a frequent object over the other objects
+                        let top_count: f64 = self.top_objects.values().map(|c| c.max(0.0)).sum();
+                        let other_objects = object_count - self.top_objects.len() as f64;
+                        if other_objects < 1.0 {
+                            0.0
+                        } else {
+                            (triple_count - top_count).max(0.0) / other_objects
+                        }
+                    }
+                };
+                estimate *= frequency / triple_count;
+            }
+        }
+
+        estimate.min(triple_count)
+    }
+}
+
+/// The statistics of every predicate, combined over a layer stack.
+#[derive(Clone, Default)]
+pub struct StackStatistics {
+    predicates: BTreeMap<u64, Combined>,
+}
+
+impl StackStatistics {
+    /// Combine the statistics of every layer in the given stack.
+    ///
+    /// Returns None if any layer in the stack was built without statistics.
+    pub fn from_stack(layer: &dyn InternalLayerImpl) -> Option<StackStatistics> {
+        let mut predicates: BTreeMap<u64, Combined> = BTreeMap::new();
+        let mut current = Some(layer);
+        while let Some(layer) = current {
+            let statistics = layer.statistics()?;
+            for (predicate, s) in statistics.additions().predicates() {
+                predicates.entry(predicate).or_default().add(s, 1.0);
+            }
+            for (predicate, s) in statistics.removals().predicates() {
+                predicates.entry(predicate).or_default().add(s, -1.0);
+            }
+
+            current = layer
+                .immediate_parent()
+                .map(|p| p as &dyn InternalLayerImpl);
+        }
+
+        Some(StackStatistics { predicates })
+    }
+
+    /// Estimate the amount of triples in the stack matching the given pattern.
+    pub fn estimate(&self, pattern: &IdPattern) -> f64 {
+        match pattern.predicate {
+            PatternTerm::Id(predicate) => self
+                .predicates
+                .get(&predicate)
+                .map(|c| c.estimate(pattern.subject, pattern.object))
+                .unwrap_or(0.0),
+            PatternTerm::Any | PatternTerm::Bound => {
+                let estimates: Vec<f64> = self
+                    .predicates
+                    .values()
+                    .filter(|c| c.triple_count > 0.0)
+                    .map(|c| c.estimate(pattern.subject, pattern.object))
+                    .collect();
+                let sum: f64 = estimates.iter().sum();
+                if pattern.predicate == PatternTerm::Bound && !estimates.is_empty() {
+                    sum / estimates.len() as f64
+                } else {
+                    sum
+                }
+            }
+        }
+    }
+}
+
+/// Count the triples matching the given pattern, if it is one of the patterns that can be counted exactly.
+///
+/// These are fully bound patterns, the pattern matching everything,
+/// and patterns that only fix a subject, a subject and predicate, a
+/// predicate or an object.
+pub(super) fn exact_count<L: Layer + ?Sized>(layer: &L, pattern: &IdPattern) -> Option<f64> {
+    let count = match (pattern.subject, pattern.predicate, pattern.object) {
+        (PatternTerm::Id(s), PatternTerm::Id(p), PatternTerm::Id(o)) => {
+            if layer.triple_exists(s, p, o) {
+                1
+            } else {
+                0
+            }
+        }
+        (PatternTerm::Any, PatternTerm::Any, PatternTerm::Any) => layer.triple_count(),
+        (PatternTerm::Id(s), PatternTerm::Any, PatternTerm::Any) => layer.count_s(s),
+        (PatternTerm::Id(s), PatternTerm::Id(p), PatternTerm::Any) => layer.count_sp(s, p),
+        (PatternTerm::Any, PatternTerm::Id(p), PatternTerm::Any) => layer.count_p(p),
+        (PatternTerm::Any, PatternTerm::Any, PatternTerm::Id(o)) => layer.count_o(o),
+        _ => return None,
+    };
+
+    Some(count as f64)
+}
+
+/// Estimate the amount of triples matching the given pattern from the exact count of part of it.
+///
+/// This is used for layers without statistics, or layer stacks in
+/// which some layer was built without them. The terms that aren't
+/// counted are assumed to be spread evenly over the triples.
+pub(super) fn estimate_from_counts<L: Layer + ?Sized>(layer: &L, pattern: &IdPattern) -> f64 {
+    let triple_count = layer.triple_count() as f64;
+    if triple_count == 0.0 {
+        return 0.0;
+    }
+
+    let mut object_counted = false;
+    let mut estimate = match (pattern.subject, pattern.predicate, pattern.object) {
+        (PatternTerm::Id(s), PatternTerm::Id(p), _) => layer.count_sp(s, p) as f64,
+        (PatternTerm::Id(s), _, _) => layer.count_s(s) as f64,
+        (_, PatternTerm::Id(p), _) => layer.count_p(p) as f64,
+        (_, _, PatternTerm::Id(o)) => {
+            object_counted = true;
+            layer.count_o(o) as f64
+        }
+        _ => triple_count,
+    };
+
+    if let PatternTerm::Id(o) = pattern.object {
+        if !object_counted {
+            estimate *= layer.count_o(o) as f64 / triple_count;
+        }
+    }
+
+    let counts = layer.all_counts();
+    // a term bound by an earlier join can be any of the values of its kind
+    let candidates = |term: PatternTerm, count: usize| {
+        if term == PatternTerm::Bound {
+            count.max(1) as f64
+        } else {
+            1.0
+        }
+    };
+    estimate /= candidates(pattern.subject, counts.node_count);
+    estimate /= candidates(pattern.predicate, counts.predicate_count);
+    estimate /= candidates(pattern.object, counts.node_count + counts.value_count);
+
+    estimate
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layer::*;
+    use crate::storage::memory::*;
+    use crate::store::sync::*;
+    use tokio::runtime::Runtime;
+
+    #[test]
+    fn statistics_round_trip() {
+        let triples = vec![
+            IdTriple::new(1, 1, 2),
+            IdTriple::new(1, 1, 3),
+            IdTriple::new(1, 2, 3),
+            IdTriple::new(2, 1, 3),
+            IdTriple::new(3, 1, 3),
+            IdTriple::new(3, 2, 0),
+        ];
+        let additions = TripleStatistics::from_triples(triples);
+        assert_eq!(5, additions.triple_count());
+        let first = additions.predicate(1).unwrap();
+        assert_eq!(4, first.triple_count);
+        assert_eq!(3, first.subject_count);
+        assert_eq!(2, first.object_count);
+        assert_eq!(vec![(3, 3), (2, 1)], first.top_objects);
+
+        let removals = TripleStatistics::from_triples(vec![IdTriple::new(4, 2, 1)]);
+        let statistics = LayerStatistics::new(additions, removals);
+        let bytes = Bytes::from(statistics.to_bytes());
+        assert_eq!(statistics, LayerStatistics::parse(bytes.clone()).unwrap());
+        assert!(LayerStatistics::parse(bytes.slice(..bytes.len() - 4)).is_err());
+    }
+
+    #[test]
+    fn statistics_are_written_when_building() {
+        let mut runtime = Runtime::new().unwrap();
+        let files = base_layer_memory_files();
+        let mut builder = SimpleLayerBuilder::new([0, 0, 0, 0, 1], files.clone());
+        builder.add_string_triple(StringTriple::new_value("cow", "says", "moo"));
+        builder.add_string_triple(StringTriple::new_value("duck", "says", "quack"));
+        builder.add_string_triple(StringTriple::new_node("cow", "likes", "duck"));
+        runtime.block_on(builder.commit()).unwrap();
+
+        let layer = runtime
+            .block_on(BaseLayer::load_from_files([0, 0, 0, 0, 1], &files))
+            .unwrap();
+        let statistics = layer.statistics().unwrap();
+        assert_eq!(3, statistics.additions().triple_count());
+        assert_eq!(&LayerStatistics::from_layer(&layer), statistics);
+    }
+
+    #[test]
+    fn estimate_without_statistics() {
+        let mut runtime = Runtime::new().unwrap();
+        let files = base_layer_memory_files();
+        let mut builder = SimpleLayerBuilder::new([0, 0, 0, 0, 1], files.clone());
+        builder.add_string_triple(StringTriple::new_value("cow", "says", "moo"));
+        builder.add_string_triple(StringTriple::new_value("duck", "says", "quack"));
+        builder.add_string_triple(StringTriple::new_node("cow", "likes", "duck"));
+        runtime.block_on(builder.commit()).unwrap();
+
+        let layer = runtime
+            .block_on(BaseLayer::load_from_files([0, 0, 0, 0, 1], &files))
+            .unwrap();
+        // the combined statistics are only computed once
+        assert!(std::ptr::eq(
+            layer.stack_statistics().unwrap(),
+            layer.stack_statistics().unwrap()
+        ));
+
+        let mut maps = runtime.block_on(files.map_all()).unwrap();
+        maps.statistics_map = None;
+        let layer = BaseLayer::load([0, 0, 0, 0, 1], maps).unwrap();
+        assert!(layer.stack_statistics().is_none());
+
+        let says = layer.predicate_id("says").unwrap();
+        let moo = layer.object_value_id("moo").unwrap();
+        let any = PatternTerm::Any;
+        let estimate = |s, p, o| layer.estimate(&IdPattern::new(s, p, o));
+        // 2 triples with this predicate, spread over 2 nodes
+        assert_eq!(
+            1.0,
+            estimate(PatternTerm::Bound, PatternTerm::Id(says), any)
+        );
+        // 1 of 3 triples has this object
+        assert_eq!(
+            2.0 / 3.0,
+            estimate(any, PatternTerm::Id(says), PatternTerm::Id(moo))
+        );
+    }
+
+    #[test]
+    fn estimate_across_layer_stack() {
+        let store = open_sync_memory_store();
+        let builder = store.create_base_layer().unwrap();
+        for i in 0..10 {
+            let subject = format!("person{}", i);
+            builder
+                .add_string_triple(StringTriple::new_node(&subject, "type", "Person"))
+                .unwrap();
+            builder
+                .add_string_triple(StringTriple::new_value(&subject, "name", &subject))
+                .unwrap();
+        }
+        builder
+            .add_string_triple(StringTriple::new_node("acme", "type", "Company"))
+            .unwrap();
+        let base = builder.commit().unwrap();
+
+        let builder = base.open_write().unwrap();
+        builder
+            .remove_string_triple(StringTriple::new_node("person0", "type", "Person"))
+            .unwrap();
+        builder
+            .add_string_triple(StringTriple::new_node("person0", "type", "Robot"))
+            .unwrap();
+        let child = builder.commit().unwrap();
+
+        let type_ = child.predicate_id("type").unwrap();
+        let name = child.predicate_id("name").unwrap();
+        let person = child.object_node_id("Person").unwrap();
+        let company = child.object_node_id("Company").unwrap();
+        let person3 = child.subject_id("person3").unwrap();
+        let any = PatternTerm::Any;
+
+        let estimate = |s, p, o| child.estimate(&IdPattern::new(s, p, o));
+        assert_eq!(21.0, estimate(any, any, any));
+        assert_eq!(11.0, estimate(any, PatternTerm::Id(type_), any));
+        // the counts of frequent objects are exact, including removals
+        assert_eq!(
+            9.0,
+            estimate(any, PatternTerm::Id(type_), PatternTerm::Id(person))
+        );
+        assert_eq!(
+            1.0,
+            estimate(any, PatternTerm::Id(type_), PatternTerm::Id(company))
+        );
+        assert_eq!(
+            1.0,
+            estimate(PatternTerm::Bound, PatternTerm::Id(name), any)
+        );
+        assert_eq!(
+            1.0,
+            estimate(
+                PatternTerm::Id(person3),
+                PatternTerm::Id(type_),
+                PatternTerm::Id(person)
+            )
+        );
+        assert_eq!(
+            0.0,
+            estimate(
+                PatternTerm::Id(person3),
+                PatternTerm::Id(type_),
+                PatternTerm::Id(company)
+            )
+        );
+        assert!(estimate(PatternTerm::Bound, any, any) < estimate(any, any, any));
+    }
+}
@@ -23,7 +23,14 @@
 //! Most users will probably only need to use the types and functions
 //! in the `store` module (or `store::sync` for the synchronous
 //! version). This module provides a high-level API which should be
-//! sufficient for creating and querying databases.
+//! sufficient for creating and querying databases. For queries that
+//! join several triple patterns, the `query` module finds all
+//! solutions to a basic graph pattern over any layer, and the
+//! `graph` module runs graph algorithms like shortest paths and
+//! PageRank directly on a layer. The `reasoning` module materializes
+//! RDFS and OWL-RL entailments into a child layer, and the `datalog`
+//! module evaluates user-defined rules over a layer. The `validation`
+//! module checks a layer against SHACL-style shapes.
 //!
 //! The `structure`, `layer`, and `storage` module expose the inner
 //! workings of terminus-store. They are useful for implementing new
@@ -31,12 +38,19 @@
 #[macro_use]
 extern crate lazy_static;
 
+pub mod datalog;
+pub mod graph;
 pub mod layer;
+pub mod query;
+pub mod reasoning;
 //pub mod logging;
 pub mod storage;
 pub mod store;
 pub mod structure;
+pub mod validation;
 
 pub use layer::Layer;
-pub use store::sync::{open_sync_directory_store, open_sync_memory_store};
-pub use store::{open_directory_store, open_memory_store};
+pub use store::sync::{
+    open_sync_directory_store, open_sync_directory_store_with_map_mode, open_sync_memory_store,
+};
+pub use store::{open_directory_store, open_directory_store_with_map_mode, open_memory_store};
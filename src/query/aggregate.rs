@@ -0,0 +1,347 @@
+//! Aggregation over triples.
+use crate::layer::{IdTriple, Layer, ObjectType};
+use std::collections::{HashMap, HashSet};
+
+/// A part of a triple.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Field {
+    Subject,
+    Predicate,
+    Object,
+}
+
+impl Field {
+    /// Returns this part of the given triple.
+    pub fn of(self, triple: &IdTriple) -> u64 {
+        match self {
+            Field::Subject => triple.subject,
+            Field::Predicate => triple.predicate,
+            Field::Object => triple.object,
+        }
+    }
+}
+
+/// A function that summarizes a set of triples.
+///
+/// `Sum`, `Min` and `Max` consider the objects of the triples that are
+/// values that parse as a finite number, and ignore all other triples.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Aggregate {
+    /// The amount of triples.
+    Count,
+    /// The amount of distinct ids in the given part of the triples.
+    CountDistinct(Field),
+    /// The sum of the numeric objects.
+    Sum,
+    /// The smallest numeric object.
+    Min,
+    /// The largest numeric object.
+    Max,
+}
+
+/// The result of an aggregate function.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AggregateValue {
+    /// The result of `Count` and `CountDistinct`.
+    Count(usize),
+    /// The result of `Sum`, `Min` and `Max`.
+    Number(f64),
+}
+
+enum Accumulator {
+    Count(usize),
+    Distinct(Field, HashSet<u64>),
+    Sum(f64),
+    Min(Option<f64>),
+    Max(Option<f64>),
+}
+
+impl Accumulator {
+    fn new(aggregate: Aggregate) -> Accumulator {
+        match aggregate {
+            Aggregate::Count => Accumulator::Count(0),
+            Aggregate::CountDistinct(field) => Accumulator::Distinct(field, HashSet::new()),
+            Aggregate::Sum => Accumulator::Sum(0.0),
+            Aggregate::Min => Accumulator::Min(None),
+            Aggregate::Max => Accumulator::Max(None),
+        }
+    }
+
+    fn add(&mut self, triple: &IdTriple, numbers: &mut Numbers) {
+        match self {
+            Accumulator::Count(count) => *count += 1,
+            Accumulator::Distinct(field, ids) => {
+                ids.insert(field.of(triple));
+            }
+            Accumulator::Sum(sum) => *sum += numbers.get(triple.object).unwrap_or(0.0),
+            Accumulator::Min(min) => {
+                if let Some(number) = numbers.get(triple.object) {
+                    *min = Some(min.map_or(number, |min| min.min(number)));
+                }
+            }
+            Accumulator::Max(max) => {
+                if let Some(number) = numbers.get(triple.object) {
+                    *max = Some(max.map_or(number, |max| max.max(number)));
+                }
+            }
+        }
+    }
+
+    fn finish(self) -> Option<AggregateValue> {
+        match self {
+            Accumulator::Count(count) => Some(AggregateValue::Count(count)),
+            Accumulator::Distinct(_, ids) => Some(AggregateValue::Count(ids.len())),
+            Accumulator::Sum(sum) => Some(AggregateValue::Number(sum)),
+            Accumulator::Min(number) | Accumulator::Max(number) => {
+                number.map(AggregateValue::Number)
+            }
+        }
+    }
+}
+
+/// The numeric values of the objects of a layer, parsed as they are needed.
+struct Numbers<'a> {
+    layer: &'a dyn Layer,
+    cache: HashMap<u64, Option<f64>>,
+}
+
+impl<'a> Numbers<'a> {
+    fn new(layer: &'a dyn Layer) -> Numbers<'a> {
+        Numbers {
+            layer,
+            cache: HashMap::new(),
+        }
+    }
+
+    fn get(&mut self, object: u64) -> Option<f64> {
+        let layer = self.layer;
+        *self
+            .cache
+            .entry(object)
+            .or_insert_with(|| match layer.id_object(object) {
+                // nan and infinity parse as floats, but aren't numbers
+                // that can be summed or compared
+                Some(ObjectType::Value(value)) => {
+                    value.trim().parse().ok().filter(|n: &f64| n.is_finite())
+                }
+                _ => None,
+            })
+    }
+}
+
+/// Aggregate the given triples of a layer.
+///
+/// This returns None for `Min` and `Max` if none of the triples have a numeric object.
+pub fn aggregate<I: IntoIterator<Item = IdTriple>>(
+    layer: &dyn Layer,
+    triples: I,
+    aggregate: Aggregate,
+) -> Option<AggregateValue> {
+    let mut numbers = Numbers::new(layer);
+    let mut accumulator = Accumulator::new(aggregate);
+    for triple in triples {
+        accumulator.add(&triple, &mut numbers);
+    }
+
+    accumulator.finish()
+}
+
+/// Aggregate the given triples of a layer, grouped by a part of the triples.
+///
+/// For `Min` and `Max`, groups without numeric objects are left out.
+pub fn aggregate_by<I: IntoIterator<Item = IdTriple>>(
+    layer: &dyn Layer,
+    triples: I,
+    group_by: Field,
+    aggregate: Aggregate,
+) -> HashMap<u64, AggregateValue> {
+    let mut numbers = Numbers::new(layer);
+    let mut groups: HashMap<u64, Accumulator> = HashMap::new();
+    for triple in triples {
+        groups
+            .entry(group_by.of(&triple))
+            .or_insert_with(|| Accumulator::new(aggregate))
+            .add(&triple, &mut numbers);
+    }
+
+    groups
+        .into_iter()
+        .filter_map(|(group, accumulator)| accumulator.finish().map(|value| (group, value)))
+        .collect()
+}
+
+/// Aggregate all triples of a layer.
+///
+/// Counting all triples doesn't iterate over them.
+pub fn aggregate_layer(layer: &dyn Layer, aggregate: Aggregate) -> Option<AggregateValue> {
+    match aggregate {
+        Aggregate::Count => Some(AggregateValue::Count(layer.triple_count())),
+        _ => self::aggregate(layer, layer.triples(), aggregate),
+    }
+}
+
+/// Aggregate all triples of a layer, grouped by a part of the triples.
+///
+/// Counting the triples per subject, predicate or object doesn't
+/// iterate over them, but uses `Layer::triple_count_by_subject`,
+/// `Layer::triple_count_by_predicate` and `Layer::count_o`.
+pub fn aggregate_layer_by(
+    layer: &dyn Layer,
+    group_by: Field,
+    aggregate: Aggregate,
+) -> HashMap<u64, AggregateValue> {
+    let counts = match (group_by, aggregate) {
+        (Field::Subject, Aggregate::Count) => layer.triple_count_by_subject(),
+        (Field::Predicate, Aggregate::Count) => layer.triple_count_by_predicate(),
+        (Field::Object, Aggregate::Count) => (1..=layer.node_and_value_count() as u64)
+            .map(|object| (object, layer.count_o(object)))
+            .filter(|(_, count)| *count != 0)
+            .collect(),
+        _ => return aggregate_by(layer, layer.triples(), group_by, aggregate),
+    };
+
+    counts
+        .into_iter()
+        .map(|(group, count)| (group, AggregateValue::Count(count)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layer::StringTriple;
+    use crate::store::sync::*;
+
+    fn example_layer() -> SyncStoreLayer {
+        let store = open_sync_memory_store();
+        let builder = store.create_base_layer().unwrap();
+        let triples = vec![
+            StringTriple::new_value("alice", "age", "30"),
+            StringTriple::new_value("alice", "score", "7.5"),
+            StringTriple::new_value("alice", "score", "2"),
+            StringTriple::new_node("alice", "knows", "bob"),
+            StringTriple::new_node("alice", "knows", "carol"),
+            StringTriple::new_value("bob", "age", "25"),
+            StringTriple::new_value("bob", "score", "unknown"),
+            StringTriple::new_node("bob", "knows", "carol"),
+            StringTriple::new_value("dave", "age", "40"),
+        ];
+        for triple in triples {
+            builder.add_string_triple(triple).unwrap();
+        }
+        let base = builder.commit().unwrap();
+
+        let builder = base.open_write().unwrap();
+        builder
+            .add_string_triple(StringTriple::new_value("carol", "age", "35"))
+            .unwrap();
+        builder
+            .add_string_triple(StringTriple::new_node("carol", "knows", "alice"))
+            .unwrap();
+        builder
+            .remove_string_triple(StringTriple::new_value("dave", "age", "40"))
+            .unwrap();
+        builder
+            .remove_string_triple(StringTriple::new_node("alice", "knows", "bob"))
+            .unwrap();
+
+        builder.commit().unwrap()
+    }
+
+    #[test]
+    fn aggregate_triples() {
+        let layer = example_layer();
+        let age = layer.predicate_id("age").unwrap();
+        let score = layer.predicate_id("score").unwrap();
+        let alice = layer.subject_id("alice").unwrap();
+
+        assert_eq!(
+            Some(AggregateValue::Count(9)),
+            aggregate_layer(&layer, Aggregate::Count)
+        );
+        assert_eq!(
+            Some(AggregateValue::Count(3)),
+            aggregate_layer(&layer, Aggregate::CountDistinct(Field::Subject))
+        );
+        assert_eq!(
+            Some(AggregateValue::Number(90.0)),
+            aggregate(&layer, layer.triples_p(age), Aggregate::Sum)
+        );
+        assert_eq!(
+            Some(AggregateValue::Number(25.0)),
+            aggregate(&layer, layer.triples_p(age), Aggregate::Min)
+        );
+        assert_eq!(
+            None,
+            aggregate(
+                &layer,
+                layer.triples_p(layer.predicate_id("knows").unwrap()),
+                Aggregate::Max
+            )
+        );
+
+        let scores = aggregate_by(
+            &layer,
+            layer.triples_p(score),
+            Field::Subject,
+            Aggregate::Max,
+        );
+        // bob's score is not a number
+        assert_eq!(1, scores.len());
+        assert_eq!(Some(&AggregateValue::Number(7.5)), scores.get(&alice));
+
+        let sums = aggregate_layer_by(&layer, Field::Subject, Aggregate::Sum);
+        assert_eq!(Some(&AggregateValue::Number(39.5)), sums.get(&alice));
+    }
+
+    #[test]
+    fn non_finite_values_are_not_numbers() {
+        let store = open_sync_memory_store();
+        let builder = store.create_base_layer().unwrap();
+        for (subject, value) in [("a", "1"), ("b", "NaN"), ("c", "inf"), ("d", "-infinity")].iter()
+        {
+            builder
+                .add_string_triple(StringTriple::new_value(subject, "score", value))
+                .unwrap();
+        }
+        let layer = builder.commit().unwrap();
+
+        assert_eq!(
+            Some(AggregateValue::Number(1.0)),
+            aggregate_layer(&layer, Aggregate::Sum)
+        );
+        assert_eq!(
+            Some(AggregateValue::Number(1.0)),
+            aggregate_layer(&layer, Aggregate::Max)
+        );
+    }
+
+    #[test]
+    fn counts_without_iterating_match_iteration() {
+        let layer = example_layer();
+        for field in [Field::Subject, Field::Predicate, Field::Object].iter() {
+            let fast = aggregate_layer_by(&layer, *field, Aggregate::Count);
+            let iterated = aggregate_by(&layer, layer.triples(), *field, Aggregate::Count);
+            assert_eq!(iterated, fast);
+        }
+
+        let by_subject = layer.triple_count_by_subject();
+        assert_eq!(3, by_subject.len());
+        assert_eq!(
+            Some(&4),
+            by_subject.get(&layer.subject_id("alice").unwrap())
+        );
+        // all of dave's triples were removed
+        assert_eq!(None, by_subject.get(&layer.subject_id("dave").unwrap()));
+
+        let by_predicate = layer.triple_count_by_predicate();
+        assert_eq!(
+            Some(&3),
+            by_predicate.get(&layer.predicate_id("age").unwrap())
+        );
+        assert_eq!(
+            Some(&3),
+            by_predicate.get(&layer.predicate_id("knows").unwrap())
+        );
+    }
+}
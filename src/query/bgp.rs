@@ -0,0 +1,740 @@
+//! Basic graph pattern queries.
+//!
+//! A basic graph pattern is a list of triple patterns, each of which
+//! has a variable or a constant in subject, predicate and object
+//! position. A solution binds every variable such that all triple
+//! patterns are triples in the layer.
+//!
+//! Queries are answered by nested index lookups. Before execution,
+//! the patterns are ordered so that the ones that are expected to
+//! match the fewest triples come first, and patterns that share a
+//! variable with an earlier pattern are preferred over ones that
+//! don't, avoiding cross products where possible. The amount of
+//! matches is estimated through `Layer::estimate`.
+use crate::layer::{IdPattern, IdTriple, Layer, ObjectType, PatternTerm};
+use std::collections::{HashMap, HashSet};
+use std::iter;
+use std::sync::Arc;
+
+/// A subject, predicate or object in a triple pattern.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Term {
+    /// A variable with the given name.
+    Variable(String),
+    /// A node, or in predicate position, a predicate.
+    Node(String),
+    /// A value. Values can only appear in object position.
+    Value(String),
+    /// An id. In predicate position this is a predicate id, and otherwise a node or value id.
+    Id(u64),
+}
+
+impl Term {
+    /// Construct a variable with the given name.
+    pub fn var(name: &str) -> Term {
+        Term::Variable(name.to_owned())
+    }
+
+    /// Construct a node (or predicate) constant.
+    pub fn node(node: &str) -> Term {
+        Term::Node(node.to_owned())
+    }
+
+    /// Construct a value constant.
+    pub fn value(value: &str) -> Term {
+        Term::Value(value.to_owned())
+    }
+
+    /// Construct an id constant.
+    pub fn id(id: u64) -> Term {
+        Term::Id(id)
+    }
+
+    /// Returns the name of this term if it is a variable.
+    pub fn variable(&self) -> Option<&str> {
+        match self {
+            Term::Variable(name) => Some(name),
+            _ => None,
+        }
+    }
+}
+
+/// A triple with variables or constants in each position.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct TriplePattern {
+    pub subject: Term,
+    pub predicate: Term,
+    pub object: Term,
+}
+
+impl TriplePattern {
+    /// Construct a new triple pattern.
+    pub fn new(subject: Term, predicate: Term, object: Term) -> TriplePattern {
+        TriplePattern {
+            subject,
+            predicate,
+            object,
+        }
+    }
+
+    /// Returns the variables in this pattern, in subject, predicate, object order.
+    pub fn variables(&self) -> impl Iterator<Item = &str> {
+        vec![&self.subject, &self.predicate, &self.object]
+            .into_iter()
+            .filter_map(|term| term.variable())
+    }
+}
+
+/// The id a variable is bound to.
+///
+/// Predicates are numbered separately from nodes and values, so a
+/// bound id remembers which of the two it is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BoundId {
+    /// A node or value id, as found in subject and object position.
+    NodeOrValue(u64),
+    /// A predicate id.
+    Predicate(u64),
+}
+
+impl BoundId {
+    /// Convert this bound id to the string it refers to in the given layer.
+    ///
+    /// Predicates are returned as an `ObjectType::Node`.
+    pub fn resolve(&self, layer: &dyn Layer) -> Option<ObjectType> {
+        match *self {
+            BoundId::NodeOrValue(id) => layer.id_object(id),
+            BoundId::Predicate(id) => layer.id_predicate(id).map(ObjectType::Node),
+        }
+    }
+}
+
+/// The values of all variables in a single solution of a query.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Bindings {
+    variables: Arc<[String]>,
+    values: Vec<BoundId>,
+}
+
+impl Bindings {
+    pub(super) fn new(variables: Arc<[String]>, values: Vec<BoundId>) -> Bindings {
+        Bindings { variables, values }
+    }
+
+    /// Returns the variable names, in the order in which they first appear in the query.
+    pub fn variables(&self) -> &[String] {
+        &self.variables
+    }
+
+    /// Returns the bound ids, in the same order as `variables`.
+    pub fn values(&self) -> &[BoundId] {
+        &self.values
+    }
+
+    /// Returns the id bound to the given variable, or None if there is no such variable.
+    pub fn get(&self, variable: &str) -> Option<BoundId> {
+        self.variables
+            .iter()
+            .position(|v| v == variable)
+            .map(|index| self.values[index])
+    }
+
+    /// Returns an iterator over all variables and the ids bound to them.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, BoundId)> {
+        self.variables
+            .iter()
+            .map(|v| v.as_str())
+            .zip(self.values.iter().cloned())
+    }
+
+    /// Convert all bound ids to the strings they refer to in the given layer.
+    ///
+    /// Returns None if any of the ids is unknown to the layer.
+    pub fn resolve(&self, layer: &dyn Layer) -> Option<HashMap<String, ObjectType>> {
+        self.iter()
+            .map(|(variable, id)| id.resolve(layer).map(|s| (variable.to_owned(), s)))
+            .collect()
+    }
+}
+
+/// A basic graph pattern query.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Query {
+    patterns: Vec<TriplePattern>,
+}
+
+impl Query {
+    /// Construct a query that finds all solutions to the given triple patterns.
+    pub fn new(patterns: Vec<TriplePattern>) -> Query {
+        Query { patterns }
+    }
+
+    /// Returns the triple patterns of this query, in the order they were given.
+    pub fn patterns(&self) -> &[TriplePattern] {
+        &self.patterns
+    }
+
+    /// Returns the variables of this query, in the order in which they first appear.
+    pub fn variables(&self) -> Vec<String> {
+        let mut result: Vec<String> = Vec::new();
+        for variable in self.patterns.iter().flat_map(|p| p.variables()) {
+            if !result.iter().any(|v| v == variable) {
+                result.push(variable.to_owned());
+            }
+        }
+
+        result
+    }
+
+    /// Decide in which order the patterns are to be matched against the given layer.
+    pub fn plan(&self, layer: &dyn Layer) -> QueryPlan {
+        let mut remaining: Vec<&TriplePattern> = self.patterns.iter().collect();
+        let mut bound: HashSet<&str> = HashSet::new();
+        let mut patterns = Vec::with_capacity(remaining.len());
+        let mut estimates = Vec::with_capacity(remaining.len());
+
+        while !remaining.is_empty() {
+            let mut best: Option<(usize, bool, f64)> = None;
+            for (index, pattern) in remaining.iter().enumerate() {
+                let connected = bound.is_empty()
+                    || pattern.variables().next().is_none()
+                    || pattern.variables().any(|v| bound.contains(v));
+                let estimate = estimate(layer, pattern, &bound);
+                let better = match best {
+                    None => true,
+                    Some((_, best_connected, best_estimate)) => {
+                        (connected && !best_connected)
+                            || (connected == best_connected && estimate < best_estimate)
+                    }
+                };
+                if better {
+                    best = Some((index, connected, estimate));
+                }
+            }
+
+            let (index, _, estimate) = best.unwrap();
+            let pattern = remaining.remove(index);
+            bound.extend(pattern.variables());
+            patterns.push(pattern.clone());
+            estimates.push(estimate);
+        }
+
+        QueryPlan {
+            patterns,
+            estimates,
+            variables: self.variables().into(),
+        }
+    }
+
+    /// Plan this query against the given layer and execute it.
+    pub fn execute(&self, layer: &dyn Layer) -> Solutions {
+        self.plan(layer).execute(layer)
+    }
+}
+
+/// Estimate the amount of triples matching the given pattern, once the given variables are bound.
+fn estimate(layer: &dyn Layer, pattern: &TriplePattern, bound: &HashSet<&str>) -> f64 {
+    let term = |position: Position, term: &Term| match term {
+        Term::Variable(v) if bound.contains(v.as_str()) => Some(PatternTerm::Bound),
+        Term::Variable(_) => Some(PatternTerm::Any),
+        _ => constant_id(layer, position, term).map(PatternTerm::Id),
+    };
+
+    match (
+        term(Position::Subject, &pattern.subject),
+        term(Position::Predicate, &pattern.predicate),
+        term(Position::Object, &pattern.object),
+    ) {
+        (Some(subject), Some(predicate), Some(object)) => {
+            layer.estimate(&IdPattern::new(subject, predicate, object))
+        }
+        // a constant that is not known to the layer matches nothing
+        _ => 0.0,
+    }
+}
+
+/// A query with its triple patterns in execution order.
+#[derive(Clone, Debug)]
+pub struct QueryPlan {
+    patterns: Vec<TriplePattern>,
+    estimates: Vec<f64>,
+    variables: Arc<[String]>,
+}
+
+impl QueryPlan {
+    /// Returns the triple patterns in the order in which they will be matched.
+    pub fn patterns(&self) -> &[TriplePattern] {
+        &self.patterns
+    }
+
+    /// Returns the estimated amount of matches for each pattern, given the patterns before it.
+    pub fn estimates(&self) -> &[f64] {
+        &self.estimates
+    }
+
+    /// Returns the variables of the query, in the order in which they first appear.
+    pub fn variables(&self) -> &[String] {
+        &self.variables
+    }
+
+    /// Execute this plan against the given layer, returning an iterator over all solutions.
+    pub fn execute(&self, layer: &dyn Layer) -> Solutions {
+        let patterns: Option<Vec<CompiledPattern>> = self
+            .patterns
+            .iter()
+            .map(|pattern| CompiledPattern::compile(layer, pattern, &self.variables))
+            .collect();
+
+        Solutions {
+            layer: layer.clone_boxed(),
+            variables: self.variables.clone(),
+            // if a constant is not known to the layer, nothing can match
+            done: patterns.is_none(),
+            patterns: patterns.unwrap_or_default(),
+            bindings: vec![None; self.variables.len()],
+            stack: Vec::new(),
+            started: false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum Position {
+    Subject,
+    Predicate,
+    Object,
+}
+
+impl Position {
+    fn bound_id(self, id: u64) -> BoundId {
+        match self {
+            Position::Predicate => BoundId::Predicate(id),
+            _ => BoundId::NodeOrValue(id),
+        }
+    }
+
+    fn of(self, triple: &IdTriple) -> u64 {
+        match self {
+            Position::Subject => triple.subject,
+            Position::Predicate => triple.predicate,
+            Position::Object => triple.object,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub(super) enum Slot {
+    Constant(u64),
+    Variable(usize),
+}
+
+#[derive(Clone, Debug)]
+pub(super) struct CompiledPattern {
+    pub(super) slots: [(Position, Slot); 3],
+}
+
+impl CompiledPattern {
+    /// Resolve the constants in the given pattern, returning None if any of them are unknown to the layer.
+    pub(super) fn compile(
+        layer: &dyn Layer,
+        pattern: &TriplePattern,
+        variables: &[String],
+    ) -> Option<CompiledPattern> {
+        let slot = |position: Position, term: &Term| match term {
+            Term::Variable(name) => variables.iter().position(|v| v == name).map(Slot::Variable),
+            _ => constant_id(layer, position, term).map(Slot::Constant),
+        };
+
+        Some(CompiledPattern {
+            slots: [
+                (
+                    Position::Subject,
+                    slot(Position::Subject, &pattern.subject)?,
+                ),
+                (
+                    Position::Predicate,
+                    slot(Position::Predicate, &pattern.predicate)?,
+                ),
+                (Position::Object, slot(Position::Object, &pattern.object)?),
+            ],
+        })
+    }
+}
+
+/// Resolve a constant term to its id in the given position, returning None if it is unknown to the layer.
+fn constant_id(layer: &dyn Layer, position: Position, term: &Term) -> Option<u64> {
+    match (position, term) {
+        (_, Term::Variable(_)) => None,
+        (_, Term::Id(id)) => Some(*id),
+        (Position::Subject, Term::Node(node)) => layer.subject_id(node),
+        (Position::Predicate, Term::Node(predicate)) => layer.predicate_id(predicate),
+        (Position::Object, Term::Node(node)) => layer.object_node_id(node),
+        (Position::Object, Term::Value(value)) => layer.object_value_id(value),
+        (_, Term::Value(_)) => None,
+    }
+}
+
+/// Convert a bound id to the id it has in the given position.
+fn id_for_position(layer: &dyn Layer, id: BoundId, position: Position) -> Option<u64> {
+    match (id, position) {
+        (BoundId::Predicate(id), Position::Predicate) => Some(id),
+        (BoundId::NodeOrValue(id), Position::Subject)
+        | (BoundId::NodeOrValue(id), Position::Object) => Some(id),
+        (BoundId::Predicate(id), Position::Subject) => layer.subject_id(&layer.id_predicate(id)?),
+        (BoundId::Predicate(id), Position::Object) => {
+            layer.object_node_id(&layer.id_predicate(id)?)
+        }
+        (BoundId::NodeOrValue(id), Position::Predicate) => match layer.id_object(id)? {
+            ObjectType::Node(node) => layer.predicate_id(&node),
+            ObjectType::Value(_) => None,
+        },
+    }
+}
+
+type TripleIterator = Box<dyn Iterator<Item = IdTriple> + Send>;
+
+/// An iterator over the solutions of a query.
+///
+/// Solutions are found lazily, by matching the patterns one by one
+/// and backtracking once a pattern has no further matches.
+pub struct Solutions {
+    layer: Box<dyn Layer>,
+    variables: Arc<[String]>,
+    patterns: Vec<CompiledPattern>,
+    bindings: Vec<Option<BoundId>>,
+    /// For every pattern that is currently being matched, the
+    /// remaining triples, and the variables bound by the current one.
+    stack: Vec<(TripleIterator, Vec<usize>)>,
+    started: bool,
+    done: bool,
+}
+
+impl Solutions {
+    /// Returns the variables of the query, in the order in which they first appear.
+    pub fn variables(&self) -> &[String] {
+        &self.variables
+    }
+
+    /// Returns an iterator over the triples matching the pattern at the given index, given the current bindings.
+    fn matches(&self, index: usize) -> TripleIterator {
+        let layer = &*self.layer;
+        let mut ids = [None; 3];
+        for (id, (position, slot)) in ids.iter_mut().zip(self.patterns[index].slots.iter()) {
+            *id = match *slot {
+                Slot::Constant(id) => Some(id),
+                Slot::Variable(variable) => match self.bindings[variable] {
+                    None => None,
+                    Some(bound) => match id_for_position(layer, bound, *position) {
+                        Some(id) => Some(id),
+                        None => return Box::new(iter::empty()),
+                    },
+                },
+            };
+        }
+
+        match ids {
+            [Some(s), Some(p), Some(o)] => {
+                if layer.triple_exists(s, p, o) {
+                    Box::new(iter::once(IdTriple::new(s, p, o)))
+                } else {
+                    Box::new(iter::empty())
+                }
+            }
+            [Some(s), Some(p), None] => layer.triples_sp(s, p),
+            [Some(s), None, Some(o)] => Box::new(layer.triples_s(s).filter(move |t| t.object == o)),
+            [Some(s), None, None] => layer.triples_s(s),
+            [None, Some(p), Some(o)] => {
+                Box::new(layer.triples_o(o).filter(move |t| t.predicate == p))
+            }
+            [None, Some(p), None] => layer.triples_p(p),
+            [None, None, Some(o)] => layer.triples_o(o),
+            [None, None, None] => layer.triples(),
+        }
+    }
+
+    /// Bind the variables of the pattern at the given index to the
+    /// given triple, returning the newly bound variables, or None if
+    /// the triple conflicts with an existing binding.
+    fn bind(&mut self, index: usize, triple: &IdTriple) -> Option<Vec<usize>> {
+        let mut newly_bound = Vec::new();
+        for (position, slot) in self.patterns[index].slots.iter() {
+            if let Slot::Variable(variable) = *slot {
+                let id = position.of(triple);
+                let consistent = match self.bindings[variable] {
+                    None => {
+                        self.bindings[variable] = Some(position.bound_id(id));
+                        newly_bound.push(variable);
+                        true
+                    }
+                    Some(bound) => id_for_position(&*self.layer, bound, *position) == Some(id),
+                };
+
+                if !consistent {
+                    self.unbind(&newly_bound);
+                    return None;
+                }
+            }
+        }
+
+        Some(newly_bound)
+    }
+
+    fn unbind(&mut self, variables: &[usize]) {
+        for &variable in variables {
+            self.bindings[variable] = None;
+        }
+    }
+
+    fn current(&self) -> Bindings {
+        Bindings::new(
+            self.variables.clone(),
+            self.bindings
+                .iter()
+                .map(|b| b.expect("all variables should be bound in a solution"))
+                .collect(),
+        )
+    }
+}
+
+impl Iterator for Solutions {
+    type Item = Bindings;
+
+    fn next(&mut self) -> Option<Bindings> {
+        if self.done {
+            return None;
+        }
+
+        if !self.started {
+            self.started = true;
+            if self.patterns.is_empty() {
+                // the empty pattern has exactly one solution
+                self.done = true;
+                return Some(self.current());
+            }
+
+            let matches = self.matches(0);
+            self.stack.push((matches, Vec::new()));
+        }
+
+        while let Some((mut matches, bound)) = self.stack.pop() {
+            self.unbind(&bound);
+            let level = self.stack.len();
+            let triple = match matches.next() {
+                Some(triple) => triple,
+                None => continue,
+            };
+
+            let newly_bound = self.bind(level, &triple);
+            match newly_bound {
+                None => self.stack.push((matches, Vec::new())),
+                Some(newly_bound) => {
+                    self.stack.push((matches, newly_bound));
+                    if level + 1 == self.patterns.len() {
+                        return Some(self.current());
+                    }
+
+                    let next_matches = self.matches(level + 1);
+                    self.stack.push((next_matches, Vec::new()));
+                }
+            }
+        }
+
+        self.done = true;
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layer::StringTriple;
+    use crate::store::sync::*;
+
+    fn example_layer() -> SyncStoreLayer {
+        let store = open_sync_memory_store();
+        let builder = store.create_base_layer().unwrap();
+        for (subject, predicate, object) in &[
+            ("cow", "likes", "pig"),
+            ("cow", "likes", "duck"),
+            ("pig", "likes", "duck"),
+            ("duck", "likes", "duck"),
+            ("likes", "likes", "cow"),
+        ] {
+            builder
+                .add_string_triple(StringTriple::new_node(subject, predicate, object))
+                .unwrap();
+        }
+        for (subject, predicate, object) in &[
+            ("cow", "says", "moo"),
+            ("pig", "says", "oink"),
+            ("duck", "says", "quack"),
+        ] {
+            builder
+                .add_string_triple(StringTriple::new_value(subject, predicate, object))
+                .unwrap();
+        }
+
+        builder.commit().unwrap()
+    }
+
+    fn resolved(layer: &dyn Layer, query: &Query, variables: &[&str]) -> Vec<Vec<String>> {
+        let mut result: Vec<Vec<String>> = query
+            .execute(layer)
+            .map(|bindings| {
+                let strings = bindings.resolve(layer).unwrap();
+                variables
+                    .iter()
+                    .map(|v| match &strings[*v] {
+                        ObjectType::Node(s) | ObjectType::Value(s) => s.clone(),
+                    })
+                    .collect()
+            })
+            .collect();
+        result.sort();
+
+        result
+    }
+
+    #[test]
+    fn join_two_patterns() {
+        let layer = example_layer();
+        let query = Query::new(vec![
+            TriplePattern::new(Term::var("x"), Term::node("likes"), Term::var("y")),
+            TriplePattern::new(Term::var("y"), Term::node("says"), Term::var("sound")),
+        ]);
+
+        assert_eq!(vec!["x", "y", "sound"], query.variables());
+        assert_eq!(
+            vec![
+                vec!["cow", "duck", "quack"],
+                vec!["cow", "pig", "oink"],
+                vec!["duck", "duck", "quack"],
+                vec!["likes", "cow", "moo"],
+                vec!["pig", "duck", "quack"],
+            ],
+            resolved(&layer, &query, &["x", "y", "sound"])
+        );
+    }
+
+    #[test]
+    fn constants_are_matched_first() {
+        let layer = example_layer();
+        let query = Query::new(vec![
+            TriplePattern::new(Term::var("x"), Term::var("p"), Term::var("y")),
+            TriplePattern::new(Term::var("z"), Term::node("likes"), Term::var("x")),
+            TriplePattern::new(Term::var("x"), Term::node("says"), Term::value("oink")),
+        ]);
+
+        let plan = query.plan(&layer);
+        assert_eq!(&query.patterns()[2], &plan.patterns()[0]);
+        assert_eq!(&query.patterns()[1], &plan.patterns()[1]);
+        assert_eq!(&query.patterns()[0], &plan.patterns()[2]);
+        assert!(plan.estimates()[0] < plan.estimates()[2]);
+
+        assert_eq!(
+            vec![
+                vec!["pig", "likes", "duck", "cow"],
+                vec!["pig", "says", "oink", "cow"],
+            ],
+            resolved(&layer, &query, &["x", "p", "y", "z"])
+        );
+    }
+
+    #[test]
+    fn unknown_constants_match_nothing() {
+        let layer = example_layer();
+        let query = Query::new(vec![
+            TriplePattern::new(Term::var("x"), Term::node("likes"), Term::var("y")),
+            TriplePattern::new(Term::var("y"), Term::node("barks"), Term::var("sound")),
+        ]);
+        assert_eq!(0, query.execute(&layer).count());
+
+        // values can't be subjects
+        let query = Query::new(vec![TriplePattern::new(
+            Term::value("cow"),
+            Term::var("p"),
+            Term::var("o"),
+        )]);
+        assert_eq!(0, query.execute(&layer).count());
+    }
+
+    #[test]
+    fn repeated_variables_must_match() {
+        let layer = example_layer();
+        let query = Query::new(vec![TriplePattern::new(
+            Term::var("x"),
+            Term::node("likes"),
+            Term::var("x"),
+        )]);
+
+        assert_eq!(vec![vec!["duck"]], resolved(&layer, &query, &["x"]));
+    }
+
+    #[test]
+    fn variables_can_join_predicates_and_nodes() {
+        let layer = example_layer();
+        let query = Query::new(vec![
+            TriplePattern::new(Term::node("cow"), Term::var("p"), Term::node("pig")),
+            TriplePattern::new(Term::var("p"), Term::var("q"), Term::var("o")),
+        ]);
+
+        let solutions: Vec<_> = query.execute(&layer).collect();
+        assert_eq!(1, solutions.len());
+        assert_eq!(
+            Some(BoundId::Predicate(layer.predicate_id("likes").unwrap())),
+            solutions[0].get("p")
+        );
+        assert_eq!(
+            vec![vec!["likes", "likes", "cow"]],
+            resolved(&layer, &query, &["p", "q", "o"])
+        );
+    }
+
+    #[test]
+    fn ids_and_fully_bound_patterns() {
+        let layer = example_layer();
+        let cow = layer.subject_id("cow").unwrap();
+        let query = Query::new(vec![
+            TriplePattern::new(Term::id(cow), Term::node("likes"), Term::var("y")),
+            TriplePattern::new(Term::var("y"), Term::node("likes"), Term::node("duck")),
+        ]);
+        assert_eq!(
+            vec![vec!["duck"], vec!["pig"]],
+            resolved(&layer, &query, &["y"])
+        );
+
+        let query = Query::new(vec![TriplePattern::new(
+            Term::node("cow"),
+            Term::node("says"),
+            Term::value("moo"),
+        )]);
+        let solutions: Vec<_> = query.execute(&layer).collect();
+        assert_eq!(1, solutions.len());
+        assert!(solutions[0].variables().is_empty());
+
+        assert_eq!(1, Query::new(Vec::new()).execute(&layer).count());
+    }
+
+    #[test]
+    fn query_child_layer_with_removals() {
+        let layer = example_layer();
+        let builder = layer.open_write().unwrap();
+        builder
+            .remove_string_triple(StringTriple::new_node("duck", "likes", "duck"))
+            .unwrap();
+        builder
+            .add_string_triple(StringTriple::new_node("pig", "likes", "cow"))
+            .unwrap();
+        let child = builder.commit().unwrap();
+
+        let query = Query::new(vec![
+            TriplePattern::new(Term::var("x"), Term::node("likes"), Term::var("y")),
+            TriplePattern::new(Term::var("y"), Term::node("likes"), Term::var("x")),
+        ]);
+
+        assert_eq!(
+            vec![vec!["cow", "pig"], vec!["pig", "cow"]],
+            resolved(&child, &query, &["x", "y"])
+        );
+    }
+}
@@ -0,0 +1,666 @@
+//! Leapfrog triejoin.
+//!
+//! Leapfrog triejoin is a worst-case optimal join algorithm. Rather
+//! than matching one triple pattern at a time, it binds one variable
+//! at a time. Every pattern containing that variable is viewed as a
+//! trie iterator positioned at the level of that variable, and the
+//! iterators leapfrog over each other by seeking to the largest key
+//! among them until they all agree. This way, cyclic queries like
+//! triangles never produce intermediate results larger than the
+//! final result allows.
+//!
+//! For this to work, the variables of every pattern must be bound in
+//! the order in which its trie iterator visits them. Each pattern
+//! can be visited in subject-predicate-object or
+//! object-subject-predicate order, and a variable order is chosen
+//! that is compatible with one of these for every pattern. Patterns
+//! with a constant object and predicate are visited in
+//! object-predicate-subject order instead, which doesn't constrain
+//! the variable order as they only have a variable subject.
+use super::bgp::{Bindings, BoundId, CompiledPattern, Query, Slot};
+use crate::layer::{Layer, TrieOrder, TripleTrieIterator};
+use std::collections::HashSet;
+use std::sync::Arc;
+
+/// The maximum amount of patterns for which both trie orders are tried when looking for a variable order.
+const MAX_ORDER_CHOICES: usize = 12;
+
+/// A level of the trie of a single pattern.
+#[derive(Clone, Copy, Debug)]
+enum Level {
+    /// A constant.
+    Fixed(u64),
+    /// A variable that is bound at a level above this one.
+    Bound(usize),
+    /// The first occurrence of a variable in this pattern.
+    Open(usize),
+}
+
+/// Returns the positions of a triple in the given order, as indexes into the slots of a compiled pattern.
+fn positions(order: TrieOrder) -> [usize; 3] {
+    match order {
+        TrieOrder::SubjectPredicateObject => [0, 1, 2],
+        TrieOrder::ObjectSubjectPredicate => [2, 0, 1],
+        TrieOrder::ObjectPredicateSubject => [2, 1, 0],
+    }
+}
+
+/// Returns the variables of the given pattern in the order they're visited in the given trie order, without repetitions.
+fn variable_sequence(pattern: &CompiledPattern, order: TrieOrder) -> Vec<usize> {
+    let mut result = Vec::new();
+    for &index in positions(order).iter() {
+        if let Slot::Variable(variable) = pattern.slots[index].1 {
+            if !result.contains(&variable) {
+                result.push(variable);
+            }
+        }
+    }
+
+    result
+}
+
+/// Returns the amount of constants before the first variable of the given pattern in the given trie order.
+fn leading_constants(pattern: &CompiledPattern, order: TrieOrder) -> usize {
+    positions(order)
+        .iter()
+        .take_while(|&&index| matches!(pattern.slots[index].1, Slot::Constant(_)))
+        .count()
+}
+
+/// Find an order for the given variables that is compatible with the given variable sequences.
+///
+/// Variables that appear in more sequences are placed first where
+/// possible. Returns None if the sequences contradict each other.
+fn topological_order(variable_count: usize, sequences: &[Vec<usize>]) -> Option<Vec<usize>> {
+    let mut occurrences = vec![0; variable_count];
+    let mut successors = vec![Vec::new(); variable_count];
+    let mut predecessor_count = vec![0; variable_count];
+    for sequence in sequences {
+        for &variable in sequence {
+            occurrences[variable] += 1;
+        }
+        for pair in sequence.windows(2) {
+            successors[pair[0]].push(pair[1]);
+            predecessor_count[pair[1]] += 1;
+        }
+    }
+
+    let mut result = Vec::with_capacity(variable_count);
+    let mut placed = vec![false; variable_count];
+    while result.len() < variable_count {
+        let next = (0..variable_count)
+            .filter(|&v| !placed[v] && predecessor_count[v] == 0)
+            .max_by_key(|&v| (occurrences[v], std::cmp::Reverse(v)))?;
+
+        placed[next] = true;
+        for &successor in successors[next].iter() {
+            predecessor_count[successor] -= 1;
+        }
+        result.push(next);
+    }
+
+    Some(result)
+}
+
+/// An iterator over the solutions of a query, found with leapfrog triejoin.
+pub struct LeapfrogJoin {
+    variables: Arc<[String]>,
+    /// Whether each variable is bound to predicates rather than nodes and values.
+    predicates: Vec<bool>,
+    /// The variables in the order in which they're bound.
+    order: Vec<usize>,
+    tries: Vec<Box<dyn TripleTrieIterator>>,
+    levels: Vec<[Level; 3]>,
+    /// For every variable in `order`, the patterns it first appears in, with the level it appears at.
+    participants: Vec<Vec<(usize, usize)>>,
+    /// For every variable in `order`, the patterns that are left with only constants and bound variables once it is bound.
+    tails: Vec<Vec<usize>>,
+    bindings: Vec<Option<u64>>,
+    /// For every bound or currently binding variable, the amount of levels each participant opened.
+    frames: Vec<Vec<usize>>,
+    advance: bool,
+    started: bool,
+    done: bool,
+}
+
+impl LeapfrogJoin {
+    /// Prepare a leapfrog triejoin for the given query over the given layer.
+    ///
+    /// Returns None if the query can't be answered this way. This is
+    /// the case if a variable appears both in predicate position and
+    /// in subject or object position, or if there is no order of
+    /// variables that agrees with the trie orders of all patterns.
+    pub fn new(layer: &dyn Layer, query: &Query) -> Option<LeapfrogJoin> {
+        let mut predicate_variables = HashSet::new();
+        let mut node_variables = HashSet::new();
+        for pattern in query.patterns() {
+            node_variables.extend(pattern.subject.variable());
+            predicate_variables.extend(pattern.predicate.variable());
+            node_variables.extend(pattern.object.variable());
+        }
+        if !predicate_variables.is_disjoint(&node_variables) {
+            return None;
+        }
+
+        let variables: Arc<[String]> = query.variables().into();
+        let predicates = variables
+            .iter()
+            .map(|v| predicate_variables.contains(v.as_str()))
+            .collect();
+        let mut join = LeapfrogJoin {
+            variables: variables.clone(),
+            predicates,
+            order: Vec::new(),
+            tries: Vec::new(),
+            levels: Vec::new(),
+            participants: Vec::new(),
+            tails: Vec::new(),
+            bindings: vec![None; variables.len()],
+            frames: Vec::new(),
+            advance: false,
+            started: false,
+            done: false,
+        };
+
+        let compiled: Option<Vec<_>> = query
+            .patterns()
+            .iter()
+            .map(|pattern| CompiledPattern::compile(layer, pattern, &variables))
+            .collect();
+        let compiled = match compiled {
+            Some(compiled) => compiled,
+            None => {
+                // if a constant is not known to the layer, nothing can match
+                join.done = true;
+                return Some(join);
+            }
+        };
+
+        let mut patterns = Vec::new();
+        for pattern in compiled {
+            match pattern.slots {
+                [(_, Slot::Constant(s)), (_, Slot::Constant(p)), (_, Slot::Constant(o))] => {
+                    if !layer.triple_exists(s, p, o) {
+                        join.done = true;
+                        return Some(join);
+                    }
+                }
+                _ => patterns.push(pattern),
+            }
+        }
+
+        let (orders, order) = Self::choose_orders(&patterns, variables.len())?;
+        join.order = order;
+        join.participants = vec![Vec::new(); variables.len()];
+        join.tails = vec![Vec::new(); variables.len()];
+        let mut depths = vec![0; variables.len()];
+        for (depth, &variable) in join.order.iter().enumerate() {
+            depths[variable] = depth;
+        }
+        for (index, (pattern, order)) in patterns.iter().zip(orders).enumerate() {
+            let mut levels = [Level::Fixed(0); 3];
+            let mut last_open = 0;
+            for (level, &position) in positions(order).iter().enumerate() {
+                levels[level] = match pattern.slots[position].1 {
+                    Slot::Constant(id) => Level::Fixed(id),
+                    Slot::Variable(variable) => {
+                        if levels[..level]
+                            .iter()
+                            .any(|l| matches!(l, Level::Open(v) if *v == variable))
+                        {
+                            Level::Bound(variable)
+                        } else {
+                            join.participants[depths[variable]].push((index, level));
+                            last_open = level;
+                            Level::Open(variable)
+                        }
+                    }
+                };
+            }
+
+            if last_open < 2 {
+                if let Level::Open(variable) = levels[last_open] {
+                    join.tails[depths[variable]].push(index);
+                }
+            }
+            join.levels.push(levels);
+            join.tries.push(layer.triple_trie(order));
+        }
+
+        Some(join)
+    }
+
+    /// Choose a trie order for every pattern, and an order in which to bind variables that is compatible with all of them.
+    fn choose_orders(
+        patterns: &[CompiledPattern],
+        variable_count: usize,
+    ) -> Option<(Vec<TrieOrder>, Vec<usize>)> {
+        let spo = TrieOrder::SubjectPredicateObject;
+        let osp = TrieOrder::ObjectSubjectPredicate;
+        let ops = TrieOrder::ObjectPredicateSubject;
+
+        // prefer the order that lets a pattern seek through its constants first
+        let preferred: Vec<TrieOrder> = patterns
+            .iter()
+            .map(|p| {
+                let mut best = spo;
+                for &order in &[osp, ops] {
+                    if leading_constants(p, order) > leading_constants(p, best) {
+                        best = order;
+                    }
+                }
+
+                best
+            })
+            .collect();
+        // the patterns for which the order matters to the variable order
+        let choices: Vec<usize> = (0..patterns.len())
+            .filter(|&i| {
+                variable_sequence(&patterns[i], spo) != variable_sequence(&patterns[i], osp)
+            })
+            .take(MAX_ORDER_CHOICES)
+            .collect();
+
+        for mask in 0..(1_usize << choices.len()) {
+            let mut orders = preferred.clone();
+            for (bit, &index) in choices.iter().enumerate() {
+                if mask & (1 << bit) != 0 {
+                    orders[index] = if orders[index] == spo { osp } else { spo };
+                }
+            }
+
+            let sequences: Vec<_> = patterns
+                .iter()
+                .zip(orders.iter())
+                .map(|(p, o)| variable_sequence(p, *o))
+                .collect();
+            if let Some(order) = topological_order(variable_count, &sequences) {
+                return Some((orders, order));
+            }
+        }
+
+        None
+    }
+
+    /// Returns the variables of the query, in the order in which they first appear.
+    pub fn variables(&self) -> &[String] {
+        &self.variables
+    }
+
+    /// Returns the variables of the query, in the order in which they are bound.
+    pub fn variable_order(&self) -> Vec<&str> {
+        self.order
+            .iter()
+            .map(|&v| self.variables[v].as_str())
+            .collect()
+    }
+
+    fn level_value(&self, level: Level) -> u64 {
+        match level {
+            Level::Fixed(id) => id,
+            Level::Bound(variable) | Level::Open(variable) => self.bindings[variable]
+                .expect("variables at levels above the current one should be bound"),
+        }
+    }
+
+    /// Open the trie of a pattern down to the given level, passing
+    /// through the constants and bound variables above it. Returns
+    /// the amount of levels opened, or None if the pattern has no
+    /// triples with those constants and bindings.
+    fn descend(&mut self, pattern: usize, level: usize) -> Option<usize> {
+        let mut opened = 0;
+        while self.tries[pattern].depth() < level {
+            let value = self.level_value(self.levels[pattern][self.tries[pattern].depth()]);
+            let trie = &mut self.tries[pattern];
+            trie.open();
+            opened += 1;
+            trie.seek(value);
+            if trie.key() != Some(value) {
+                for _ in 0..opened {
+                    trie.up();
+                }
+                return None;
+            }
+        }
+
+        self.tries[pattern].open();
+        Some(opened + 1)
+    }
+
+    /// Check that a pattern has a triple for the constants and bound variables below its current level.
+    fn tail_exists(&mut self, pattern: usize) -> bool {
+        let mut opened = 0;
+        let mut exists = true;
+        while self.tries[pattern].depth() < 3 {
+            let value = self.level_value(self.levels[pattern][self.tries[pattern].depth()]);
+            let trie = &mut self.tries[pattern];
+            trie.open();
+            opened += 1;
+            trie.seek(value);
+            if trie.key() != Some(value) {
+                exists = false;
+                break;
+            }
+        }
+
+        for _ in 0..opened {
+            self.tries[pattern].up();
+        }
+
+        exists
+    }
+
+    /// Open the tries of all patterns containing the variable at the given depth.
+    fn push_frame(&mut self, depth: usize) -> bool {
+        let mut frame = Vec::with_capacity(self.participants[depth].len());
+        for i in 0..self.participants[depth].len() {
+            let (pattern, level) = self.participants[depth][i];
+            match self.descend(pattern, level) {
+                Some(opened) => frame.push(opened),
+                None => {
+                    self.undo_frame(depth, &frame);
+                    return false;
+                }
+            }
+        }
+
+        self.frames.push(frame);
+        true
+    }
+
+    fn undo_frame(&mut self, depth: usize, frame: &[usize]) {
+        for (&(pattern, _), &opened) in self.participants[depth].iter().zip(frame.iter()) {
+            for _ in 0..opened {
+                self.tries[pattern].up();
+            }
+        }
+    }
+
+    fn pop_frame(&mut self) {
+        let depth = self.frames.len() - 1;
+        let frame = self.frames.pop().unwrap();
+        self.undo_frame(depth, &frame);
+        self.bindings[self.order[depth]] = None;
+    }
+
+    /// Leapfrog the tries at the given depth until they agree on a key, returning that key.
+    fn leapfrog(&mut self, depth: usize) -> Option<u64> {
+        loop {
+            let mut max = 0;
+            for &(pattern, _) in self.participants[depth].iter() {
+                max = max.max(self.tries[pattern].key()?);
+            }
+
+            let mut agreed = true;
+            for &(pattern, _) in self.participants[depth].iter() {
+                let trie = &mut self.tries[pattern];
+                if trie.key() != Some(max) {
+                    trie.seek(max);
+                    agreed = false;
+                }
+            }
+
+            if agreed {
+                return Some(max);
+            }
+        }
+    }
+
+    /// Find the next key at the given depth that all patterns agree on and that completes the patterns ending at it.
+    fn next_key(&mut self, depth: usize, advance: bool) -> Option<u64> {
+        let first = self.participants[depth][0].0;
+        if advance {
+            self.tries[first].next();
+        }
+
+        loop {
+            let key = self.leapfrog(depth)?;
+            self.bindings[self.order[depth]] = Some(key);
+            let mut complete = true;
+            for i in 0..self.tails[depth].len() {
+                if !self.tail_exists(self.tails[depth][i]) {
+                    complete = false;
+                    break;
+                }
+            }
+
+            if complete {
+                return Some(key);
+            }
+
+            self.tries[first].next();
+        }
+    }
+
+    fn current(&self) -> Bindings {
+        Bindings::new(
+            self.variables.clone(),
+            self.bindings
+                .iter()
+                .zip(self.predicates.iter())
+                .map(|(b, &predicate)| {
+                    let id = b.expect("all variables should be bound in a solution");
+                    if predicate {
+                        BoundId::Predicate(id)
+                    } else {
+                        BoundId::NodeOrValue(id)
+                    }
+                })
+                .collect(),
+        )
+    }
+}
+
+impl Iterator for LeapfrogJoin {
+    type Item = Bindings;
+
+    fn next(&mut self) -> Option<Bindings> {
+        if self.done {
+            return None;
+        }
+
+        if !self.started {
+            self.started = true;
+            if self.order.is_empty() {
+                // all patterns are constant and known to exist
+                self.done = true;
+                return Some(self.current());
+            }
+
+            if !self.push_frame(0) {
+                self.done = true;
+                return None;
+            }
+        }
+
+        while !self.frames.is_empty() {
+            let depth = self.frames.len() - 1;
+            match self.next_key(depth, self.advance) {
+                None => {
+                    self.pop_frame();
+                    self.advance = true;
+                }
+                Some(_) if depth + 1 == self.order.len() => {
+                    self.advance = true;
+                    return Some(self.current());
+                }
+                Some(_) => self.advance = !self.push_frame(depth + 1),
+            }
+        }
+
+        self.done = true;
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layer::{ObjectType, StringTriple};
+    use crate::query::{Term, TriplePattern};
+    use crate::store::sync::*;
+
+    fn example_layer() -> SyncStoreLayer {
+        let store = open_sync_memory_store();
+        let builder = store.create_base_layer().unwrap();
+        for (subject, object) in &[
+            ("a", "b"),
+            ("b", "c"),
+            ("c", "a"),
+            ("a", "d"),
+            ("d", "c"),
+            ("c", "e"),
+            ("e", "e"),
+            ("d", "b"),
+        ] {
+            builder
+                .add_string_triple(StringTriple::new_node(subject, "knows", object))
+                .unwrap();
+        }
+        for (subject, object) in &[("a", "alice"), ("b", "bob"), ("c", "carol")] {
+            builder
+                .add_string_triple(StringTriple::new_value(subject, "name", object))
+                .unwrap();
+        }
+
+        builder.commit().unwrap()
+    }
+
+    fn resolve_all(
+        layer: &dyn Layer,
+        solutions: impl Iterator<Item = Bindings>,
+        variables: &[&str],
+    ) -> Vec<Vec<String>> {
+        let mut result: Vec<Vec<String>> = solutions
+            .map(|bindings| {
+                let strings = bindings.resolve(layer).unwrap();
+                variables
+                    .iter()
+                    .map(|v| match &strings[*v] {
+                        ObjectType::Node(s) | ObjectType::Value(s) => s.clone(),
+                    })
+                    .collect()
+            })
+            .collect();
+        result.sort();
+
+        result
+    }
+
+    fn triangle() -> Query {
+        Query::new(vec![
+            TriplePattern::new(Term::var("x"), Term::node("knows"), Term::var("y")),
+            TriplePattern::new(Term::var("y"), Term::node("knows"), Term::var("z")),
+            TriplePattern::new(Term::var("z"), Term::node("knows"), Term::var("x")),
+        ])
+    }
+
+    #[test]
+    fn triangles_match_nested_lookups() {
+        let layer = example_layer();
+        let query = triangle();
+        let join = LeapfrogJoin::new(&layer, &query).unwrap();
+        assert_eq!(3, join.variable_order().len());
+
+        let expected = resolve_all(&layer, query.execute(&layer), &["x", "y", "z"]);
+        let result = resolve_all(&layer, join, &["x", "y", "z"]);
+        assert_eq!(expected, result);
+        assert_eq!(
+            vec![
+                vec!["a", "b", "c"],
+                vec!["a", "d", "c"],
+                vec!["b", "c", "a"],
+                vec!["c", "a", "b"],
+                vec!["c", "a", "d"],
+                vec!["d", "c", "a"],
+                vec!["e", "e", "e"],
+            ],
+            result
+        );
+    }
+
+    #[test]
+    fn constants_and_repeated_variables() {
+        let layer = example_layer();
+        let query = Query::new(vec![
+            TriplePattern::new(Term::var("x"), Term::node("knows"), Term::var("y")),
+            TriplePattern::new(Term::var("y"), Term::node("name"), Term::var("name")),
+            TriplePattern::new(Term::node("a"), Term::var("p"), Term::var("x")),
+        ]);
+        let join = LeapfrogJoin::new(&layer, &query).unwrap();
+        assert_eq!(
+            resolve_all(&layer, query.execute(&layer), &["x", "y", "name", "p"]),
+            resolve_all(&layer, join, &["x", "y", "name", "p"])
+        );
+
+        let query = Query::new(vec![TriplePattern::new(
+            Term::var("x"),
+            Term::node("knows"),
+            Term::var("x"),
+        )]);
+        let join = LeapfrogJoin::new(&layer, &query).unwrap();
+        assert_eq!(vec![vec!["e"]], resolve_all(&layer, join, &["x"]));
+
+        let query = Query::new(vec![
+            TriplePattern::new(Term::var("x"), Term::node("knows"), Term::var("y")),
+            TriplePattern::new(Term::node("a"), Term::node("name"), Term::value("alice")),
+        ]);
+        let join = LeapfrogJoin::new(&layer, &query).unwrap();
+        assert_eq!(8, join.count());
+
+        let query = Query::new(vec![
+            TriplePattern::new(Term::var("x"), Term::node("knows"), Term::node("c")),
+            TriplePattern::new(Term::var("x"), Term::node("knows"), Term::var("y")),
+        ]);
+        let join = LeapfrogJoin::new(&layer, &query).unwrap();
+        assert_eq!(
+            vec![vec!["b", "c"], vec!["d", "b"], vec!["d", "c"]],
+            resolve_all(&layer, join, &["x", "y"])
+        );
+
+        let query = Query::new(vec![
+            TriplePattern::new(Term::var("x"), Term::node("knows"), Term::var("y")),
+            TriplePattern::new(Term::var("y"), Term::node("barks"), Term::var("z")),
+        ]);
+        let join = LeapfrogJoin::new(&layer, &query).unwrap();
+        assert_eq!(0, join.count());
+    }
+
+    #[test]
+    fn variables_of_mixed_kinds_are_rejected() {
+        let layer = example_layer();
+        let query = Query::new(vec![
+            TriplePattern::new(Term::node("a"), Term::var("p"), Term::var("o")),
+            TriplePattern::new(Term::var("p"), Term::var("q"), Term::var("r")),
+        ]);
+
+        assert!(LeapfrogJoin::new(&layer, &query).is_none());
+    }
+
+    #[test]
+    fn triangles_in_child_layer() {
+        let layer = example_layer();
+        let builder = layer.open_write().unwrap();
+        builder
+            .remove_string_triple(StringTriple::new_node("d", "knows", "c"))
+            .unwrap();
+        builder
+            .add_string_triple(StringTriple::new_node("e", "knows", "c"))
+            .unwrap();
+        let child = builder.commit().unwrap();
+
+        let join = LeapfrogJoin::new(&child, &triangle()).unwrap();
+        assert_eq!(
+            vec![
+                vec!["a", "b", "c"],
+                vec!["b", "c", "a"],
+                vec!["c", "a", "b"],
+                vec!["c", "e", "e"],
+                vec!["e", "c", "e"],
+                vec!["e", "e", "c"],
+                vec!["e", "e", "e"],
+            ],
+            resolve_all(&child, join, &["x", "y", "z"])
+        );
+    }
+}
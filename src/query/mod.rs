@@ -0,0 +1,29 @@
+//! Queries over layers.
+//!
+//! Rather than nesting calls to `triples_s`, `triples_sp`,
+//! `triples_p` and `triples_o` by hand, a query can be described as a
+//! set of triple patterns containing variables. The query is planned
+//! against a particular layer and then executed, producing a binding
+//! for each variable for every solution.
+//!
+//! Queries with cycles, such as finding all triangles in a graph, can
+//! instead be answered with a `LeapfrogJoin`, which binds one variable
+//! at a time over trie iterators and never materializes the results of
+//! individual patterns.
+//!
+//! Property paths, such as `subClassOf*`, find everything that can be
+//! reached from a node by repeatedly following predicates.
+//!
+//! Triples can be counted, or their numeric values summed, with
+//! aggregate functions, optionally grouped by subject, predicate or
+//! object. Counts over a whole layer are computed from the layer
+//! structures rather than by iterating over its triples.
+mod aggregate;
+mod bgp;
+mod leapfrog;
+mod path;
+
+pub use aggregate::*;
+pub use bgp::*;
+pub use leapfrog::*;
+pub use path::*;
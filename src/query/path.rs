@@ -0,0 +1,408 @@
+//! Property paths.
+//!
+//! A property path describes a route through the graph, built up from
+//! predicates using sequences, alternatives, inverses and
+//! repetitions, like SPARQL property paths. For example,
+//! `subClassOf*` finds a class and all its superclasses.
+//!
+//! A path is evaluated by turning it into a small automaton, and then
+//! exploring the graph breadth first, keeping track of both the
+//! current node and the current state of the automaton. Every
+//! combination of node and state is only visited once, so cycles in
+//! the graph or in the path don't lead to infinite loops.
+use crate::layer::Layer;
+use std::collections::{HashSet, VecDeque};
+
+/// A property path.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Path {
+    /// A single step along the given predicate, from subject to object.
+    Predicate(String),
+    /// The given paths, one after another.
+    Sequence(Vec<Path>),
+    /// Any one of the given paths.
+    Alternative(Vec<Path>),
+    /// The given path, walked backwards from object to subject.
+    Inverse(Box<Path>),
+    /// The given path, repeated any number of times, including zero.
+    ZeroOrMore(Box<Path>),
+    /// The given path, repeated at least once.
+    OneOrMore(Box<Path>),
+    /// The given path, or the empty path.
+    ZeroOrOne(Box<Path>),
+}
+
+impl Path {
+    /// Construct a path that is a single step along the given predicate.
+    pub fn predicate(predicate: &str) -> Path {
+        Path::Predicate(predicate.to_owned())
+    }
+
+    /// Construct a path that walks the given paths one after another.
+    pub fn sequence(paths: Vec<Path>) -> Path {
+        Path::Sequence(paths)
+    }
+
+    /// Construct a path that walks any one of the given paths.
+    pub fn alternative(paths: Vec<Path>) -> Path {
+        Path::Alternative(paths)
+    }
+
+    /// Returns this path walked backwards.
+    pub fn inverse(self) -> Path {
+        Path::Inverse(Box::new(self))
+    }
+
+    /// Returns this path repeated any number of times, including zero.
+    pub fn zero_or_more(self) -> Path {
+        Path::ZeroOrMore(Box::new(self))
+    }
+
+    /// Returns this path repeated at least once.
+    pub fn one_or_more(self) -> Path {
+        Path::OneOrMore(Box::new(self))
+    }
+
+    /// Returns this path or the empty path.
+    pub fn zero_or_one(self) -> Path {
+        Path::ZeroOrOne(Box::new(self))
+    }
+
+    /// Returns an iterator over all node and value ids reachable from the given node id by walking this path forwards.
+    ///
+    /// Every id is returned once, in the order in which it is first reached.
+    pub fn evaluate_from(&self, layer: &dyn Layer, start: u64) -> PathIterator {
+        PathIterator::new(layer, Automaton::new(layer, self, false), start)
+    }
+
+    /// Returns an iterator over all node ids from which the given node or value id is reachable by walking this path.
+    ///
+    /// This walks the path backwards, starting at the end. Every id
+    /// is returned once, in the order in which it is first reached.
+    pub fn evaluate_to(&self, layer: &dyn Layer, end: u64) -> PathIterator {
+        PathIterator::new(layer, Automaton::new(layer, self, true), end)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Transition {
+    /// Move to another state without moving through the graph.
+    Epsilon(usize),
+    /// Move along a predicate, either from subject to object, or backwards from object to subject.
+    Step {
+        predicate: u64,
+        forward: bool,
+        to: usize,
+    },
+}
+
+/// A nondeterministic automaton accepting the sequences of steps that make up a path.
+#[derive(Clone, Debug)]
+struct Automaton {
+    transitions: Vec<Vec<Transition>>,
+    accept: usize,
+}
+
+impl Automaton {
+    /// Construct the automaton for the given path, or for the path walked backwards if `inverted` is set.
+    fn new(layer: &dyn Layer, path: &Path, inverted: bool) -> Automaton {
+        let mut automaton = Automaton {
+            transitions: vec![Vec::new()],
+            accept: 0,
+        };
+        automaton.accept = automaton.build(layer, path, 0, inverted);
+
+        automaton
+    }
+
+    fn add_state(&mut self) -> usize {
+        self.transitions.push(Vec::new());
+        self.transitions.len() - 1
+    }
+
+    /// Add the states for the given path, starting at the given state, and return the state in which it ends.
+    fn build(&mut self, layer: &dyn Layer, path: &Path, from: usize, inverted: bool) -> usize {
+        match path {
+            Path::Predicate(predicate) => {
+                let to = self.add_state();
+                // a predicate unknown to the layer leads nowhere
+                if let Some(predicate) = layer.predicate_id(predicate) {
+                    self.transitions[from].push(Transition::Step {
+                        predicate,
+                        forward: !inverted,
+                        to,
+                    });
+                }
+
+                to
+            }
+            Path::Sequence(paths) => {
+                let mut current = from;
+                if inverted {
+                    for path in paths.iter().rev() {
+                        current = self.build(layer, path, current, inverted);
+                    }
+                } else {
+                    for path in paths.iter() {
+                        current = self.build(layer, path, current, inverted);
+                    }
+                }
+
+                current
+            }
+            Path::Alternative(paths) => {
+                let to = self.add_state();
+                for path in paths.iter() {
+                    let end = self.build(layer, path, from, inverted);
+                    self.transitions[end].push(Transition::Epsilon(to));
+                }
+
+                to
+            }
+            Path::Inverse(path) => self.build(layer, path, from, !inverted),
+            Path::ZeroOrMore(path) => {
+                let repeat = self.add_state();
+                self.transitions[from].push(Transition::Epsilon(repeat));
+                let end = self.build(layer, path, repeat, inverted);
+                self.transitions[end].push(Transition::Epsilon(repeat));
+
+                repeat
+            }
+            Path::OneOrMore(path) => {
+                let repeat = self.add_state();
+                self.transitions[from].push(Transition::Epsilon(repeat));
+                let end = self.build(layer, path, repeat, inverted);
+                self.transitions[end].push(Transition::Epsilon(repeat));
+
+                end
+            }
+            Path::ZeroOrOne(path) => {
+                let to = self.add_state();
+                self.transitions[from].push(Transition::Epsilon(to));
+                let end = self.build(layer, path, from, inverted);
+                self.transitions[end].push(Transition::Epsilon(to));
+
+                to
+            }
+        }
+    }
+}
+
+/// An iterator over the ids reachable through a path.
+///
+/// The graph is explored lazily, so only as much of it is visited as
+/// is needed for the ids that are actually retrieved.
+pub struct PathIterator {
+    layer: Box<dyn Layer>,
+    automaton: Automaton,
+    queue: VecDeque<(u64, usize)>,
+    visited: HashSet<(u64, usize)>,
+    returned: HashSet<u64>,
+}
+
+impl PathIterator {
+    fn new(layer: &dyn Layer, automaton: Automaton, start: u64) -> PathIterator {
+        let mut queue = VecDeque::new();
+        queue.push_back((start, 0));
+        let mut visited = HashSet::new();
+        visited.insert((start, 0));
+
+        PathIterator {
+            layer: layer.clone_boxed(),
+            automaton,
+            queue,
+            visited,
+            returned: HashSet::new(),
+        }
+    }
+
+    fn visit(&mut self, node: u64, state: usize) {
+        if self.visited.insert((node, state)) {
+            self.queue.push_back((node, state));
+        }
+    }
+}
+
+impl Iterator for PathIterator {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<u64> {
+        while let Some((node, state)) = self.queue.pop_front() {
+            for i in 0..self.automaton.transitions[state].len() {
+                match self.automaton.transitions[state][i] {
+                    Transition::Epsilon(to) => self.visit(node, to),
+                    Transition::Step {
+                        predicate,
+                        forward: true,
+                        to,
+                    } => {
+                        for triple in self.layer.triples_sp(node, predicate) {
+                            self.visit(triple.object, to);
+                        }
+                    }
+                    Transition::Step {
+                        predicate,
+                        forward: false,
+                        to,
+                    } => {
+                        let triples = self.layer.triples_o(node);
+                        for triple in triples.filter(|t| t.predicate == predicate) {
+                            self.visit(triple.subject, to);
+                        }
+                    }
+                }
+            }
+
+            if state == self.automaton.accept && self.returned.insert(node) {
+                return Some(node);
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layer::{ObjectType, StringTriple};
+    use crate::store::sync::*;
+
+    fn example_layer() -> SyncStoreLayer {
+        let store = open_sync_memory_store();
+        let builder = store.create_base_layer().unwrap();
+        for (subject, predicate, object) in &[
+            ("dog", "subClassOf", "mammal"),
+            ("cat", "subClassOf", "mammal"),
+            ("mammal", "subClassOf", "animal"),
+            ("animal", "subClassOf", "thing"),
+            // a cycle
+            ("thing", "subClassOf", "animal"),
+            ("rex", "type", "dog"),
+            ("tom", "type", "cat"),
+            ("rex", "friend", "tom"),
+        ] {
+            builder
+                .add_string_triple(StringTriple::new_node(subject, predicate, object))
+                .unwrap();
+        }
+        builder
+            .add_string_triple(StringTriple::new_value("dog", "label", "Dog"))
+            .unwrap();
+
+        builder.commit().unwrap()
+    }
+
+    fn names(layer: &dyn Layer, ids: PathIterator) -> Vec<String> {
+        let mut result: Vec<String> = ids
+            .map(|id| match layer.id_object(id).unwrap() {
+                ObjectType::Node(s) | ObjectType::Value(s) => s,
+            })
+            .collect();
+        result.sort();
+
+        result
+    }
+
+    fn from(layer: &dyn Layer, path: &Path, start: &str) -> Vec<String> {
+        names(
+            layer,
+            path.evaluate_from(layer, layer.subject_id(start).unwrap()),
+        )
+    }
+
+    fn to(layer: &dyn Layer, path: &Path, end: &str) -> Vec<String> {
+        names(
+            layer,
+            path.evaluate_to(layer, layer.object_node_id(end).unwrap()),
+        )
+    }
+
+    #[test]
+    fn closures_terminate_on_cycles() {
+        let layer = example_layer();
+        let sub_class = Path::predicate("subClassOf");
+
+        assert_eq!(
+            vec!["animal", "dog", "mammal", "thing"],
+            from(&layer, &sub_class.clone().zero_or_more(), "dog")
+        );
+        assert_eq!(
+            vec!["animal", "mammal", "thing"],
+            from(&layer, &sub_class.clone().one_or_more(), "dog")
+        );
+        // animal reaches itself through the cycle
+        assert_eq!(
+            vec!["animal", "thing"],
+            from(&layer, &sub_class.clone().one_or_more(), "animal")
+        );
+        assert_eq!(
+            vec!["dog", "mammal"],
+            from(&layer, &sub_class.zero_or_one(), "dog")
+        );
+    }
+
+    #[test]
+    fn sequences_alternatives_and_inverses() {
+        let layer = example_layer();
+        let type_of = Path::sequence(vec![
+            Path::predicate("type"),
+            Path::predicate("subClassOf").zero_or_more(),
+        ]);
+        assert_eq!(
+            vec!["animal", "dog", "mammal", "thing"],
+            from(&layer, &type_of, "rex")
+        );
+
+        let friend_or_type = Path::alternative(vec![Path::predicate("friend"), type_of.clone()]);
+        assert_eq!(
+            vec!["animal", "dog", "mammal", "thing", "tom"],
+            from(&layer, &friend_or_type, "rex")
+        );
+
+        // everything of the same class as tom
+        let same_class = Path::sequence(vec![
+            Path::predicate("type"),
+            Path::predicate("type").inverse(),
+        ]);
+        assert_eq!(vec!["tom"], from(&layer, &same_class, "tom"));
+
+        let labels = Path::sequence(vec![Path::predicate("type"), Path::predicate("label")]);
+        assert_eq!(vec!["Dog"], from(&layer, &labels, "rex"));
+
+        assert!(from(&layer, &Path::predicate("unknown"), "rex").is_empty());
+        assert_eq!(
+            vec!["rex"],
+            from(&layer, &Path::sequence(Vec::new()), "rex")
+        );
+        assert!(from(&layer, &Path::alternative(Vec::new()), "rex").is_empty());
+    }
+
+    #[test]
+    fn evaluate_backwards() {
+        let layer = example_layer();
+        let type_of = Path::sequence(vec![
+            Path::predicate("type"),
+            Path::predicate("subClassOf").zero_or_more(),
+        ]);
+
+        assert_eq!(vec!["rex", "tom"], to(&layer, &type_of, "mammal"));
+        assert_eq!(vec!["rex"], to(&layer, &type_of, "dog"));
+        assert_eq!(
+            vec!["animal", "cat", "dog", "mammal", "thing"],
+            to(
+                &layer,
+                &Path::predicate("subClassOf").one_or_more(),
+                "animal"
+            )
+        );
+        assert_eq!(
+            vec!["animal", "cat", "dog", "mammal", "thing"],
+            from(
+                &layer,
+                &Path::predicate("subClassOf").inverse().one_or_more(),
+                "animal"
+            )
+        );
+    }
+}
@@ -0,0 +1,170 @@
+//! A layer with pending changes, as seen by the reasoner.
+use crate::layer::{Layer, ObjectType, StringTriple};
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+/// The triples of a layer, together with triples that are to be added to or removed from it.
+pub(super) struct Facts<'a> {
+    layer: &'a dyn Layer,
+    added: BTreeSet<StringTriple>,
+    added_by_predicate: HashMap<String, HashSet<StringTriple>>,
+    added_by_object: HashMap<ObjectType, HashSet<StringTriple>>,
+    removed: HashSet<StringTriple>,
+}
+
+impl<'a> Facts<'a> {
+    pub(super) fn new(layer: &'a dyn Layer) -> Facts<'a> {
+        Facts {
+            layer,
+            added: BTreeSet::new(),
+            added_by_predicate: HashMap::new(),
+            added_by_object: HashMap::new(),
+            removed: HashSet::new(),
+        }
+    }
+
+    /// Returns the triples that are not in the layer, but are in these facts.
+    pub(super) fn additions(&self) -> impl Iterator<Item = &StringTriple> {
+        self.added.iter()
+    }
+
+    /// Returns the triples that are in the layer, but not in these facts.
+    pub(super) fn removals(&self) -> impl Iterator<Item = &StringTriple> {
+        self.removed.iter()
+    }
+
+    pub(super) fn contains(&self, triple: &StringTriple) -> bool {
+        self.added.contains(triple)
+            || (!self.removed.contains(triple) && self.layer.string_triple_exists(triple))
+    }
+
+    /// Add the given triple, returning false if it was already there.
+    pub(super) fn insert(&mut self, triple: StringTriple) -> bool {
+        if self.contains(&triple) {
+            return false;
+        }
+
+        if !self.removed.remove(&triple) {
+            self.added_by_predicate
+                .entry(triple.predicate.clone())
+                .or_default()
+                .insert(triple.clone());
+            self.added_by_object
+                .entry(triple.object.clone())
+                .or_default()
+                .insert(triple.clone());
+            self.added.insert(triple);
+        }
+
+        true
+    }
+
+    /// Remove the given triple, returning false if it wasn't there.
+    pub(super) fn remove(&mut self, triple: &StringTriple) -> bool {
+        if self.added.remove(triple) {
+            if let Some(triples) = self.added_by_predicate.get_mut(&triple.predicate) {
+                triples.remove(triple);
+            }
+            if let Some(triples) = self.added_by_object.get_mut(&triple.object) {
+                triples.remove(triple);
+            }
+
+            true
+        } else if self.layer.string_triple_exists(triple) {
+            self.removed.insert(triple.clone())
+        } else {
+            false
+        }
+    }
+
+    /// Returns all triples matching the given subject, predicate and object, any of which may be left open.
+    pub(super) fn matching(
+        &self,
+        subject: Option<&str>,
+        predicate: Option<&str>,
+        object: Option<&ObjectType>,
+    ) -> Vec<StringTriple> {
+        let matches = |t: &StringTriple| {
+            subject.map_or(true, |s| t.subject == s)
+                && predicate.map_or(true, |p| t.predicate == p)
+                && object.map_or(true, |o| t.object == *o)
+        };
+
+        let mut result: Vec<StringTriple> = self
+            .layer_matching(subject, predicate, object)
+            .into_iter()
+            .filter(|t| !self.removed.contains(t))
+            .collect();
+
+        let added: Vec<StringTriple> = match (subject, predicate, object) {
+            (Some(subject), _, _) => {
+                let start = StringTriple::new_node(subject, "", "");
+                self.added
+                    .range(start..)
+                    .take_while(|t| t.subject == subject)
+                    .filter(|t| matches(t))
+                    .cloned()
+                    .collect()
+            }
+            (None, _, Some(object)) => self
+                .added_by_object
+                .get(object)
+                .into_iter()
+                .flatten()
+                .filter(|t| matches(t))
+                .cloned()
+                .collect(),
+            (None, Some(predicate), None) => self
+                .added_by_predicate
+                .get(predicate)
+                .into_iter()
+                .flatten()
+                .cloned()
+                .collect(),
+            (None, None, None) => self.added.iter().cloned().collect(),
+        };
+        result.extend(added);
+
+        result
+    }
+
+    fn layer_matching(
+        &self,
+        subject: Option<&str>,
+        predicate: Option<&str>,
+        object: Option<&ObjectType>,
+    ) -> Vec<StringTriple> {
+        let layer = self.layer;
+        // a string unknown to the layer can't be part of any of its triples
+        let subject_id = match subject.map(|s| layer.subject_id(s)) {
+            Some(None) => return Vec::new(),
+            id => id.flatten(),
+        };
+        let predicate_id = match predicate.map(|p| layer.predicate_id(p)) {
+            Some(None) => return Vec::new(),
+            id => id.flatten(),
+        };
+        let object_id = match object.map(|o| match o {
+            ObjectType::Node(node) => layer.object_node_id(node),
+            ObjectType::Value(value) => layer.object_value_id(value),
+        }) {
+            Some(None) => return Vec::new(),
+            id => id.flatten(),
+        };
+
+        let triples = match (subject_id, predicate_id, object_id) {
+            (Some(s), Some(p), _) => layer.triples_sp(s, p),
+            (Some(s), None, _) => layer.triples_s(s),
+            (None, _, Some(o)) => layer.triples_o(o),
+            (None, Some(p), None) => layer.triples_p(p),
+            (None, None, None) => layer.triples(),
+        };
+
+        triples
+            .filter(|t| {
+                predicate_id.map_or(true, |p| t.predicate == p)
+                    && object_id.map_or(true, |o| t.object == o)
+            })
+            .filter_map(|t| layer.id_triple_to_string(&t))
+            .collect()
+    }
+}
@@ -0,0 +1,362 @@
+//! Forward-chaining reasoning.
+//!
+//! A `Reasoner` computes the triples that are entailed by the triples
+//! in a layer under the RDFS rules, and optionally a practical subset
+//! of OWL-RL. Rather than answering queries through inference, the
+//! entailed triples are materialized into a child layer, so the
+//! result can be queried like any other layer.
+//!
+//! Once a closure has been materialized, it can be kept up to date
+//! incrementally. Additions are handled by applying the rules to just
+//! the new triples. Removals are handled by first removing everything
+//! that may have been derived from the removed triples, and then
+//! re-deriving what can still be derived from the rest.
+mod facts;
+mod rules;
+
+use self::facts::Facts;
+use crate::layer::{Layer, ObjectType, StringTriple};
+use crate::store::StoreLayer;
+use std::collections::HashSet;
+use std::io;
+
+/// The IRIs of the RDF, RDFS and OWL terms the reasoner knows about.
+pub mod vocabulary {
+    pub const RDF_TYPE: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#type";
+    pub const RDFS_SUB_CLASS_OF: &str = "http://www.w3.org/2000/01/rdf-schema#subClassOf";
+    pub const RDFS_SUB_PROPERTY_OF: &str = "http://www.w3.org/2000/01/rdf-schema#subPropertyOf";
+    pub const RDFS_DOMAIN: &str = "http://www.w3.org/2000/01/rdf-schema#domain";
+    pub const RDFS_RANGE: &str = "http://www.w3.org/2000/01/rdf-schema#range";
+    pub const OWL_INVERSE_OF: &str = "http://www.w3.org/2002/07/owl#inverseOf";
+    pub const OWL_SYMMETRIC_PROPERTY: &str = "http://www.w3.org/2002/07/owl#SymmetricProperty";
+    pub const OWL_TRANSITIVE_PROPERTY: &str = "http://www.w3.org/2002/07/owl#TransitiveProperty";
+    pub const OWL_EQUIVALENT_CLASS: &str = "http://www.w3.org/2002/07/owl#equivalentClass";
+    pub const OWL_EQUIVALENT_PROPERTY: &str = "http://www.w3.org/2002/07/owl#equivalentProperty";
+}
+
+/// The set of rules a reasoner applies.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Profile {
+    /// Subclasses, subproperties, domains and ranges.
+    Rdfs,
+    /// The RDFS rules, plus inverse, symmetric and transitive
+    /// properties, and equivalent classes and properties.
+    OwlRl,
+}
+
+/// A forward-chaining reasoner.
+#[derive(Clone, Copy, Debug)]
+pub struct Reasoner {
+    profile: Profile,
+}
+
+impl Reasoner {
+    /// Construct a reasoner that applies the rules of the given profile.
+    pub fn new(profile: Profile) -> Reasoner {
+        Reasoner { profile }
+    }
+
+    /// Returns the profile of this reasoner.
+    pub fn profile(&self) -> Profile {
+        self.profile
+    }
+
+    /// Apply the rules to the given triples and everything derived from them, adding the results to the facts.
+    fn saturate(&self, facts: &mut Facts, mut pending: Vec<StringTriple>) {
+        while let Some(triple) = pending.pop() {
+            for derived in rules::consequences(self.profile, facts, &triple) {
+                if facts.insert(derived.clone()) {
+                    pending.push(derived);
+                }
+            }
+        }
+    }
+
+    /// Returns all triples that are entailed by the given layer, but are not in it.
+    pub fn infer(&self, layer: &dyn Layer) -> Vec<StringTriple> {
+        let mut facts = Facts::new(layer);
+        let triples = layer
+            .triples()
+            .filter_map(|t| layer.id_triple_to_string(&t))
+            .collect();
+        self.saturate(&mut facts, triples);
+
+        facts.additions().cloned().collect()
+    }
+
+    /// Materialize the closure of the given layer.
+    ///
+    /// This returns a new child layer of the given layer, which adds
+    /// only the inferred triples.
+    pub async fn materialize(&self, layer: &StoreLayer) -> io::Result<StoreLayer> {
+        let inferred = self.infer(layer);
+        let builder = layer.open_write().await?;
+        for triple in inferred {
+            builder.add_string_triple(triple)?;
+        }
+
+        builder.commit().await
+    }
+
+    /// Update a materialized closure with the changes made in a new layer.
+    ///
+    /// `closure` is a closure of the parent of `asserted`, as returned
+    /// by `materialize` or an earlier `update`. The triples that
+    /// `asserted` adds and removes are applied to the closure, along
+    /// with the inferred triples that are gained or lost as a result.
+    /// This returns a new child layer of `closure`, containing the
+    /// closure of `asserted`.
+    pub async fn update(
+        &self,
+        closure: &StoreLayer,
+        asserted: &StoreLayer,
+    ) -> io::Result<StoreLayer> {
+        let (additions, removals) = self.changes(closure, asserted);
+        let builder = closure.open_write().await?;
+        for triple in additions {
+            builder.add_string_triple(triple)?;
+        }
+        for triple in removals {
+            builder.remove_string_triple(triple)?;
+        }
+
+        builder.commit().await
+    }
+
+    /// Returns the triples to add to and remove from the given closure to account for the changes in the asserted layer.
+    fn changes(
+        &self,
+        closure: &dyn Layer,
+        asserted: &dyn Layer,
+    ) -> (Vec<StringTriple>, Vec<StringTriple>) {
+        let mut facts = Facts::new(closure);
+
+        // find everything that may have been derived from the removed triples
+        let mut pending: Vec<StringTriple> = asserted
+            .triple_removals()
+            .filter_map(|t| asserted.id_triple_to_string(&t))
+            .filter(|t| facts.contains(t))
+            .collect();
+        let mut overdeleted: HashSet<StringTriple> = pending.iter().cloned().collect();
+        while let Some(triple) = pending.pop() {
+            for derived in rules::consequences(self.profile, &facts, &triple) {
+                if facts.contains(&derived) && overdeleted.insert(derived.clone()) {
+                    pending.push(derived);
+                }
+            }
+        }
+        for triple in overdeleted.iter() {
+            facts.remove(triple);
+        }
+
+        // restore what is still asserted or can be derived in a single step
+        let mut restored: Vec<StringTriple> = overdeleted
+            .into_iter()
+            .filter(|t| asserted.string_triple_exists(t) || self.is_derivable(&facts, t))
+            .collect();
+
+        restored.extend(
+            asserted
+                .triple_additions()
+                .filter_map(|t| asserted.id_triple_to_string(&t)),
+        );
+        for triple in restored.iter() {
+            facts.insert(triple.clone());
+        }
+        self.saturate(&mut facts, restored);
+
+        (
+            facts.additions().cloned().collect(),
+            facts.removals().cloned().collect(),
+        )
+    }
+
+    /// Check whether the given triple can be derived from the facts in a single step.
+    ///
+    /// Every rule has a premise that mentions the subject of its
+    /// conclusion, either as subject or as object, so only those
+    /// triples need to be tried.
+    fn is_derivable(&self, facts: &Facts, triple: &StringTriple) -> bool {
+        let subject = triple.subject.as_str();
+        let as_object = ObjectType::Node(subject.to_owned());
+        facts
+            .matching(Some(subject), None, None)
+            .into_iter()
+            .chain(facts.matching(None, None, Some(&as_object)))
+            .any(|premise| {
+                rules::consequences(self.profile, facts, &premise)
+                    .iter()
+                    .any(|t| t == triple)
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::vocabulary::*;
+    use super::*;
+    use crate::store::*;
+    use std::collections::BTreeSet;
+    use tokio::runtime::Runtime;
+
+    fn all_triples(layer: &dyn Layer) -> BTreeSet<StringTriple> {
+        layer
+            .triples()
+            .map(|t| layer.id_triple_to_string(&t).unwrap())
+            .collect()
+    }
+
+    fn build(
+        runtime: &mut Runtime,
+        store: &Store,
+        parent: Option<&StoreLayer>,
+        additions: &[StringTriple],
+        removals: &[StringTriple],
+    ) -> StoreLayer {
+        let builder = match parent {
+            None => runtime.block_on(store.create_base_layer()),
+            Some(parent) => runtime.block_on(parent.open_write()),
+        }
+        .unwrap();
+        for triple in additions {
+            builder.add_string_triple(triple.clone()).unwrap();
+        }
+        for triple in removals {
+            builder.remove_string_triple(triple.clone()).unwrap();
+        }
+
+        runtime.block_on(builder.commit()).unwrap()
+    }
+
+    fn schema() -> Vec<StringTriple> {
+        vec![
+            StringTriple::new_node("Dog", RDFS_SUB_CLASS_OF, "Mammal"),
+            StringTriple::new_node("Mammal", RDFS_SUB_CLASS_OF, "Animal"),
+            StringTriple::new_node("owns", RDFS_DOMAIN, "Person"),
+            StringTriple::new_node("owns", RDFS_RANGE, "Animal"),
+            StringTriple::new_node("adopted", RDFS_SUB_PROPERTY_OF, "owns"),
+        ]
+    }
+
+    #[test]
+    fn materialize_rdfs_closure() {
+        let mut runtime = Runtime::new().unwrap();
+        let store = open_memory_store();
+        let mut triples = schema();
+        triples.push(StringTriple::new_node("rex", RDF_TYPE, "Dog"));
+        triples.push(StringTriple::new_node("alice", "adopted", "tom"));
+        triples.push(StringTriple::new_value("alice", "adopted", "a goldfish"));
+        let layer = build(&mut runtime, &store, None, &triples, &[]);
+
+        let reasoner = Reasoner::new(Profile::Rdfs);
+        let closure = runtime.block_on(reasoner.materialize(&layer)).unwrap();
+
+        let expected: BTreeSet<_> = vec![
+            StringTriple::new_node("Dog", RDFS_SUB_CLASS_OF, "Animal"),
+            StringTriple::new_node("rex", RDF_TYPE, "Mammal"),
+            StringTriple::new_node("rex", RDF_TYPE, "Animal"),
+            StringTriple::new_node("alice", "owns", "tom"),
+            StringTriple::new_value("alice", "owns", "a goldfish"),
+            StringTriple::new_node("alice", RDF_TYPE, "Person"),
+            StringTriple::new_node("tom", RDF_TYPE, "Animal"),
+        ]
+        .into_iter()
+        .collect();
+        let inferred: BTreeSet<_> = all_triples(&closure)
+            .difference(&all_triples(&layer))
+            .cloned()
+            .collect();
+        assert_eq!(expected, inferred);
+        assert_eq!(expected.len(), closure.triple_layer_addition_count());
+        assert_eq!(0, closure.triple_layer_removal_count());
+
+        // the closure is closed
+        assert!(reasoner.infer(&closure).is_empty());
+    }
+
+    #[test]
+    fn infer_owl_rules() {
+        let mut runtime = Runtime::new().unwrap();
+        let store = open_memory_store();
+        let layer = build(
+            &mut runtime,
+            &store,
+            None,
+            &[
+                StringTriple::new_node("parentOf", OWL_INVERSE_OF, "childOf"),
+                StringTriple::new_node("knows", RDF_TYPE, OWL_SYMMETRIC_PROPERTY),
+                StringTriple::new_node("ancestorOf", RDF_TYPE, OWL_TRANSITIVE_PROPERTY),
+                StringTriple::new_node("Human", OWL_EQUIVALENT_CLASS, "Person"),
+                StringTriple::new_node("a", "parentOf", "b"),
+                StringTriple::new_node("a", "knows", "c"),
+                StringTriple::new_node("a", "ancestorOf", "b"),
+                StringTriple::new_node("b", "ancestorOf", "d"),
+                StringTriple::new_node("d", "ancestorOf", "e"),
+                StringTriple::new_node("a", RDF_TYPE, "Human"),
+            ],
+            &[],
+        );
+
+        let inferred: BTreeSet<_> = Reasoner::new(Profile::OwlRl)
+            .infer(&layer)
+            .into_iter()
+            .collect();
+        for triple in &[
+            StringTriple::new_node("b", "childOf", "a"),
+            StringTriple::new_node("c", "knows", "a"),
+            StringTriple::new_node("a", "ancestorOf", "d"),
+            StringTriple::new_node("a", "ancestorOf", "e"),
+            StringTriple::new_node("b", "ancestorOf", "e"),
+            StringTriple::new_node("Person", RDFS_SUB_CLASS_OF, "Human"),
+            StringTriple::new_node("a", RDF_TYPE, "Person"),
+        ] {
+            assert!(inferred.contains(triple), "{:?} was not inferred", triple);
+        }
+
+        // plain RDFS knows nothing about these
+        assert!(Reasoner::new(Profile::Rdfs).infer(&layer).is_empty());
+    }
+
+    #[test]
+    fn update_closure_incrementally() {
+        let mut runtime = Runtime::new().unwrap();
+        let store = open_memory_store();
+        let mut triples = schema();
+        triples.push(StringTriple::new_node("rex", RDF_TYPE, "Dog"));
+        triples.push(StringTriple::new_node("bob", "owns", "rex"));
+        triples.push(StringTriple::new_node("bob", RDF_TYPE, "Person"));
+        triples.push(StringTriple::new_node("carol", "adopted", "tom"));
+        let asserted = build(&mut runtime, &store, None, &triples, &[]);
+
+        let reasoner = Reasoner::new(Profile::Rdfs);
+        let closure = runtime.block_on(reasoner.materialize(&asserted)).unwrap();
+
+        let asserted = build(
+            &mut runtime,
+            &store,
+            Some(&asserted),
+            &[
+                StringTriple::new_node("tom", RDF_TYPE, "Dog"),
+                StringTriple::new_node("Animal", RDFS_SUB_CLASS_OF, "Thing"),
+            ],
+            &[
+                // bob is still a person because he owns rex
+                StringTriple::new_node("bob", RDF_TYPE, "Person"),
+                // rex is only an animal because bob owns him
+                StringTriple::new_node("rex", RDF_TYPE, "Dog"),
+                StringTriple::new_node("carol", "adopted", "tom"),
+            ],
+        );
+        let updated = runtime
+            .block_on(reasoner.update(&closure, &asserted))
+            .unwrap();
+        let expected = runtime.block_on(reasoner.materialize(&asserted)).unwrap();
+
+        assert_eq!(all_triples(&expected), all_triples(&updated));
+        assert!(updated.string_triple_exists(&StringTriple::new_node("bob", RDF_TYPE, "Person")));
+        assert!(updated.string_triple_exists(&StringTriple::new_node("rex", RDF_TYPE, "Animal")));
+        assert!(!updated.string_triple_exists(&StringTriple::new_node("rex", RDF_TYPE, "Mammal")));
+        assert!(!updated.string_triple_exists(&StringTriple::new_node("carol", "owns", "tom")));
+        assert!(updated.string_triple_exists(&StringTriple::new_node("tom", RDF_TYPE, "Thing")));
+    }
+}
@@ -0,0 +1,209 @@
+//! The entailment rules.
+//!
+//! Rules are applied to one triple at a time. For a given triple,
+//! all rules in which it can appear as a premise are tried, looking up
+//! the other premises in the facts. A triple that is derived this way
+//! is in turn passed through the rules, until nothing new is found.
+use super::facts::Facts;
+use super::vocabulary::*;
+use super::Profile;
+use crate::layer::{ObjectType, StringTriple};
+
+fn node(object: &ObjectType) -> Option<&str> {
+    match object {
+        ObjectType::Node(node) => Some(node),
+        ObjectType::Value(_) => None,
+    }
+}
+
+/// Returns the nodes that are the object of a triple with the given subject and predicate.
+fn object_nodes(facts: &Facts, subject: &str, predicate: &str) -> Vec<String> {
+    facts
+        .matching(Some(subject), Some(predicate), None)
+        .into_iter()
+        .filter_map(|t| match t.object {
+            ObjectType::Node(node) => Some(node),
+            ObjectType::Value(_) => None,
+        })
+        .collect()
+}
+
+/// Returns the subjects of triples with the given predicate and node object.
+fn subjects(facts: &Facts, predicate: &str, object: &str) -> Vec<String> {
+    facts
+        .matching(
+            None,
+            Some(predicate),
+            Some(&ObjectType::Node(object.to_owned())),
+        )
+        .into_iter()
+        .map(|t| t.subject)
+        .collect()
+}
+
+/// Returns all triples that follow from the given triple and the facts in a single step.
+pub(super) fn consequences(
+    profile: Profile,
+    facts: &Facts,
+    triple: &StringTriple,
+) -> Vec<StringTriple> {
+    let mut result = Vec::new();
+    rdfs_consequences(facts, triple, &mut result);
+    if profile == Profile::OwlRl {
+        owl_consequences(facts, triple, &mut result);
+    }
+
+    result
+}
+
+fn rdfs_consequences(facts: &Facts, triple: &StringTriple, result: &mut Vec<StringTriple>) {
+    let subject = triple.subject.as_str();
+    let predicate = triple.predicate.as_str();
+    let object = node(&triple.object);
+
+    // the schema of the predicate applies to this triple
+    for class in object_nodes(facts, predicate, RDFS_DOMAIN) {
+        result.push(StringTriple::new_node(subject, RDF_TYPE, &class));
+    }
+    if let Some(object) = object {
+        for class in object_nodes(facts, predicate, RDFS_RANGE) {
+            result.push(StringTriple::new_node(object, RDF_TYPE, &class));
+        }
+    }
+    for super_property in object_nodes(facts, predicate, RDFS_SUB_PROPERTY_OF) {
+        result.push(StringTriple {
+            subject: subject.to_owned(),
+            predicate: super_property,
+            object: triple.object.clone(),
+        });
+    }
+
+    // this triple is part of the schema
+    match (predicate, object) {
+        (RDFS_DOMAIN, Some(class)) => {
+            for t in facts.matching(None, Some(subject), None) {
+                result.push(StringTriple::new_node(&t.subject, RDF_TYPE, class));
+            }
+        }
+        (RDFS_RANGE, Some(class)) => {
+            for t in facts.matching(None, Some(subject), None) {
+                if let Some(node) = node(&t.object) {
+                    result.push(StringTriple::new_node(node, RDF_TYPE, class));
+                }
+            }
+        }
+        (RDFS_SUB_PROPERTY_OF, Some(super_property)) => {
+            for p in object_nodes(facts, super_property, RDFS_SUB_PROPERTY_OF) {
+                result.push(StringTriple::new_node(subject, RDFS_SUB_PROPERTY_OF, &p));
+            }
+            for p in subjects(facts, RDFS_SUB_PROPERTY_OF, subject) {
+                result.push(StringTriple::new_node(
+                    &p,
+                    RDFS_SUB_PROPERTY_OF,
+                    super_property,
+                ));
+            }
+            for t in facts.matching(None, Some(subject), None) {
+                result.push(StringTriple {
+                    subject: t.subject,
+                    predicate: super_property.to_owned(),
+                    object: t.object,
+                });
+            }
+        }
+        (RDF_TYPE, Some(class)) => {
+            for super_class in object_nodes(facts, class, RDFS_SUB_CLASS_OF) {
+                result.push(StringTriple::new_node(subject, RDF_TYPE, &super_class));
+            }
+        }
+        (RDFS_SUB_CLASS_OF, Some(super_class)) => {
+            for c in object_nodes(facts, super_class, RDFS_SUB_CLASS_OF) {
+                result.push(StringTriple::new_node(subject, RDFS_SUB_CLASS_OF, &c));
+            }
+            for c in subjects(facts, RDFS_SUB_CLASS_OF, subject) {
+                result.push(StringTriple::new_node(&c, RDFS_SUB_CLASS_OF, super_class));
+            }
+            for instance in subjects(facts, RDF_TYPE, subject) {
+                result.push(StringTriple::new_node(&instance, RDF_TYPE, super_class));
+            }
+        }
+        _ => {}
+    }
+}
+
+fn owl_consequences(facts: &Facts, triple: &StringTriple, result: &mut Vec<StringTriple>) {
+    let subject = triple.subject.as_str();
+    let predicate = triple.predicate.as_str();
+    let object = node(&triple.object);
+
+    // the characteristics of the predicate apply to this triple
+    if let Some(object) = object {
+        for inverse in object_nodes(facts, predicate, OWL_INVERSE_OF) {
+            result.push(StringTriple::new_node(object, &inverse, subject));
+        }
+        for inverse in subjects(facts, OWL_INVERSE_OF, predicate) {
+            result.push(StringTriple::new_node(object, &inverse, subject));
+        }
+        let characteristic = |c| facts.contains(&StringTriple::new_node(predicate, RDF_TYPE, c));
+        if characteristic(OWL_SYMMETRIC_PROPERTY) {
+            result.push(StringTriple::new_node(object, predicate, subject));
+        }
+        if characteristic(OWL_TRANSITIVE_PROPERTY) {
+            for next in object_nodes(facts, object, predicate) {
+                result.push(StringTriple::new_node(subject, predicate, &next));
+            }
+            for previous in subjects(facts, predicate, subject) {
+                result.push(StringTriple::new_node(&previous, predicate, object));
+            }
+        }
+    }
+
+    // this triple is part of the schema
+    match (predicate, object) {
+        (OWL_INVERSE_OF, Some(inverse)) => {
+            for t in facts.matching(None, Some(subject), None) {
+                if let Some(node) = node(&t.object) {
+                    result.push(StringTriple::new_node(node, inverse, &t.subject));
+                }
+            }
+            for t in facts.matching(None, Some(inverse), None) {
+                if let Some(node) = node(&t.object) {
+                    result.push(StringTriple::new_node(node, subject, &t.subject));
+                }
+            }
+        }
+        (RDF_TYPE, Some(OWL_SYMMETRIC_PROPERTY)) => {
+            for t in facts.matching(None, Some(subject), None) {
+                if let Some(node) = node(&t.object) {
+                    result.push(StringTriple::new_node(node, subject, &t.subject));
+                }
+            }
+        }
+        (RDF_TYPE, Some(OWL_TRANSITIVE_PROPERTY)) => {
+            for t in facts.matching(None, Some(subject), None) {
+                if let Some(node) = node(&t.object) {
+                    for next in object_nodes(facts, node, subject) {
+                        result.push(StringTriple::new_node(&t.subject, subject, &next));
+                    }
+                }
+            }
+        }
+        (OWL_EQUIVALENT_CLASS, Some(class)) => {
+            result.push(StringTriple::new_node(subject, RDFS_SUB_CLASS_OF, class));
+            result.push(StringTriple::new_node(class, RDFS_SUB_CLASS_OF, subject));
+        }
+        (OWL_EQUIVALENT_PROPERTY, Some(property)) => {
+            result.push(StringTriple::new_node(
+                subject,
+                RDFS_SUB_PROPERTY_OF,
+                property,
+            ));
+            result.push(StringTriple::new_node(
+                property,
+                RDFS_SUB_PROPERTY_OF,
+                subject,
+            ));
+        }
+        _ => {}
+    }
+}
@@ -0,0 +1,254 @@
+//! Per-file checksums for layer files.
+//!
+//! When a layer is built, a CRC32 checksum is computed for every file
+//! that was written for it. These are stored together in a checksum
+//! file inside the layer directory, one line per file, in the form
+//! `<checksum in hex> <file name>`.
+//!
+//! Layers that were written before checksums were introduced have no
+//! checksum file. Such layers are simply not verified.
+use std::collections::BTreeMap;
+use std::io;
+
+use tokio::io::AsyncWriteExt;
+
+use super::file::*;
+
+/// Calculate the checksum of the given file contents.
+pub fn file_checksum(data: &[u8]) -> u32 {
+    crc32fast::hash(data)
+}
+
+/// The checksums of all files in a layer, keyed by file name.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LayerChecksums {
+    checksums: BTreeMap<String, u32>,
+}
+
+impl LayerChecksums {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record the checksum for the given file.
+    pub fn insert(&mut self, file: &str, checksum: u32) {
+        self.checksums.insert(file.to_owned(), checksum);
+    }
+
+    /// Returns the recorded checksum for the given file, if any.
+    pub fn get(&self, file: &str) -> Option<u32> {
+        self.checksums.get(file).copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.checksums.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.checksums.is_empty()
+    }
+
+    /// Iterate over all file names and their checksums, in file name order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, u32)> {
+        self.checksums.iter().map(|(f, c)| (f.as_str(), *c))
+    }
+
+    /// Check the given file contents against the recorded checksum.
+    ///
+    /// Files without a recorded checksum are always considered valid.
+    pub fn matches(&self, file: &str, data: &[u8]) -> bool {
+        match self.get(file) {
+            Some(checksum) => checksum == file_checksum(data),
+            None => true,
+        }
+    }
+
+    /// Parse the contents of a checksum file.
+    pub fn parse(data: &[u8]) -> io::Result<Self> {
+        let s =
+            std::str::from_utf8(data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        let mut result = Self::new();
+        for line in s.lines() {
+            if line.is_empty() {
+                continue;
+            }
+
+            let mut parts = line.splitn(2, ' ');
+            let checksum = parts.next().unwrap();
+            let file = parts.next().ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("checksum line without file name: {:?}", line),
+                )
+            })?;
+            let checksum = u32::from_str_radix(checksum, 16)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+            result.insert(file, checksum);
+        }
+
+        Ok(result)
+    }
+
+    /// Serialize these checksums in the checksum file format.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut result = String::new();
+        for (file, checksum) in self.iter() {
+            result.push_str(&format!("{:08x} {}\n", checksum, file));
+        }
+
+        result.into_bytes()
+    }
+}
+
+/// Calculate the checksums of all the given files that exist.
+pub async fn compute_checksums<F: FileLoad>(
+    files: &[(&'static str, F)],
+) -> io::Result<LayerChecksums> {
+    let mut checksums = LayerChecksums::new();
+    for (name, file) in files {
+        if file.exists() {
+            let data = file.map().await?;
+            checksums.insert(name, file_checksum(&data));
+        }
+    }
+
+    Ok(checksums)
+}
+
+/// Calculate the checksums of all the given files that exist, and write them to the checksum file.
+pub async fn write_checksums<F: FileLoad + FileStore>(
+    files: &[(&'static str, F)],
+    checksums_file: &F,
+) -> io::Result<()> {
+    let checksums = compute_checksums(files).await?;
+
+    let mut writer = checksums_file.open_write();
+    writer.write_all(&checksums.to_bytes()).await?;
+    writer.flush().await?;
+
+    Ok(())
+}
+
+/// Read the checksum file, returning None if it does not exist.
+pub async fn read_checksums<F: FileLoad>(checksums_file: &F) -> io::Result<Option<LayerChecksums>> {
+    match checksums_file.map_if_exists().await? {
+        Some(data) => Ok(Some(LayerChecksums::parse(&data)?)),
+        None => Ok(None),
+    }
+}
+
+/// Returns the names of all files whose contents do not match the
+/// checksum file. Files that have a checksum but do not exist are
+/// included.
+///
+/// If there is no checksum file, nothing is checked.
+pub async fn mismatched_checksums<F: FileLoad>(
+    files: &[(&'static str, F)],
+    checksums_file: &F,
+) -> io::Result<Vec<&'static str>> {
+    let checksums = match read_checksums(checksums_file).await? {
+        Some(checksums) => checksums,
+        None => return Ok(Vec::new()),
+    };
+
+    let mut result = Vec::new();
+    for (name, file) in files {
+        if checksums.get(name).is_none() {
+            continue;
+        }
+
+        if !file.exists() || !checksums.matches(name, &file.map().await?) {
+            result.push(*name);
+        }
+    }
+
+    Ok(result)
+}
+
+/// Check all the given files against the checksum file, returning an
+/// error if any of them does not match.
+pub async fn verify_checksums<F: FileLoad>(
+    files: &[(&'static str, F)],
+    checksums_file: &F,
+) -> io::Result<()> {
+    let mismatched = mismatched_checksums(files, checksums_file).await?;
+    if mismatched.is_empty() {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("checksum mismatch for {}", mismatched.join(", ")),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::memory::*;
+
+    #[test]
+    fn checksums_roundtrip() {
+        let mut checksums = LayerChecksums::new();
+        checksums.insert("foo.logarray", file_checksum(b"foo"));
+        checksums.insert("bar.bitarray", file_checksum(b""));
+
+        let parsed = LayerChecksums::parse(&checksums.to_bytes()).unwrap();
+        assert_eq!(checksums, parsed);
+        assert!(parsed.matches("foo.logarray", b"foo"));
+        assert!(!parsed.matches("foo.logarray", b"fop"));
+        assert!(parsed.matches("baz.logarray", b"anything"));
+    }
+
+    #[test]
+    fn malformed_checksum_file_is_an_error() {
+        assert!(LayerChecksums::parse(b"zzzzzzzz foo\n").is_err());
+        assert!(LayerChecksums::parse(b"0000abcd\n").is_err());
+    }
+
+    #[tokio::test]
+    async fn corrupted_files_are_detected() {
+        let file1 = MemoryBackedStore::new();
+        let file2 = MemoryBackedStore::new();
+        let unwritten = MemoryBackedStore::new();
+        let checksums_file = MemoryBackedStore::new();
+        file1.open_write().write_all(b"hello").await.unwrap();
+        file2.open_write().write_all(b"world").await.unwrap();
+
+        let files = vec![
+            ("file1", file1.clone()),
+            ("file2", file2),
+            ("unwritten", unwritten),
+        ];
+        write_checksums(&files, &checksums_file).await.unwrap();
+        assert_eq!(
+            2,
+            read_checksums(&checksums_file)
+                .await
+                .unwrap()
+                .unwrap()
+                .len()
+        );
+        verify_checksums(&files, &checksums_file).await.unwrap();
+
+        file1.open_write().write_all(b"jello").await.unwrap();
+        assert_eq!(
+            vec!["file1"],
+            mismatched_checksums(&files, &checksums_file).await.unwrap()
+        );
+        assert!(verify_checksums(&files, &checksums_file).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn missing_checksum_file_is_not_verified() {
+        let file = MemoryBackedStore::new();
+        let checksums_file = MemoryBackedStore::new();
+        file.open_write().write_all(b"hello").await.unwrap();
+
+        let files = vec![("file", file)];
+        assert!(read_checksums(&checksums_file).await.unwrap().is_none());
+        verify_checksums(&files, &checksums_file).await.unwrap();
+    }
+}
@@ -11,10 +11,12 @@ pub struct Filenames {
     pub node_value_idmap_bits: &'static str,
     pub node_value_idmap_bit_index_blocks: &'static str,
     pub node_value_idmap_bit_index_sblocks: &'static str,
+    pub node_value_idmap_bit_index_select: &'static str,
 
     pub predicate_idmap_bits: &'static str,
     pub predicate_idmap_bit_index_blocks: &'static str,
     pub predicate_idmap_bit_index_sblocks: &'static str,
+    pub predicate_idmap_bit_index_select: &'static str,
 
     pub base_subjects: &'static str,
     pub base_objects: &'static str,
@@ -23,16 +25,19 @@ pub struct Filenames {
     pub base_s_p_adjacency_list_bits: &'static str,
     pub base_s_p_adjacency_list_bit_index_blocks: &'static str,
     pub base_s_p_adjacency_list_bit_index_sblocks: &'static str,
+    pub base_s_p_adjacency_list_bit_index_select: &'static str,
 
     pub base_sp_o_adjacency_list_nums: &'static str,
     pub base_sp_o_adjacency_list_bits: &'static str,
     pub base_sp_o_adjacency_list_bit_index_blocks: &'static str,
     pub base_sp_o_adjacency_list_bit_index_sblocks: &'static str,
+    pub base_sp_o_adjacency_list_bit_index_select: &'static str,
 
     pub base_o_ps_adjacency_list_nums: &'static str,
     pub base_o_ps_adjacency_list_bits: &'static str,
     pub base_o_ps_adjacency_list_bit_index_blocks: &'static str,
     pub base_o_ps_adjacency_list_bit_index_sblocks: &'static str,
+    pub base_o_ps_adjacency_list_bit_index_select: &'static str,
 
     pub pos_subjects: &'static str,
     pub pos_objects: &'static str,
@@ -41,16 +46,19 @@ pub struct Filenames {
     pub pos_s_p_adjacency_list_bits: &'static str,
     pub pos_s_p_adjacency_list_bit_index_blocks: &'static str,
     pub pos_s_p_adjacency_list_bit_index_sblocks: &'static str,
+    pub pos_s_p_adjacency_list_bit_index_select: &'static str,
 
     pub pos_sp_o_adjacency_list_nums: &'static str,
     pub pos_sp_o_adjacency_list_bits: &'static str,
     pub pos_sp_o_adjacency_list_bit_index_blocks: &'static str,
     pub pos_sp_o_adjacency_list_bit_index_sblocks: &'static str,
+    pub pos_sp_o_adjacency_list_bit_index_select: &'static str,
 
     pub pos_o_ps_adjacency_list_nums: &'static str,
     pub pos_o_ps_adjacency_list_bits: &'static str,
     pub pos_o_ps_adjacency_list_bit_index_blocks: &'static str,
     pub pos_o_ps_adjacency_list_bit_index_sblocks: &'static str,
+    pub pos_o_ps_adjacency_list_bit_index_select: &'static str,
 
     pub neg_subjects: &'static str,
     pub neg_objects: &'static str,
@@ -59,30 +67,43 @@ pub struct Filenames {
     pub neg_s_p_adjacency_list_bits: &'static str,
     pub neg_s_p_adjacency_list_bit_index_blocks: &'static str,
     pub neg_s_p_adjacency_list_bit_index_sblocks: &'static str,
+    pub neg_s_p_adjacency_list_bit_index_select: &'static str,
 
     pub neg_sp_o_adjacency_list_nums: &'static str,
     pub neg_sp_o_adjacency_list_bits: &'static str,
     pub neg_sp_o_adjacency_list_bit_index_blocks: &'static str,
     pub neg_sp_o_adjacency_list_bit_index_sblocks: &'static str,
+    pub neg_sp_o_adjacency_list_bit_index_select: &'static str,
 
     pub neg_o_ps_adjacency_list_nums: &'static str,
     pub neg_o_ps_adjacency_list_bits: &'static str,
     pub neg_o_ps_adjacency_list_bit_index_blocks: &'static str,
     pub neg_o_ps_adjacency_list_bit_index_sblocks: &'static str,
+    pub neg_o_ps_adjacency_list_bit_index_select: &'static str,
 
     pub base_predicate_wavelet_tree_bits: &'static str,
     pub base_predicate_wavelet_tree_bit_index_blocks: &'static str,
     pub base_predicate_wavelet_tree_bit_index_sblocks: &'static str,
+    pub base_predicate_wavelet_tree_bit_index_select: &'static str,
 
     pub pos_predicate_wavelet_tree_bits: &'static str,
     pub pos_predicate_wavelet_tree_bit_index_blocks: &'static str,
     pub pos_predicate_wavelet_tree_bit_index_sblocks: &'static str,
+    pub pos_predicate_wavelet_tree_bit_index_select: &'static str,
 
     pub neg_predicate_wavelet_tree_bits: &'static str,
     pub neg_predicate_wavelet_tree_bit_index_blocks: &'static str,
     pub neg_predicate_wavelet_tree_bit_index_sblocks: &'static str,
+    pub neg_predicate_wavelet_tree_bit_index_select: &'static str,
+
+    pub search_index_text: &'static str,
+    pub search_index_suffixes: &'static str,
+    pub search_index_starts: &'static str,
+
+    pub statistics: &'static str,
 
     pub parent: &'static str,
+    pub checksums: &'static str,
 }
 
 pub const FILENAMES: Filenames = Filenames {
@@ -98,10 +119,12 @@ pub const FILENAMES: Filenames = Filenames {
     node_value_idmap_bits: "node_value_idmap_bits.bitarray",
     node_value_idmap_bit_index_blocks: "node_value_idmap_bit_index_blocks.bitarray",
     node_value_idmap_bit_index_sblocks: "node_value_idmap_bit_index_sblocks.bitarray",
+    node_value_idmap_bit_index_select: "node_value_idmap_bit_index_select.bin",
 
     predicate_idmap_bits: "predicate_idmap_bits.bitarray",
     predicate_idmap_bit_index_blocks: "predicate_idmap_bit_index_blocks.bitarray",
     predicate_idmap_bit_index_sblocks: "predicate_idmap_bit_index_sblocks.bitarray",
+    predicate_idmap_bit_index_select: "predicate_idmap_bit_index_select.bin",
 
     base_subjects: "base_subjects.logarray",
     base_objects: "base_objects.logarray",
@@ -110,18 +133,21 @@ pub const FILENAMES: Filenames = Filenames {
     base_s_p_adjacency_list_bits: "base_s_p_adjacency_list_bits.bitarray",
     base_s_p_adjacency_list_bit_index_blocks: "base_s_p_adjacency_list_bit_index_blocks.logarray",
     base_s_p_adjacency_list_bit_index_sblocks: "base_s_p_adjacency_list_bit_index_sblocks.logarray",
+    base_s_p_adjacency_list_bit_index_select: "base_s_p_adjacency_list_bit_index_select.bin",
 
     base_sp_o_adjacency_list_nums: "base_sp_o_adjacency_list_nums.logarray",
     base_sp_o_adjacency_list_bits: "base_sp_o_adjacency_list_bits.bitarray",
     base_sp_o_adjacency_list_bit_index_blocks: "base_sp_o_adjacency_list_bit_index_blocks.logarray",
     base_sp_o_adjacency_list_bit_index_sblocks:
         "base_sp_o_adjacency_list_bit_index_sblocks.logarray",
+    base_sp_o_adjacency_list_bit_index_select: "base_sp_o_adjacency_list_bit_index_select.bin",
 
     base_o_ps_adjacency_list_nums: "base_o_ps_adjacency_list_nums.logarray",
     base_o_ps_adjacency_list_bits: "base_o_ps_adjacency_list_bits.bitarray",
     base_o_ps_adjacency_list_bit_index_blocks: "base_o_ps_adjacency_list_bit_index_blocks.logarray",
     base_o_ps_adjacency_list_bit_index_sblocks:
         "base_o_ps_adjacency_list_bit_index_sblocks.logarray",
+    base_o_ps_adjacency_list_bit_index_select: "base_o_ps_adjacency_list_bit_index_select.bin",
 
     pos_subjects: "child_pos_subjects.logarray",
     pos_objects: "child_pos_objects.logarray",
@@ -130,16 +156,19 @@ pub const FILENAMES: Filenames = Filenames {
     pos_s_p_adjacency_list_bits: "pos_s_p_adjacency_list_bits.bitarray",
     pos_s_p_adjacency_list_bit_index_blocks: "pos_s_p_adjacency_list_bit_index_blocks.logarray",
     pos_s_p_adjacency_list_bit_index_sblocks: "pos_s_p_adjacency_list_bit_index_sblocks.logarray",
+    pos_s_p_adjacency_list_bit_index_select: "pos_s_p_adjacency_list_bit_index_select.bin",
 
     pos_sp_o_adjacency_list_nums: "pos_sp_o_adjacency_list_nums.logarray",
     pos_sp_o_adjacency_list_bits: "pos_sp_o_adjacency_list_bits.bitarray",
     pos_sp_o_adjacency_list_bit_index_blocks: "pos_sp_o_adjacency_list_bit_index_blocks.logarray",
     pos_sp_o_adjacency_list_bit_index_sblocks: "pos_sp_o_adjacency_list_bit_index_sblocks.logarray",
+    pos_sp_o_adjacency_list_bit_index_select: "pos_sp_o_adjacency_list_bit_index_select.bin",
 
     pos_o_ps_adjacency_list_nums: "pos_o_ps_adjacency_list_nums.logarray",
     pos_o_ps_adjacency_list_bits: "pos_o_ps_adjacency_list_bits.bitarray",
     pos_o_ps_adjacency_list_bit_index_blocks: "pos_o_ps_adjacency_list_bit_index_blocks.logarray",
     pos_o_ps_adjacency_list_bit_index_sblocks: "pos_o_ps_adjacency_list_bit_index_sblocks.logarray",
+    pos_o_ps_adjacency_list_bit_index_select: "pos_o_ps_adjacency_list_bit_index_select.bin",
 
     neg_subjects: "child_neg_subjects.logarray",
     neg_objects: "child_neg_objects.logarray",
@@ -148,34 +177,48 @@ pub const FILENAMES: Filenames = Filenames {
     neg_s_p_adjacency_list_bits: "neg_s_p_adjacency_list_bits.bitarray",
     neg_s_p_adjacency_list_bit_index_blocks: "neg_s_p_adjacency_list_bit_index_blocks.logarray",
     neg_s_p_adjacency_list_bit_index_sblocks: "neg_s_p_adjacency_list_bit_index_sblocks.logarray",
+    neg_s_p_adjacency_list_bit_index_select: "neg_s_p_adjacency_list_bit_index_select.bin",
 
     neg_sp_o_adjacency_list_nums: "neg_sp_o_adjacency_list_nums.logarray",
     neg_sp_o_adjacency_list_bits: "neg_sp_o_adjacency_list_bits.bitarray",
     neg_sp_o_adjacency_list_bit_index_blocks: "neg_sp_o_adjacency_list_bit_index_blocks.logarray",
     neg_sp_o_adjacency_list_bit_index_sblocks: "neg_sp_o_adjacency_list_bit_index_sblocks.logarray",
+    neg_sp_o_adjacency_list_bit_index_select: "neg_sp_o_adjacency_list_bit_index_select.bin",
 
     neg_o_ps_adjacency_list_nums: "neg_o_ps_adjacency_list_nums.logarray",
     neg_o_ps_adjacency_list_bits: "neg_o_ps_adjacency_list_bits.bitarray",
     neg_o_ps_adjacency_list_bit_index_blocks: "neg_o_ps_adjacency_list_bit_index_blocks.logarray",
     neg_o_ps_adjacency_list_bit_index_sblocks: "neg_o_ps_adjacency_list_bit_index_sblocks.logarray",
+    neg_o_ps_adjacency_list_bit_index_select: "neg_o_ps_adjacency_list_bit_index_select.bin",
 
     base_predicate_wavelet_tree_bits: "base_predicate_wavelet_tree_bits.bitarray",
     base_predicate_wavelet_tree_bit_index_blocks:
         "base_predicate_wavelet_tree_bit_index_blocks.logarray",
     base_predicate_wavelet_tree_bit_index_sblocks:
         "base_predicate_wavelet_tree_bit_index_sblocks.logarray",
+    base_predicate_wavelet_tree_bit_index_select:
+        "base_predicate_wavelet_tree_bit_index_select.bin",
 
     pos_predicate_wavelet_tree_bits: "pos_predicate_wavelet_tree_bits.bitarray",
     pos_predicate_wavelet_tree_bit_index_blocks:
         "pos_predicate_wavelet_tree_bit_index_blocks.logarray",
     pos_predicate_wavelet_tree_bit_index_sblocks:
         "pos_predicate_wavelet_tree_bit_index_sblocks.logarray",
+    pos_predicate_wavelet_tree_bit_index_select: "pos_predicate_wavelet_tree_bit_index_select.bin",
 
     neg_predicate_wavelet_tree_bits: "neg_predicate_wavelet_tree_bits.bitarray",
     neg_predicate_wavelet_tree_bit_index_blocks:
         "neg_predicate_wavelet_tree_bit_index_blocks.logarray",
     neg_predicate_wavelet_tree_bit_index_sblocks:
         "neg_predicate_wavelet_tree_bit_index_sblocks.logarray",
+    neg_predicate_wavelet_tree_bit_index_select: "neg_predicate_wavelet_tree_bit_index_select.bin",
+
+    search_index_text: "search_index_text.bin",
+    search_index_suffixes: "search_index_suffixes.logarray",
+    search_index_starts: "search_index_starts.logarray",
+
+    statistics: "layer_statistics.bin",
 
     parent: "parent.hex",
+    checksums: "layer_checksums.crc32",
 };
@@ -4,30 +4,69 @@ use bytes::Bytes;
 use flate2::read::GzDecoder;
 use flate2::write::GzEncoder;
 use flate2::Compression;
+use fs2::FileExt;
 use futures::stream::TryStreamExt;
-use futures::{future, Future};
+use futures::Future;
 use locking::*;
 use std::collections::{HashMap, HashSet};
 use std::fmt::Display;
 use std::io::{self, Read, Seek, SeekFrom};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::pin::Pin;
+use std::sync::{Arc, Mutex};
 use tar::Archive;
 use tokio::fs::{self, *};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
+use super::consts::FILENAMES;
 use super::*;
 
 const PREFIX_DIR_SIZE: usize = 3;
+const STAGING_DIR: &str = ".staging";
+const STAGING_LOCK_FILE: &str = ".staging.lock";
+
+fn is_layer_prefix(name: &str) -> bool {
+    name.len() == PREFIX_DIR_SIZE && name.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// How a `FileBackedStore` loads its file into memory when mapped.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FileMapMode {
+    /// Read the entire file into a buffer.
+    #[default]
+    Read,
+    /// Memory-map the file, letting the OS page it in as it is accessed.
+    ///
+    /// The map is released once the last buffer that refers to it is
+    /// dropped. This relies on files not being changed once they're
+    /// written, which is the case for layer files.
+    Mmap,
+}
+
+fn mmap_file(path: &Path) -> io::Result<Bytes> {
+    let file = std::fs::File::open(path)?;
+    // safety: layer files are never modified or truncated after they've been written
+    let map = unsafe { memmap2::Mmap::map(&file)? };
+
+    Ok(Bytes::from_owner(map))
+}
 
 #[derive(Clone)]
 pub struct FileBackedStore {
     path: PathBuf,
+    map_mode: FileMapMode,
 }
 
 impl FileBackedStore {
     pub fn new<P: Into<PathBuf>>(path: P) -> FileBackedStore {
-        FileBackedStore { path: path.into() }
+        Self::with_map_mode(path, FileMapMode::default())
+    }
+
+    pub fn with_map_mode<P: Into<PathBuf>>(path: P, map_mode: FileMapMode) -> FileBackedStore {
+        FileBackedStore {
+            path: path.into(),
+            map_mode,
+        }
     }
 
     fn open_read_from_std(&self, offset: usize) -> std::fs::File {
@@ -66,6 +105,8 @@ impl FileLoad for FileBackedStore {
             let size = file.size();
             if size == 0 {
                 Ok(Bytes::new())
+            } else if file.map_mode == FileMapMode::Mmap {
+                mmap_file(&file.path)
             } else {
                 let mut f = file.open_read();
                 let mut v = Vec::with_capacity(file.size());
@@ -93,58 +134,239 @@ impl FileStore for FileBackedStore {
 #[derive(Clone)]
 pub struct DirectoryLayerStore {
     path: PathBuf,
+    verify_checksums: bool,
+    map_mode: FileMapMode,
+    /// The layers this store is still writing, each with a shared
+    /// lock on the staging lock file, so that no store removes their
+    /// staging directories until they are published.
+    staging: Arc<Mutex<HashMap<[u32; 5], std::fs::File>>>,
+}
+
+fn open_staging_lock(path: &Path) -> io::Result<std::fs::File> {
+    let file = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path.join(STAGING_LOCK_FILE))?;
+
+    Ok(file)
 }
 
 impl DirectoryLayerStore {
     pub fn new<P: Into<PathBuf>>(path: P) -> DirectoryLayerStore {
-        DirectoryLayerStore { path: path.into() }
+        DirectoryLayerStore {
+            path: path.into(),
+            verify_checksums: false,
+            map_mode: FileMapMode::default(),
+            staging: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Set how layer files are loaded into memory.
+    pub fn with_map_mode(mut self, map_mode: FileMapMode) -> DirectoryLayerStore {
+        self.map_mode = map_mode;
+        self
+    }
+
+    /// Set whether layer files are checked against their checksums whenever a layer is loaded.
+    pub fn with_checksum_verification(mut self, verify_checksums: bool) -> DirectoryLayerStore {
+        self.verify_checksums = verify_checksums;
+        self
+    }
+
+    fn layer_path(&self, name: [u32; 5]) -> PathBuf {
+        let mut p = self.path.clone();
+        let name_str = name_to_string(name);
+        p.push(&name_str[0..PREFIX_DIR_SIZE]);
+        p.push(name_str);
+
+        p
+    }
+
+    fn staging_path(&self, name: [u32; 5]) -> PathBuf {
+        let mut p = self.path.clone();
+        p.push(STAGING_DIR);
+        p.push(name_to_string(name));
+
+        p
+    }
+
+    /// Returns the staging path if the given layer is still being written by this store, and the final path otherwise.
+    fn current_layer_path(&self, name: [u32; 5]) -> PathBuf {
+        if self.staging.lock().unwrap().contains_key(&name) {
+            self.staging_path(name)
+        } else {
+            self.layer_path(name)
+        }
+    }
+
+    /// Create a staging directory for a new layer.
+    ///
+    /// The shared lock is taken before the directory is created, so
+    /// that it can't be removed as an abandoned layer in between.
+    fn create_staging_directory(&self, name: [u32; 5]) -> io::Result<()> {
+        std::fs::create_dir_all(&self.path)?;
+        let lock = open_staging_lock(&self.path)?;
+        // The fs2 methods are called explicitly, as newer versions of std
+        // have inherent locking methods on File that would shadow them.
+        FileExt::lock_shared(&lock)?;
+        std::fs::create_dir_all(self.staging_path(name))?;
+        self.staging.lock().unwrap().insert(name, lock);
+
+        Ok(())
+    }
+
+    /// Move a layer from its staging directory into its final place.
+    ///
+    /// All files are synced to disk before the rename, so that a
+    /// layer directory in its final place is always complete, even
+    /// after a crash.
+    fn publish_staged_layer(&self, name: [u32; 5]) -> io::Result<()> {
+        let staging_path = self.staging_path(name);
+        for entry in std::fs::read_dir(&staging_path)? {
+            let entry = entry?;
+            if entry.file_type()?.is_file() {
+                std::fs::File::open(entry.path())?.sync_all()?;
+            }
+        }
+        sync_dir(&staging_path)?;
+
+        let layer_path = self.layer_path(name);
+        let prefix_path = layer_path.parent().unwrap();
+        std::fs::create_dir_all(prefix_path)?;
+        std::fs::rename(&staging_path, &layer_path)?;
+        sync_dir(prefix_path)
+    }
+
+    /// Remove all staging directories, which contain layers that were never finished.
+    ///
+    /// This is meant to be called on startup, as any layer that is
+    /// still being written at that point was abandoned by a crashed
+    /// process. Every layer that is being written holds a shared lock
+    /// on the staging lock file until it is published, so nothing is
+    /// removed while any store, in this process or another, is still
+    /// writing a layer in the same directory. Returns whether the
+    /// staging directories were removed.
+    ///
+    /// A layer builder that is dropped without being committed keeps
+    /// its lock until the store that created it is dropped.
+    pub fn remove_staging_directories(&self) -> io::Result<bool> {
+        let mut p = self.path.clone();
+        p.push(STAGING_DIR);
+        if !p.exists() {
+            return Ok(true);
+        }
+
+        let lock = open_staging_lock(&self.path)?;
+        if let Err(e) = FileExt::try_lock_exclusive(&lock) {
+            if e.kind() == fs2::lock_contended_error().kind() {
+                return Ok(false);
+            }
+
+            return Err(e);
+        }
+
+        let result = match std::fs::remove_dir_all(p) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(true),
+        };
+        FileExt::unlock(&lock)?;
+
+        result
     }
 }
 
+#[cfg(unix)]
+fn sync_dir(path: &std::path::Path) -> io::Result<()> {
+    std::fs::File::open(path)?.sync_all()
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_path: &std::path::Path) -> io::Result<()> {
+    // directories cannot be opened as files on this platform
+    Ok(())
+}
+
 impl PersistentLayerStore for DirectoryLayerStore {
     type File = FileBackedStore;
+
+    fn verify_checksums_on_load(&self) -> bool {
+        self.verify_checksums
+    }
+
     fn directories(&self) -> Pin<Box<dyn Future<Output = io::Result<Vec<[u32; 5]>>> + Send>> {
         let path = self.path.clone();
         Box::pin(async move {
-            let mut stream = fs::read_dir(path).await?;
+            // layers are stored as <prefix>/<name>, where prefix is the start of the name.
+            // anything else, like the staging directory, is not a layer.
+            let mut prefixes = fs::read_dir(path).await?;
             let mut result = Vec::new();
-            while let Some(direntry) = stream.try_next().await? {
-                if direntry.file_type().await?.is_dir() {
-                    let os_name = direntry.file_name();
-                    let name = os_name.to_str().ok_or(io::Error::new(
-                        io::ErrorKind::InvalidData,
-                        "unexpected non-utf8 directory name",
-                    ))?;
-                    result.push(string_to_name(name)?);
+            while let Some(prefix_entry) = prefixes.try_next().await? {
+                if !prefix_entry.file_type().await?.is_dir() {
+                    continue;
+                }
+                let prefix_os_name = prefix_entry.file_name();
+                let prefix = match prefix_os_name.to_str() {
+                    Some(prefix) if is_layer_prefix(prefix) => prefix,
+                    _ => continue,
+                };
+
+                let mut layers = fs::read_dir(prefix_entry.path()).await?;
+                while let Some(layer_entry) = layers.try_next().await? {
+                    if !layer_entry.file_type().await?.is_dir() {
+                        continue;
+                    }
+                    let layer_os_name = layer_entry.file_name();
+                    if let Some(name) = layer_os_name.to_str() {
+                        if name.starts_with(prefix) {
+                            if let Ok(name) = string_to_name(name) {
+                                result.push(name);
+                            }
+                        }
+                    }
                 }
             }
 
+            result.sort();
+
             Ok(result)
         })
     }
 
     fn create_directory(&self) -> Pin<Box<dyn Future<Output = io::Result<[u32; 5]>> + Send>> {
         let name = rand::random();
-        let mut p = self.path.clone();
-        let name_str = name_to_string(name);
-        p.push(&name_str[0..PREFIX_DIR_SIZE]);
-        p.push(name_str);
+        let self_ = self.clone();
 
         Box::pin(async move {
-            fs::create_dir_all(p).await?;
+            tokio::task::spawn_blocking(move || self_.create_staging_directory(name)).await??;
 
             Ok(name)
         })
     }
 
+    fn finalize_directory(
+        &self,
+        name: [u32; 5],
+    ) -> Pin<Box<dyn Future<Output = io::Result<()>> + Send>> {
+        let self_ = self.clone();
+        Box::pin(async move {
+            tokio::task::spawn_blocking(move || {
+                let result = self_.publish_staged_layer(name);
+                // dropping the file releases the lock on the staging directory
+                self_.staging.lock().unwrap().remove(&name);
+
+                result
+            })
+            .await?
+        })
+    }
+
     fn directory_exists(
         &self,
         name: [u32; 5],
     ) -> Pin<Box<dyn Future<Output = io::Result<bool>> + Send>> {
-        let mut p = self.path.clone();
-        let name = name_to_string(name);
-        p.push(&name[0..PREFIX_DIR_SIZE]);
-        p.push(name);
+        let p = self.layer_path(name);
 
         Box::pin(async move {
             match fs::metadata(p).await {
@@ -159,12 +381,13 @@ impl PersistentLayerStore for DirectoryLayerStore {
         directory: [u32; 5],
         name: &str,
     ) -> Pin<Box<dyn Future<Output = io::Result<Self::File>> + Send>> {
-        let mut p = self.path.clone();
-        let dir_name = name_to_string(directory);
-        p.push(&dir_name[0..PREFIX_DIR_SIZE]);
-        p.push(dir_name);
-        p.push(name);
-        Box::pin(future::ok(FileBackedStore::new(p)))
+        let self_ = self.clone();
+        let name = name.to_owned();
+        Box::pin(async move {
+            let mut p = self_.current_layer_path(directory);
+            p.push(name);
+            Ok(FileBackedStore::with_map_mode(p, self_.map_mode))
+        })
     }
 
     fn file_exists(
@@ -172,13 +395,11 @@ impl PersistentLayerStore for DirectoryLayerStore {
         directory: [u32; 5],
         file: &str,
     ) -> Pin<Box<dyn Future<Output = io::Result<bool>> + Send>> {
-        let mut p = self.path.clone();
-        let dir_name = name_to_string(directory);
-        p.push(&dir_name[0..PREFIX_DIR_SIZE]);
-        p.push(dir_name);
-        p.push(file);
-
+        let self_ = self.clone();
+        let file = file.to_owned();
         Box::pin(async move {
+            let mut p = self_.current_layer_path(directory);
+            p.push(file);
             match fs::metadata(p).await {
                 Ok(m) => Ok(m.is_file()),
                 Err(_) => Ok(false),
@@ -222,20 +443,78 @@ impl PersistentLayerStore for DirectoryLayerStore {
         // In terminus-server we are currently already doing this validation. Due to time constraints, we're not implementing it here.
         //
         // This should definitely be done in the future though, to make this part of the library independently usable in a safe manner.
+        //
+        // What we do check is that every layer file matches the checksum file included in the pack.
+        // For that, all files are read before anything gets written, so a corrupt pack leaves the store untouched.
+        let mut layer_files: HashMap<String, HashMap<String, Vec<u8>>> = HashMap::new();
         for e in archive.entries()? {
             let mut entry = e?;
-            let path = entry.path()?;
+            if !entry.header().entry_type().is_file() {
+                continue;
+            }
 
+            let path = entry.path()?.into_owned();
+            let mut components = path.iter().map(|p| p.to_str().unwrap_or(""));
             // check if entry is prefixed with a layer id we are interested in
-            let layer_id = path.iter().next().and_then(|p| p.to_str()).unwrap_or("");
-            if layer_id_set.contains(layer_id) {
-                let mut path: PathBuf = (&self.path).into();
-                let prefix = &layer_id[0..PREFIX_DIR_SIZE];
-                path.push(prefix);
-
-                // extract!
-                entry.unpack_in(path)?;
+            let layer_id = components.next().unwrap_or("");
+            if !layer_id_set.contains(layer_id) {
+                continue;
             }
+
+            let file_name = match (components.next(), components.next()) {
+                (Some(file_name), None) if !file_name.is_empty() => file_name,
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("unexpected path in pack: {}", path.display()),
+                    ))
+                }
+            };
+
+            let mut data = Vec::with_capacity(entry.header().size()? as usize);
+            entry.read_to_end(&mut data)?;
+            layer_files
+                .entry(layer_id.to_owned())
+                .or_default()
+                .insert(file_name.to_owned(), data);
+        }
+
+        for (layer_id, files) in layer_files.iter() {
+            if let Some(checksums) = files.get(FILENAMES.checksums) {
+                let checksums = LayerChecksums::parse(checksums)?;
+                for (file_name, _) in checksums.iter() {
+                    let valid = files
+                        .get(file_name)
+                        .map(|data| checksums.matches(file_name, data))
+                        .unwrap_or(false);
+                    if !valid {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!(
+                                "checksum mismatch for {} in layer {} of pack",
+                                file_name, layer_id
+                            ),
+                        ));
+                    }
+                }
+            }
+        }
+
+        for (layer_id, files) in layer_files {
+            let name = string_to_name(&layer_id)?;
+            if self.layer_path(name).is_dir() {
+                // layers never change once written, so there's nothing to do
+                continue;
+            }
+
+            let path = self.staging_path(name);
+            std::fs::create_dir_all(&path)?;
+
+            for (file_name, data) in files {
+                std::fs::write(path.join(file_name), data)?;
+            }
+
+            self.publish_staged_layer(name)?;
         }
 
         Ok(())
@@ -518,6 +797,79 @@ mod tests {
         assert_eq!(&vec![1, 2, 3][..], &map.as_ref()[..]);
     }
 
+    #[test]
+    fn write_and_mmap_file_backed() {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("foo");
+        let file = FileBackedStore::with_map_mode(&file_path, FileMapMode::Mmap);
+        let mut runtime = Runtime::new().unwrap();
+
+        let mut w = file.open_write();
+        let (map1, map2) = runtime
+            .block_on(async {
+                w.write_all(&[1, 2, 3]).await?;
+                w.flush().await?;
+                let map1 = file.map().await?;
+
+                w.write_all(&[4, 5]).await?;
+                w.flush().await?;
+                let map2 = file.map().await?;
+
+                Ok::<_, io::Error>((map1, map2))
+            })
+            .unwrap();
+
+        assert_eq!(&[1, 2, 3][..], &map1[..]);
+        assert_eq!(&[1, 2, 3, 4, 5][..], &map2[..]);
+
+        // a map is released once the last buffer referring to it is dropped
+        #[cfg(target_os = "linux")]
+        {
+            let path = file_path.to_str().unwrap().to_owned();
+            let is_mapped = || {
+                std::fs::read_to_string("/proc/self/maps")
+                    .unwrap()
+                    .contains(&path)
+            };
+            let clone = map2.clone();
+            drop(map1);
+            drop(map2);
+            assert!(is_mapped());
+            drop(clone);
+            assert!(!is_mapped());
+        }
+    }
+
+    #[test]
+    fn create_and_load_mmapped_layers() {
+        let mut runtime = Runtime::new().unwrap();
+        let dir = tempdir().unwrap();
+        let store = DirectoryLayerStore::new(dir.path()).with_map_mode(FileMapMode::Mmap);
+
+        let layer = runtime
+            .block_on(async {
+                let mut builder = store.create_base_layer().await?;
+                let base_name = builder.name();
+                builder.add_string_triple(StringTriple::new_value("cow", "says", "moo"));
+                builder.add_string_triple(StringTriple::new_value("duck", "says", "quack"));
+                builder.commit_boxed().await?;
+
+                let mut builder = store.create_child_layer(base_name).await?;
+                let child_name = builder.name();
+                builder.remove_string_triple(StringTriple::new_value("duck", "says", "quack"));
+                builder.add_string_triple(StringTriple::new_node("cow", "likes", "pig"));
+                builder.commit_boxed().await?;
+
+                store.get_layer(child_name).await
+            })
+            .unwrap()
+            .unwrap();
+
+        assert!(layer.string_triple_exists(&StringTriple::new_value("cow", "says", "moo")));
+        assert!(layer.string_triple_exists(&StringTriple::new_node("cow", "likes", "pig")));
+        assert!(!layer.string_triple_exists(&StringTriple::new_value("duck", "says", "quack")));
+    }
+
     #[test]
     fn write_and_map_large_file_backed() {
         let dir = tempdir().unwrap();
@@ -578,6 +930,251 @@ mod tests {
         assert!(!layer.string_triple_exists(&StringTriple::new_value("duck", "says", "quack")));
     }
 
+    #[test]
+    fn corrupted_layer_files_are_detected() {
+        let mut runtime = Runtime::new().unwrap();
+        let dir = tempdir().unwrap();
+        let store = DirectoryLayerStore::new(dir.path()).with_checksum_verification(true);
+
+        let name = runtime
+            .block_on(async {
+                let mut builder = store.create_base_layer().await?;
+                let name = builder.name();
+                builder.add_string_triple(StringTriple::new_value("cow", "says", "moo"));
+                builder.add_string_triple(StringTriple::new_value("pig", "says", "oink"));
+                builder.commit_boxed().await?;
+
+                Ok::<_, io::Error>(name)
+            })
+            .unwrap();
+
+        assert!(runtime.block_on(store.get_layer(name)).unwrap().is_some());
+        let good_pack =
+            PersistentLayerStore::export_layers(&store, Box::new(vec![name].into_iter()));
+
+        let name_string = name_to_string(name);
+        let path = dir
+            .path()
+            .join(&name_string[0..PREFIX_DIR_SIZE])
+            .join(&name_string)
+            .join(FILENAMES.value_dictionary_blocks);
+        let mut data = std::fs::read(&path).unwrap();
+        data[0] ^= 1;
+        std::fs::write(&path, data).unwrap();
+
+        let error = runtime.block_on(store.get_layer(name)).err().unwrap();
+        assert_eq!(io::ErrorKind::InvalidData, error.kind());
+
+        let bad_pack =
+            PersistentLayerStore::export_layers(&store, Box::new(vec![name].into_iter()));
+
+        let dir2 = tempdir().unwrap();
+        let store2 = DirectoryLayerStore::new(dir2.path()).with_checksum_verification(true);
+        let error = PersistentLayerStore::import_layers(
+            &store2,
+            &bad_pack,
+            Box::new(vec![name].into_iter()),
+        )
+        .err()
+        .unwrap();
+        assert_eq!(io::ErrorKind::InvalidData, error.kind());
+        assert!(!runtime.block_on(store2.directory_exists(name)).unwrap());
+
+        PersistentLayerStore::import_layers(&store2, &good_pack, Box::new(vec![name].into_iter()))
+            .unwrap();
+        let layer = runtime.block_on(store2.get_layer(name)).unwrap().unwrap();
+        assert!(layer.string_triple_exists(&StringTriple::new_value("pig", "says", "oink")));
+    }
+
+    fn write_test_layers(
+        runtime: &mut Runtime,
+        store: &DirectoryLayerStore,
+    ) -> ([u32; 5], [u32; 5]) {
+        runtime
+            .block_on(async {
+                let mut builder = store.create_base_layer().await?;
+                let base_name = builder.name();
+                for i in 0..100 {
+                    builder.add_string_triple(StringTriple::new_value(
+                        &format!("node{}", i),
+                        "says",
+                        &format!("value{}", i),
+                    ));
+                }
+                builder.commit_boxed().await?;
+
+                let mut builder = store.create_child_layer(base_name).await?;
+                let name = builder.name();
+                builder.add_string_triple(StringTriple::new_node("node1", "likes", "node2"));
+                builder.commit_boxed().await?;
+
+                Ok::<_, io::Error>((base_name, name))
+            })
+            .unwrap()
+    }
+
+    fn layer_file_path(dir: &Path, name: [u32; 5], file: &str) -> PathBuf {
+        let name_string = name_to_string(name);
+        dir.join(&name_string[0..PREFIX_DIR_SIZE])
+            .join(&name_string)
+            .join(file)
+    }
+
+    #[test]
+    fn damaged_layer_files_fail_to_load() {
+        let mut runtime = Runtime::new().unwrap();
+        // each file, and whether it belongs to the child layer
+        let damaged_files = vec![
+            (FILENAMES.node_dictionary_blocks, false),
+            (FILENAMES.node_dictionary_offsets, false),
+            (FILENAMES.base_sp_o_adjacency_list_bits, false),
+            (
+                FILENAMES.base_predicate_wavelet_tree_bit_index_blocks,
+                false,
+            ),
+            (FILENAMES.pos_subjects, true),
+            (FILENAMES.pos_o_ps_adjacency_list_nums, true),
+        ];
+
+        for (file, in_child) in damaged_files {
+            let dir = tempdir().unwrap();
+            let store = DirectoryLayerStore::new(dir.path());
+            let (base_name, child_name) = write_test_layers(&mut runtime, &store);
+            let name = if in_child { child_name } else { base_name };
+
+            let path = layer_file_path(dir.path(), name, file);
+            let data = std::fs::read(&path).unwrap();
+            std::fs::write(&path, &data[..data.len() - 3]).unwrap();
+
+            let error = runtime.block_on(store.get_layer(name)).err().unwrap();
+            assert_eq!(io::ErrorKind::InvalidData, error.kind(), "{}", file);
+        }
+    }
+
+    #[test]
+    fn badly_encoded_dictionary_blocks_are_found_by_verification() {
+        let mut runtime = Runtime::new().unwrap();
+        let dir = tempdir().unwrap();
+        let store = DirectoryLayerStore::new(dir.path());
+        let (name, _) = write_test_layers(&mut runtime, &store);
+
+        let path = layer_file_path(dir.path(), name, FILENAMES.node_dictionary_blocks);
+        let mut data = std::fs::read(&path).unwrap();
+        data[2] ^= 0x80;
+        std::fs::write(&path, &data).unwrap();
+
+        // loading only checks the dictionary offsets, the blocks
+        // themselves are left to the verifier.
+        assert!(runtime.block_on(store.get_layer(name)).unwrap().is_some());
+
+        let verification = runtime.block_on(store.verify_layer(name)).unwrap().unwrap();
+        assert!(verification.issues.iter().any(|issue| matches!(
+            issue,
+            LayerIssue::Corrupt { file, .. } if *file == FILENAMES.node_dictionary_blocks
+        )));
+    }
+
+    #[test]
+    fn layers_are_staged_until_committed() {
+        let mut runtime = Runtime::new().unwrap();
+        let dir = tempdir().unwrap();
+        let store = DirectoryLayerStore::new(dir.path());
+
+        let (name, child_name) = runtime
+            .block_on(async {
+                let mut builder = store.create_base_layer().await?;
+                let name = builder.name();
+                builder.add_string_triple(StringTriple::new_value("cow", "says", "moo"));
+
+                assert!(store.staging_path(name).is_dir());
+                assert!(!store.directory_exists(name).await?);
+                assert!(store.get_layer(name).await?.is_none());
+
+                builder.commit_boxed().await?;
+
+                assert!(!store.staging_path(name).exists());
+                assert!(store.directory_exists(name).await?);
+                assert!(store.get_layer(name).await?.is_some());
+
+                let mut builder = store.create_child_layer(name).await?;
+                let child_name = builder.name();
+                builder.add_string_triple(StringTriple::new_value("pig", "says", "oink"));
+                std::mem::drop(builder);
+
+                assert!(store.staging_path(child_name).is_dir());
+                assert!(!store.directory_exists(child_name).await?);
+
+                // the store that started the layer still holds its lock
+                assert!(!store.remove_staging_directories()?);
+                assert!(store.staging_path(child_name).is_dir());
+
+                Ok::<_, io::Error>((name, child_name))
+            })
+            .unwrap();
+        std::mem::drop(store);
+
+        // simulate a restart after a crash
+        let store = DirectoryLayerStore::new(dir.path());
+        assert!(store.remove_staging_directories().unwrap());
+        assert!(!store.staging_path(child_name).exists());
+        assert!(runtime.block_on(store.get_layer(name)).unwrap().is_some());
+    }
+
+    #[test]
+    fn list_layers_in_prefix_directories() {
+        let mut runtime = Runtime::new().unwrap();
+        let dir = tempdir().unwrap();
+        let store = DirectoryLayerStore::new(dir.path());
+
+        let expected = runtime
+            .block_on(async {
+                let mut expected = Vec::new();
+                for _ in 0..3 {
+                    let mut builder = store.create_base_layer().await?;
+                    let base_name = builder.name();
+                    builder.add_string_triple(StringTriple::new_value("cow", "says", "moo"));
+                    builder.commit_boxed().await?;
+                    expected.push(LayerInfo {
+                        name: base_name,
+                        layer_type: LayerType::Base,
+                        parent: None,
+                    });
+
+                    let mut builder = store.create_child_layer(base_name).await?;
+                    let child_name = builder.name();
+                    builder.add_string_triple(StringTriple::new_value("pig", "says", "oink"));
+                    builder.commit_boxed().await?;
+                    expected.push(LayerInfo {
+                        name: child_name,
+                        layer_type: LayerType::Child,
+                        parent: Some(base_name),
+                    });
+                }
+
+                // an uncommitted layer and some unrelated files should not be listed
+                let mut builder = store.create_base_layer().await?;
+                builder.add_string_triple(StringTriple::new_value("duck", "says", "quack"));
+                std::fs::create_dir(dir.path().join("not_a_layer"))?;
+                std::fs::write(dir.path().join("abc"), b"not a layer either")?;
+
+                Ok::<_, io::Error>(expected)
+            })
+            .unwrap();
+
+        let mut expected_names: Vec<_> = expected.iter().map(|info| info.name).collect();
+        expected_names.sort();
+        let names = runtime.block_on(store.layers()).unwrap();
+        assert_eq!(expected_names, names);
+
+        let mut infos: Vec<_> = runtime
+            .block_on(store.layer_infos().try_collect::<Vec<_>>())
+            .unwrap();
+        infos.sort_by_key(|info| info.name);
+        let mut expected = expected;
+        expected.sort_by_key(|info| info.name);
+        assert_eq!(expected, infos);
+    }
+
     #[test]
     fn directory_create_and_retrieve_equal_label() {
         let dir = tempdir().unwrap();
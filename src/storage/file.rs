@@ -6,6 +6,9 @@ use futures::io;
 use std::pin::Pin;
 use tokio::io::{AsyncRead, AsyncWrite};
 
+use super::checksum;
+use super::consts::FILENAMES;
+
 pub trait FileStore: Clone + Send + Sync {
     type Write: AsyncWrite + Unpin + Send;
     fn open_write(&self) -> Self::Write {
@@ -77,6 +80,12 @@ pub struct BaseLayerFiles<F: 'static + FileLoad + FileStore> {
     pub o_ps_adjacency_list_files: AdjacencyListFiles<F>,
 
     pub predicate_wavelet_tree_files: BitIndexFiles<F>,
+
+    pub search_index_files: SearchIndexFiles<F>,
+
+    pub statistics_file: F,
+
+    pub checksums_file: F,
 }
 
 #[derive(Clone)]
@@ -96,9 +105,216 @@ pub struct BaseLayerMaps {
     pub o_ps_adjacency_list_maps: AdjacencyListMaps,
 
     pub predicate_wavelet_tree_maps: BitIndexMaps,
+
+    pub search_index_maps: Option<SearchIndexMaps>,
+
+    pub statistics_map: Option<Bytes>,
 }
 
 impl<F: FileLoad + FileStore> BaseLayerFiles<F> {
+    /// All files of this layer paired with their file name, excluding the checksum file.
+    pub fn named_files(&self) -> Vec<(&'static str, F)> {
+        vec![
+            (
+                FILENAMES.node_dictionary_blocks,
+                self.node_dictionary_files.blocks_file.clone(),
+            ),
+            (
+                FILENAMES.node_dictionary_offsets,
+                self.node_dictionary_files.offsets_file.clone(),
+            ),
+            (
+                FILENAMES.predicate_dictionary_blocks,
+                self.predicate_dictionary_files.blocks_file.clone(),
+            ),
+            (
+                FILENAMES.predicate_dictionary_offsets,
+                self.predicate_dictionary_files.offsets_file.clone(),
+            ),
+            (
+                FILENAMES.value_dictionary_blocks,
+                self.value_dictionary_files.blocks_file.clone(),
+            ),
+            (
+                FILENAMES.value_dictionary_offsets,
+                self.value_dictionary_files.offsets_file.clone(),
+            ),
+            (
+                FILENAMES.node_value_idmap_bits,
+                self.id_map_files.node_value_idmap_files.bits_file.clone(),
+            ),
+            (
+                FILENAMES.node_value_idmap_bit_index_blocks,
+                self.id_map_files.node_value_idmap_files.blocks_file.clone(),
+            ),
+            (
+                FILENAMES.node_value_idmap_bit_index_sblocks,
+                self.id_map_files
+                    .node_value_idmap_files
+                    .sblocks_file
+                    .clone(),
+            ),
+            (
+                FILENAMES.node_value_idmap_bit_index_select,
+                self.id_map_files.node_value_idmap_files.select_file.clone(),
+            ),
+            (
+                FILENAMES.predicate_idmap_bits,
+                self.id_map_files.predicate_idmap_files.bits_file.clone(),
+            ),
+            (
+                FILENAMES.predicate_idmap_bit_index_blocks,
+                self.id_map_files.predicate_idmap_files.blocks_file.clone(),
+            ),
+            (
+                FILENAMES.predicate_idmap_bit_index_sblocks,
+                self.id_map_files.predicate_idmap_files.sblocks_file.clone(),
+            ),
+            (
+                FILENAMES.predicate_idmap_bit_index_select,
+                self.id_map_files.predicate_idmap_files.select_file.clone(),
+            ),
+            (FILENAMES.base_subjects, self.subjects_file.clone()),
+            (FILENAMES.base_objects, self.objects_file.clone()),
+            (
+                FILENAMES.base_s_p_adjacency_list_nums,
+                self.s_p_adjacency_list_files.nums_file.clone(),
+            ),
+            (
+                FILENAMES.base_s_p_adjacency_list_bits,
+                self.s_p_adjacency_list_files
+                    .bitindex_files
+                    .bits_file
+                    .clone(),
+            ),
+            (
+                FILENAMES.base_s_p_adjacency_list_bit_index_blocks,
+                self.s_p_adjacency_list_files
+                    .bitindex_files
+                    .blocks_file
+                    .clone(),
+            ),
+            (
+                FILENAMES.base_s_p_adjacency_list_bit_index_sblocks,
+                self.s_p_adjacency_list_files
+                    .bitindex_files
+                    .sblocks_file
+                    .clone(),
+            ),
+            (
+                FILENAMES.base_s_p_adjacency_list_bit_index_select,
+                self.s_p_adjacency_list_files
+                    .bitindex_files
+                    .select_file
+                    .clone(),
+            ),
+            (
+                FILENAMES.base_sp_o_adjacency_list_nums,
+                self.sp_o_adjacency_list_files.nums_file.clone(),
+            ),
+            (
+                FILENAMES.base_sp_o_adjacency_list_bits,
+                self.sp_o_adjacency_list_files
+                    .bitindex_files
+                    .bits_file
+                    .clone(),
+            ),
+            (
+                FILENAMES.base_sp_o_adjacency_list_bit_index_blocks,
+                self.sp_o_adjacency_list_files
+                    .bitindex_files
+                    .blocks_file
+                    .clone(),
+            ),
+            (
+                FILENAMES.base_sp_o_adjacency_list_bit_index_sblocks,
+                self.sp_o_adjacency_list_files
+                    .bitindex_files
+                    .sblocks_file
+                    .clone(),
+            ),
+            (
+                FILENAMES.base_sp_o_adjacency_list_bit_index_select,
+                self.sp_o_adjacency_list_files
+                    .bitindex_files
+                    .select_file
+                    .clone(),
+            ),
+            (
+                FILENAMES.base_o_ps_adjacency_list_nums,
+                self.o_ps_adjacency_list_files.nums_file.clone(),
+            ),
+            (
+                FILENAMES.base_o_ps_adjacency_list_bits,
+                self.o_ps_adjacency_list_files
+                    .bitindex_files
+                    .bits_file
+                    .clone(),
+            ),
+            (
+                FILENAMES.base_o_ps_adjacency_list_bit_index_blocks,
+                self.o_ps_adjacency_list_files
+                    .bitindex_files
+                    .blocks_file
+                    .clone(),
+            ),
+            (
+                FILENAMES.base_o_ps_adjacency_list_bit_index_sblocks,
+                self.o_ps_adjacency_list_files
+                    .bitindex_files
+                    .sblocks_file
+                    .clone(),
+            ),
+            (
+                FILENAMES.base_o_ps_adjacency_list_bit_index_select,
+                self.o_ps_adjacency_list_files
+                    .bitindex_files
+                    .select_file
+                    .clone(),
+            ),
+            (
+                FILENAMES.base_predicate_wavelet_tree_bits,
+                self.predicate_wavelet_tree_files.bits_file.clone(),
+            ),
+            (
+                FILENAMES.base_predicate_wavelet_tree_bit_index_blocks,
+                self.predicate_wavelet_tree_files.blocks_file.clone(),
+            ),
+            (
+                FILENAMES.base_predicate_wavelet_tree_bit_index_sblocks,
+                self.predicate_wavelet_tree_files.sblocks_file.clone(),
+            ),
+            (
+                FILENAMES.base_predicate_wavelet_tree_bit_index_select,
+                self.predicate_wavelet_tree_files.select_file.clone(),
+            ),
+            (
+                FILENAMES.search_index_text,
+                self.search_index_files.text_file.clone(),
+            ),
+            (
+                FILENAMES.search_index_suffixes,
+                self.search_index_files.suffixes_file.clone(),
+            ),
+            (
+                FILENAMES.search_index_starts,
+                self.search_index_files.starts_file.clone(),
+            ),
+            (FILENAMES.statistics, self.statistics_file.clone()),
+        ]
+    }
+
+    /// Compute the checksums of all files of this layer and write them to the checksum file.
+    ///
+    /// This should be done after all other files have been written.
+    pub async fn write_checksums(&self) -> io::Result<()> {
+        checksum::write_checksums(&self.named_files(), &self.checksums_file).await
+    }
+
+    /// Check all files of this layer against the checksum file, if there is one.
+    pub async fn verify_checksums(&self) -> io::Result<()> {
+        checksum::verify_checksums(&self.named_files(), &self.checksums_file).await
+    }
     pub async fn map_all(&self) -> io::Result<BaseLayerMaps> {
         let node_dictionary_maps = self.node_dictionary_files.map_all().await?;
         let predicate_dictionary_maps = self.predicate_dictionary_files.map_all().await?;
@@ -115,6 +331,10 @@ impl<F: FileLoad + FileStore> BaseLayerFiles<F> {
 
         let predicate_wavelet_tree_maps = self.predicate_wavelet_tree_files.map_all().await?;
 
+        let search_index_maps = self.search_index_files.map_all_if_exists().await?;
+
+        let statistics_map = self.statistics_file.map_if_exists().await?;
+
         Ok(BaseLayerMaps {
             node_dictionary_maps,
             predicate_dictionary_maps,
@@ -130,6 +350,10 @@ impl<F: FileLoad + FileStore> BaseLayerFiles<F> {
             o_ps_adjacency_list_maps,
 
             predicate_wavelet_tree_maps,
+
+            search_index_maps,
+
+            statistics_map,
         })
     }
 }
@@ -156,6 +380,12 @@ pub struct ChildLayerFiles<F: 'static + FileLoad + FileStore + Clone + Send + Sy
 
     pub pos_predicate_wavelet_tree_files: BitIndexFiles<F>,
     pub neg_predicate_wavelet_tree_files: BitIndexFiles<F>,
+
+    pub search_index_files: SearchIndexFiles<F>,
+
+    pub statistics_file: F,
+
+    pub checksums_file: F,
 }
 
 #[derive(Clone)]
@@ -180,9 +410,330 @@ pub struct ChildLayerMaps {
 
     pub pos_predicate_wavelet_tree_maps: BitIndexMaps,
     pub neg_predicate_wavelet_tree_maps: BitIndexMaps,
+
+    pub search_index_maps: Option<SearchIndexMaps>,
+
+    pub statistics_map: Option<Bytes>,
 }
 
 impl<F: FileLoad + FileStore + Clone> ChildLayerFiles<F> {
+    /// All files of this layer paired with their file name, excluding the checksum file.
+    pub fn named_files(&self) -> Vec<(&'static str, F)> {
+        vec![
+            (
+                FILENAMES.node_dictionary_blocks,
+                self.node_dictionary_files.blocks_file.clone(),
+            ),
+            (
+                FILENAMES.node_dictionary_offsets,
+                self.node_dictionary_files.offsets_file.clone(),
+            ),
+            (
+                FILENAMES.predicate_dictionary_blocks,
+                self.predicate_dictionary_files.blocks_file.clone(),
+            ),
+            (
+                FILENAMES.predicate_dictionary_offsets,
+                self.predicate_dictionary_files.offsets_file.clone(),
+            ),
+            (
+                FILENAMES.value_dictionary_blocks,
+                self.value_dictionary_files.blocks_file.clone(),
+            ),
+            (
+                FILENAMES.value_dictionary_offsets,
+                self.value_dictionary_files.offsets_file.clone(),
+            ),
+            (
+                FILENAMES.node_value_idmap_bits,
+                self.id_map_files.node_value_idmap_files.bits_file.clone(),
+            ),
+            (
+                FILENAMES.node_value_idmap_bit_index_blocks,
+                self.id_map_files.node_value_idmap_files.blocks_file.clone(),
+            ),
+            (
+                FILENAMES.node_value_idmap_bit_index_sblocks,
+                self.id_map_files
+                    .node_value_idmap_files
+                    .sblocks_file
+                    .clone(),
+            ),
+            (
+                FILENAMES.node_value_idmap_bit_index_select,
+                self.id_map_files.node_value_idmap_files.select_file.clone(),
+            ),
+            (
+                FILENAMES.predicate_idmap_bits,
+                self.id_map_files.predicate_idmap_files.bits_file.clone(),
+            ),
+            (
+                FILENAMES.predicate_idmap_bit_index_blocks,
+                self.id_map_files.predicate_idmap_files.blocks_file.clone(),
+            ),
+            (
+                FILENAMES.predicate_idmap_bit_index_sblocks,
+                self.id_map_files.predicate_idmap_files.sblocks_file.clone(),
+            ),
+            (
+                FILENAMES.predicate_idmap_bit_index_select,
+                self.id_map_files.predicate_idmap_files.select_file.clone(),
+            ),
+            (FILENAMES.pos_subjects, self.pos_subjects_file.clone()),
+            (FILENAMES.pos_objects, self.pos_objects_file.clone()),
+            (FILENAMES.neg_subjects, self.neg_subjects_file.clone()),
+            (FILENAMES.neg_objects, self.neg_objects_file.clone()),
+            (
+                FILENAMES.pos_s_p_adjacency_list_nums,
+                self.pos_s_p_adjacency_list_files.nums_file.clone(),
+            ),
+            (
+                FILENAMES.pos_s_p_adjacency_list_bits,
+                self.pos_s_p_adjacency_list_files
+                    .bitindex_files
+                    .bits_file
+                    .clone(),
+            ),
+            (
+                FILENAMES.pos_s_p_adjacency_list_bit_index_blocks,
+                self.pos_s_p_adjacency_list_files
+                    .bitindex_files
+                    .blocks_file
+                    .clone(),
+            ),
+            (
+                FILENAMES.pos_s_p_adjacency_list_bit_index_sblocks,
+                self.pos_s_p_adjacency_list_files
+                    .bitindex_files
+                    .sblocks_file
+                    .clone(),
+            ),
+            (
+                FILENAMES.pos_s_p_adjacency_list_bit_index_select,
+                self.pos_s_p_adjacency_list_files
+                    .bitindex_files
+                    .select_file
+                    .clone(),
+            ),
+            (
+                FILENAMES.pos_sp_o_adjacency_list_nums,
+                self.pos_sp_o_adjacency_list_files.nums_file.clone(),
+            ),
+            (
+                FILENAMES.pos_sp_o_adjacency_list_bits,
+                self.pos_sp_o_adjacency_list_files
+                    .bitindex_files
+                    .bits_file
+                    .clone(),
+            ),
+            (
+                FILENAMES.pos_sp_o_adjacency_list_bit_index_blocks,
+                self.pos_sp_o_adjacency_list_files
+                    .bitindex_files
+                    .blocks_file
+                    .clone(),
+            ),
+            (
+                FILENAMES.pos_sp_o_adjacency_list_bit_index_sblocks,
+                self.pos_sp_o_adjacency_list_files
+                    .bitindex_files
+                    .sblocks_file
+                    .clone(),
+            ),
+            (
+                FILENAMES.pos_sp_o_adjacency_list_bit_index_select,
+                self.pos_sp_o_adjacency_list_files
+                    .bitindex_files
+                    .select_file
+                    .clone(),
+            ),
+            (
+                FILENAMES.pos_o_ps_adjacency_list_nums,
+                self.pos_o_ps_adjacency_list_files.nums_file.clone(),
+            ),
+            (
+                FILENAMES.pos_o_ps_adjacency_list_bits,
+                self.pos_o_ps_adjacency_list_files
+                    .bitindex_files
+                    .bits_file
+                    .clone(),
+            ),
+            (
+                FILENAMES.pos_o_ps_adjacency_list_bit_index_blocks,
+                self.pos_o_ps_adjacency_list_files
+                    .bitindex_files
+                    .blocks_file
+                    .clone(),
+            ),
+            (
+                FILENAMES.pos_o_ps_adjacency_list_bit_index_sblocks,
+                self.pos_o_ps_adjacency_list_files
+                    .bitindex_files
+                    .sblocks_file
+                    .clone(),
+            ),
+            (
+                FILENAMES.pos_o_ps_adjacency_list_bit_index_select,
+                self.pos_o_ps_adjacency_list_files
+                    .bitindex_files
+                    .select_file
+                    .clone(),
+            ),
+            (
+                FILENAMES.neg_s_p_adjacency_list_nums,
+                self.neg_s_p_adjacency_list_files.nums_file.clone(),
+            ),
+            (
+                FILENAMES.neg_s_p_adjacency_list_bits,
+                self.neg_s_p_adjacency_list_files
+                    .bitindex_files
+                    .bits_file
+                    .clone(),
+            ),
+            (
+                FILENAMES.neg_s_p_adjacency_list_bit_index_blocks,
+                self.neg_s_p_adjacency_list_files
+                    .bitindex_files
+                    .blocks_file
+                    .clone(),
+            ),
+            (
+                FILENAMES.neg_s_p_adjacency_list_bit_index_sblocks,
+                self.neg_s_p_adjacency_list_files
+                    .bitindex_files
+                    .sblocks_file
+                    .clone(),
+            ),
+            (
+                FILENAMES.neg_s_p_adjacency_list_bit_index_select,
+                self.neg_s_p_adjacency_list_files
+                    .bitindex_files
+                    .select_file
+                    .clone(),
+            ),
+            (
+                FILENAMES.neg_sp_o_adjacency_list_nums,
+                self.neg_sp_o_adjacency_list_files.nums_file.clone(),
+            ),
+            (
+                FILENAMES.neg_sp_o_adjacency_list_bits,
+                self.neg_sp_o_adjacency_list_files
+                    .bitindex_files
+                    .bits_file
+                    .clone(),
+            ),
+            (
+                FILENAMES.neg_sp_o_adjacency_list_bit_index_blocks,
+                self.neg_sp_o_adjacency_list_files
+                    .bitindex_files
+                    .blocks_file
+                    .clone(),
+            ),
+            (
+                FILENAMES.neg_sp_o_adjacency_list_bit_index_sblocks,
+                self.neg_sp_o_adjacency_list_files
+                    .bitindex_files
+                    .sblocks_file
+                    .clone(),
+            ),
+            (
+                FILENAMES.neg_sp_o_adjacency_list_bit_index_select,
+                self.neg_sp_o_adjacency_list_files
+                    .bitindex_files
+                    .select_file
+                    .clone(),
+            ),
+            (
+                FILENAMES.neg_o_ps_adjacency_list_nums,
+                self.neg_o_ps_adjacency_list_files.nums_file.clone(),
+            ),
+            (
+                FILENAMES.neg_o_ps_adjacency_list_bits,
+                self.neg_o_ps_adjacency_list_files
+                    .bitindex_files
+                    .bits_file
+                    .clone(),
+            ),
+            (
+                FILENAMES.neg_o_ps_adjacency_list_bit_index_blocks,
+                self.neg_o_ps_adjacency_list_files
+                    .bitindex_files
+                    .blocks_file
+                    .clone(),
+            ),
+            (
+                FILENAMES.neg_o_ps_adjacency_list_bit_index_sblocks,
+                self.neg_o_ps_adjacency_list_files
+                    .bitindex_files
+                    .sblocks_file
+                    .clone(),
+            ),
+            (
+                FILENAMES.neg_o_ps_adjacency_list_bit_index_select,
+                self.neg_o_ps_adjacency_list_files
+                    .bitindex_files
+                    .select_file
+                    .clone(),
+            ),
+            (
+                FILENAMES.pos_predicate_wavelet_tree_bits,
+                self.pos_predicate_wavelet_tree_files.bits_file.clone(),
+            ),
+            (
+                FILENAMES.pos_predicate_wavelet_tree_bit_index_blocks,
+                self.pos_predicate_wavelet_tree_files.blocks_file.clone(),
+            ),
+            (
+                FILENAMES.pos_predicate_wavelet_tree_bit_index_sblocks,
+                self.pos_predicate_wavelet_tree_files.sblocks_file.clone(),
+            ),
+            (
+                FILENAMES.pos_predicate_wavelet_tree_bit_index_select,
+                self.pos_predicate_wavelet_tree_files.select_file.clone(),
+            ),
+            (
+                FILENAMES.neg_predicate_wavelet_tree_bits,
+                self.neg_predicate_wavelet_tree_files.bits_file.clone(),
+            ),
+            (
+                FILENAMES.neg_predicate_wavelet_tree_bit_index_blocks,
+                self.neg_predicate_wavelet_tree_files.blocks_file.clone(),
+            ),
+            (
+                FILENAMES.neg_predicate_wavelet_tree_bit_index_sblocks,
+                self.neg_predicate_wavelet_tree_files.sblocks_file.clone(),
+            ),
+            (
+                FILENAMES.neg_predicate_wavelet_tree_bit_index_select,
+                self.neg_predicate_wavelet_tree_files.select_file.clone(),
+            ),
+            (
+                FILENAMES.search_index_text,
+                self.search_index_files.text_file.clone(),
+            ),
+            (
+                FILENAMES.search_index_suffixes,
+                self.search_index_files.suffixes_file.clone(),
+            ),
+            (
+                FILENAMES.search_index_starts,
+                self.search_index_files.starts_file.clone(),
+            ),
+            (FILENAMES.statistics, self.statistics_file.clone()),
+        ]
+    }
+
+    /// Compute the checksums of all files of this layer and write them to the checksum file.
+    ///
+    /// This should be done after all other files have been written.
+    pub async fn write_checksums(&self) -> io::Result<()> {
+        checksum::write_checksums(&self.named_files(), &self.checksums_file).await
+    }
+
+    /// Check all files of this layer against the checksum file, if there is one.
+    pub async fn verify_checksums(&self) -> io::Result<()> {
+        checksum::verify_checksums(&self.named_files(), &self.checksums_file).await
+    }
     pub async fn map_all(&self) -> io::Result<ChildLayerMaps> {
         let node_dictionary_maps = self.node_dictionary_files.map_all().await?;
         let predicate_dictionary_maps = self.predicate_dictionary_files.map_all().await?;
@@ -208,6 +759,10 @@ impl<F: FileLoad + FileStore + Clone> ChildLayerFiles<F> {
         let neg_predicate_wavelet_tree_maps =
             self.neg_predicate_wavelet_tree_files.map_all().await?;
 
+        let search_index_maps = self.search_index_files.map_all_if_exists().await?;
+
+        let statistics_map = self.statistics_file.map_if_exists().await?;
+
         Ok(ChildLayerMaps {
             node_dictionary_maps,
             predicate_dictionary_maps,
@@ -229,6 +784,10 @@ impl<F: FileLoad + FileStore + Clone> ChildLayerFiles<F> {
 
             pos_predicate_wavelet_tree_maps,
             neg_predicate_wavelet_tree_maps,
+
+            search_index_maps,
+
+            statistics_map,
         })
     }
 }
@@ -287,6 +846,7 @@ pub struct BitIndexMaps {
     pub bits_map: Bytes,
     pub blocks_map: Bytes,
     pub sblocks_map: Bytes,
+    pub select_map: Option<Bytes>,
 }
 
 #[derive(Clone)]
@@ -294,6 +854,8 @@ pub struct BitIndexFiles<F: 'static + FileLoad> {
     pub bits_file: F,
     pub blocks_file: F,
     pub sblocks_file: F,
+    /// The optional sampled select index (see `SelectIndex`).
+    pub select_file: F,
 }
 
 impl<F: 'static + FileLoad + FileStore> BitIndexFiles<F> {
@@ -301,11 +863,13 @@ impl<F: 'static + FileLoad + FileStore> BitIndexFiles<F> {
         let bits_map = self.bits_file.map().await?;
         let blocks_map = self.blocks_file.map().await?;
         let sblocks_map = self.sblocks_file.map().await?;
+        let select_map = self.select_file.map_if_exists().await?;
 
         Ok(BitIndexMaps {
             bits_map,
             blocks_map,
             sblocks_map,
+            select_map,
         })
     }
 
@@ -318,6 +882,39 @@ impl<F: 'static + FileLoad + FileStore> BitIndexFiles<F> {
     }
 }
 
+#[derive(Clone)]
+pub struct SearchIndexMaps {
+    pub text_map: Bytes,
+    pub suffixes_map: Bytes,
+    pub starts_map: Bytes,
+}
+
+/// The files of the optional full-text search index of a layer.
+#[derive(Clone)]
+pub struct SearchIndexFiles<F: 'static + FileLoad> {
+    pub text_file: F,
+    pub suffixes_file: F,
+    pub starts_file: F,
+}
+
+impl<F: 'static + FileLoad + FileStore> SearchIndexFiles<F> {
+    pub async fn map_all_if_exists(&self) -> io::Result<Option<SearchIndexMaps>> {
+        if self.starts_file.exists() {
+            let text_map = self.text_file.map().await?;
+            let suffixes_map = self.suffixes_file.map().await?;
+            let starts_map = self.starts_file.map().await?;
+
+            Ok(Some(SearchIndexMaps {
+                text_map,
+                suffixes_map,
+                starts_map,
+            }))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct AdjacencyListMaps {
     pub bitindex_maps: BitIndexMaps,
@@ -1,16 +1,20 @@
 use super::consts::FILENAMES;
 use super::file::*;
+use super::verify::*;
 use crate::layer::{
-    BaseLayer, ChildLayer, InternalLayer, Layer, LayerBuilder, LayerType, SimpleLayerBuilder,
+    BaseLayer, ChildLayer, IdTriple, InternalLayer, Layer, LayerBuilder, LayerOptions, LayerType,
+    SimpleLayerBuilder, StringTriple,
 };
 use std::io;
-use std::sync::{Arc, Weak};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Weak};
 
 use futures::future::{self, Future};
+use futures::stream::{self, Stream, TryStreamExt};
 use std::sync::RwLock;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::pin::Pin;
 
 pub trait LayerCache: 'static + Send + Sync {
@@ -32,8 +36,22 @@ lazy_static! {
     static ref NOCACHE: Arc<dyn LayerCache> = Arc::new(NoCache);
 }
 
+/// The name, type and parent of a layer in a layer store.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LayerInfo {
+    pub name: [u32; 5],
+    pub layer_type: LayerType,
+    pub parent: Option<[u32; 5]>,
+}
+
+/// A future resolving to a builder for a new layer.
+pub type LayerBuilderFuture =
+    Pin<Box<dyn Future<Output = io::Result<Box<dyn LayerBuilder>>> + Send>>;
+
 pub trait LayerStore: 'static + Send + Sync {
     fn layers(&self) -> Pin<Box<dyn Future<Output = io::Result<Vec<[u32; 5]>>> + Send>>;
+    /// Stream the name, type and parent of every layer in this store, without loading the layers themselves.
+    fn layer_infos(&self) -> Pin<Box<dyn Stream<Item = io::Result<LayerInfo>> + Send>>;
     fn get_layer_with_cache(
         &self,
         name: [u32; 5],
@@ -46,20 +64,35 @@ pub trait LayerStore: 'static + Send + Sync {
         self.get_layer_with_cache(name, NOCACHE.clone())
     }
 
-    fn create_base_layer(
-        &self,
-    ) -> Pin<Box<dyn Future<Output = io::Result<Box<dyn LayerBuilder>>> + Send>>;
+    fn create_base_layer(&self) -> LayerBuilderFuture {
+        self.create_base_layer_with_options(LayerOptions::default())
+    }
+    /// Create a builder for a new base layer, which will be built according to the given options.
+    fn create_base_layer_with_options(&self, options: LayerOptions) -> LayerBuilderFuture;
     fn create_child_layer_with_cache(
         &self,
         parent: [u32; 5],
         cache: Arc<dyn LayerCache>,
-    ) -> Pin<Box<dyn Future<Output = io::Result<Box<dyn LayerBuilder>>> + Send>>;
-    fn create_child_layer(
+    ) -> LayerBuilderFuture {
+        self.create_child_layer_with_cache_and_options(parent, cache, LayerOptions::default())
+    }
+    /// Create a builder for a new child layer, which will be built according to the given options.
+    fn create_child_layer_with_cache_and_options(
         &self,
         parent: [u32; 5],
-    ) -> Pin<Box<dyn Future<Output = io::Result<Box<dyn LayerBuilder>>> + Send>> {
+        cache: Arc<dyn LayerCache>,
+        options: LayerOptions,
+    ) -> LayerBuilderFuture;
+    fn create_child_layer(&self, parent: [u32; 5]) -> LayerBuilderFuture {
         self.create_child_layer_with_cache(parent, NOCACHE.clone())
     }
+    fn create_child_layer_with_options(
+        &self,
+        parent: [u32; 5],
+        options: LayerOptions,
+    ) -> LayerBuilderFuture {
+        self.create_child_layer_with_cache_and_options(parent, NOCACHE.clone(), options)
+    }
 
     fn export_layers(&self, layer_ids: Box<dyn Iterator<Item = [u32; 5]>>) -> Vec<u8>;
     fn import_layers(
@@ -73,12 +106,31 @@ pub trait LayerStore: 'static + Send + Sync {
         descendant: [u32; 5],
         ancestor: [u32; 5],
     ) -> Pin<Box<dyn Future<Output = io::Result<bool>> + Send>>;
+
+    /// Check the files of the given layer for missing or corrupt data, returning None if the layer does not exist.
+    ///
+    /// Unlike `get_layer`, this reads every file in full, so it also
+    /// finds damage that loading a layer doesn't look for.
+    fn verify_layer(
+        &self,
+        name: [u32; 5],
+    ) -> Pin<Box<dyn Future<Output = io::Result<Option<LayerVerification>>> + Send>>;
 }
 
 pub trait PersistentLayerStore: 'static + Send + Sync + Clone {
     type File: FileLoad + FileStore + Clone;
     fn directories(&self) -> Pin<Box<dyn Future<Output = io::Result<Vec<[u32; 5]>>> + Send>>;
+    /// Create a directory for a new layer.
+    ///
+    /// Files can be written into this directory right away, but it
+    /// should not be visible through `directories` or
+    /// `directory_exists` until `finalize_directory` is called.
     fn create_directory(&self) -> Pin<Box<dyn Future<Output = io::Result<[u32; 5]>> + Send>>;
+    /// Make a directory created with `create_directory` visible, once all its files have been written.
+    fn finalize_directory(
+        &self,
+        name: [u32; 5],
+    ) -> Pin<Box<dyn Future<Output = io::Result<()>> + Send>>;
     fn export_layers(&self, layer_ids: Box<dyn Iterator<Item = [u32; 5]>>) -> Vec<u8>;
     fn import_layers(
         &self,
@@ -101,6 +153,13 @@ pub trait PersistentLayerStore: 'static + Send + Sync + Clone {
         file: &str,
     ) -> Pin<Box<dyn Future<Output = io::Result<bool>> + Send>>;
 
+    /// Whether layer files should be checked against their checksums when a layer is loaded.
+    ///
+    /// This requires reading every file of a layer an extra time, so it is off by default.
+    fn verify_checksums_on_load(&self) -> bool {
+        false
+    }
+
     fn layer_type(
         &self,
         name: [u32; 5],
@@ -151,6 +210,17 @@ pub trait PersistentLayerStore: 'static + Send + Sync + Clone {
                 FILENAMES.base_predicate_wavelet_tree_bits,
                 FILENAMES.base_predicate_wavelet_tree_bit_index_blocks,
                 FILENAMES.base_predicate_wavelet_tree_bit_index_sblocks,
+                FILENAMES.checksums,
+                FILENAMES.search_index_text,
+                FILENAMES.search_index_suffixes,
+                FILENAMES.search_index_starts,
+                FILENAMES.node_value_idmap_bit_index_select,
+                FILENAMES.predicate_idmap_bit_index_select,
+                FILENAMES.base_s_p_adjacency_list_bit_index_select,
+                FILENAMES.base_sp_o_adjacency_list_bit_index_select,
+                FILENAMES.base_o_ps_adjacency_list_bit_index_select,
+                FILENAMES.base_predicate_wavelet_tree_bit_index_select,
+                FILENAMES.statistics,
             ];
 
             let mut files = Vec::with_capacity(filenames.len());
@@ -178,11 +248,13 @@ pub trait PersistentLayerStore: 'static + Send + Sync + Clone {
                         bits_file: files[6].clone(),
                         blocks_file: files[7].clone(),
                         sblocks_file: files[8].clone(),
+                        select_file: files[33].clone(),
                     },
                     predicate_idmap_files: BitIndexFiles {
                         bits_file: files[9].clone(),
                         blocks_file: files[10].clone(),
                         sblocks_file: files[11].clone(),
+                        select_file: files[34].clone(),
                     },
                 },
 
@@ -194,6 +266,7 @@ pub trait PersistentLayerStore: 'static + Send + Sync + Clone {
                         bits_file: files[14].clone(),
                         blocks_file: files[15].clone(),
                         sblocks_file: files[16].clone(),
+                        select_file: files[35].clone(),
                     },
                     nums_file: files[17].clone(),
                 },
@@ -202,6 +275,7 @@ pub trait PersistentLayerStore: 'static + Send + Sync + Clone {
                         bits_file: files[18].clone(),
                         blocks_file: files[19].clone(),
                         sblocks_file: files[20].clone(),
+                        select_file: files[36].clone(),
                     },
                     nums_file: files[21].clone(),
                 },
@@ -210,6 +284,7 @@ pub trait PersistentLayerStore: 'static + Send + Sync + Clone {
                         bits_file: files[22].clone(),
                         blocks_file: files[23].clone(),
                         sblocks_file: files[24].clone(),
+                        select_file: files[37].clone(),
                     },
                     nums_file: files[25].clone(),
                 },
@@ -217,7 +292,18 @@ pub trait PersistentLayerStore: 'static + Send + Sync + Clone {
                     bits_file: files[26].clone(),
                     blocks_file: files[27].clone(),
                     sblocks_file: files[28].clone(),
+                    select_file: files[38].clone(),
                 },
+
+                search_index_files: SearchIndexFiles {
+                    text_file: files[30].clone(),
+                    suffixes_file: files[31].clone(),
+                    starts_file: files[32].clone(),
+                },
+
+                statistics_file: files[39].clone(),
+
+                checksums_file: files[29].clone(),
             })
         })
     }
@@ -276,6 +362,21 @@ pub trait PersistentLayerStore: 'static + Send + Sync + Clone {
                 FILENAMES.neg_predicate_wavelet_tree_bits,
                 FILENAMES.neg_predicate_wavelet_tree_bit_index_blocks,
                 FILENAMES.neg_predicate_wavelet_tree_bit_index_sblocks,
+                FILENAMES.checksums,
+                FILENAMES.search_index_text,
+                FILENAMES.search_index_suffixes,
+                FILENAMES.search_index_starts,
+                FILENAMES.node_value_idmap_bit_index_select,
+                FILENAMES.predicate_idmap_bit_index_select,
+                FILENAMES.pos_s_p_adjacency_list_bit_index_select,
+                FILENAMES.pos_sp_o_adjacency_list_bit_index_select,
+                FILENAMES.pos_o_ps_adjacency_list_bit_index_select,
+                FILENAMES.neg_s_p_adjacency_list_bit_index_select,
+                FILENAMES.neg_sp_o_adjacency_list_bit_index_select,
+                FILENAMES.neg_o_ps_adjacency_list_bit_index_select,
+                FILENAMES.pos_predicate_wavelet_tree_bit_index_select,
+                FILENAMES.neg_predicate_wavelet_tree_bit_index_select,
+                FILENAMES.statistics,
             ];
 
             let mut files = Vec::with_capacity(filenames.len());
@@ -302,11 +403,13 @@ pub trait PersistentLayerStore: 'static + Send + Sync + Clone {
                         bits_file: files[6].clone(),
                         blocks_file: files[7].clone(),
                         sblocks_file: files[8].clone(),
+                        select_file: files[50].clone(),
                     },
                     predicate_idmap_files: BitIndexFiles {
                         bits_file: files[9].clone(),
                         blocks_file: files[10].clone(),
                         sblocks_file: files[11].clone(),
+                        select_file: files[51].clone(),
                     },
                 },
 
@@ -320,6 +423,7 @@ pub trait PersistentLayerStore: 'static + Send + Sync + Clone {
                         bits_file: files[16].clone(),
                         blocks_file: files[17].clone(),
                         sblocks_file: files[18].clone(),
+                        select_file: files[52].clone(),
                     },
                     nums_file: files[19].clone(),
                 },
@@ -328,6 +432,7 @@ pub trait PersistentLayerStore: 'static + Send + Sync + Clone {
                         bits_file: files[20].clone(),
                         blocks_file: files[21].clone(),
                         sblocks_file: files[22].clone(),
+                        select_file: files[53].clone(),
                     },
                     nums_file: files[23].clone(),
                 },
@@ -336,6 +441,7 @@ pub trait PersistentLayerStore: 'static + Send + Sync + Clone {
                         bits_file: files[24].clone(),
                         blocks_file: files[25].clone(),
                         sblocks_file: files[26].clone(),
+                        select_file: files[54].clone(),
                     },
                     nums_file: files[27].clone(),
                 },
@@ -344,6 +450,7 @@ pub trait PersistentLayerStore: 'static + Send + Sync + Clone {
                         bits_file: files[28].clone(),
                         blocks_file: files[29].clone(),
                         sblocks_file: files[30].clone(),
+                        select_file: files[55].clone(),
                     },
                     nums_file: files[31].clone(),
                 },
@@ -352,6 +459,7 @@ pub trait PersistentLayerStore: 'static + Send + Sync + Clone {
                         bits_file: files[32].clone(),
                         blocks_file: files[33].clone(),
                         sblocks_file: files[34].clone(),
+                        select_file: files[56].clone(),
                     },
                     nums_file: files[35].clone(),
                 },
@@ -360,6 +468,7 @@ pub trait PersistentLayerStore: 'static + Send + Sync + Clone {
                         bits_file: files[36].clone(),
                         blocks_file: files[37].clone(),
                         sblocks_file: files[38].clone(),
+                        select_file: files[57].clone(),
                     },
                     nums_file: files[39].clone(),
                 },
@@ -367,12 +476,24 @@ pub trait PersistentLayerStore: 'static + Send + Sync + Clone {
                     bits_file: files[40].clone(),
                     blocks_file: files[41].clone(),
                     sblocks_file: files[42].clone(),
+                    select_file: files[58].clone(),
                 },
                 neg_predicate_wavelet_tree_files: BitIndexFiles {
                     bits_file: files[43].clone(),
                     blocks_file: files[44].clone(),
                     sblocks_file: files[45].clone(),
+                    select_file: files[59].clone(),
+                },
+
+                search_index_files: SearchIndexFiles {
+                    text_file: files[47].clone(),
+                    suffixes_file: files[48].clone(),
+                    starts_file: files[49].clone(),
                 },
+
+                statistics_file: files[60].clone(),
+
+                checksums_file: files[46].clone(),
             })
         })
     }
@@ -479,6 +600,31 @@ impl<F: 'static + FileLoad + FileStore + Clone, T: 'static + PersistentLayerStor
         self.directories()
     }
 
+    fn layer_infos(&self) -> Pin<Box<dyn Stream<Item = io::Result<LayerInfo>> + Send>> {
+        let self_ = self.clone();
+        Box::pin(
+            stream::once(self.directories())
+                .map_ok(|names| stream::iter(names.into_iter().map(Ok)))
+                .try_flatten()
+                .and_then(move |name| {
+                    let self_ = self_.clone();
+                    async move {
+                        let layer_type = self_.layer_type(name).await?;
+                        let parent = match layer_type {
+                            LayerType::Base => None,
+                            LayerType::Child => Some(self_.read_parent_file(name).await?),
+                        };
+
+                        Ok(LayerInfo {
+                            name,
+                            layer_type,
+                            parent,
+                        })
+                    }
+                }),
+        )
+    }
+
     fn get_layer_with_cache(
         &self,
         name: [u32; 5],
@@ -524,6 +670,9 @@ impl<F: 'static + FileLoad + FileStore + Clone, T: 'static + PersistentLayerStor
                 // load the base layer
                 let base_id = layers_to_load.pop().unwrap();
                 let files = self_.base_layer_files(base_id).await?;
+                if self_.verify_checksums_on_load() {
+                    files.verify_checksums().await?;
+                }
                 let base_layer: Arc<InternalLayer> =
                     Arc::new(BaseLayer::load_from_files(base_id, &files).await?.into());
 
@@ -536,6 +685,9 @@ impl<F: 'static + FileLoad + FileStore + Clone, T: 'static + PersistentLayerStor
 
             for layer_id in layers_to_load {
                 let files = self_.child_layer_files(layer_id).await?;
+                if self_.verify_checksums_on_load() {
+                    files.verify_checksums().await?;
+                }
                 let child_layer: Arc<InternalLayer> = Arc::new(
                     ChildLayer::load_from_files(layer_id, ancestor, &files)
                         .await?
@@ -550,22 +702,24 @@ impl<F: 'static + FileLoad + FileStore + Clone, T: 'static + PersistentLayerStor
         })
     }
 
-    fn create_base_layer(
-        &self,
-    ) -> Pin<Box<dyn Future<Output = io::Result<Box<dyn LayerBuilder>>> + Send>> {
+    fn create_base_layer_with_options(&self, options: LayerOptions) -> LayerBuilderFuture {
         let self_ = self.clone();
         Box::pin(async move {
             let dir_name = self_.create_directory().await?;
             let files = self_.base_layer_files(dir_name).await?;
-            Ok(Box::new(SimpleLayerBuilder::new(dir_name, files)) as Box<dyn LayerBuilder>)
+            let builder = Box::new(SimpleLayerBuilder::new(dir_name, files).with_options(options));
+            Ok(Box::new(FinalizingLayerBuilder::new(builder, move || {
+                self_.finalize_directory(dir_name)
+            })) as Box<dyn LayerBuilder>)
         })
     }
 
-    fn create_child_layer_with_cache(
+    fn create_child_layer_with_cache_and_options(
         &self,
         parent: [u32; 5],
         cache: Arc<dyn LayerCache>,
-    ) -> Pin<Box<dyn Future<Output = io::Result<Box<dyn LayerBuilder>>> + Send>> {
+        options: LayerOptions,
+    ) -> LayerBuilderFuture {
         let self_ = self.clone();
         Box::pin(async move {
             let parent_layer = match self_.get_layer_with_cache(parent, cache).await? {
@@ -581,11 +735,13 @@ impl<F: 'static + FileLoad + FileStore + Clone, T: 'static + PersistentLayerStor
             let layer_dir = self_.create_directory().await?;
             self_.write_parent_file(layer_dir, parent).await?;
             let child_layer_files = self_.child_layer_files(layer_dir).await?;
-            Ok(Box::new(SimpleLayerBuilder::from_parent(
-                layer_dir,
-                parent_layer,
-                child_layer_files,
-            )) as Box<dyn LayerBuilder>)
+            let builder = Box::new(
+                SimpleLayerBuilder::from_parent(layer_dir, parent_layer, child_layer_files)
+                    .with_options(options),
+            );
+            Ok(Box::new(FinalizingLayerBuilder::new(builder, move || {
+                self_.finalize_directory(layer_dir)
+            })) as Box<dyn LayerBuilder>)
         })
     }
 
@@ -626,6 +782,113 @@ impl<F: 'static + FileLoad + FileStore + Clone, T: 'static + PersistentLayerStor
             }
         })
     }
+
+    fn verify_layer(
+        &self,
+        name: [u32; 5],
+    ) -> Pin<Box<dyn Future<Output = io::Result<Option<LayerVerification>>> + Send>> {
+        let self_ = self.clone();
+        Box::pin(async move {
+            if !self_.directory_exists(name).await? {
+                return Ok(None);
+            }
+
+            let layer_type = self_.layer_type(name).await?;
+            let mut parent = None;
+            let mut issues = Vec::new();
+            match layer_type {
+                LayerType::Base => {
+                    let files = self_.base_layer_files(name).await?;
+                    issues.extend(verify_base_layer_files(&files).await);
+                }
+                LayerType::Child => {
+                    match self_.read_parent_file(name).await {
+                        Ok(parent_name) => {
+                            parent = Some(parent_name);
+                            if !self_.directory_exists(parent_name).await? {
+                                issues.push(LayerIssue::MissingParent(parent_name));
+                            }
+                        }
+                        Err(e) => issues.push(LayerIssue::Corrupt {
+                            file: FILENAMES.parent,
+                            reason: e.to_string(),
+                        }),
+                    }
+
+                    let files = self_.child_layer_files(name).await?;
+                    issues.extend(verify_child_layer_files(&files).await);
+                }
+            }
+
+            Ok(Some(LayerVerification {
+                name,
+                layer_type,
+                parent,
+                issues,
+            }))
+        })
+    }
+}
+
+/// A layer builder that runs a finalization step once the layer it wraps has been committed.
+///
+/// Layer stores use this to only make a layer visible after all its files have been written.
+pub(crate) struct FinalizingLayerBuilder {
+    builder: Box<dyn LayerBuilder>,
+    finalize: Box<dyn FnOnce() -> FinalizeFuture + Send + Sync>,
+}
+
+type FinalizeFuture = Pin<Box<dyn Future<Output = io::Result<()>> + Send>>;
+
+impl FinalizingLayerBuilder {
+    pub(crate) fn new<F: 'static + FnOnce() -> FinalizeFuture + Send + Sync>(
+        builder: Box<dyn LayerBuilder>,
+        finalize: F,
+    ) -> Self {
+        FinalizingLayerBuilder {
+            builder,
+            finalize: Box::new(finalize),
+        }
+    }
+}
+
+impl LayerBuilder for FinalizingLayerBuilder {
+    fn name(&self) -> [u32; 5] {
+        self.builder.name()
+    }
+
+    fn parent(&self) -> Option<Arc<dyn Layer>> {
+        self.builder.parent()
+    }
+
+    fn add_string_triple(&mut self, triple: StringTriple) {
+        self.builder.add_string_triple(triple)
+    }
+
+    fn add_id_triple(&mut self, triple: IdTriple) {
+        self.builder.add_id_triple(triple)
+    }
+
+    fn remove_string_triple(&mut self, triple: StringTriple) {
+        self.builder.remove_string_triple(triple)
+    }
+
+    fn remove_id_triple(&mut self, triple: IdTriple) {
+        self.builder.remove_id_triple(triple)
+    }
+
+    fn commit(self) -> Pin<Box<dyn Future<Output = io::Result<()>> + Send>> {
+        let FinalizingLayerBuilder { builder, finalize } = self;
+        Box::pin(async move {
+            builder.commit_boxed().await?;
+            finalize().await
+        })
+    }
+
+    fn commit_boxed(self: Box<Self>) -> Pin<Box<dyn Future<Output = io::Result<()>> + Send>> {
+        let builder = *self;
+        builder.commit()
+    }
 }
 
 // locking isn't really ideal but the lock window will be relatively small so it shouldn't hurt performance too much except on heavy updates.
@@ -677,6 +940,221 @@ impl LayerCache for LockingHashMapLayerCache {
     }
 }
 
+/// Statistics about the use of a layer cache.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LayerCacheStats {
+    /// The number of lookups that found a cached layer.
+    pub hits: u64,
+    /// The number of lookups that did not find a cached layer.
+    pub misses: u64,
+    /// The number of layers that were evicted to stay within budget.
+    pub evictions: u64,
+    /// The combined size in bytes of all cached layers.
+    pub size: usize,
+    /// The number of cached layers.
+    pub layers: usize,
+}
+
+struct LruEntry {
+    layer: Arc<InternalLayer>,
+    size: usize,
+    last_used: u64,
+}
+
+#[derive(Default)]
+struct LruState {
+    entries: HashMap<[u32; 5], LruEntry>,
+    /// The names of all cached layers, by the tick they were last used at.
+    by_use: BTreeMap<u64, [u32; 5]>,
+    tick: u64,
+    size: usize,
+}
+
+impl LruState {
+    /// Mark a layer and all its cached ancestors as used.
+    ///
+    /// Ancestors are marked after the layer itself, so a layer is
+    /// always evicted before any of its ancestors.
+    fn touch(&mut self, layer: &InternalLayer) {
+        let mut current = Some(layer);
+        while let Some(layer) = current {
+            let name = Layer::name(layer);
+            if let Some(entry) = self.entries.get_mut(&name) {
+                self.tick += 1;
+                self.by_use.remove(&entry.last_used);
+                self.by_use.insert(self.tick, name);
+                entry.last_used = self.tick;
+            }
+            current = layer.immediate_parent();
+        }
+    }
+
+    fn insert(&mut self, name: [u32; 5], entry: LruEntry) {
+        self.remove(name);
+        self.size += entry.size;
+        self.tick += 1;
+        self.by_use.insert(self.tick, name);
+        self.entries.insert(
+            name,
+            LruEntry {
+                last_used: self.tick,
+                ..entry
+            },
+        );
+    }
+
+    fn remove(&mut self, name: [u32; 5]) {
+        if let Some(entry) = self.entries.remove(&name) {
+            self.by_use.remove(&entry.last_used);
+            self.size -= entry.size;
+        }
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.by_use.clear();
+        self.size = 0;
+    }
+
+    fn evict_least_recently_used(&mut self) -> bool {
+        let name = match self.by_use.values().next() {
+            Some(name) => *name,
+            None => return false,
+        };
+        self.remove(name);
+
+        true
+    }
+}
+
+struct LruLayerCacheInner {
+    budget: usize,
+    state: Mutex<LruState>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+}
+
+/// A layer cache which keeps layers alive until it runs out of budget.
+///
+/// Unlike `LockingHashMapLayerCache`, this cache holds strong
+/// references, so layers stay loaded even when nobody is using
+/// them. The size of a layer is the combined size of its structures,
+/// not counting its ancestors. When the total size goes over the
+/// budget, the least recently used layers are evicted. Using a layer
+/// also counts as using all of its ancestors, so whole stacks are
+/// evicted from the top down.
+///
+/// The budget limits the combined size of the cached layers, with
+/// each layer counted once, no matter how many cached layers share
+/// it as an ancestor. It is not a limit on resident memory: a cached
+/// layer keeps all of its ancestors alive, including ones that are
+/// not in the cache, such as ones that are larger than the budget.
+///
+/// This cache can be cloned cheaply. All clones share the same
+/// entries and statistics.
+#[derive(Clone)]
+pub struct LruLayerCache {
+    inner: Arc<LruLayerCacheInner>,
+}
+
+impl LruLayerCache {
+    /// Create a new cache which will hold at most `budget` bytes worth of layers.
+    pub fn new(budget: usize) -> Self {
+        Self {
+            inner: Arc::new(LruLayerCacheInner {
+                budget,
+                state: Mutex::new(LruState::default()),
+                hits: AtomicU64::new(0),
+                misses: AtomicU64::new(0),
+                evictions: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    /// Returns the budget in bytes of this cache.
+    pub fn budget(&self) -> usize {
+        self.inner.budget
+    }
+
+    /// Returns statistics about the use of this cache.
+    pub fn stats(&self) -> LayerCacheStats {
+        let state = self
+            .inner
+            .state
+            .lock()
+            .expect("mutex lock should always succeed");
+
+        LayerCacheStats {
+            hits: self.inner.hits.load(Ordering::Relaxed),
+            misses: self.inner.misses.load(Ordering::Relaxed),
+            evictions: self.inner.evictions.load(Ordering::Relaxed),
+            size: state.size,
+            layers: state.entries.len(),
+        }
+    }
+
+    /// Remove all layers from this cache.
+    pub fn clear(&self) {
+        let mut state = self
+            .inner
+            .state
+            .lock()
+            .expect("mutex lock should always succeed");
+        state.clear();
+    }
+}
+
+impl LayerCache for LruLayerCache {
+    fn get_layer_from_cache(&self, name: [u32; 5]) -> Option<Arc<InternalLayer>> {
+        let mut state = self
+            .inner
+            .state
+            .lock()
+            .expect("mutex lock should always succeed");
+
+        match state.entries.get(&name).map(|entry| entry.layer.clone()) {
+            Some(layer) => {
+                state.touch(&layer);
+                self.inner.hits.fetch_add(1, Ordering::Relaxed);
+                Some(layer)
+            }
+            None => {
+                self.inner.misses.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+
+    fn cache_layer(&self, layer: Arc<InternalLayer>) {
+        let size = crate::layer::InternalLayerImpl::size_in_bytes(&*layer);
+        if size > self.inner.budget {
+            // this layer would push everything else out and still not fit
+            return;
+        }
+
+        let mut state = self
+            .inner
+            .state
+            .lock()
+            .expect("mutex lock should always succeed");
+
+        state.insert(
+            Layer::name(&*layer),
+            LruEntry {
+                layer: layer.clone(),
+                size,
+                last_used: 0,
+            },
+        );
+        state.touch(&layer);
+
+        while state.size > self.inner.budget && state.evict_least_recently_used() {
+            self.inner.evictions.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct CachedLayerStore {
     inner: Arc<dyn LayerStore>,
@@ -697,6 +1175,10 @@ impl LayerStore for CachedLayerStore {
         self.inner.layers()
     }
 
+    fn layer_infos(&self) -> Pin<Box<dyn Stream<Item = io::Result<LayerInfo>> + Send>> {
+        self.inner.layer_infos()
+    }
+
     fn get_layer(
         &self,
         name: [u32; 5],
@@ -712,26 +1194,32 @@ impl LayerStore for CachedLayerStore {
         self.inner.get_layer_with_cache(name, cache)
     }
 
-    fn create_base_layer(
-        &self,
-    ) -> Pin<Box<dyn Future<Output = io::Result<Box<dyn LayerBuilder>>> + Send>> {
-        self.inner.create_base_layer()
+    fn create_base_layer_with_options(&self, options: LayerOptions) -> LayerBuilderFuture {
+        self.inner.create_base_layer_with_options(options)
+    }
+
+    fn create_child_layer(&self, parent: [u32; 5]) -> LayerBuilderFuture {
+        self.inner
+            .create_child_layer_with_cache(parent, self.cache.clone())
     }
 
-    fn create_child_layer(
+    fn create_child_layer_with_options(
         &self,
         parent: [u32; 5],
-    ) -> Pin<Box<dyn Future<Output = io::Result<Box<dyn LayerBuilder>>> + Send>> {
+        options: LayerOptions,
+    ) -> LayerBuilderFuture {
         self.inner
-            .create_child_layer_with_cache(parent, self.cache.clone())
+            .create_child_layer_with_cache_and_options(parent, self.cache.clone(), options)
     }
 
-    fn create_child_layer_with_cache(
+    fn create_child_layer_with_cache_and_options(
         &self,
         parent: [u32; 5],
         cache: Arc<dyn LayerCache>,
-    ) -> Pin<Box<dyn Future<Output = io::Result<Box<dyn LayerBuilder>>> + Send>> {
-        self.inner.create_child_layer_with_cache(parent, cache)
+        options: LayerOptions,
+    ) -> LayerBuilderFuture {
+        self.inner
+            .create_child_layer_with_cache_and_options(parent, cache, options)
     }
 
     fn export_layers(&self, layer_ids: Box<dyn Iterator<Item = [u32; 5]>>) -> Vec<u8> {
@@ -752,6 +1240,13 @@ impl LayerStore for CachedLayerStore {
     ) -> Pin<Box<dyn Future<Output = io::Result<bool>> + Send>> {
         self.inner.layer_is_ancestor_of(descendant, ancestor)
     }
+
+    fn verify_layer(
+        &self,
+        name: [u32; 5],
+    ) -> Pin<Box<dyn Future<Output = io::Result<Option<LayerVerification>>> + Send>> {
+        self.inner.verify_layer(name)
+    }
 }
 
 #[cfg(test)]
@@ -904,4 +1399,152 @@ pub mod tests {
         //let store = CachedLayerStore::new(MemoryLayerStore::new());
         //let builder = store.create_base_layer().wait().unwrap();
     }
+
+    fn build_stack(runtime: &mut Runtime, store: &CachedLayerStore) -> ([u32; 5], [u32; 5]) {
+        let mut builder = runtime.block_on(store.create_base_layer()).unwrap();
+        let base_name = builder.name();
+        builder.add_string_triple(StringTriple::new_value("cow", "says", "moo"));
+        builder.add_string_triple(StringTriple::new_value("pig", "says", "oink"));
+        runtime.block_on(builder.commit_boxed()).unwrap();
+
+        let mut builder = runtime
+            .block_on(store.create_child_layer(base_name))
+            .unwrap();
+        let child_name = builder.name();
+        builder.add_string_triple(StringTriple::new_node("cow", "likes", "pig"));
+        runtime.block_on(builder.commit_boxed()).unwrap();
+
+        (base_name, child_name)
+    }
+
+    #[test]
+    fn lru_cache_keeps_layers_alive() {
+        let mut runtime = Runtime::new().unwrap();
+        let cache = LruLayerCache::new(usize::MAX);
+        let store = CachedLayerStore::new(MemoryLayerStore::new(), cache.clone());
+        let (base_name, child_name) = build_stack(&mut runtime, &store);
+
+        let layer = runtime
+            .block_on(store.get_layer(child_name))
+            .unwrap()
+            .unwrap();
+        let weak = Arc::downgrade(&layer);
+        std::mem::drop(layer);
+        assert!(weak.upgrade().is_some());
+
+        let stats = cache.stats();
+        assert_eq!(2, stats.layers);
+        assert_eq!(0, stats.evictions);
+        assert!(stats.size > 0);
+
+        let layer = runtime
+            .block_on(store.get_layer(child_name))
+            .unwrap()
+            .unwrap();
+        assert!(Arc::ptr_eq(&weak.upgrade().unwrap(), &layer));
+        assert_eq!(Some(base_name), Layer::parent_name(&*layer));
+        assert_eq!(stats.hits + 1, cache.stats().hits);
+        assert_eq!(stats.misses, cache.stats().misses);
+    }
+
+    #[test]
+    fn lru_cache_counts_misses() {
+        let cache = LruLayerCache::new(1024);
+        assert!(cache.get_layer_from_cache([1, 2, 3, 4, 5]).is_none());
+        assert!(cache.get_layer_from_cache([1, 2, 3, 4, 5]).is_none());
+
+        assert_eq!(
+            LayerCacheStats {
+                misses: 2,
+                ..Default::default()
+            },
+            cache.stats()
+        );
+    }
+
+    #[test]
+    fn lru_cache_evicts_least_recently_used_stack() {
+        let mut runtime = Runtime::new().unwrap();
+        let unbounded = LruLayerCache::new(usize::MAX);
+        let store = CachedLayerStore::new(MemoryLayerStore::new(), unbounded.clone());
+        let (base1, child1) = build_stack(&mut runtime, &store);
+        let (base2, child2) = build_stack(&mut runtime, &store);
+        runtime.block_on(store.get_layer(child1)).unwrap();
+        runtime.block_on(store.get_layer(child2)).unwrap();
+
+        let layer1 = unbounded.get_layer_from_cache(child1).unwrap();
+        let layer2 = unbounded.get_layer_from_cache(child2).unwrap();
+        let base_layer1 = unbounded.get_layer_from_cache(base1).unwrap();
+        let base_layer2 = unbounded.get_layer_from_cache(base2).unwrap();
+        let stack_size = |child: &InternalLayer, base: &InternalLayer| {
+            crate::layer::InternalLayerImpl::size_in_bytes(child)
+                + crate::layer::InternalLayerImpl::size_in_bytes(base)
+        };
+        let size1 = stack_size(&layer1, &base_layer1);
+        let size2 = stack_size(&layer2, &base_layer2);
+
+        // only room for one of the two stacks
+        let cache = LruLayerCache::new(std::cmp::max(size1, size2));
+        cache.cache_layer(base_layer1.clone());
+        cache.cache_layer(layer1.clone());
+        assert_eq!(2, cache.stats().layers);
+        assert_eq!(size1, cache.stats().size);
+
+        cache.cache_layer(base_layer2.clone());
+        cache.cache_layer(layer2.clone());
+
+        let stats = cache.stats();
+        assert_eq!(2, stats.evictions);
+        assert_eq!(2, stats.layers);
+        assert_eq!(size2, stats.size);
+        assert!(cache.get_layer_from_cache(child1).is_none());
+        assert!(cache.get_layer_from_cache(base1).is_none());
+        assert!(cache.get_layer_from_cache(child2).is_some());
+        assert!(cache.get_layer_from_cache(base2).is_some());
+    }
+
+    #[test]
+    fn lru_cache_evicts_children_before_parents() {
+        let mut runtime = Runtime::new().unwrap();
+        let cache = LruLayerCache::new(usize::MAX);
+        let store = CachedLayerStore::new(MemoryLayerStore::new(), cache.clone());
+        let (base_name, child_name) = build_stack(&mut runtime, &store);
+        let child = runtime
+            .block_on(store.get_layer(child_name))
+            .unwrap()
+            .unwrap();
+        let base = cache.get_layer_from_cache(base_name).unwrap();
+
+        let child_size = crate::layer::InternalLayerImpl::size_in_bytes(&*child);
+        let base_size = crate::layer::InternalLayerImpl::size_in_bytes(&*base);
+
+        // room for the base layer but not for the whole stack
+        let cache = LruLayerCache::new(base_size + child_size - 1);
+        cache.cache_layer(base.clone());
+        cache.cache_layer(child.clone());
+        // using the base layer should not keep the child alive
+        cache.get_layer_from_cache(base_name).unwrap();
+        cache.cache_layer(child);
+
+        assert!(cache.get_layer_from_cache(child_name).is_none());
+        assert!(cache.get_layer_from_cache(base_name).is_some());
+        assert_eq!(base_size, cache.stats().size);
+    }
+
+    #[test]
+    fn lru_cache_ignores_layers_over_budget() {
+        let mut runtime = Runtime::new().unwrap();
+        let cache = LruLayerCache::new(0);
+        let store = CachedLayerStore::new(MemoryLayerStore::new(), cache.clone());
+        let (_base_name, child_name) = build_stack(&mut runtime, &store);
+
+        runtime
+            .block_on(store.get_layer(child_name))
+            .unwrap()
+            .unwrap();
+        let stats = cache.stats();
+        assert_eq!(0, stats.layers);
+        assert_eq!(0, stats.size);
+        assert_eq!(0, stats.evictions);
+    }
 }
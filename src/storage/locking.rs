@@ -50,11 +50,9 @@ impl Future for LockedFileLockFuture {
                 let exclusive = self.exclusive;
                 self.spawn = Some(spawn_blocking(move || {
                     if exclusive {
-                        file.lock_exclusive()
-                            .expect("failed to acquire exclusive lock")
+                        FileExt::lock_exclusive(&file).expect("failed to acquire exclusive lock")
                     } else {
-                        file.lock_shared()
-                            .expect("failed to acquire exclusive lock")
+                        FileExt::lock_shared(&file).expect("failed to acquire exclusive lock")
                     }
                 }));
             }
@@ -89,7 +87,7 @@ impl LockedFile {
             .await?
             .into_std()
             .await;
-        let file = match file.try_lock_shared() {
+        let file = match FileExt::try_lock_shared(&file) {
             Ok(()) => file,
             Err(_) => LockedFileLockFuture::new_shared(file).await?,
         };
@@ -144,14 +142,11 @@ impl AsyncRead for LockedFile {
 
 impl Drop for LockedFile {
     fn drop(&mut self) {
-        let mut file = None;
-        std::mem::swap(&mut file, &mut self.file);
-        if file.is_some() {
-            file.unwrap()
+        if let Some(file) = self.file.take() {
+            let file = file
                 .try_into_std()
-                .expect("could not convert tokio file into std")
-                .unlock()
-                .unwrap();
+                .expect("could not convert tokio file into std");
+            FileExt::unlock(&file).unwrap();
         }
     }
 }
@@ -259,14 +254,11 @@ impl AsyncWrite for ExclusiveLockedFile {
 
 impl Drop for ExclusiveLockedFile {
     fn drop(&mut self) {
-        let mut file = None;
-        std::mem::swap(&mut file, &mut self.file);
-        if file.is_some() {
-            file.unwrap()
+        if let Some(file) = self.file.take() {
+            let file = file
                 .try_into_std()
-                .expect("could not convert tokio file into std")
-                .unlock()
-                .unwrap();
+                .expect("could not convert tokio file into std");
+            FileExt::unlock(&file).unwrap();
         }
     }
 }
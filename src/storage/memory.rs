@@ -3,6 +3,7 @@
 use bytes::Bytes;
 use futures::future::{self, Future};
 use futures::io;
+use futures::stream::{self, Stream, StreamExt};
 use futures::task::{Context, Poll};
 use futures_locks;
 use std::collections::HashMap;
@@ -11,7 +12,9 @@ use std::sync::{self, Arc, RwLock};
 use tokio::prelude::*;
 
 use super::*;
-use crate::layer::{BaseLayer, ChildLayer, InternalLayer, LayerBuilder, SimpleLayerBuilder};
+use crate::layer::{
+    BaseLayer, ChildLayer, InternalLayer, LayerBuilder, LayerOptions, LayerType, SimpleLayerBuilder,
+};
 
 pub struct MemoryBackedStoreWriter {
     vec: Arc<sync::RwLock<Vec<u8>>>,
@@ -180,11 +183,13 @@ pub fn base_layer_memory_files() -> BaseLayerFiles<MemoryBackedStore> {
                 bits_file: MemoryBackedStore::new(),
                 blocks_file: MemoryBackedStore::new(),
                 sblocks_file: MemoryBackedStore::new(),
+                select_file: MemoryBackedStore::new(),
             },
             predicate_idmap_files: BitIndexFiles {
                 bits_file: MemoryBackedStore::new(),
                 blocks_file: MemoryBackedStore::new(),
                 sblocks_file: MemoryBackedStore::new(),
+                select_file: MemoryBackedStore::new(),
             },
         },
 
@@ -196,6 +201,7 @@ pub fn base_layer_memory_files() -> BaseLayerFiles<MemoryBackedStore> {
                 bits_file: MemoryBackedStore::new(),
                 blocks_file: MemoryBackedStore::new(),
                 sblocks_file: MemoryBackedStore::new(),
+                select_file: MemoryBackedStore::new(),
             },
             nums_file: MemoryBackedStore::new(),
         },
@@ -204,6 +210,7 @@ pub fn base_layer_memory_files() -> BaseLayerFiles<MemoryBackedStore> {
                 bits_file: MemoryBackedStore::new(),
                 blocks_file: MemoryBackedStore::new(),
                 sblocks_file: MemoryBackedStore::new(),
+                select_file: MemoryBackedStore::new(),
             },
             nums_file: MemoryBackedStore::new(),
         },
@@ -212,6 +219,7 @@ pub fn base_layer_memory_files() -> BaseLayerFiles<MemoryBackedStore> {
                 bits_file: MemoryBackedStore::new(),
                 blocks_file: MemoryBackedStore::new(),
                 sblocks_file: MemoryBackedStore::new(),
+                select_file: MemoryBackedStore::new(),
             },
             nums_file: MemoryBackedStore::new(),
         },
@@ -219,7 +227,18 @@ pub fn base_layer_memory_files() -> BaseLayerFiles<MemoryBackedStore> {
             bits_file: MemoryBackedStore::new(),
             blocks_file: MemoryBackedStore::new(),
             sblocks_file: MemoryBackedStore::new(),
+            select_file: MemoryBackedStore::new(),
         },
+
+        search_index_files: SearchIndexFiles {
+            text_file: MemoryBackedStore::new(),
+            suffixes_file: MemoryBackedStore::new(),
+            starts_file: MemoryBackedStore::new(),
+        },
+
+        statistics_file: MemoryBackedStore::new(),
+
+        checksums_file: MemoryBackedStore::new(),
     }
 }
 
@@ -243,11 +262,13 @@ pub fn child_layer_memory_files() -> ChildLayerFiles<MemoryBackedStore> {
                 bits_file: MemoryBackedStore::new(),
                 blocks_file: MemoryBackedStore::new(),
                 sblocks_file: MemoryBackedStore::new(),
+                select_file: MemoryBackedStore::new(),
             },
             predicate_idmap_files: BitIndexFiles {
                 bits_file: MemoryBackedStore::new(),
                 blocks_file: MemoryBackedStore::new(),
                 sblocks_file: MemoryBackedStore::new(),
+                select_file: MemoryBackedStore::new(),
             },
         },
 
@@ -261,6 +282,7 @@ pub fn child_layer_memory_files() -> ChildLayerFiles<MemoryBackedStore> {
                 bits_file: MemoryBackedStore::new(),
                 blocks_file: MemoryBackedStore::new(),
                 sblocks_file: MemoryBackedStore::new(),
+                select_file: MemoryBackedStore::new(),
             },
             nums_file: MemoryBackedStore::new(),
         },
@@ -269,6 +291,7 @@ pub fn child_layer_memory_files() -> ChildLayerFiles<MemoryBackedStore> {
                 bits_file: MemoryBackedStore::new(),
                 blocks_file: MemoryBackedStore::new(),
                 sblocks_file: MemoryBackedStore::new(),
+                select_file: MemoryBackedStore::new(),
             },
             nums_file: MemoryBackedStore::new(),
         },
@@ -277,6 +300,7 @@ pub fn child_layer_memory_files() -> ChildLayerFiles<MemoryBackedStore> {
                 bits_file: MemoryBackedStore::new(),
                 blocks_file: MemoryBackedStore::new(),
                 sblocks_file: MemoryBackedStore::new(),
+                select_file: MemoryBackedStore::new(),
             },
             nums_file: MemoryBackedStore::new(),
         },
@@ -285,6 +309,7 @@ pub fn child_layer_memory_files() -> ChildLayerFiles<MemoryBackedStore> {
                 bits_file: MemoryBackedStore::new(),
                 blocks_file: MemoryBackedStore::new(),
                 sblocks_file: MemoryBackedStore::new(),
+                select_file: MemoryBackedStore::new(),
             },
             nums_file: MemoryBackedStore::new(),
         },
@@ -293,6 +318,7 @@ pub fn child_layer_memory_files() -> ChildLayerFiles<MemoryBackedStore> {
                 bits_file: MemoryBackedStore::new(),
                 blocks_file: MemoryBackedStore::new(),
                 sblocks_file: MemoryBackedStore::new(),
+                select_file: MemoryBackedStore::new(),
             },
             nums_file: MemoryBackedStore::new(),
         },
@@ -301,6 +327,7 @@ pub fn child_layer_memory_files() -> ChildLayerFiles<MemoryBackedStore> {
                 bits_file: MemoryBackedStore::new(),
                 blocks_file: MemoryBackedStore::new(),
                 sblocks_file: MemoryBackedStore::new(),
+                select_file: MemoryBackedStore::new(),
             },
             nums_file: MemoryBackedStore::new(),
         },
@@ -308,12 +335,24 @@ pub fn child_layer_memory_files() -> ChildLayerFiles<MemoryBackedStore> {
             bits_file: MemoryBackedStore::new(),
             blocks_file: MemoryBackedStore::new(),
             sblocks_file: MemoryBackedStore::new(),
+            select_file: MemoryBackedStore::new(),
         },
         neg_predicate_wavelet_tree_files: BitIndexFiles {
             bits_file: MemoryBackedStore::new(),
             blocks_file: MemoryBackedStore::new(),
             sblocks_file: MemoryBackedStore::new(),
+            select_file: MemoryBackedStore::new(),
         },
+
+        search_index_files: SearchIndexFiles {
+            text_file: MemoryBackedStore::new(),
+            suffixes_file: MemoryBackedStore::new(),
+            starts_file: MemoryBackedStore::new(),
+        },
+
+        statistics_file: MemoryBackedStore::new(),
+
+        checksums_file: MemoryBackedStore::new(),
     }
 }
 
@@ -326,6 +365,31 @@ impl LayerStore for MemoryLayerStore {
         })
     }
 
+    fn layer_infos(&self) -> Pin<Box<dyn Stream<Item = io::Result<LayerInfo>> + Send>> {
+        let guard = self.layers.read();
+        Box::pin(
+            stream::once(async move {
+                let layers = guard.await;
+                let infos: Vec<_> = layers
+                    .iter()
+                    .map(|(name, (parent, _))| {
+                        Ok(LayerInfo {
+                            name: *name,
+                            layer_type: match parent {
+                                Some(_) => LayerType::Child,
+                                None => LayerType::Base,
+                            },
+                            parent: *parent,
+                        })
+                    })
+                    .collect();
+
+                stream::iter(infos)
+            })
+            .flatten(),
+        )
+    }
+
     fn get_layer_with_cache(
         &self,
         name: [u32; 5],
@@ -407,25 +471,32 @@ impl LayerStore for MemoryLayerStore {
         })
     }
 
-    fn create_base_layer(
-        &self,
-    ) -> Pin<Box<dyn Future<Output = io::Result<Box<dyn LayerBuilder>>> + Send>> {
+    fn create_base_layer_with_options(&self, options: LayerOptions) -> LayerBuilderFuture {
         let name = rand::random();
         let blf = base_layer_memory_files();
 
-        let guard = self.layers.write();
-        Box::pin(async move {
-            let mut layers = guard.await;
-            layers.insert(name, (None, LayerFiles::Base(blf.clone())));
-            Ok(Box::new(SimpleLayerBuilder::new(name, blf)) as Box<dyn LayerBuilder>)
-        })
+        // like the directory store, only make the layer visible once it is fully written
+        let layers = self.layers.clone();
+        let files = LayerFiles::Base(blf.clone());
+        let builder = FinalizingLayerBuilder::new(
+            Box::new(SimpleLayerBuilder::new(name, blf).with_options(options)),
+            move || {
+                Box::pin(async move {
+                    layers.write().await.insert(name, (None, files));
+                    Ok(())
+                })
+            },
+        );
+
+        Box::pin(future::ok(Box::new(builder) as Box<dyn LayerBuilder>))
     }
 
-    fn create_child_layer_with_cache(
+    fn create_child_layer_with_cache_and_options(
         &self,
         parent: [u32; 5],
         cache: Arc<dyn LayerCache>,
-    ) -> Pin<Box<dyn Future<Output = io::Result<Box<dyn LayerBuilder>>> + Send>> {
+        options: LayerOptions,
+    ) -> LayerBuilderFuture {
         let layers = self.layers.clone();
         let get_layer_with_cache = self.get_layer_with_cache(parent, cache);
         Box::pin(async move {
@@ -441,14 +512,20 @@ impl LayerStore for MemoryLayerStore {
             let name = rand::random();
             let clf = child_layer_memory_files();
 
-            layers
-                .write()
-                .await
-                .insert(name, (Some(parent), LayerFiles::Child(clf.clone())));
-            Ok(
-                Box::new(SimpleLayerBuilder::from_parent(name, parent_layer, clf))
-                    as Box<dyn LayerBuilder>,
-            )
+            let files = LayerFiles::Child(clf.clone());
+            let builder = FinalizingLayerBuilder::new(
+                Box::new(
+                    SimpleLayerBuilder::from_parent(name, parent_layer, clf).with_options(options),
+                ),
+                move || {
+                    Box::pin(async move {
+                        layers.write().await.insert(name, (Some(parent), files));
+                        Ok(())
+                    })
+                },
+            );
+
+            Ok(Box::new(builder) as Box<dyn LayerBuilder>)
         })
     }
 
@@ -485,6 +562,41 @@ impl LayerStore for MemoryLayerStore {
             }
         })
     }
+
+    fn verify_layer(
+        &self,
+        name: [u32; 5],
+    ) -> Pin<Box<dyn Future<Output = io::Result<Option<LayerVerification>>> + Send>> {
+        let guard = self.layers.read();
+        Box::pin(async move {
+            let layers = guard.await;
+            let (parent, files) = match layers.get(&name) {
+                None => return Ok(None),
+                Some((parent, files)) => (*parent, files.clone()),
+            };
+
+            let mut issues = Vec::new();
+            if let Some(parent) = parent {
+                if !layers.contains_key(&parent) {
+                    issues.push(LayerIssue::MissingParent(parent));
+                }
+            }
+            std::mem::drop(layers);
+
+            let layer_type = match files {
+                LayerFiles::Base(_) => LayerType::Base,
+                LayerFiles::Child(_) => LayerType::Child,
+            };
+            issues.extend(verify_layer_files(&files).await);
+
+            Ok(Some(LayerVerification {
+                name,
+                layer_type,
+                parent,
+                issues,
+            }))
+        })
+    }
 }
 
 #[derive(Clone)]
@@ -573,8 +685,51 @@ impl LabelStore for MemoryLabelStore {
 mod tests {
     use super::*;
     use crate::layer::*;
+    use futures::stream::TryStreamExt;
     use tokio::runtime::Runtime;
 
+    #[test]
+    fn list_layer_infos() {
+        let mut runtime = Runtime::new().unwrap();
+        let store = MemoryLayerStore::new();
+
+        let (base_name, child_name) = runtime
+            .block_on(async {
+                let mut builder = store.create_base_layer().await?;
+                let base_name = builder.name();
+                builder.add_string_triple(StringTriple::new_value("cow", "says", "moo"));
+                builder.commit_boxed().await?;
+
+                let mut builder = store.create_child_layer(base_name).await?;
+                let child_name = builder.name();
+                builder.add_string_triple(StringTriple::new_value("pig", "says", "oink"));
+                builder.commit_boxed().await?;
+
+                Ok::<_, io::Error>((base_name, child_name))
+            })
+            .unwrap();
+
+        let mut infos: Vec<_> = runtime
+            .block_on(store.layer_infos().try_collect::<Vec<_>>())
+            .unwrap();
+        infos.sort_by_key(|info| info.layer_type == LayerType::Child);
+
+        assert_eq!(
+            vec![
+                LayerInfo {
+                    name: base_name,
+                    layer_type: LayerType::Base,
+                    parent: None,
+                },
+                LayerInfo {
+                    name: child_name,
+                    layer_type: LayerType::Child,
+                    parent: Some(base_name),
+                },
+            ],
+            infos
+        );
+    }
     #[test]
     fn write_and_read_memory_backed() {
         let mut runtime = Runtime::new().unwrap();
@@ -644,6 +799,37 @@ mod tests {
         assert!(!layer.string_triple_exists(&StringTriple::new_value("duck", "says", "quack")));
     }
 
+    #[test]
+    fn uncommitted_layers_are_invisible() {
+        let mut runtime = Runtime::new().unwrap();
+        let store = MemoryLayerStore::new();
+
+        runtime
+            .block_on(async {
+                let mut builder = store.create_base_layer().await?;
+                let name = builder.name();
+                builder.add_string_triple(StringTriple::new_value("cow", "says", "moo"));
+
+                assert!(store.layers().await?.is_empty());
+                assert!(store.get_layer(name).await?.is_none());
+
+                builder.commit_boxed().await?;
+
+                assert_eq!(vec![name], store.layers().await?);
+                assert!(store.get_layer(name).await?.is_some());
+
+                // an abandoned builder leaves nothing behind
+                let builder = store.create_child_layer(name).await?;
+                let child_name = builder.name();
+                std::mem::drop(builder);
+                assert_eq!(vec![name], store.layers().await?);
+                assert!(store.get_layer(child_name).await?.is_none());
+
+                Ok::<_, io::Error>(())
+            })
+            .unwrap();
+    }
+
     #[test]
     fn memory_create_and_retrieve_equal_label() {
         let mut runtime = Runtime::new().unwrap();
@@ -22,6 +22,7 @@
 //! A label store is a set of files. The file name is of the format
 //! `foo.label`, for database `foo`. This file contains the name of
 //! the layer this label is pointing at.
+mod checksum;
 mod consts;
 pub mod directory;
 mod file;
@@ -29,7 +30,10 @@ mod label;
 mod layer;
 mod locking;
 pub mod memory;
+mod verify;
 
+pub use checksum::*;
 pub use file::*;
 pub use label::*;
 pub use layer::*;
+pub use verify::*;
@@ -0,0 +1,1429 @@
+//! Integrity verification of stored layers.
+//!
+//! Loading a layer only checks what it needs to map each file, such
+//! as headers, lengths and dictionary offsets, and returns an error
+//! for the first problem it finds. The functions in this module read
+//! every file in full: they decode all dictionary blocks, compare the
+//! files against their recorded checksums, check that the structures
+//! of a layer agree with each other, and have the layer store check
+//! that a child layer's parent exists. Every problem is collected
+//! instead of stopping at the first one.
+use byteorder::{BigEndian, ByteOrder};
+use bytes::Bytes;
+use std::collections::HashMap;
+use std::fmt;
+
+use super::checksum::read_checksums;
+use super::consts::FILENAMES;
+use super::file::*;
+use super::label::Label;
+use crate::layer::{LayerStatistics, LayerType};
+use crate::structure::bitindex::SBLOCK_SIZE;
+use crate::structure::pfc::parse_header;
+use crate::structure::util::calculate_width;
+use crate::structure::*;
+
+/// A problem found while verifying a layer.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LayerIssue {
+    /// A file that is required for this type of layer does not exist.
+    MissingFile(&'static str),
+    /// A file could not be read, or its contents could not be parsed.
+    Corrupt { file: &'static str, reason: String },
+    /// The structures in this layer parsed fine, but disagree with each other.
+    Inconsistent(String),
+    /// The parent of this child layer is not in the store.
+    MissingParent([u32; 5]),
+    /// The contents of a file do not match its recorded checksum.
+    ChecksumMismatch(&'static str),
+}
+
+impl fmt::Display for LayerIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LayerIssue::MissingFile(file) => write!(f, "missing file {}", file),
+            LayerIssue::Corrupt { file, reason } => write!(f, "corrupt file {}: {}", file, reason),
+            LayerIssue::Inconsistent(reason) => write!(f, "inconsistent layer: {}", reason),
+            LayerIssue::ChecksumMismatch(file) => write!(f, "checksum mismatch for {}", file),
+            LayerIssue::MissingParent(parent) => {
+                write!(
+                    f,
+                    "parent layer {} not found",
+                    super::name_to_string(*parent)
+                )
+            }
+        }
+    }
+}
+
+/// The result of verifying a single layer.
+#[derive(Clone, Debug)]
+pub struct LayerVerification {
+    pub name: [u32; 5],
+    pub layer_type: LayerType,
+    /// The parent of this layer, if it is a child layer with a readable parent file.
+    pub parent: Option<[u32; 5]>,
+    pub issues: Vec<LayerIssue>,
+}
+
+impl LayerVerification {
+    /// Returns true if no issues were found in this layer.
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+/// The result of verifying a complete store.
+#[derive(Clone, Debug, Default)]
+pub struct VerificationReport {
+    pub layers: Vec<LayerVerification>,
+    /// Labels pointing at a layer that does not exist in the store.
+    pub dangling_labels: Vec<Label>,
+}
+
+impl VerificationReport {
+    /// Returns true if no issues were found in any layer or label.
+    pub fn is_ok(&self) -> bool {
+        self.dangling_labels.is_empty() && self.layers.iter().all(|l| l.is_ok())
+    }
+
+    /// Returns the verification results of all layers that have issues.
+    pub fn failed_layers(&self) -> impl Iterator<Item = &LayerVerification> {
+        self.layers.iter().filter(|l| !l.is_ok())
+    }
+}
+
+struct BitIndexNames {
+    bits: &'static str,
+    blocks: &'static str,
+    sblocks: &'static str,
+    select: &'static str,
+}
+
+struct AdjacencyListNames {
+    nums: &'static str,
+    bitindex: BitIndexNames,
+}
+
+fn node_value_idmap_names() -> BitIndexNames {
+    BitIndexNames {
+        bits: FILENAMES.node_value_idmap_bits,
+        blocks: FILENAMES.node_value_idmap_bit_index_blocks,
+        sblocks: FILENAMES.node_value_idmap_bit_index_sblocks,
+        select: FILENAMES.node_value_idmap_bit_index_select,
+    }
+}
+
+fn predicate_idmap_names() -> BitIndexNames {
+    BitIndexNames {
+        bits: FILENAMES.predicate_idmap_bits,
+        blocks: FILENAMES.predicate_idmap_bit_index_blocks,
+        sblocks: FILENAMES.predicate_idmap_bit_index_sblocks,
+        select: FILENAMES.predicate_idmap_bit_index_select,
+    }
+}
+
+struct TripleStructures<'a> {
+    description: &'static str,
+    subjects: Option<&'a MonotonicLogArray>,
+    objects: Option<&'a MonotonicLogArray>,
+    s_p: Option<&'a AdjacencyList>,
+    sp_o: Option<&'a AdjacencyList>,
+    o_ps: Option<&'a AdjacencyList>,
+}
+
+struct Verifier {
+    issues: Vec<LayerIssue>,
+}
+
+impl Verifier {
+    fn new() -> Self {
+        Verifier { issues: Vec::new() }
+    }
+
+    fn corrupt<E: fmt::Display>(&mut self, file: &'static str, reason: E) {
+        self.issues.push(LayerIssue::Corrupt {
+            file,
+            reason: reason.to_string(),
+        });
+    }
+
+    fn inconsistent(&mut self, reason: String) {
+        self.issues.push(LayerIssue::Inconsistent(reason));
+    }
+
+    /// Map all the given files into memory, reporting any that are missing or unreadable.
+    async fn map_files<F: FileLoad>(
+        &mut self,
+        files: Vec<(&'static str, F)>,
+    ) -> HashMap<&'static str, Bytes> {
+        let mut result = HashMap::with_capacity(files.len());
+        for (name, file) in files {
+            if !file.exists() {
+                self.issues.push(LayerIssue::MissingFile(name));
+                continue;
+            }
+
+            match file.map().await {
+                Ok(bytes) => {
+                    result.insert(name, bytes);
+                }
+                Err(e) => self.corrupt(name, e),
+            }
+        }
+
+        result
+    }
+
+    /// Check the mapped files against the checksum file, if there is one.
+    async fn checksums<F: FileLoad>(
+        &mut self,
+        maps: &HashMap<&'static str, Bytes>,
+        checksums_file: &F,
+    ) {
+        let checksums = match read_checksums(checksums_file).await {
+            Ok(Some(checksums)) => checksums,
+            Ok(None) => return,
+            Err(e) => return self.corrupt(FILENAMES.checksums, e),
+        };
+
+        let mut names: Vec<_> = maps.keys().copied().collect();
+        names.sort_unstable();
+        for name in names {
+            if !checksums.matches(name, &maps[name]) {
+                self.issues.push(LayerIssue::ChecksumMismatch(name));
+            }
+        }
+
+        for (name, _) in checksums.iter() {
+            let reported_missing = self
+                .issues
+                .iter()
+                .any(|issue| matches!(issue, LayerIssue::MissingFile(missing) if *missing == name));
+            if !maps.contains_key(name) && !reported_missing {
+                self.inconsistent(format!("file {} has a checksum but does not exist", name));
+            }
+        }
+    }
+
+    fn logarray(
+        &mut self,
+        maps: &HashMap<&'static str, Bytes>,
+        name: &'static str,
+    ) -> Option<LogArray> {
+        let bytes = maps.get(name)?.clone();
+        match LogArray::parse(bytes) {
+            Ok(logarray) => Some(logarray),
+            Err(e) => {
+                self.corrupt(name, e);
+                None
+            }
+        }
+    }
+
+    fn monotonic_logarray(
+        &mut self,
+        maps: &HashMap<&'static str, Bytes>,
+        name: &'static str,
+    ) -> Option<MonotonicLogArray> {
+        let bytes = maps.get(name)?.clone();
+        if is_elias_fano(&bytes) {
+            let sequence = match EliasFano::parse(bytes) {
+                Ok(sequence) => sequence,
+                Err(e) => {
+                    self.corrupt(name, e);
+                    return None;
+                }
+            };
+            if !self.strictly_increasing(name, sequence.iter()) {
+                return None;
+            }
+
+            Some(MonotonicLogArray::from_elias_fano(sequence))
+        } else {
+            let logarray = self.logarray(maps, name)?;
+            if !self.strictly_increasing(name, logarray.iter()) {
+                return None;
+            }
+
+            Some(MonotonicLogArray::from_logarray(logarray))
+        }
+    }
+
+    fn strictly_increasing<I: Iterator<Item = u64>>(
+        &mut self,
+        name: &'static str,
+        entries: I,
+    ) -> bool {
+        let mut iter = entries.enumerate();
+        if let Some((_, mut pred)) = iter.next() {
+            for (index, succ) in iter {
+                if pred >= succ {
+                    self.corrupt(
+                        name,
+                        format!(
+                            "entries not strictly increasing at index {} ({} >= {})",
+                            index, pred, succ
+                        ),
+                    );
+                    return false;
+                }
+                pred = succ;
+            }
+        }
+
+        true
+    }
+
+    /// Check the search index, if there is one, against the amount of strings it should contain.
+    fn search_index(&mut self, maps: &HashMap<&'static str, Bytes>, string_count: usize) {
+        let starts = match self.monotonic_logarray(maps, FILENAMES.search_index_starts) {
+            Some(starts) => starts,
+            None => return,
+        };
+        let (text, suffixes) = match (
+            maps.get(FILENAMES.search_index_text),
+            maps.get(FILENAMES.search_index_suffixes),
+        ) {
+            (Some(text), Some(suffixes)) => (text.clone(), suffixes.clone()),
+            _ => return,
+        };
+        let suffix_array = match SuffixArray::parse(
+            text.clone(),
+            suffixes,
+            maps[FILENAMES.search_index_starts].clone(),
+        ) {
+            Ok(suffix_array) => suffix_array,
+            Err(e) => return self.corrupt(FILENAMES.search_index_suffixes, e),
+        };
+
+        if suffix_array.len() != string_count {
+            self.inconsistent(format!(
+                "search index contains {} strings but the dictionaries contain {}",
+                suffix_array.len(),
+                string_count
+            ));
+        }
+        if starts.iter().any(|start| start >= text.len() as u64) {
+            self.corrupt(
+                FILENAMES.search_index_starts,
+                "string starts beyond the end of the text",
+            );
+        }
+    }
+
+    /// Check that the layer statistics, if there are any, can be parsed.
+    fn statistics(&mut self, maps: &HashMap<&'static str, Bytes>) {
+        if let Some(statistics) = maps.get(FILENAMES.statistics) {
+            if let Err(e) = LayerStatistics::parse(statistics.clone()) {
+                self.corrupt(FILENAMES.statistics, e);
+            }
+        }
+    }
+
+    fn bitindex(
+        &mut self,
+        maps: &HashMap<&'static str, Bytes>,
+        names: &BitIndexNames,
+    ) -> Option<BitIndex> {
+        let bits = maps.get(names.bits).cloned();
+        let blocks = self.logarray(maps, names.blocks);
+        let sblocks = self.logarray(maps, names.sblocks);
+
+        let bits = bits?;
+        if is_rrr(&bits) {
+            // compressed bits carry their own samples, and leave the blocks and superblocks empty.
+            let rrr = match RrrBitVector::parse(bits) {
+                Ok(rrr) => rrr,
+                Err(e) => {
+                    self.corrupt(names.bits, e);
+                    return None;
+                }
+            };
+            if let Err(reason) = rrr.check_consistency() {
+                self.corrupt(names.bits, reason);
+                return None;
+            }
+
+            return Some(BitIndex::from_rrr(rrr));
+        }
+
+        let bits = match BitArray::from_bits(bits) {
+            Ok(bits) => bits,
+            Err(e) => {
+                self.corrupt(names.bits, e);
+                return None;
+            }
+        };
+        let blocks = blocks?;
+        let sblocks = sblocks?;
+
+        let block_count = bits.len().div_ceil(64);
+        if blocks.len() != block_count {
+            self.corrupt(
+                names.blocks,
+                format!(
+                    "expected {} blocks for {} bits, but found {}",
+                    block_count,
+                    bits.len(),
+                    blocks.len()
+                ),
+            );
+            return None;
+        }
+        let sblock_count = block_count.div_ceil(SBLOCK_SIZE);
+        if sblocks.len() != sblock_count {
+            self.corrupt(
+                names.sblocks,
+                format!(
+                    "expected {} superblocks for {} blocks, but found {}",
+                    sblock_count,
+                    block_count,
+                    sblocks.len()
+                ),
+            );
+            return None;
+        }
+
+        // recalculate the ranks the same way build_bitindex does, and compare them to what is stored.
+        let words = bits.bits();
+        let mut rank = 0;
+        for sblock in 0..sblock_count {
+            let start = sblock * SBLOCK_SIZE;
+            let end = std::cmp::min(start + SBLOCK_SIZE, block_count);
+            let block_ranks: Vec<u64> = (start..end)
+                .map(|block| {
+                    BigEndian::read_u64(&words[block * 8..block * 8 + 8]).count_ones() as u64
+                })
+                .collect();
+
+            let mut subrank: u64 = block_ranks.iter().sum();
+            rank += subrank;
+            for (block, block_rank) in (start..end).zip(block_ranks) {
+                if blocks.entry(block) != subrank {
+                    self.corrupt(
+                        names.blocks,
+                        format!(
+                            "block {} has rank {}, expected {}",
+                            block,
+                            blocks.entry(block),
+                            subrank
+                        ),
+                    );
+                    return None;
+                }
+                subrank -= block_rank;
+            }
+
+            if sblocks.entry(sblock) != rank {
+                self.corrupt(
+                    names.sblocks,
+                    format!(
+                        "superblock {} has rank {}, expected {}",
+                        sblock,
+                        sblocks.entry(sblock),
+                        rank
+                    ),
+                );
+                return None;
+            }
+        }
+
+        let index = BitIndex::from_parts(bits, blocks, sblocks);
+        match maps.get(names.select) {
+            Some(select) => self.select_index(select.clone(), names.select, index),
+            None => Some(index),
+        }
+    }
+
+    fn select_index(
+        &mut self,
+        select: Bytes,
+        name: &'static str,
+        index: BitIndex,
+    ) -> Option<BitIndex> {
+        let select = match SelectIndex::parse(select) {
+            Ok(select) => select,
+            Err(e) => {
+                self.corrupt(name, e);
+                return None;
+            }
+        };
+        if select.len() != index.len() as u64 {
+            self.corrupt(
+                name,
+                format!(
+                    "select index is for {} bits, but the bit index has {}",
+                    select.len(),
+                    index.len()
+                ),
+            );
+            return None;
+        }
+
+        let rate = select.sample_rate();
+        let ones = select.ones().iter().enumerate();
+        let zeros = select.zeros().iter().enumerate();
+        for (bit, (sample, position)) in ones.map(|s| (true, s)).chain(zeros.map(|s| (false, s))) {
+            let rank = (sample as u64 + 1) * rate;
+            let expected = match bit {
+                true => index.select1(rank),
+                false => index.select0(rank),
+            };
+            if expected != Some(position) {
+                self.corrupt(
+                    name,
+                    format!(
+                        "sampled {}-bit with rank {} is at {}, expected {:?}",
+                        bit as u8, rank, position, expected
+                    ),
+                );
+                return None;
+            }
+        }
+        let expected_ones = index.rank1_from_range(0, index.len() as u64) / rate;
+        let expected_zeros = index.rank0_from_range(0, index.len() as u64) / rate;
+        if select.ones().len() as u64 != expected_ones
+            || select.zeros().len() as u64 != expected_zeros
+        {
+            self.corrupt(
+                name,
+                format!(
+                    "expected {} sampled 1-bits and {} sampled 0-bits, but found {} and {}",
+                    expected_ones,
+                    expected_zeros,
+                    select.ones().len(),
+                    select.zeros().len()
+                ),
+            );
+            return None;
+        }
+
+        Some(index.with_select_index(select))
+    }
+
+    fn adjacency_list(
+        &mut self,
+        maps: &HashMap<&'static str, Bytes>,
+        names: &AdjacencyListNames,
+    ) -> Option<AdjacencyList> {
+        let nums = self.logarray(maps, names.nums);
+        let bits = self.bitindex(maps, &names.bitindex)?;
+        let nums = nums?;
+
+        if nums.len() != bits.len() {
+            self.corrupt(
+                names.nums,
+                format!(
+                    "adjacency list has {} numbers but {} bits",
+                    nums.len(),
+                    bits.len()
+                ),
+            );
+            return None;
+        }
+
+        if bits.len() != 0 && !bits.get(bits.len() as u64 - 1) {
+            self.corrupt(
+                names.bitindex.bits,
+                "adjacency list does not end with a 1-bit",
+            );
+            return None;
+        }
+
+        // within a single left-hand side, the right-hand sides have to be strictly increasing
+        let mut last = 0;
+        for (pos, bit) in bits.iter().enumerate() {
+            let num = nums.entry(pos);
+            if num != 0 && last >= num {
+                self.corrupt(
+                    names.nums,
+                    format!("adjacency list is not sorted at position {}", pos),
+                );
+                return None;
+            }
+            last = if bit { 0 } else { num };
+        }
+
+        Some(AdjacencyList::from_parts(nums, bits))
+    }
+
+    fn wavelet_tree(
+        &mut self,
+        maps: &HashMap<&'static str, Bytes>,
+        names: &BitIndexNames,
+        width: u8,
+    ) -> Option<WaveletTree> {
+        let bits = self.bitindex(maps, names)?;
+        if (width == 0 && bits.len() != 0) || (width != 0 && bits.len() % width as usize != 0) {
+            self.corrupt(
+                names.bits,
+                format!(
+                    "wavelet tree length {} is not a multiple of its width {}",
+                    bits.len(),
+                    width
+                ),
+            );
+            return None;
+        }
+
+        Some(WaveletTree::from_parts(bits, width))
+    }
+
+    fn dictionary(
+        &mut self,
+        maps: &HashMap<&'static str, Bytes>,
+        blocks_name: &'static str,
+        offsets_name: &'static str,
+    ) -> Option<PfcDict> {
+        let offsets_bytes = maps.get(offsets_name)?.clone();
+        let offsets = self.monotonic_logarray(maps, offsets_name);
+        let blocks = maps.get(blocks_name)?.clone();
+        let offsets = offsets?;
+
+        if blocks.len() < 8 {
+            self.corrupt(
+                blocks_name,
+                format!("expected dictionary size ({}) >= 8", blocks.len()),
+            );
+            return None;
+        }
+
+        let data = &blocks[..blocks.len() - 8];
+        let (count, block_size) = parse_header(BigEndian::read_u64(&blocks[blocks.len() - 8..]));
+        let count = count as usize;
+        let block_count = count.div_ceil(block_size);
+        if offsets.len() != block_count.saturating_sub(1) {
+            self.corrupt(
+                offsets_name,
+                format!(
+                    "expected {} block offsets for {} strings, but found {}",
+                    block_count.saturating_sub(1),
+                    count,
+                    offsets.len()
+                ),
+            );
+            return None;
+        }
+
+        if let Err(reason) = check_dictionary_blocks(data, count, block_size, &offsets) {
+            self.corrupt(blocks_name, reason);
+            return None;
+        }
+
+        match PfcDict::parse(blocks, offsets_bytes) {
+            Ok(dict) => Some(dict),
+            Err(e) => {
+                self.corrupt(blocks_name, e);
+                None
+            }
+        }
+    }
+
+    fn id_map(&mut self, maps: &HashMap<&'static str, Bytes>, names: &BitIndexNames, width: u8) {
+        if !maps.contains_key(names.bits) {
+            // id maps are optional
+            return;
+        }
+
+        let wavelet_tree = match self.wavelet_tree(maps, names, width) {
+            Some(wavelet_tree) => wavelet_tree,
+            None => return,
+        };
+
+        let len = wavelet_tree.len();
+        let mut seen = vec![false; len];
+        for (index, id) in wavelet_tree.decode().enumerate() {
+            if id as usize >= len || seen[id as usize] {
+                self.inconsistent(format!(
+                    "id map {} is not a permutation: entry {} maps to {}",
+                    names.bits, index, id
+                ));
+                return;
+            }
+            seen[id as usize] = true;
+        }
+    }
+
+    fn predicate_wavelet_tree(
+        &mut self,
+        maps: &HashMap<&'static str, Bytes>,
+        names: &BitIndexNames,
+        s_p: Option<&AdjacencyList>,
+    ) {
+        let s_p = match s_p {
+            Some(s_p) => s_p,
+            // without the s_p list we don't know the width
+            None => return,
+        };
+
+        let wavelet_tree = match self.wavelet_tree(maps, names, s_p.nums().width()) {
+            Some(wavelet_tree) => wavelet_tree,
+            None => return,
+        };
+
+        if wavelet_tree.len() != s_p.nums().len() || wavelet_tree.decode().ne(s_p.nums().iter()) {
+            self.inconsistent(format!(
+                "predicate wavelet tree {} does not match the s_p adjacency list",
+                names.bits
+            ));
+        }
+    }
+
+    fn triple_structures(&mut self, t: TripleStructures) {
+        if let (Some(subjects), Some(s_p)) = (t.subjects, t.s_p) {
+            if s_p.left_count() != subjects.len() {
+                self.inconsistent(format!(
+                    "{} s_p adjacency list has {} subjects, but the subject list has {}",
+                    t.description,
+                    s_p.left_count(),
+                    subjects.len()
+                ));
+            }
+        }
+
+        if let (Some(objects), Some(o_ps)) = (t.objects, t.o_ps) {
+            if o_ps.left_count() != objects.len() {
+                self.inconsistent(format!(
+                    "{} o_ps adjacency list has {} objects, but the object list has {}",
+                    t.description,
+                    o_ps.left_count(),
+                    objects.len()
+                ));
+            }
+        }
+
+        if let (Some(s_p), Some(sp_o)) = (t.s_p, t.sp_o) {
+            if sp_o.left_count() != s_p.right_count() {
+                self.inconsistent(format!(
+                    "{} sp_o adjacency list has {} subject-predicate pairs, but the s_p adjacency list has {}",
+                    t.description,
+                    sp_o.left_count(),
+                    s_p.right_count()
+                ));
+            }
+        }
+
+        if let (Some(sp_o), Some(o_ps)) = (t.sp_o, t.o_ps) {
+            let sp_o_count = sp_o.nums().iter().filter(|&n| n != 0).count();
+            let o_ps_count = o_ps.nums().iter().filter(|&n| n != 0).count();
+            if sp_o_count != o_ps_count {
+                self.inconsistent(format!(
+                    "{} sp_o adjacency list has {} triples, but the o_ps adjacency list has {}",
+                    t.description, sp_o_count, o_ps_count
+                ));
+            }
+
+            let sp_count = sp_o.left_count() as u64;
+            if o_ps.nums().iter().any(|sp| sp > sp_count) {
+                self.inconsistent(format!(
+                    "{} o_ps adjacency list refers to subject-predicate pairs beyond {}",
+                    t.description, sp_count
+                ));
+            }
+        }
+    }
+}
+
+/// Walk the front-coded blocks of a dictionary, checking that every
+/// entry can be decoded, is valid utf-8, and is strictly greater than
+/// the entry before it.
+fn check_dictionary_blocks(
+    data: &[u8],
+    count: usize,
+    block_size: usize,
+    offsets: &MonotonicLogArray,
+) -> Result<(), String> {
+    let mut pos = 0;
+    let mut last: Option<Vec<u8>> = None;
+    for index in 0..count {
+        let mut current = if index % block_size == 0 {
+            let block = index / block_size;
+            if block != 0 {
+                let offset = offsets.entry(block - 1) as usize;
+                if offset != pos {
+                    return Err(format!(
+                        "block {} starts at {}, but its offset says {}",
+                        block, pos, offset
+                    ));
+                }
+            }
+
+            Vec::new()
+        } else {
+            let (common, len) = data
+                .get(pos..)
+                .ok_or_else(|| format!("entry {} starts beyond the end of the data", index))
+                .and_then(|slice| {
+                    vbyte::decode(slice)
+                        .map_err(|e| format!("bad prefix length for entry {}: {:?}", index, e))
+                })?;
+            pos += len;
+
+            let last = last.as_ref().unwrap();
+            if common as usize > last.len() {
+                return Err(format!(
+                    "entry {} shares {} bytes with an entry of length {}",
+                    index,
+                    common,
+                    last.len()
+                ));
+            }
+
+            last[..common as usize].to_vec()
+        };
+
+        let rest = data
+            .get(pos..)
+            .ok_or_else(|| format!("entry {} starts beyond the end of the data", index))?;
+        let end = rest
+            .iter()
+            .position(|&b| b == 0)
+            .ok_or_else(|| format!("entry {} is not nul-terminated", index))?;
+        current.extend_from_slice(&rest[..end]);
+        pos += end + 1;
+
+        if std::str::from_utf8(&current).is_err() {
+            return Err(format!("entry {} is not valid utf-8", index));
+        }
+
+        if let Some(last) = last.as_ref() {
+            if *last >= current {
+                return Err(format!(
+                    "entry {} is not strictly greater than the entry before it",
+                    index
+                ));
+            }
+        }
+
+        last = Some(current);
+    }
+
+    Ok(())
+}
+
+fn dictionary_names() -> Vec<(&'static str, &'static str)> {
+    vec![
+        (
+            FILENAMES.node_dictionary_blocks,
+            FILENAMES.node_dictionary_offsets,
+        ),
+        (
+            FILENAMES.predicate_dictionary_blocks,
+            FILENAMES.predicate_dictionary_offsets,
+        ),
+        (
+            FILENAMES.value_dictionary_blocks,
+            FILENAMES.value_dictionary_offsets,
+        ),
+    ]
+}
+
+fn bitindex_files<F: FileLoad + Clone>(
+    names: &BitIndexNames,
+    files: &BitIndexFiles<F>,
+) -> Vec<(&'static str, F)> {
+    let mut result = vec![
+        (names.bits, files.bits_file.clone()),
+        (names.blocks, files.blocks_file.clone()),
+        (names.sblocks, files.sblocks_file.clone()),
+    ];
+    // the select index is optional
+    if files.select_file.exists() {
+        result.push((names.select, files.select_file.clone()));
+    }
+
+    result
+}
+
+fn adjacency_list_files<F: FileLoad + Clone>(
+    names: &AdjacencyListNames,
+    files: &AdjacencyListFiles<F>,
+) -> Vec<(&'static str, F)> {
+    let mut result = bitindex_files(&names.bitindex, &files.bitindex_files);
+    result.push((names.nums, files.nums_file.clone()));
+
+    result
+}
+
+fn dictionary_and_id_map_files<F: FileLoad + FileStore>(
+    node_dictionary_files: &DictionaryFiles<F>,
+    predicate_dictionary_files: &DictionaryFiles<F>,
+    value_dictionary_files: &DictionaryFiles<F>,
+    id_map_files: &IdMapFiles<F>,
+) -> Vec<(&'static str, F)> {
+    let mut result = Vec::new();
+    for ((blocks, offsets), files) in dictionary_names().into_iter().zip(
+        [
+            node_dictionary_files,
+            predicate_dictionary_files,
+            value_dictionary_files,
+        ]
+        .iter(),
+    ) {
+        result.push((blocks, files.blocks_file.clone()));
+        result.push((offsets, files.offsets_file.clone()));
+    }
+
+    // id maps are optional, but if one is there, it has to be complete.
+    if id_map_files.node_value_idmap_files.bits_file.exists() {
+        result.extend(bitindex_files(
+            &node_value_idmap_names(),
+            &id_map_files.node_value_idmap_files,
+        ));
+    }
+    if id_map_files.predicate_idmap_files.bits_file.exists() {
+        result.extend(bitindex_files(
+            &predicate_idmap_names(),
+            &id_map_files.predicate_idmap_files,
+        ));
+    }
+
+    result
+}
+
+/// Returns the search index files, if this layer has a search index.
+fn search_index_files<F: FileLoad + FileStore>(
+    files: &SearchIndexFiles<F>,
+) -> Vec<(&'static str, F)> {
+    // the search index is optional, but if it is there, it has to be complete.
+    if files.starts_file.exists() {
+        vec![
+            (FILENAMES.search_index_text, files.text_file.clone()),
+            (FILENAMES.search_index_suffixes, files.suffixes_file.clone()),
+            (FILENAMES.search_index_starts, files.starts_file.clone()),
+        ]
+    } else {
+        Vec::new()
+    }
+}
+
+/// Returns the statistics file, if this layer was built with statistics.
+fn statistics_files<F: FileLoad + FileStore>(file: &F) -> Vec<(&'static str, F)> {
+    if file.exists() {
+        vec![(FILENAMES.statistics, file.clone())]
+    } else {
+        Vec::new()
+    }
+}
+
+/// Verify dictionaries and id maps, returning the node, predicate and value dictionary.
+fn verify_dictionaries(
+    v: &mut Verifier,
+    maps: &HashMap<&'static str, Bytes>,
+) -> (Option<PfcDict>, Option<PfcDict>, Option<PfcDict>) {
+    let mut dicts = dictionary_names()
+        .into_iter()
+        .map(|(blocks, offsets)| v.dictionary(maps, blocks, offsets))
+        .collect::<Vec<_>>()
+        .into_iter();
+    let node_dictionary = dicts.next().unwrap();
+    let predicate_dictionary = dicts.next().unwrap();
+    let value_dictionary = dicts.next().unwrap();
+
+    if let (Some(nodes), Some(values)) = (&node_dictionary, &value_dictionary) {
+        v.id_map(
+            maps,
+            &node_value_idmap_names(),
+            calculate_width((nodes.len() + values.len()) as u64),
+        );
+    }
+    if let Some(predicates) = &predicate_dictionary {
+        v.id_map(
+            maps,
+            &predicate_idmap_names(),
+            calculate_width(predicates.len() as u64),
+        );
+    }
+    if let (Some(nodes), Some(values)) = (&node_dictionary, &value_dictionary) {
+        v.search_index(maps, nodes.len() + values.len());
+    }
+
+    (node_dictionary, predicate_dictionary, value_dictionary)
+}
+
+macro_rules! adjacency_list_names {
+    ($nums:ident, $bits:ident, $blocks:ident, $sblocks:ident, $select:ident, $wt_bits:ident, $wt_blocks:ident, $wt_sblocks:ident, $wt_select:ident) => {
+        (
+            AdjacencyListNames {
+                nums: FILENAMES.$nums,
+                bitindex: BitIndexNames {
+                    bits: FILENAMES.$bits,
+                    blocks: FILENAMES.$blocks,
+                    sblocks: FILENAMES.$sblocks,
+                    select: FILENAMES.$select,
+                },
+            },
+            BitIndexNames {
+                bits: FILENAMES.$wt_bits,
+                blocks: FILENAMES.$wt_blocks,
+                sblocks: FILENAMES.$wt_sblocks,
+                select: FILENAMES.$wt_select,
+            },
+        )
+    };
+    ($nums:ident, $bits:ident, $blocks:ident, $sblocks:ident, $select:ident) => {
+        AdjacencyListNames {
+            nums: FILENAMES.$nums,
+            bitindex: BitIndexNames {
+                bits: FILENAMES.$bits,
+                blocks: FILENAMES.$blocks,
+                sblocks: FILENAMES.$sblocks,
+                select: FILENAMES.$select,
+            },
+        }
+    };
+}
+
+/// Verify the files of a base layer.
+pub async fn verify_base_layer_files<F: 'static + FileLoad + FileStore>(
+    files: &BaseLayerFiles<F>,
+) -> Vec<LayerIssue> {
+    let (s_p_names, wavelet_names) = adjacency_list_names!(
+        base_s_p_adjacency_list_nums,
+        base_s_p_adjacency_list_bits,
+        base_s_p_adjacency_list_bit_index_blocks,
+        base_s_p_adjacency_list_bit_index_sblocks,
+        base_s_p_adjacency_list_bit_index_select,
+        base_predicate_wavelet_tree_bits,
+        base_predicate_wavelet_tree_bit_index_blocks,
+        base_predicate_wavelet_tree_bit_index_sblocks,
+        base_predicate_wavelet_tree_bit_index_select
+    );
+    let sp_o_names = adjacency_list_names!(
+        base_sp_o_adjacency_list_nums,
+        base_sp_o_adjacency_list_bits,
+        base_sp_o_adjacency_list_bit_index_blocks,
+        base_sp_o_adjacency_list_bit_index_sblocks,
+        base_sp_o_adjacency_list_bit_index_select
+    );
+    let o_ps_names = adjacency_list_names!(
+        base_o_ps_adjacency_list_nums,
+        base_o_ps_adjacency_list_bits,
+        base_o_ps_adjacency_list_bit_index_blocks,
+        base_o_ps_adjacency_list_bit_index_sblocks,
+        base_o_ps_adjacency_list_bit_index_select
+    );
+
+    let mut file_list = dictionary_and_id_map_files(
+        &files.node_dictionary_files,
+        &files.predicate_dictionary_files,
+        &files.value_dictionary_files,
+        &files.id_map_files,
+    );
+    // subject and object lists are optional for base layers
+    if files.subjects_file.exists() {
+        file_list.push((FILENAMES.base_subjects, files.subjects_file.clone()));
+    }
+    if files.objects_file.exists() {
+        file_list.push((FILENAMES.base_objects, files.objects_file.clone()));
+    }
+    file_list.extend(adjacency_list_files(
+        &s_p_names,
+        &files.s_p_adjacency_list_files,
+    ));
+    file_list.extend(adjacency_list_files(
+        &sp_o_names,
+        &files.sp_o_adjacency_list_files,
+    ));
+    file_list.extend(adjacency_list_files(
+        &o_ps_names,
+        &files.o_ps_adjacency_list_files,
+    ));
+    file_list.extend(bitindex_files(
+        &wavelet_names,
+        &files.predicate_wavelet_tree_files,
+    ));
+    file_list.extend(search_index_files(&files.search_index_files));
+    file_list.extend(statistics_files(&files.statistics_file));
+
+    let mut v = Verifier::new();
+    let maps = v.map_files(file_list).await;
+    v.checksums(&maps, &files.checksums_file).await;
+    v.statistics(&maps);
+
+    let (node_dictionary, predicate_dictionary, value_dictionary) =
+        verify_dictionaries(&mut v, &maps);
+
+    let subjects = v.monotonic_logarray(&maps, FILENAMES.base_subjects);
+    let objects = v.monotonic_logarray(&maps, FILENAMES.base_objects);
+    let s_p = v.adjacency_list(&maps, &s_p_names);
+    let sp_o = v.adjacency_list(&maps, &sp_o_names);
+    let o_ps = v.adjacency_list(&maps, &o_ps_names);
+
+    v.predicate_wavelet_tree(&maps, &wavelet_names, s_p.as_ref());
+    v.triple_structures(TripleStructures {
+        description: "base",
+        subjects: subjects.as_ref(),
+        objects: objects.as_ref(),
+        s_p: s_p.as_ref(),
+        sp_o: sp_o.as_ref(),
+        o_ps: o_ps.as_ref(),
+    });
+
+    // a base layer has no parent, so all ids have to be in its own dictionaries
+    if let (Some(predicates), Some(s_p)) = (&predicate_dictionary, &s_p) {
+        if s_p.nums().iter().any(|p| p > predicates.len() as u64) {
+            v.inconsistent(format!(
+                "s_p adjacency list refers to predicates beyond {}",
+                predicates.len()
+            ));
+        }
+    }
+    if let (Some(nodes), Some(values), Some(sp_o)) = (&node_dictionary, &value_dictionary, &sp_o) {
+        let node_value_count = (nodes.len() + values.len()) as u64;
+        if sp_o.nums().iter().any(|o| o > node_value_count) {
+            v.inconsistent(format!(
+                "sp_o adjacency list refers to objects beyond {}",
+                node_value_count
+            ));
+        }
+    }
+
+    v.issues
+}
+
+/// Verify the files of a child layer.
+///
+/// This does not check the parent file, as that is handled by the
+/// layer store, which knows how to resolve the parent.
+pub async fn verify_child_layer_files<F: 'static + FileLoad + FileStore>(
+    files: &ChildLayerFiles<F>,
+) -> Vec<LayerIssue> {
+    let (pos_s_p_names, pos_wavelet_names) = adjacency_list_names!(
+        pos_s_p_adjacency_list_nums,
+        pos_s_p_adjacency_list_bits,
+        pos_s_p_adjacency_list_bit_index_blocks,
+        pos_s_p_adjacency_list_bit_index_sblocks,
+        pos_s_p_adjacency_list_bit_index_select,
+        pos_predicate_wavelet_tree_bits,
+        pos_predicate_wavelet_tree_bit_index_blocks,
+        pos_predicate_wavelet_tree_bit_index_sblocks,
+        pos_predicate_wavelet_tree_bit_index_select
+    );
+    let pos_sp_o_names = adjacency_list_names!(
+        pos_sp_o_adjacency_list_nums,
+        pos_sp_o_adjacency_list_bits,
+        pos_sp_o_adjacency_list_bit_index_blocks,
+        pos_sp_o_adjacency_list_bit_index_sblocks,
+        pos_sp_o_adjacency_list_bit_index_select
+    );
+    let pos_o_ps_names = adjacency_list_names!(
+        pos_o_ps_adjacency_list_nums,
+        pos_o_ps_adjacency_list_bits,
+        pos_o_ps_adjacency_list_bit_index_blocks,
+        pos_o_ps_adjacency_list_bit_index_sblocks,
+        pos_o_ps_adjacency_list_bit_index_select
+    );
+    let (neg_s_p_names, neg_wavelet_names) = adjacency_list_names!(
+        neg_s_p_adjacency_list_nums,
+        neg_s_p_adjacency_list_bits,
+        neg_s_p_adjacency_list_bit_index_blocks,
+        neg_s_p_adjacency_list_bit_index_sblocks,
+        neg_s_p_adjacency_list_bit_index_select,
+        neg_predicate_wavelet_tree_bits,
+        neg_predicate_wavelet_tree_bit_index_blocks,
+        neg_predicate_wavelet_tree_bit_index_sblocks,
+        neg_predicate_wavelet_tree_bit_index_select
+    );
+    let neg_sp_o_names = adjacency_list_names!(
+        neg_sp_o_adjacency_list_nums,
+        neg_sp_o_adjacency_list_bits,
+        neg_sp_o_adjacency_list_bit_index_blocks,
+        neg_sp_o_adjacency_list_bit_index_sblocks,
+        neg_sp_o_adjacency_list_bit_index_select
+    );
+    let neg_o_ps_names = adjacency_list_names!(
+        neg_o_ps_adjacency_list_nums,
+        neg_o_ps_adjacency_list_bits,
+        neg_o_ps_adjacency_list_bit_index_blocks,
+        neg_o_ps_adjacency_list_bit_index_sblocks,
+        neg_o_ps_adjacency_list_bit_index_select
+    );
+
+    let mut file_list = dictionary_and_id_map_files(
+        &files.node_dictionary_files,
+        &files.predicate_dictionary_files,
+        &files.value_dictionary_files,
+        &files.id_map_files,
+    );
+    file_list.push((FILENAMES.pos_subjects, files.pos_subjects_file.clone()));
+    file_list.push((FILENAMES.pos_objects, files.pos_objects_file.clone()));
+    file_list.push((FILENAMES.neg_subjects, files.neg_subjects_file.clone()));
+    file_list.push((FILENAMES.neg_objects, files.neg_objects_file.clone()));
+    file_list.extend(adjacency_list_files(
+        &pos_s_p_names,
+        &files.pos_s_p_adjacency_list_files,
+    ));
+    file_list.extend(adjacency_list_files(
+        &pos_sp_o_names,
+        &files.pos_sp_o_adjacency_list_files,
+    ));
+    file_list.extend(adjacency_list_files(
+        &pos_o_ps_names,
+        &files.pos_o_ps_adjacency_list_files,
+    ));
+    file_list.extend(adjacency_list_files(
+        &neg_s_p_names,
+        &files.neg_s_p_adjacency_list_files,
+    ));
+    file_list.extend(adjacency_list_files(
+        &neg_sp_o_names,
+        &files.neg_sp_o_adjacency_list_files,
+    ));
+    file_list.extend(adjacency_list_files(
+        &neg_o_ps_names,
+        &files.neg_o_ps_adjacency_list_files,
+    ));
+    file_list.extend(bitindex_files(
+        &pos_wavelet_names,
+        &files.pos_predicate_wavelet_tree_files,
+    ));
+    file_list.extend(bitindex_files(
+        &neg_wavelet_names,
+        &files.neg_predicate_wavelet_tree_files,
+    ));
+    file_list.extend(search_index_files(&files.search_index_files));
+    file_list.extend(statistics_files(&files.statistics_file));
+
+    let mut v = Verifier::new();
+    let maps = v.map_files(file_list).await;
+    v.checksums(&maps, &files.checksums_file).await;
+    v.statistics(&maps);
+
+    verify_dictionaries(&mut v, &maps);
+
+    let pos_subjects = v.monotonic_logarray(&maps, FILENAMES.pos_subjects);
+    let pos_objects = v.monotonic_logarray(&maps, FILENAMES.pos_objects);
+    let neg_subjects = v.monotonic_logarray(&maps, FILENAMES.neg_subjects);
+    let neg_objects = v.monotonic_logarray(&maps, FILENAMES.neg_objects);
+
+    let pos_s_p = v.adjacency_list(&maps, &pos_s_p_names);
+    let pos_sp_o = v.adjacency_list(&maps, &pos_sp_o_names);
+    let pos_o_ps = v.adjacency_list(&maps, &pos_o_ps_names);
+    let neg_s_p = v.adjacency_list(&maps, &neg_s_p_names);
+    let neg_sp_o = v.adjacency_list(&maps, &neg_sp_o_names);
+    let neg_o_ps = v.adjacency_list(&maps, &neg_o_ps_names);
+
+    v.predicate_wavelet_tree(&maps, &pos_wavelet_names, pos_s_p.as_ref());
+    v.predicate_wavelet_tree(&maps, &neg_wavelet_names, neg_s_p.as_ref());
+
+    v.triple_structures(TripleStructures {
+        description: "positive",
+        subjects: pos_subjects.as_ref(),
+        objects: pos_objects.as_ref(),
+        s_p: pos_s_p.as_ref(),
+        sp_o: pos_sp_o.as_ref(),
+        o_ps: pos_o_ps.as_ref(),
+    });
+    v.triple_structures(TripleStructures {
+        description: "negative",
+        subjects: neg_subjects.as_ref(),
+        objects: neg_objects.as_ref(),
+        s_p: neg_s_p.as_ref(),
+        sp_o: neg_sp_o.as_ref(),
+        o_ps: neg_o_ps.as_ref(),
+    });
+
+    v.issues
+}
+
+/// Verify the files of a layer.
+pub async fn verify_layer_files<F: 'static + FileLoad + FileStore>(
+    files: &LayerFiles<F>,
+) -> Vec<LayerIssue> {
+    match files {
+        LayerFiles::Base(files) => verify_base_layer_files(files).await,
+        LayerFiles::Child(files) => verify_child_layer_files(files).await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layer::*;
+    use crate::storage::memory::*;
+    use std::sync::Arc;
+    use tokio::io::AsyncWriteExt;
+    use tokio::runtime::{Handle, Runtime};
+
+    fn example_base_layer_files(handle: &Handle) -> BaseLayerFiles<MemoryBackedStore> {
+        let nodes = vec!["aaaaa", "baa", "bbbbb", "ccccc", "mooo"];
+        let predicates = vec!["abcde", "fghij", "klmno", "lll"];
+        let values = vec!["chicken", "cow", "dog", "pig", "zebra"];
+
+        let files = base_layer_memory_files();
+        let mut builder = BaseLayerFileBuilder::from_files(&files);
+        handle
+            .block_on(async {
+                builder
+                    .add_nodes(nodes.into_iter().map(|s| s.to_string()))
+                    .await?;
+                builder
+                    .add_predicates(predicates.into_iter().map(|s| s.to_string()))
+                    .await?;
+                builder
+                    .add_values(values.into_iter().map(|s| s.to_string()))
+                    .await?;
+
+                let mut builder = builder.into_phase2().await?;
+                builder.add_triple(1, 1, 1).await?;
+                builder.add_triple(2, 1, 1).await?;
+                builder.add_triple(2, 1, 3).await?;
+                builder.add_triple(2, 3, 6).await?;
+                builder.add_triple(3, 2, 5).await?;
+                builder.add_triple(3, 3, 6).await?;
+                builder.add_triple(4, 3, 6).await?;
+
+                builder.finalize().await
+            })
+            .unwrap();
+
+        files
+    }
+
+    #[test]
+    fn valid_layers_have_no_issues() {
+        let mut runtime = Runtime::new().unwrap();
+        let base_files = example_base_layer_files(runtime.handle());
+        assert_eq!(
+            Vec::<LayerIssue>::new(),
+            runtime.block_on(verify_base_layer_files(&base_files))
+        );
+
+        let base_layer = runtime
+            .block_on(BaseLayer::load_from_files([1, 2, 3, 4, 5], &base_files))
+            .unwrap();
+        let parent: Arc<InternalLayer> = Arc::new(base_layer.into());
+        let child_files = child_layer_memory_files();
+        let child_builder = ChildLayerFileBuilder::from_files(parent, &child_files);
+        runtime
+            .block_on(async {
+                let mut b = child_builder.into_phase2().await?;
+                b.add_triple(2, 1, 2).await?;
+                b.add_triple(3, 3, 3).await?;
+                b.remove_triple(3, 2, 5).await?;
+                b.finalize().await
+            })
+            .unwrap();
+
+        assert_eq!(
+            Vec::<LayerIssue>::new(),
+            runtime.block_on(verify_child_layer_files(&child_files))
+        );
+    }
+
+    #[test]
+    fn missing_files_are_reported() {
+        let mut runtime = Runtime::new().unwrap();
+        let mut files = example_base_layer_files(runtime.handle());
+        files.s_p_adjacency_list_files.nums_file = MemoryBackedStore::new();
+
+        let issues = runtime.block_on(verify_base_layer_files(&files));
+        assert_eq!(
+            vec![LayerIssue::MissingFile(
+                FILENAMES.base_s_p_adjacency_list_nums
+            )],
+            issues
+        );
+    }
+
+    #[test]
+    fn corrupt_files_are_reported() {
+        let mut runtime = Runtime::new().unwrap();
+        let mut files = example_base_layer_files(runtime.handle());
+        let garbage = MemoryBackedStore::new();
+        runtime
+            .block_on(garbage.open_write().write_all(&[1, 2, 3]))
+            .unwrap();
+        files.sp_o_adjacency_list_files.nums_file = garbage;
+        // only look at the structural problem here
+        files.checksums_file = MemoryBackedStore::new();
+
+        let issues = runtime.block_on(verify_base_layer_files(&files));
+        assert_eq!(1, issues.len());
+        match &issues[0] {
+            LayerIssue::Corrupt { file, .. } => {
+                assert_eq!(FILENAMES.base_sp_o_adjacency_list_nums, *file)
+            }
+            issue => panic!("unexpected issue {}", issue),
+        }
+    }
+
+    #[test]
+    fn unsorted_dictionary_is_reported() {
+        let mut runtime = Runtime::new().unwrap();
+        let mut files = example_base_layer_files(runtime.handle());
+        let blocks = MemoryBackedStore::new();
+        let offsets = MemoryBackedStore::new();
+        let mut builder = PfcDictFileBuilder::new(blocks.open_write(), offsets.open_write());
+        runtime
+            .block_on(async {
+                builder.add("aaaaa").await?;
+                builder.add("ccccc").await?;
+                builder.add("bbbbb").await?;
+                builder.add("ddddd").await?;
+                builder.add("eeeee").await?;
+                builder.finalize().await
+            })
+            .unwrap();
+        files.node_dictionary_files.blocks_file = blocks;
+        files.node_dictionary_files.offsets_file = offsets;
+        files.checksums_file = MemoryBackedStore::new();
+
+        let issues = runtime.block_on(verify_base_layer_files(&files));
+        assert_eq!(1, issues.len());
+        match &issues[0] {
+            LayerIssue::Corrupt { file, reason } => {
+                assert_eq!(FILENAMES.node_dictionary_blocks, *file);
+                assert!(reason.contains("entry 2"));
+            }
+            issue => panic!("unexpected issue {}", issue),
+        }
+    }
+
+    #[test]
+    fn checksum_mismatches_are_reported() {
+        let mut runtime = Runtime::new().unwrap();
+        let files = example_base_layer_files(runtime.handle());
+        let file = &files.value_dictionary_files.blocks_file;
+        let mut data = runtime.block_on(file.map()).unwrap().to_vec();
+        // flip a bit in one of the strings, which still makes for a valid dictionary
+        data[0] ^= 1;
+        runtime
+            .block_on(file.open_write().write_all(&data))
+            .unwrap();
+
+        let issues = runtime.block_on(verify_base_layer_files(&files));
+        assert_eq!(
+            vec![LayerIssue::ChecksumMismatch(
+                FILENAMES.value_dictionary_blocks
+            )],
+            issues
+        );
+    }
+
+    #[test]
+    fn mismatched_indexes_are_reported() {
+        let mut runtime = Runtime::new().unwrap();
+        let mut files = example_base_layer_files(runtime.handle());
+        let other_files = base_layer_memory_files();
+        runtime
+            .block_on(async {
+                let mut builder = BaseLayerFileBuilder::from_files(&other_files);
+                builder.add_node("a").await?;
+                builder.add_predicate("p").await?;
+                let mut builder = builder.into_phase2().await?;
+                builder.add_triple(1, 1, 1).await?;
+                builder.finalize().await
+            })
+            .unwrap();
+        files.o_ps_adjacency_list_files = other_files.o_ps_adjacency_list_files;
+
+        let issues = runtime.block_on(verify_base_layer_files(&files));
+        assert!(issues.iter().any(|issue| match issue {
+            LayerIssue::Inconsistent(reason) => reason.contains("o_ps adjacency list has 1"),
+            _ => false,
+        }));
+    }
+}
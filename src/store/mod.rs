@@ -7,13 +7,18 @@ use std::path::PathBuf;
 use std::sync::{Arc, RwLock};
 
 use crate::layer::{
-    IdTriple, Layer, LayerBuilder, LayerCounts, LayerObjectLookup, LayerPredicateLookup,
-    LayerSubjectLookup, ObjectLookup, ObjectType, PredicateLookup, StringTriple, SubjectLookup,
+    IdPattern, IdTriple, Layer, LayerBuilder, LayerCounts, LayerObjectLookup, LayerOptions,
+    LayerPredicateLookup, LayerSubjectLookup, ObjectLookup, ObjectType, PredicateLookup,
+    StringTriple, SubjectLookup, TrieOrder, TripleTrieIterator,
 };
-use crate::storage::directory::{DirectoryLabelStore, DirectoryLayerStore};
+use crate::storage::directory::{DirectoryLabelStore, DirectoryLayerStore, FileMapMode};
 use crate::storage::memory::{MemoryLabelStore, MemoryLayerStore};
-use crate::storage::{CachedLayerStore, LabelStore, LayerStore, LockingHashMapLayerCache};
+use crate::storage::{
+    CachedLayerStore, LabelStore, LayerStore, LockingHashMapLayerCache, VerificationReport,
+};
 
+use crate::structure::SearchPattern;
+use std::collections::{HashMap, HashSet};
 use std::io;
 
 use rayon;
@@ -42,8 +47,11 @@ pub struct StoreLayerBuilder {
 }
 
 impl StoreLayerBuilder {
-    async fn new(store: Store) -> io::Result<Self> {
-        let builder = store.layer_store.create_base_layer().await?;
+    async fn new(store: Store, options: LayerOptions) -> io::Result<Self> {
+        let builder = store
+            .layer_store
+            .create_base_layer_with_options(options)
+            .await?;
 
         Ok(Self {
             parent: builder.parent(),
@@ -231,6 +239,20 @@ impl StoreLayer {
         Ok(StoreLayerBuilder::wrap(layer, self.store.clone()))
     }
 
+    /// Create a builder for a child layer on top of this layer, which will be built according to the given options.
+    pub async fn open_write_with_options(
+        &self,
+        options: LayerOptions,
+    ) -> io::Result<StoreLayerBuilder> {
+        let layer = self
+            .store
+            .layer_store
+            .create_child_layer_with_options(self.layer.name(), options)
+            .await?;
+
+        Ok(StoreLayerBuilder::wrap(layer, self.store.clone()))
+    }
+
     pub async fn parent(&self) -> io::Result<Option<StoreLayer>> {
         let parent_name = self.layer.parent_name();
 
@@ -303,6 +325,10 @@ impl Layer for StoreLayer {
         self.layer.id_object(id)
     }
 
+    fn id_object_is_node(&self, id: u64) -> Option<bool> {
+        self.layer.id_object_is_node(id)
+    }
+
     fn subjects(&self) -> Box<dyn Iterator<Item = Box<dyn SubjectLookup>>> {
         self.layer.subjects()
     }
@@ -331,6 +357,21 @@ impl Layer for StoreLayer {
         self.layer.objects()
     }
 
+    fn subjects_with_prefix(
+        &self,
+        prefix: &str,
+    ) -> Box<dyn Iterator<Item = Box<dyn SubjectLookup>>> {
+        self.layer.subjects_with_prefix(prefix)
+    }
+
+    fn objects_with_prefix(&self, prefix: &str) -> Box<dyn Iterator<Item = Box<dyn ObjectLookup>>> {
+        self.layer.objects_with_prefix(prefix)
+    }
+
+    fn search_values(&self, pattern: &SearchPattern) -> Box<dyn Iterator<Item = u64>> {
+        self.layer.search_values(pattern)
+    }
+
     fn object_additions(&self) -> Box<dyn Iterator<Item = Box<dyn LayerObjectLookup>>> {
         self.layer.object_additions()
     }
@@ -460,6 +501,38 @@ impl Layer for StoreLayer {
         self.layer.triple_removals_o(object)
     }
 
+    fn triple_count_by_subject(&self) -> HashMap<u64, usize> {
+        self.layer.triple_count_by_subject()
+    }
+
+    fn triple_count_by_predicate(&self) -> HashMap<u64, usize> {
+        self.layer.triple_count_by_predicate()
+    }
+
+    fn count_s(&self, subject: u64) -> usize {
+        self.layer.count_s(subject)
+    }
+
+    fn count_sp(&self, subject: u64, predicate: u64) -> usize {
+        self.layer.count_sp(subject, predicate)
+    }
+
+    fn count_p(&self, predicate: u64) -> usize {
+        self.layer.count_p(predicate)
+    }
+
+    fn count_o(&self, object: u64) -> usize {
+        self.layer.count_o(object)
+    }
+
+    fn estimate(&self, pattern: &IdPattern) -> f64 {
+        self.layer.estimate(pattern)
+    }
+
+    fn triple_trie(&self, order: TrieOrder) -> Box<dyn TripleTrieIterator> {
+        self.layer.triple_trie(order)
+    }
+
     fn clone_boxed(&self) -> Box<dyn Layer> {
         Box::new(self.clone())
     }
@@ -607,7 +680,15 @@ impl Store {
     ///
     /// After having committed it, use `set_head` on a `NamedGraph` to attach it.
     pub async fn create_base_layer(&self) -> io::Result<StoreLayerBuilder> {
-        StoreLayerBuilder::new(self.clone()).await
+        StoreLayerBuilder::new(self.clone(), LayerOptions::default()).await
+    }
+
+    /// Create a builder for a base layer, which will be built according to the given options.
+    pub async fn create_base_layer_with_options(
+        &self,
+        options: LayerOptions,
+    ) -> io::Result<StoreLayerBuilder> {
+        StoreLayerBuilder::new(self.clone(), options).await
     }
 
     pub fn export_layers(&self, layer_ids: Box<dyn Iterator<Item = [u32; 5]>>) -> Vec<u8> {
@@ -620,6 +701,42 @@ impl Store {
     ) -> Result<(), io::Error> {
         self.layer_store.import_layers(pack, layer_ids)
     }
+
+    /// Verify the integrity of every layer and label in this store
+    ///
+    /// Every layer is checked for missing files, files that fail to
+    /// parse, and structures that disagree with each other. Labels
+    /// are checked to point at existing layers. Problems are
+    /// collected in the returned report rather than returned as
+    /// errors, which are reserved for failures to access the store
+    /// itself.
+    pub async fn verify(&self) -> io::Result<VerificationReport> {
+        let mut report = VerificationReport::default();
+        let mut seen = HashSet::new();
+
+        for name in self.layer_store.layers().await? {
+            if seen.insert(name) {
+                if let Some(layer) = self.layer_store.verify_layer(name).await? {
+                    report.layers.push(layer);
+                }
+            }
+        }
+
+        for label in self.label_store.labels().await? {
+            if let Some(name) = label.layer {
+                if seen.insert(name) {
+                    match self.layer_store.verify_layer(name).await? {
+                        Some(layer) => report.layers.push(layer),
+                        None => report.dangling_labels.push(label),
+                    }
+                } else if !report.layers.iter().any(|l| l.name == name) {
+                    report.dangling_labels.push(label);
+                }
+            }
+        }
+
+        Ok(report)
+    }
 }
 
 /// Open a store that is entirely in memory
@@ -633,17 +750,34 @@ pub fn open_memory_store() -> Store {
 }
 
 /// Open a store that stores its data in the given directory
-pub fn open_directory_store<P: Into<PathBuf>>(path: P) -> Store {
+///
+/// Layers that were left half-written by a crashed process are
+/// cleaned up on open, unless some store is still writing a layer in
+/// the same directory. An error is returned if they could not be
+/// removed.
+pub fn open_directory_store<P: Into<PathBuf>>(path: P) -> io::Result<Store> {
+    open_directory_store_with_map_mode(path, FileMapMode::default())
+}
+
+/// Open a store that stores its data in the given directory, loading layer files according to the given map mode
+pub fn open_directory_store_with_map_mode<P: Into<PathBuf>>(
+    path: P,
+    map_mode: FileMapMode,
+) -> io::Result<Store> {
     let p = path.into();
-    Store::new(
-        DirectoryLabelStore::new(p.clone()),
-        CachedLayerStore::new(DirectoryLayerStore::new(p), LockingHashMapLayerCache::new()),
-    )
+    let layer_store = DirectoryLayerStore::new(p.clone()).with_map_mode(map_mode);
+    layer_store.remove_staging_directories()?;
+
+    Ok(Store::new(
+        DirectoryLabelStore::new(p),
+        CachedLayerStore::new(layer_store, LockingHashMapLayerCache::new()),
+    ))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::structure::BitIndexEncoding;
     use tempfile::tempdir;
     use tokio::runtime::Runtime;
 
@@ -689,11 +823,251 @@ mod tests {
     fn create_and_manipulate_directory_database() {
         let runtime = Runtime::new().unwrap();
         let dir = tempdir().unwrap();
-        let store = open_directory_store(dir.path());
+        let store = open_directory_store(dir.path()).unwrap();
 
         create_and_manipulate_database(runtime, store);
     }
 
+    #[test]
+    fn opening_directory_store_keeps_layers_being_written_elsewhere() {
+        let mut runtime = Runtime::new().unwrap();
+        let dir = tempdir().unwrap();
+
+        let abandoned_name = {
+            let store = open_directory_store(dir.path()).unwrap();
+            let builder = runtime.block_on(store.create_base_layer()).unwrap();
+            builder
+                .add_string_triple(StringTriple::new_value("cow", "says", "moo"))
+                .unwrap();
+
+            // another store opening the same directory must not clean up our staging directory
+            let store2 = open_directory_store(dir.path()).unwrap();
+            let layer = runtime.block_on(builder.commit()).unwrap();
+            let layer2 = runtime
+                .block_on(store2.get_layer_from_id(layer.name()))
+                .unwrap()
+                .unwrap();
+            assert!(layer2.string_triple_exists(&StringTriple::new_value("cow", "says", "moo")));
+
+            let abandoned = runtime.block_on(store2.create_base_layer()).unwrap();
+            abandoned.name()
+        };
+
+        // once nothing else has the directory open, abandoned layers are removed
+        let _store = open_directory_store(dir.path()).unwrap();
+        assert!(!dir
+            .path()
+            .join(".staging")
+            .join(crate::storage::name_to_string(abandoned_name))
+            .exists());
+    }
+
+    /// Build a base layer and a child layer with the given options,
+    /// then load the child layer from a reopened store and check that
+    /// it has the expected triples and that the store verifies.
+    ///
+    /// The base layer is a chain of 1000 nodes and a few animal
+    /// sounds. The child layer names every 97th node, adds one more
+    /// sound, and breaks the chain between node500 and node501.
+    fn build_and_reopen_layers(
+        runtime: &mut Runtime,
+        dir: &std::path::Path,
+        base_options: LayerOptions,
+        child_options: LayerOptions,
+    ) -> StoreLayer {
+        let store = open_directory_store(dir).unwrap();
+        let builder = runtime
+            .block_on(store.create_base_layer_with_options(base_options))
+            .unwrap();
+        for i in 0..1000 {
+            builder
+                .add_string_triple(StringTriple::new_node(
+                    &format!("node{}", i),
+                    "next",
+                    &format!("node{}", i + 1),
+                ))
+                .unwrap();
+        }
+        builder
+            .add_string_triple(StringTriple::new_value("cow", "says", "moo"))
+            .unwrap();
+        builder
+            .add_string_triple(StringTriple::new_value("duck", "says", "quack"))
+            .unwrap();
+        let base_layer = runtime.block_on(builder.commit()).unwrap();
+
+        let builder = runtime
+            .block_on(base_layer.open_write_with_options(child_options))
+            .unwrap();
+        for i in (0..1000).step_by(97) {
+            builder
+                .add_string_triple(StringTriple::new_value(
+                    &format!("node{}", i),
+                    "name",
+                    &format!("name {}", i),
+                ))
+                .unwrap();
+        }
+        builder
+            .add_string_triple(StringTriple::new_value("pig", "says", "oink"))
+            .unwrap();
+        builder
+            .remove_string_triple(StringTriple::new_node("node500", "next", "node501"))
+            .unwrap();
+        let name = runtime.block_on(builder.commit()).unwrap().name();
+
+        let store = open_directory_store(dir).unwrap();
+        let layer = runtime
+            .block_on(store.get_layer_from_id(name))
+            .unwrap()
+            .unwrap();
+
+        for i in 0..1000 {
+            let triple =
+                StringTriple::new_node(&format!("node{}", i), "next", &format!("node{}", i + 1));
+            assert_eq!(i != 500, layer.string_triple_exists(&triple));
+        }
+        for i in (0..1000).step_by(97) {
+            assert!(layer.string_triple_exists(&StringTriple::new_value(
+                &format!("node{}", i),
+                "name",
+                &format!("name {}", i),
+            )));
+        }
+        assert!(layer.string_triple_exists(&StringTriple::new_value("cow", "says", "moo")));
+        assert!(layer.string_triple_exists(&StringTriple::new_value("pig", "says", "oink")));
+        assert_eq!(1000 - 1 + 11 + 3, layer.triples().count());
+        assert!(runtime.block_on(store.verify()).unwrap().is_ok());
+
+        layer
+    }
+
+    #[test]
+    fn search_values_in_reopened_directory_store() {
+        let mut runtime = Runtime::new().unwrap();
+        let dir = tempdir().unwrap();
+        let options = LayerOptions {
+            search_index: true,
+            ..Default::default()
+        };
+        let layer = build_and_reopen_layers(&mut runtime, dir.path(), options, options);
+
+        let found: Vec<_> = layer
+            .search_values(&SearchPattern::substring("k"))
+            .map(|id| layer.id_object(id).unwrap())
+            .collect();
+        assert_eq!(
+            vec![
+                ObjectType::Node("duck".to_string()),
+                ObjectType::Value("quack".to_string()),
+                ObjectType::Value("oink".to_string()),
+            ],
+            found
+        );
+    }
+
+    #[test]
+    fn layers_with_custom_dictionary_block_size() {
+        let mut runtime = Runtime::new().unwrap();
+        let dir = tempdir().unwrap();
+        let options = LayerOptions {
+            dictionary_block_size: 32,
+            ..Default::default()
+        };
+
+        // the child layer uses the default block size
+        build_and_reopen_layers(&mut runtime, dir.path(), options, LayerOptions::default());
+    }
+
+    #[test]
+    fn child_layer_with_elias_fano_lists() {
+        let mut runtime = Runtime::new().unwrap();
+        let dir = tempdir().unwrap();
+        let options = LayerOptions {
+            elias_fano: true,
+            ..Default::default()
+        };
+        let layer =
+            build_and_reopen_layers(&mut runtime, dir.path(), LayerOptions::default(), options);
+
+        let name_string = crate::storage::name_to_string(layer.name());
+        let layer_path = dir.path().join(&name_string[0..3]).join(&name_string);
+        for file in &[
+            "child_pos_subjects.logarray",
+            "value_dictionary_offsets.logarray",
+        ] {
+            let data = std::fs::read(layer_path.join(file)).unwrap();
+            assert!(crate::structure::is_elias_fano(&data), "{}", file);
+        }
+    }
+
+    #[test]
+    fn layers_with_rrr_bit_indexes() {
+        let mut runtime = Runtime::new().unwrap();
+        let dir = tempdir().unwrap();
+        let options = LayerOptions {
+            bit_index_encoding: BitIndexEncoding::Rrr,
+            ..Default::default()
+        };
+        let layer = build_and_reopen_layers(&mut runtime, dir.path(), options, options);
+
+        let next = layer.predicate_id("next").unwrap();
+        assert_eq!(999, layer.triples_p(next).count());
+        let node7 = layer.object_node_id("node7").unwrap();
+        assert_eq!(1, layer.triples_o(node7).count());
+    }
+
+    #[test]
+    fn layers_with_select_indexes() {
+        let mut runtime = Runtime::new().unwrap();
+        let dir = tempdir().unwrap();
+        let options = LayerOptions {
+            select_sample_rate: Some(16),
+            ..Default::default()
+        };
+        let layer = build_and_reopen_layers(&mut runtime, dir.path(), options, options);
+
+        let node97 = layer.subject_id("node97").unwrap();
+        assert_eq!(2, layer.triples_s(node97).count());
+        let name_predicate = layer.predicate_id("name").unwrap();
+        assert_eq!(11, layer.triples_p(name_predicate).count());
+
+        // a select index that disagrees with its bit index is reported
+        let name_string = crate::storage::name_to_string(layer.parent_name().unwrap());
+        let path = dir
+            .path()
+            .join(&name_string[0..3])
+            .join(&name_string)
+            .join("base_sp_o_adjacency_list_bit_index_select.bin");
+        let mut data = std::fs::read(&path).unwrap();
+        data[0] ^= 0x80;
+        std::fs::write(&path, data).unwrap();
+
+        let store = open_directory_store(dir.path()).unwrap();
+        let report = runtime.block_on(store.verify()).unwrap();
+        assert!(!report.is_ok());
+    }
+
+    #[test]
+    fn invalid_dictionary_block_size_fails_commit() {
+        let mut runtime = Runtime::new().unwrap();
+        let store = open_memory_store();
+
+        let options = LayerOptions {
+            dictionary_block_size: 0,
+            ..Default::default()
+        };
+        let builder = runtime
+            .block_on(store.create_base_layer_with_options(options))
+            .unwrap();
+        builder
+            .add_string_triple(StringTriple::new_value("cow", "says", "moo"))
+            .unwrap();
+
+        let error = runtime.block_on(builder.commit()).err().unwrap();
+        assert_eq!(io::ErrorKind::InvalidInput, error.kind());
+    }
+
     #[test]
     fn create_layer_and_retrieve_it_by_id() {
         let mut runtime = Runtime::new().unwrap();
@@ -846,4 +1220,47 @@ mod tests {
         assert!(rebase_layer.string_triple_exists(&StringTriple::new_value("dog", "says", "woof")));
         assert!(!rebase_layer.string_triple_exists(&StringTriple::new_value("cat", "says", "meow")));
     }
+
+    #[test]
+    fn verify_store_reports_dangling_labels() {
+        let mut runtime = Runtime::new().unwrap();
+
+        let store = open_memory_store();
+        let database = runtime.block_on(store.create("foodb")).unwrap();
+        let builder = runtime.block_on(store.create_base_layer()).unwrap();
+        builder
+            .add_string_triple(StringTriple::new_value("cow", "says", "moo"))
+            .unwrap();
+        let layer = runtime.block_on(builder.commit()).unwrap();
+
+        let builder2 = runtime.block_on(layer.open_write()).unwrap();
+        builder2
+            .add_string_triple(StringTriple::new_value("dog", "says", "woof"))
+            .unwrap();
+        builder2
+            .remove_string_triple(StringTriple::new_value("cow", "says", "moo"))
+            .unwrap();
+        let layer2 = runtime.block_on(builder2.commit()).unwrap();
+        let squashed = runtime.block_on(layer2.squash()).unwrap();
+        assert!(runtime.block_on(database.set_head(&squashed)).unwrap());
+
+        let report = runtime.block_on(store.verify()).unwrap();
+        assert!(report.is_ok());
+        assert_eq!(3, report.layers.len());
+
+        let dangling = runtime.block_on(store.create("dangling")).unwrap();
+        let label = runtime
+            .block_on(store.label_store.get_label(dangling.name()))
+            .unwrap()
+            .unwrap();
+        runtime
+            .block_on(store.label_store.set_label(&label, [1, 2, 3, 4, 5]))
+            .unwrap();
+
+        let report = runtime.block_on(store.verify()).unwrap();
+        assert!(!report.is_ok());
+        assert_eq!(0, report.failed_layers().count());
+        assert_eq!(1, report.dangling_labels.len());
+        assert_eq!("dangling", report.dangling_labels[0].name);
+    }
 }
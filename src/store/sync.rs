@@ -6,16 +6,22 @@
 use futures::Future;
 use tokio::runtime::Runtime;
 
+use std::collections::HashMap;
 use std::io;
 use std::path::PathBuf;
 
 use crate::layer::{
-    IdTriple, Layer, LayerCounts, LayerObjectLookup, LayerPredicateLookup, LayerSubjectLookup,
-    ObjectLookup, ObjectType, PredicateLookup, StringTriple, SubjectLookup,
+    IdPattern, IdTriple, Layer, LayerCounts, LayerObjectLookup, LayerOptions, LayerPredicateLookup,
+    LayerSubjectLookup, ObjectLookup, ObjectType, PredicateLookup, StringTriple, SubjectLookup,
+    TrieOrder, TripleTrieIterator,
 };
+use crate::storage::directory::FileMapMode;
+use crate::storage::VerificationReport;
 use crate::store::{
-    open_directory_store, open_memory_store, NamedGraph, Store, StoreLayer, StoreLayerBuilder,
+    open_directory_store, open_directory_store_with_map_mode, open_memory_store, NamedGraph, Store,
+    StoreLayer, StoreLayerBuilder,
 };
+use crate::structure::SearchPattern;
 
 lazy_static! {
     static ref RUNTIME: Runtime = Runtime::new().unwrap();
@@ -117,6 +123,16 @@ impl SyncStoreLayer {
         inner.map(|i| SyncStoreLayerBuilder::wrap(i))
     }
 
+    /// Create a builder for a child layer on top of this layer, which will be built according to the given options.
+    pub fn open_write_with_options(
+        &self,
+        options: LayerOptions,
+    ) -> Result<SyncStoreLayerBuilder, io::Error> {
+        let inner = task_sync(self.inner.open_write_with_options(options));
+
+        inner.map(SyncStoreLayerBuilder::wrap)
+    }
+
     pub fn parent(&self) -> Result<Option<SyncStoreLayer>, io::Error> {
         let inner = task_sync(self.inner.parent());
         inner.map(|p| p.map(|p| SyncStoreLayer { inner: p }))
@@ -174,6 +190,10 @@ impl Layer for SyncStoreLayer {
         self.inner.id_object(id)
     }
 
+    fn id_object_is_node(&self, id: u64) -> Option<bool> {
+        self.inner.id_object_is_node(id)
+    }
+
     fn subjects(&self) -> Box<dyn Iterator<Item = Box<dyn SubjectLookup>>> {
         self.inner.subjects()
     }
@@ -202,6 +222,21 @@ impl Layer for SyncStoreLayer {
         self.inner.objects()
     }
 
+    fn subjects_with_prefix(
+        &self,
+        prefix: &str,
+    ) -> Box<dyn Iterator<Item = Box<dyn SubjectLookup>>> {
+        self.inner.subjects_with_prefix(prefix)
+    }
+
+    fn objects_with_prefix(&self, prefix: &str) -> Box<dyn Iterator<Item = Box<dyn ObjectLookup>>> {
+        self.inner.objects_with_prefix(prefix)
+    }
+
+    fn search_values(&self, pattern: &SearchPattern) -> Box<dyn Iterator<Item = u64>> {
+        self.inner.search_values(pattern)
+    }
+
     fn object_additions(&self) -> Box<dyn Iterator<Item = Box<dyn LayerObjectLookup>>> {
         self.inner.object_additions()
     }
@@ -331,6 +366,38 @@ impl Layer for SyncStoreLayer {
         self.inner.triple_removals_o(object)
     }
 
+    fn triple_count_by_subject(&self) -> HashMap<u64, usize> {
+        self.inner.triple_count_by_subject()
+    }
+
+    fn triple_count_by_predicate(&self) -> HashMap<u64, usize> {
+        self.inner.triple_count_by_predicate()
+    }
+
+    fn count_s(&self, subject: u64) -> usize {
+        self.inner.count_s(subject)
+    }
+
+    fn count_sp(&self, subject: u64, predicate: u64) -> usize {
+        self.inner.count_sp(subject, predicate)
+    }
+
+    fn count_p(&self, predicate: u64) -> usize {
+        self.inner.count_p(predicate)
+    }
+
+    fn count_o(&self, object: u64) -> usize {
+        self.inner.count_o(object)
+    }
+
+    fn estimate(&self, pattern: &IdPattern) -> f64 {
+        self.inner.estimate(pattern)
+    }
+
+    fn triple_trie(&self, order: TrieOrder) -> Box<dyn TripleTrieIterator> {
+        self.inner.triple_trie(order)
+    }
+
     fn clone_boxed(&self) -> Box<dyn Layer> {
         Box::new(self.clone())
     }
@@ -441,6 +508,16 @@ impl SyncStore {
         inner.map(|i| SyncStoreLayerBuilder::wrap(i))
     }
 
+    /// Create a builder for a base layer, which will be built according to the given options.
+    pub fn create_base_layer_with_options(
+        &self,
+        options: LayerOptions,
+    ) -> Result<SyncStoreLayerBuilder, io::Error> {
+        let inner = task_sync(self.inner.create_base_layer_with_options(options));
+
+        inner.map(SyncStoreLayerBuilder::wrap)
+    }
+
     pub fn export_layers(&self, layer_ids: Box<dyn Iterator<Item = [u32; 5]>>) -> Vec<u8> {
         self.inner.layer_store.export_layers(layer_ids)
     }
@@ -451,6 +528,11 @@ impl SyncStore {
     ) -> Result<(), io::Error> {
         self.inner.layer_store.import_layers(pack, layer_ids)
     }
+
+    /// Verify the integrity of every layer and label in this store
+    pub fn verify(&self) -> Result<VerificationReport, io::Error> {
+        task_sync(self.inner.verify())
+    }
 }
 
 /// Open a store that is entirely in memory
@@ -461,8 +543,18 @@ pub fn open_sync_memory_store() -> SyncStore {
 }
 
 /// Open a store that stores its data in the given directory
-pub fn open_sync_directory_store<P: Into<PathBuf>>(path: P) -> SyncStore {
-    SyncStore::wrap(open_directory_store(path))
+///
+/// See `open_directory_store` for how half-written layers are cleaned up.
+pub fn open_sync_directory_store<P: Into<PathBuf>>(path: P) -> io::Result<SyncStore> {
+    open_directory_store(path).map(SyncStore::wrap)
+}
+
+/// Open a store that stores its data in the given directory, loading layer files according to the given map mode
+pub fn open_sync_directory_store_with_map_mode<P: Into<PathBuf>>(
+    path: P,
+    map_mode: FileMapMode,
+) -> io::Result<SyncStore> {
+    open_directory_store_with_map_mode(path, map_mode).map(SyncStore::wrap)
 }
 
 #[cfg(test)]
@@ -502,10 +594,25 @@ mod tests {
         assert!(layer.string_triple_exists(&StringTriple::new_value("pig", "says", "oink")));
     }
 
+    #[test]
+    fn memory_mapped_sync_directory_store() {
+        let dir = tempdir().unwrap();
+        let store = open_sync_directory_store_with_map_mode(dir.path(), FileMapMode::Mmap).unwrap();
+        let builder = store.create_base_layer().unwrap();
+        builder
+            .add_string_triple(StringTriple::new_value("cow", "says", "moo"))
+            .unwrap();
+        let name = builder.commit().unwrap().name();
+
+        let store = open_sync_directory_store_with_map_mode(dir.path(), FileMapMode::Mmap).unwrap();
+        let layer = store.get_layer_from_id(name).unwrap().unwrap();
+        assert!(layer.string_triple_exists(&StringTriple::new_value("cow", "says", "moo")));
+    }
+
     #[test]
     fn create_and_manipulate_sync_directory_database() {
         let dir = tempdir().unwrap();
-        let store = open_sync_directory_store(dir.path());
+        let store = open_sync_directory_store(dir.path()).unwrap();
         let database = store.create("foodb").unwrap();
 
         let head = database.head().unwrap();
@@ -570,10 +677,10 @@ mod tests {
     #[test]
     fn export_and_import_pack() {
         let dir1 = tempdir().unwrap();
-        let store1 = open_sync_directory_store(dir1.path());
+        let store1 = open_sync_directory_store(dir1.path()).unwrap();
 
         let dir2 = tempdir().unwrap();
-        let store2 = open_sync_directory_store(dir2.path());
+        let store2 = open_sync_directory_store(dir2.path()).unwrap();
 
         let builder1 = store1.create_base_layer().unwrap();
         builder1
@@ -11,6 +11,8 @@
 //! pair).
 
 use std::convert::TryInto;
+use std::error;
+use std::fmt;
 use std::pin::Pin;
 
 use bytes::Bytes;
@@ -24,6 +26,50 @@ use futures::future;
 use futures::stream::{Stream, StreamExt, TryStreamExt};
 use futures::task::{Context, Poll};
 
+/// An error that occurred while parsing an adjacency list.
+#[derive(Debug)]
+pub enum AdjacencyListError {
+    LogArray(LogArrayError),
+    BitIndex(BitIndexError),
+    /// The amount of numbers (`.0`) does not match the amount of bits (`.1`).
+    UnexpectedLength(usize, usize),
+}
+
+impl fmt::Display for AdjacencyListError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use AdjacencyListError::*;
+        match self {
+            LogArray(err) => write!(f, "invalid adjacency list numbers: {}", err),
+            BitIndex(err) => write!(f, "invalid adjacency list bits: {}", err),
+            UnexpectedLength(nums_len, bits_len) => write!(
+                f,
+                "expected adjacency list numbers length ({}) to be {}",
+                nums_len, bits_len
+            ),
+        }
+    }
+}
+
+impl error::Error for AdjacencyListError {}
+
+impl From<LogArrayError> for AdjacencyListError {
+    fn from(err: LogArrayError) -> AdjacencyListError {
+        AdjacencyListError::LogArray(err)
+    }
+}
+
+impl From<BitIndexError> for AdjacencyListError {
+    fn from(err: BitIndexError) -> AdjacencyListError {
+        AdjacencyListError::BitIndex(err)
+    }
+}
+
+impl From<AdjacencyListError> for io::Error {
+    fn from(err: AdjacencyListError) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, err)
+    }
+}
+
 #[derive(Clone)]
 pub struct AdjacencyList {
     pub nums: LogArray,
@@ -41,14 +87,25 @@ impl AdjacencyList {
         bits_slice: Bytes,
         bits_block_slice: Bytes,
         bits_sblock_slice: Bytes,
-    ) -> AdjacencyList {
-        let nums = LogArray::parse(nums_slice).unwrap();
-        let bit_array = BitArray::from_bits(bits_slice).unwrap();
-        let bits_block_array = LogArray::parse(bits_block_slice).unwrap();
-        let bits_sblock_array = LogArray::parse(bits_sblock_slice).unwrap();
-        let bits = BitIndex::from_parts(bit_array, bits_block_array, bits_sblock_array);
+    ) -> Result<AdjacencyList, AdjacencyListError> {
+        let nums = LogArray::parse(nums_slice)?;
+        let bits = BitIndex::from_maps(bits_slice, bits_block_slice, bits_sblock_slice)?;
+        if nums.len() != bits.len() {
+            return Err(AdjacencyListError::UnexpectedLength(nums.len(), bits.len()));
+        }
 
-        Self::from_parts(nums, bits)
+        Ok(Self::from_parts(nums, bits))
+    }
+
+    /// Use the select index in the given buffer, if there is one, to speed up `offset_for`.
+    pub fn with_select_map(
+        self,
+        select_map: Option<Bytes>,
+    ) -> Result<AdjacencyList, AdjacencyListError> {
+        Ok(AdjacencyList {
+            bits: self.bits.with_select_map(select_map)?,
+            ..self
+        })
     }
 
     pub fn left_count(&self) -> usize {
@@ -63,6 +120,11 @@ impl AdjacencyList {
         self.bits.len()
     }
 
+    /// Returns the size in bytes of the buffers backing this adjacency list.
+    pub fn size_in_bytes(&self) -> usize {
+        self.nums.size_in_bytes() + self.bits.size_in_bytes()
+    }
+
     pub fn offset_for(&self, index: u64) -> u64 {
         if index == 1 {
             0
@@ -210,7 +272,7 @@ pub fn adjacency_list_stream_pairs<F: 'static + FileLoad>(
     bits_file: F,
     nums_file: F,
 ) -> impl Stream<Item = io::Result<(u64, u64)>> + Unpin + Send {
-    AdjacencyBitCountStream::new(bitarray_stream_bits(bits_file), 1)
+    AdjacencyBitCountStream::new(bitindex_stream_bits(bits_file), 1)
         .zip(logarray_stream_entries(nums_file))
         .map(|(left, right)| {
             let left = left?;
@@ -222,6 +284,138 @@ pub fn adjacency_list_stream_pairs<F: 'static + FileLoad>(
         .into_stream()
 }
 
+/// What has to be pushed onto an adjacency list before the right-hand side of a new pair.
+enum PairStep {
+    /// This is the first pair, so nothing is pushed.
+    First,
+    /// The previous pair has the same left-hand side, so it was not
+    /// the last one, and the bitarray gets a 0 appended.
+    SameLeft,
+    /// There's a different left-hand side. The previous left-hand
+    /// sides are closed with 1s in the bitarray, and the ones that were
+    /// skipped over get a 0 in the num array.
+    NewLeft { ones: u64, padding: u64 },
+}
+
+impl PairStep {
+    fn new(last_left: u64, last_right: u64, left: u64, right: u64) -> PairStep {
+        // the tricky thing with this code is that the bitarray lags one entry behind the logarray.
+        // The reason for this is that at push time, we do not yet know if this entry is going to be
+        // the last entry for `left`, we only know this when we push a greater `left` later on.
+        if left < last_left || (left == last_left && right <= last_right) {
+            panic!("tried to push an unordered adjacent pair");
+        }
+
+        // the left hand side of the adjacencylist is expected to be a continuous range from 1 up to the max
+        // but when adding entries, there may be holes. We handle holes by writing a '0' to the logarray
+        // (which is otherwise an invalid right-hand side) and pushing a 1 onto the bitarray to immediately close the segment.
+        let skip = left - last_left;
+
+        if last_left == 0 && skip == 1 {
+            PairStep::First
+        } else if skip == 0 {
+            PairStep::SameLeft
+        } else {
+            // if this is the first element, but we do need to skip, make sure we write one less bit than we'd usually do
+            let ones = if last_left == 0 { skip - 1 } else { skip };
+
+            PairStep::NewLeft {
+                ones,
+                padding: skip - 1,
+            }
+        }
+    }
+}
+
+/// Build an adjacency list in memory.
+///
+/// This produces the same bytes as `AdjacencyListBuilder` with a
+/// plain bit index, but doesn't need files or an async runtime.
+pub struct AdjacencyListBufBuilder {
+    bits: BitArrayBufBuilder,
+    nums: LogArrayBufBuilder,
+    last_left: u64,
+    last_right: u64,
+}
+
+impl AdjacencyListBufBuilder {
+    pub fn new(width: u8) -> AdjacencyListBufBuilder {
+        AdjacencyListBufBuilder {
+            bits: BitArrayBufBuilder::new(),
+            nums: LogArrayBufBuilder::new(width),
+            last_left: 0,
+            last_right: 0,
+        }
+    }
+
+    /// Push a pair onto the adjacency list.
+    ///
+    /// Panics if the pair doesn't come after the previous pair.
+    pub fn push(&mut self, left: u64, right: u64) {
+        match PairStep::new(self.last_left, self.last_right, left, right) {
+            PairStep::First => {}
+            PairStep::SameLeft => self.bits.push(false),
+            PairStep::NewLeft { ones, padding } => {
+                for _ in 0..ones {
+                    self.bits.push(true);
+                }
+                for _ in 0..padding {
+                    self.nums.push(0);
+                }
+            }
+        }
+
+        self.nums.push(right);
+        self.last_left = left;
+        self.last_right = right;
+    }
+
+    pub fn push_all<I: IntoIterator<Item = (u64, u64)>>(&mut self, pairs: I) {
+        for (left, right) in pairs {
+            self.push(left, right);
+        }
+    }
+
+    pub fn count(&self) -> u64 {
+        self.bits.count()
+    }
+
+    /// Finish the adjacency list, returning the contents of its files.
+    pub fn finalize(mut self) -> AdjacencyListMaps {
+        if self.nums.count() != 0 {
+            // push last bit to bitarray
+            self.bits.push(true);
+        }
+
+        let bits_map = self.bits.finalize();
+        let bits = BitArray::from_bits(bits_map.clone()).unwrap();
+        let (blocks_map, sblocks_map) = build_bitindex_buf(&bits);
+
+        AdjacencyListMaps {
+            bitindex_maps: BitIndexMaps {
+                bits_map,
+                blocks_map,
+                sblocks_map,
+                select_map: None,
+            },
+            nums_map: self.nums.finalize(),
+        }
+    }
+
+    /// Finish the adjacency list and parse it.
+    pub fn build(self) -> AdjacencyList {
+        let maps = self.finalize();
+
+        AdjacencyList::parse(
+            maps.nums_map,
+            maps.bitindex_maps.bits_map,
+            maps.bitindex_maps.blocks_map,
+            maps.bitindex_maps.sblocks_map,
+        )
+        .expect("adjacency list built in memory should be valid")
+    }
+}
+
 pub struct AdjacencyListBuilder<F, W1, W2, W3>
 where
     F: 'static + FileLoad + FileStore,
@@ -230,7 +424,7 @@ where
     W3: 'static + AsyncWrite + Unpin + Send,
 {
     bitfile: F,
-    bitarray: BitArrayFileBuilder<F::Write>,
+    bitarray: AdjacencyBitsBuilder<F::Write>,
     bitindex_blocks: W1,
     bitindex_sblocks: W2,
     nums: LogArrayFileBuilder<W3>,
@@ -252,7 +446,7 @@ where
         nums_writer: W3,
         width: u8,
     ) -> AdjacencyListBuilder<F, W1, W2, W3> {
-        let bitarray = BitArrayFileBuilder::new(bitfile.open_write());
+        let bitarray = AdjacencyBitsBuilder::File(BitArrayFileBuilder::new(bitfile.open_write()));
 
         let nums = LogArrayFileBuilder::new(nums_writer, width);
 
@@ -267,33 +461,32 @@ where
         }
     }
 
-    pub async fn push(&mut self, left: u64, right: u64) -> io::Result<()> {
-        // the tricky thing with this code is that the bitarray lags one entry behind the logarray.
-        // The reason for this is that at push time, we do not yet know if this entry is going to be
-        // the last entry for `left`, we only know this when we push a greater `left` later on.
-        if left < self.last_left || (left == self.last_left && right <= self.last_right) {
-            panic!("tried to push an unordered adjacent pair");
+    /// Set the encoding of the bit index of this adjacency list.
+    ///
+    /// Anything other than a plain encoding keeps the bits in memory
+    /// until the adjacency list is finalized. This has to be called
+    /// before anything is pushed.
+    pub fn with_bit_index_encoding(mut self, encoding: BitIndexEncoding) -> Self {
+        assert_eq!(0, self.count(), "bits were already pushed");
+        if encoding != BitIndexEncoding::Plain {
+            self.bitarray =
+                AdjacencyBitsBuilder::Buffered(BitArrayFileBuilder::new(Vec::new()), encoding);
         }
 
-        // the left hand side of the adjacencylist is expected to be a continuous range from 1 up to the max
-        // but when adding entries, there may be holes. We handle holes by writing a '0' to the logarray
-        // (which is otherwise an invalid right-hand side) and pushing a 1 onto the bitarray to immediately close the segment.
-        let skip = left - self.last_left;
+        self
+    }
 
-        if self.last_left == 0 && skip == 1 {
-            // this is the first entry. we can't push a bit yet
-        } else if skip == 0 {
-            // same `left` as before. so the previous entry was not the last one, and the bitarray gets a 0 appended.
-            self.bitarray.push(false).await?;
-        } else {
-            // if this is the first element, but we do need to skip, make sure we write one less bit than we'd usually do
-            let bitskip = if self.last_left == 0 { skip - 1 } else { skip };
-            // there's a different `left`. we push a bunch of 1s to the bitarray, and 0s to the num array.
-            for _ in 0..bitskip {
-                self.bitarray.push(true).await?;
-            }
-            for _ in 0..(skip - 1) {
-                self.nums.push(0).await?;
+    pub async fn push(&mut self, left: u64, right: u64) -> io::Result<()> {
+        match PairStep::new(self.last_left, self.last_right, left, right) {
+            PairStep::First => {}
+            PairStep::SameLeft => self.bitarray.push(false).await?,
+            PairStep::NewLeft { ones, padding } => {
+                for _ in 0..ones {
+                    self.bitarray.push(true).await?;
+                }
+                for _ in 0..padding {
+                    self.nums.push(0).await?;
+                }
             }
         }
 
@@ -332,10 +525,25 @@ where
             bitarray.push(true).await?;
         }
 
-        bitarray.finalize().await?;
         nums.finalize().await?;
 
-        build_bitindex(bitfile.open_read(), bitindex_blocks, bitindex_sblocks).await?;
+        match bitarray {
+            AdjacencyBitsBuilder::File(bitarray) => {
+                bitarray.finalize().await?;
+                build_bitindex(bitfile.open_read(), bitindex_blocks, bitindex_sblocks).await?;
+            }
+            AdjacencyBitsBuilder::Buffered(bitarray, encoding) => {
+                let bits = bitarray.finalize().await?;
+                build_bitindex_with_encoding(
+                    bits,
+                    encoding,
+                    bitfile.open_write(),
+                    bitindex_blocks,
+                    bitindex_sblocks,
+                )
+                .await?;
+            }
+        }
 
         Ok(())
     }
@@ -345,6 +553,31 @@ where
     }
 }
 
+/// Destination of the bits of an adjacency list that is being built.
+///
+/// Bits are either written to their file directly, or kept in memory
+/// so that they can be encoded once they're complete.
+enum AdjacencyBitsBuilder<W: 'static + AsyncWrite + Unpin + Send> {
+    File(BitArrayFileBuilder<W>),
+    Buffered(BitArrayFileBuilder<Vec<u8>>, BitIndexEncoding),
+}
+
+impl<W: 'static + AsyncWrite + Unpin + Send> AdjacencyBitsBuilder<W> {
+    async fn push(&mut self, bit: bool) -> io::Result<()> {
+        match self {
+            AdjacencyBitsBuilder::File(bitarray) => bitarray.push(bit).await,
+            AdjacencyBitsBuilder::Buffered(bitarray, _) => bitarray.push(bit).await,
+        }
+    }
+
+    fn count(&self) -> u64 {
+        match self {
+            AdjacencyBitsBuilder::File(bitarray) => bitarray.count(),
+            AdjacencyBitsBuilder::Buffered(bitarray, _) => bitarray.count(),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -387,7 +620,8 @@ mod tests {
             bitfile_contents,
             bitindex_blocks_contents,
             bitindex_sblocks_contents,
-        );
+        )
+        .unwrap();
 
         let slice = adjacencylist.get(1);
         assert_eq!(2, slice.len());
@@ -451,7 +685,8 @@ mod tests {
             bitfile_contents,
             bitindex_blocks_contents,
             bitindex_sblocks_contents,
-        );
+        )
+        .unwrap();
 
         assert_eq!(0, adjacencylist.left_count());
     }
@@ -490,7 +725,8 @@ mod tests {
             bitfile_contents,
             bitindex_blocks_contents,
             bitindex_sblocks_contents,
-        );
+        )
+        .unwrap();
 
         let slice = adjacencylist.get(1);
         assert_eq!(1, slice.len());
@@ -555,7 +791,8 @@ mod tests {
             bitfile_contents,
             bitindex_blocks_contents,
             bitindex_sblocks_contents,
-        );
+        )
+        .unwrap();
 
         assert_eq!(
             vec![(1, 1), (1, 3), (2, 5), (7, 4)],
@@ -644,7 +881,8 @@ mod tests {
             bitfile_contents,
             bitindex_blocks_contents,
             bitindex_sblocks_contents,
-        );
+        )
+        .unwrap();
 
         let result: Vec<_> = (0..adjacencylist.right_count())
             .map(|i| adjacencylist.pair_at_pos(i as u64))
@@ -721,7 +959,8 @@ mod tests {
             bitfile_contents,
             bitindex_blocks_contents,
             bitindex_sblocks_contents,
-        );
+        )
+        .unwrap();
 
         let result: Vec<_> = (0..adjacencylist.right_count())
             .map(|i| adjacencylist.pair_at_pos(i as u64))
@@ -749,4 +988,137 @@ mod tests {
             result
         );
     }
+
+    #[test]
+    fn build_rrr_adjacencylist_and_stream_pairs() {
+        let bitfile = MemoryBackedStore::new();
+        let bitindex_blocks_file = MemoryBackedStore::new();
+        let bitindex_sblocks_file = MemoryBackedStore::new();
+        let nums_file = MemoryBackedStore::new();
+        let contents: Vec<(u64, u64)> = (1..2000).map(|i| (i / 3 + 1, i % 7 + 1)).collect();
+        let mut contents = contents;
+        contents.sort();
+        contents.dedup();
+
+        let mut builder = AdjacencyListBuilder::new(
+            bitfile.clone(),
+            bitindex_blocks_file.open_write(),
+            bitindex_sblocks_file.open_write(),
+            nums_file.open_write(),
+            8,
+        )
+        .with_bit_index_encoding(BitIndexEncoding::Rrr);
+
+        block_on(async {
+            builder
+                .push_all(util::stream_iter_ok(contents.clone()))
+                .await?;
+            builder.finalize().await?;
+
+            Ok::<_, io::Error>(())
+        })
+        .unwrap();
+
+        let adjacencylist = AdjacencyList::parse(
+            block_on(nums_file.map()).unwrap(),
+            block_on(bitfile.map()).unwrap(),
+            block_on(bitindex_blocks_file.map()).unwrap(),
+            block_on(bitindex_sblocks_file.map()).unwrap(),
+        )
+        .unwrap();
+
+        assert!(adjacencylist.bits().is_rrr());
+        assert_eq!(contents, adjacencylist.iter().collect::<Vec<_>>());
+
+        let streamed: Vec<_> =
+            block_on(adjacency_list_stream_pairs(bitfile, nums_file).try_collect()).unwrap();
+        assert_eq!(contents, streamed);
+    }
+
+    #[test]
+    fn buf_builder_matches_file_builder() {
+        let contents = vec![(1, 3), (1, 5), (3, 2), (3, 7), (4, 1), (7, 8)];
+        let bitfile = MemoryBackedStore::new();
+        let blocks_file = MemoryBackedStore::new();
+        let sblocks_file = MemoryBackedStore::new();
+        let nums_file = MemoryBackedStore::new();
+        let mut file_builder = AdjacencyListBuilder::new(
+            bitfile.clone(),
+            blocks_file.open_write(),
+            sblocks_file.open_write(),
+            nums_file.open_write(),
+            8,
+        );
+        block_on(async {
+            file_builder
+                .push_all(util::stream_iter_ok(contents.clone()))
+                .await?;
+            file_builder.finalize().await
+        })
+        .unwrap();
+
+        let mut builder = AdjacencyListBufBuilder::new(8);
+        builder.push_all(contents.clone());
+        let maps = builder.finalize();
+        assert_eq!(block_on(nums_file.map()).unwrap(), maps.nums_map);
+        assert_eq!(
+            block_on(bitfile.map()).unwrap(),
+            maps.bitindex_maps.bits_map
+        );
+        assert_eq!(
+            block_on(blocks_file.map()).unwrap(),
+            maps.bitindex_maps.blocks_map
+        );
+        assert_eq!(
+            block_on(sblocks_file.map()).unwrap(),
+            maps.bitindex_maps.sblocks_map
+        );
+
+        let mut builder = AdjacencyListBufBuilder::new(8);
+        builder.push_all(contents);
+        let adjacencylist = builder.build();
+        assert_eq!(7, adjacencylist.left_count());
+        assert_eq!(vec![2, 7], adjacencylist.get(3).iter().collect::<Vec<_>>());
+        assert_eq!(0, adjacencylist.get(2).entry(0));
+    }
+
+    #[test]
+    fn corrupt_adjacencylist_fails_to_parse() {
+        let mut builder = AdjacencyListBufBuilder::new(8);
+        builder.push_all(vec![(1, 3), (1, 5), (3, 2), (3, 7), (4, 1), (7, 8)]);
+        let maps = builder.finalize();
+        let mut other_builder = AdjacencyListBufBuilder::new(8);
+        other_builder.push_all(vec![(1, 3), (2, 5)]);
+        let other_maps = other_builder.finalize();
+
+        let error = AdjacencyList::parse(
+            other_maps.nums_map,
+            maps.bitindex_maps.bits_map.clone(),
+            maps.bitindex_maps.blocks_map.clone(),
+            maps.bitindex_maps.sblocks_map.clone(),
+        )
+        .err()
+        .unwrap();
+        assert!(matches!(error, AdjacencyListError::UnexpectedLength(2, 9)));
+
+        let error = AdjacencyList::parse(
+            maps.nums_map.slice(..4),
+            maps.bitindex_maps.bits_map.clone(),
+            maps.bitindex_maps.blocks_map.clone(),
+            maps.bitindex_maps.sblocks_map.clone(),
+        )
+        .err()
+        .unwrap();
+        assert!(matches!(error, AdjacencyListError::LogArray(_)));
+
+        let error = AdjacencyList::parse(
+            maps.nums_map,
+            maps.bitindex_maps.bits_map,
+            maps.bitindex_maps.blocks_map,
+            Bytes::new(),
+        )
+        .err()
+        .unwrap();
+        assert!(matches!(error, AdjacencyListError::BitIndex(_)));
+    }
 }
@@ -32,7 +32,8 @@ use super::util;
 use crate::storage::*;
 use crate::structure::bititer::BitIter;
 use byteorder::{BigEndian, ByteOrder};
-use bytes::{Bytes, BytesMut};
+use bytes::Bytes;
+use bytes05::BytesMut;
 use futures::future::FutureExt;
 use futures::io;
 use futures::stream::{Stream, StreamExt, TryStreamExt};
@@ -181,6 +182,11 @@ impl BitArray {
         self.len == 0
     }
 
+    /// Returns the size in bytes of the buffer backing this bit array.
+    pub fn size_in_bytes(&self) -> usize {
+        self.buf.len()
+    }
+
     /// Reads the data buffer and returns the logical value of the bit at the bit `index`.
     ///
     /// Panics if `index` is >= the length of the bit array.
@@ -200,6 +206,65 @@ impl BitArray {
     }
 }
 
+/// Build a bit array in memory.
+///
+/// This produces the same bytes as `BitArrayFileBuilder`, but doesn't
+/// need a file or an async runtime.
+#[derive(Default)]
+pub struct BitArrayBufBuilder {
+    /// Destination of the bit array data.
+    buf: Vec<u8>,
+    /// Storage for the next word to be written.
+    current: u64,
+    /// Number of bits written to the buffer
+    count: u64,
+}
+
+impl BitArrayBufBuilder {
+    pub fn new() -> BitArrayBufBuilder {
+        Self::default()
+    }
+
+    pub fn push(&mut self, bit: bool) {
+        if bit {
+            let pos = self.count & 0b11_1111;
+            self.current |= 0x8000_0000_0000_0000 >> pos;
+        }
+
+        self.count += 1;
+
+        if self.count & 0b11_1111 == 0 {
+            self.buf.extend_from_slice(&self.current.to_be_bytes());
+            self.current = 0;
+        }
+    }
+
+    pub fn push_all<I: IntoIterator<Item = bool>>(&mut self, bits: I) {
+        for bit in bits {
+            self.push(bit);
+        }
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Finish the bit array, returning its contents.
+    pub fn finalize(mut self) -> Bytes {
+        if self.count & 0b11_1111 != 0 {
+            self.buf.extend_from_slice(&self.current.to_be_bytes());
+        }
+        self.buf.extend_from_slice(&self.count.to_be_bytes());
+
+        Bytes::from(self.buf)
+    }
+
+    /// Finish the bit array and parse it.
+    pub fn build(self) -> BitArray {
+        BitArray::from_bits(self.finalize()).unwrap()
+    }
+}
+
 pub struct BitArrayFileBuilder<W> {
     /// Destination of the bit array data.
     dest: W,
@@ -532,4 +597,22 @@ mod tests {
 
         assert_eq!(contents, result);
     }
+
+    #[test]
+    fn buf_builder_matches_file_builder() {
+        for &len in &[0, 1, 63, 64, 65, 200] {
+            let bits: Vec<bool> = (0..len).map(|i| i % 3 == 0 || i % 7 == 0).collect();
+            let mut file_builder = BitArrayFileBuilder::new(Vec::new());
+            block_on(file_builder.push_all(util::stream_iter_ok(bits.clone()))).unwrap();
+            let expected = block_on(file_builder.finalize()).unwrap();
+
+            let mut builder = BitArrayBufBuilder::new();
+            builder.push_all(bits.clone());
+            assert_eq!(expected, builder.finalize().as_ref());
+
+            let mut builder = BitArrayBufBuilder::new();
+            builder.push_all(bits.clone());
+            assert_eq!(bits, builder.build().iter().collect::<Vec<_>>());
+        }
+    }
 }
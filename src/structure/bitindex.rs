@@ -4,62 +4,373 @@ use bytes::Bytes;
 
 use super::bitarray::*;
 use super::logarray::*;
+use super::rrr::*;
+use super::selectindex::*;
+use crate::storage::memory::MemoryBackedStore;
+use crate::storage::{FileLoad, FileStore};
+use futures::future::FutureExt;
 use futures::io;
-use futures::stream::StreamExt;
-use tokio::io::{AsyncRead, AsyncWrite};
+use futures::stream::{self, Stream, StreamExt};
+use std::error;
+use std::fmt;
+use std::pin::Pin;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 
 // a block is 64 bit, which is the register size on modern architectures
 // Block size is not tunable, and therefore no const is defined here.
 
 /// The amount of 64-bit blocks that go into a superblock.
-const SBLOCK_SIZE: usize = 52;
+pub(crate) const SBLOCK_SIZE: usize = 52;
+
+/// An error that occurred while parsing a bit index.
+#[derive(Debug)]
+pub enum BitIndexError {
+    BitArray(BitArrayError),
+    LogArray(LogArrayError),
+    /// The bits file holds an RRR compressed bit vector that could not be parsed.
+    Rrr(io::Error),
+    /// The select index could not be parsed.
+    SelectIndex(io::Error),
+    /// The amount of blocks (`.0`) does not match the amount expected for the bits (`.1`).
+    UnexpectedBlockCount(usize, usize),
+    /// The amount of superblocks (`.0`) does not match the amount expected for the blocks (`.1`).
+    UnexpectedSblockCount(usize, usize),
+    /// The length of the select index (`.0`) does not match the amount of bits (`.1`).
+    UnexpectedSelectIndexLength(u64, u64),
+}
+
+impl fmt::Display for BitIndexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use BitIndexError::*;
+        match self {
+            BitArray(err) => write!(f, "invalid bit array: {}", err),
+            LogArray(err) => write!(f, "invalid rank array: {}", err),
+            Rrr(err) => write!(f, "invalid rrr bit vector: {}", err),
+            SelectIndex(err) => write!(f, "invalid select index: {}", err),
+            UnexpectedBlockCount(found, expected) => {
+                write!(f, "expected block count ({}) to be {}", found, expected)
+            }
+            UnexpectedSblockCount(found, expected) => write!(
+                f,
+                "expected superblock count ({}) to be {}",
+                found, expected
+            ),
+            UnexpectedSelectIndexLength(found, expected) => write!(
+                f,
+                "expected select index length ({}) to be {}",
+                found, expected
+            ),
+        }
+    }
+}
+
+impl error::Error for BitIndexError {}
+
+impl From<BitArrayError> for BitIndexError {
+    fn from(err: BitArrayError) -> BitIndexError {
+        BitIndexError::BitArray(err)
+    }
+}
+
+impl From<LogArrayError> for BitIndexError {
+    fn from(err: LogArrayError) -> BitIndexError {
+        BitIndexError::LogArray(err)
+    }
+}
+
+impl From<BitIndexError> for io::Error {
+    fn from(err: BitIndexError) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, err)
+    }
+}
 
 /// A bitarray with an index, supporting rank and select queries.
+///
+/// The bits are either stored as a plain bitarray alongside
+/// precomputed ranks, or as an RRR compressed bit vector (see the
+/// `rrr` module), which is much smaller for very sparse or very dense
+/// bits.
+///
+/// A plain bit index can additionally have a sampled select index
+/// (see the `selectindex` module), which speeds up select queries.
+#[derive(Clone)]
+pub struct BitIndex(BitIndexRepr);
+
 #[derive(Clone)]
-pub struct BitIndex {
+enum BitIndexRepr {
+    Plain(PlainBitIndex),
+    Rrr(RrrBitVector),
+}
+
+#[derive(Clone)]
+struct PlainBitIndex {
     array: BitArray,
     blocks: LogArray,
     sblocks: LogArray,
+    select: Option<SelectIndex>,
 }
 
 impl BitIndex {
-    pub fn from_maps(bitarray_map: Bytes, blocks_map: Bytes, sblocks_map: Bytes) -> BitIndex {
-        let bitarray = BitArray::from_bits(bitarray_map).unwrap();
-        let blocks_logarray = LogArray::parse(blocks_map).unwrap();
-        let sblocks_logarray = LogArray::parse(sblocks_map).unwrap();
+    /// Construct a `BitIndex` from the contents of its files.
+    ///
+    /// If the bits file holds an RRR compressed bit vector, the blocks
+    /// and superblocks are not needed, and are ignored.
+    pub fn from_maps(
+        bitarray_map: Bytes,
+        blocks_map: Bytes,
+        sblocks_map: Bytes,
+    ) -> Result<BitIndex, BitIndexError> {
+        if is_rrr(&bitarray_map) {
+            let rrr = RrrBitVector::parse(bitarray_map).map_err(BitIndexError::Rrr)?;
+            return Ok(BitIndex::from_rrr(rrr));
+        }
+
+        let bitarray = BitArray::from_bits(bitarray_map)?;
+        let blocks_logarray = LogArray::parse(blocks_map)?;
+        let sblocks_logarray = LogArray::parse(sblocks_map)?;
 
-        BitIndex::from_parts(bitarray, blocks_logarray, sblocks_logarray)
+        let expected_blocks = bitarray.len().div_ceil(64);
+        if blocks_logarray.len() != expected_blocks {
+            return Err(BitIndexError::UnexpectedBlockCount(
+                blocks_logarray.len(),
+                expected_blocks,
+            ));
+        }
+        let expected_sblocks = blocks_logarray.len().div_ceil(SBLOCK_SIZE);
+        if sblocks_logarray.len() != expected_sblocks {
+            return Err(BitIndexError::UnexpectedSblockCount(
+                sblocks_logarray.len(),
+                expected_sblocks,
+            ));
+        }
+
+        Ok(BitIndex::from_parts(
+            bitarray,
+            blocks_logarray,
+            sblocks_logarray,
+        ))
     }
 
     pub fn from_parts(array: BitArray, blocks: LogArray, sblocks: LogArray) -> BitIndex {
         assert!(sblocks.len() == (blocks.len() + SBLOCK_SIZE - 1) / SBLOCK_SIZE);
         assert!(blocks.len() == (array.len() + 63) / 64);
 
-        BitIndex {
+        BitIndex(BitIndexRepr::Plain(PlainBitIndex {
             array,
             blocks,
             sblocks,
+            select: None,
+        }))
+    }
+
+    /// Construct a plain `BitIndex` by calculating the ranks of a bitarray in memory.
+    pub fn from_bitarray(array: BitArray) -> Result<BitIndex, BitIndexError> {
+        let (blocks, sblocks) = build_bitindex_buf(&array);
+
+        Ok(BitIndex::from_parts(
+            array,
+            LogArray::parse(blocks)?,
+            LogArray::parse(sblocks)?,
+        ))
+    }
+
+    pub fn from_rrr(rrr: RrrBitVector) -> BitIndex {
+        BitIndex(BitIndexRepr::Rrr(rrr))
+    }
+
+    /// Use the given select index to speed up select queries.
+    ///
+    /// RRR compressed bits sample their own ranks, so for those this
+    /// does nothing.
+    pub fn with_select_index(self, select: SelectIndex) -> BitIndex {
+        match self.0 {
+            BitIndexRepr::Plain(plain) => {
+                assert_eq!(
+                    plain.array.len() as u64,
+                    select.len(),
+                    "select index length should match the bit index length"
+                );
+
+                BitIndex(BitIndexRepr::Plain(PlainBitIndex {
+                    select: Some(select),
+                    ..plain
+                }))
+            }
+            BitIndexRepr::Rrr(_) => self,
         }
     }
 
-    fn block_bits(&self, block_index: usize) -> &[u8] {
-        let bit_index = block_index * 8;
+    /// Use the select index in the given buffer, if there is one, to speed up select queries.
+    pub fn with_select_map(self, select_map: Option<Bytes>) -> Result<BitIndex, BitIndexError> {
+        let select = match select_map {
+            Some(select_map) => {
+                SelectIndex::parse(select_map).map_err(BitIndexError::SelectIndex)?
+            }
+            None => return Ok(self),
+        };
+        if let BitIndexRepr::Plain(plain) = &self.0 {
+            if plain.array.len() as u64 != select.len() {
+                return Err(BitIndexError::UnexpectedSelectIndexLength(
+                    select.len(),
+                    plain.array.len() as u64,
+                ));
+            }
+        }
 
-        &self.array.bits()[bit_index..bit_index + 8]
+        Ok(self.with_select_index(select))
+    }
+
+    /// Returns the select index of this bit index, if it has one.
+    pub fn select_index(&self) -> Option<&SelectIndex> {
+        match &self.0 {
+            BitIndexRepr::Plain(plain) => plain.select.as_ref(),
+            BitIndexRepr::Rrr(_) => None,
+        }
+    }
+
+    /// Returns true if the bits are RRR compressed.
+    pub fn is_rrr(&self) -> bool {
+        matches!(self.0, BitIndexRepr::Rrr(_))
     }
 
     /// Returns the length of the underlying bitarray.
     pub fn len(&self) -> usize {
-        self.array.len()
+        match &self.0 {
+            BitIndexRepr::Plain(plain) => plain.array.len(),
+            BitIndexRepr::Rrr(rrr) => rrr.len(),
+        }
+    }
+
+    /// Returns the size in bytes of the buffers backing this bit index.
+    pub fn size_in_bytes(&self) -> usize {
+        match &self.0 {
+            BitIndexRepr::Plain(plain) => {
+                plain.array.size_in_bytes()
+                    + plain.blocks.size_in_bytes()
+                    + plain.sblocks.size_in_bytes()
+                    + plain
+                        .select
+                        .as_ref()
+                        .map_or(0, |select| select.size_in_bytes())
+            }
+            BitIndexRepr::Rrr(rrr) => rrr.size_in_bytes(),
+        }
     }
 
     /// Returns the bit at the given index.
     pub fn get(&self, index: u64) -> bool {
-        self.array.get(index as usize)
+        match &self.0 {
+            BitIndexRepr::Plain(plain) => plain.array.get(index as usize),
+            BitIndexRepr::Rrr(rrr) => rrr.get(index),
+        }
     }
 
     /// Returns the amount of 1-bits in the bitarray up to and including the given index.
     pub fn rank1(&self, index: u64) -> u64 {
+        match &self.0 {
+            BitIndexRepr::Plain(plain) => plain.rank1(index),
+            BitIndexRepr::Rrr(rrr) => rrr.rank1(index),
+        }
+    }
+
+    /// Returns the amount of 1-bits in the given range (up to but excluding end).
+    pub fn rank1_from_range(&self, start: u64, end: u64) -> u64 {
+        if start == end {
+            return 0;
+        }
+        let mut rank = self.rank1(end - 1);
+        if start != 0 {
+            rank -= self.rank1(start - 1);
+        }
+
+        rank
+    }
+
+    /// Returns the index of the 1-bit in the bitarray corresponding with the given rank.
+    pub fn select1(&self, rank: u64) -> Option<u64> {
+        match &self.0 {
+            BitIndexRepr::Plain(plain) => plain.select1(rank),
+            BitIndexRepr::Rrr(rrr) => rrr.select1(rank),
+        }
+    }
+
+    pub fn select1_from_range(&self, subrank: u64, start: u64, end: u64) -> Option<u64> {
+        // todo this is a dumb implementation. we can actually do a much faster select by making sblock/block lookup ranged. for now this will work.
+        let rank_offset = if start == 0 { 0 } else { self.rank1(start - 1) };
+
+        let result = self.select1(rank_offset + subrank)?;
+
+        if result < start && start < end && subrank == 0 && !self.get(start) {
+            Some(start)
+        } else if result < start || result >= end {
+            None
+        } else {
+            Some(result)
+        }
+    }
+
+    /// Returns the amount of 0-bits in the bitarray up to and including the given index.
+    pub fn rank0(&self, index: u64) -> u64 {
+        let r0 = self.rank1(index);
+        1 + index - r0
+    }
+
+    /// Returns the amount of 0-bits in the given range (up to but excluding end).
+    pub fn rank0_from_range(&self, start: u64, end: u64) -> u64 {
+        if start == end {
+            return 0;
+        }
+        let mut rank = self.rank0(end - 1);
+        if start != 0 {
+            rank -= self.rank0(start - 1);
+        }
+
+        rank
+    }
+
+    /// Returns the index of the 0-bit in the bitarray corresponding with the given rank.
+    pub fn select0(&self, rank: u64) -> Option<u64> {
+        match &self.0 {
+            BitIndexRepr::Plain(plain) => plain.select0(rank),
+            BitIndexRepr::Rrr(rrr) => rrr.select0(rank),
+        }
+    }
+
+    pub fn select0_from_range(&self, subrank: u64, start: u64, end: u64) -> Option<u64> {
+        // todo this is a dumb implementation. we can actually do a much faster select by making sblock/block lookup ranged. for now this will work.
+        let rank_offset = if start == 0 { 0 } else { self.rank0(start - 1) };
+
+        let result = self.select0(rank_offset + subrank)?;
+
+        if result < start && start < end && subrank == 0 && self.get(start) {
+            Some(start)
+        } else if result < start || result >= end {
+            None
+        } else {
+            Some(result)
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = bool> {
+        // only one of these is present
+        let (plain, rrr) = match &self.0 {
+            BitIndexRepr::Plain(plain) => (Some(plain.array.iter()), None),
+            BitIndexRepr::Rrr(rrr) => (None, Some(rrr.iter())),
+        };
+
+        plain.into_iter().flatten().chain(rrr.into_iter().flatten())
+    }
+}
+
+impl PlainBitIndex {
+    fn block_bits(&self, block_index: usize) -> &[u8] {
+        let bit_index = block_index * 8;
+
+        &self.array.bits()[bit_index..bit_index + 8]
+    }
+
+    /// Returns the amount of 1-bits in the bitarray up to and including the given index.
+    fn rank1(&self, index: u64) -> u64 {
         let block_index = index / 64;
         let sblock_index = block_index / SBLOCK_SIZE as u64;
 
@@ -75,22 +386,21 @@ impl BitIndex {
         sblock_rank - block_rank + bits_rank
     }
 
-    /// Returns the amount of 1-bits in the given range (up to but excluding end).
-    pub fn rank1_from_range(&self, start: u64, end: u64) -> u64 {
-        if start == end {
-            return 0;
-        }
-        let mut rank = self.rank1(end - 1);
-        if start != 0 {
-            rank -= self.rank1(start - 1);
-        }
+    /// Returns the range of superblocks that can contain the bit at the given position bounds.
+    fn sblock_range(&self, (start, end): (u64, Option<u64>)) -> (usize, usize) {
+        let last = self.sblocks.len() - 1;
+        let sblock_bits = SBLOCK_SIZE as u64 * 64;
+        let start = ((start / sblock_bits) as usize).min(last);
+        let end = end.map_or(last, |end| ((end / sblock_bits) as usize).min(last));
 
-        rank
+        (start, end)
     }
 
     fn select1_sblock(&self, rank: u64) -> usize {
-        let mut start = 0;
-        let mut end = self.sblocks.len() - 1;
+        let (mut start, mut end) = match &self.select {
+            Some(select) => self.sblock_range(select.ones_bounds(rank)),
+            None => (0, self.sblocks.len() - 1),
+        };
         let mut mid;
 
         loop {
@@ -142,7 +452,7 @@ impl BitIndex {
     }
 
     /// Returns the index of the 1-bit in the bitarray corresponding with the given rank.
-    pub fn select1(&self, rank: u64) -> Option<u64> {
+    fn select1(&self, rank: u64) -> Option<u64> {
         let sblock = self.select1_sblock(rank);
         let sblock_rank = self.sblocks.entry(sblock);
         if sblock_rank < rank {
@@ -172,43 +482,11 @@ impl BitIndex {
         None
     }
 
-    pub fn select1_from_range(&self, subrank: u64, start: u64, end: u64) -> Option<u64> {
-        // todo this is a dumb implementation. we can actually do a much faster select by making sblock/block lookup ranged. for now this will work.
-        let rank_offset = if start == 0 { 0 } else { self.rank1(start - 1) };
-
-        let result = self.select1(rank_offset + subrank)?;
-
-        if result < start && start < end && subrank == 0 && !self.get(start) {
-            Some(start)
-        } else if result < start || result >= end {
-            None
-        } else {
-            Some(result)
-        }
-    }
-
-    /// Returns the amount of 0-bits in the bitarray up to and including the given index.
-    pub fn rank0(&self, index: u64) -> u64 {
-        let r0 = self.rank1(index);
-        1 + index - r0
-    }
-
-    /// Returns the amount of 0-bits in the given range (up to but excluding end).
-    pub fn rank0_from_range(&self, start: u64, end: u64) -> u64 {
-        if start == end {
-            return 0;
-        }
-        let mut rank = self.rank0(end - 1);
-        if start != 0 {
-            rank -= self.rank0(start - 1);
-        }
-
-        rank
-    }
-
     fn select0_sblock(&self, rank: u64) -> usize {
-        let mut start = 0;
-        let mut end = self.sblocks.len() - 1;
+        let (mut start, mut end) = match &self.select {
+            Some(select) => self.sblock_range(select.zeros_bounds(rank)),
+            None => (0, self.sblocks.len() - 1),
+        };
         let mut mid;
 
         loop {
@@ -261,7 +539,7 @@ impl BitIndex {
     }
 
     /// Returns the index of the 0-bit in the bitarray corresponding with the given rank.
-    pub fn select0(&self, rank: u64) -> Option<u64> {
+    fn select0(&self, rank: u64) -> Option<u64> {
         let sblock = self.select0_sblock(rank);
         let sblock_rank = ((1 + sblock) * SBLOCK_SIZE * 64) as u64 - self.sblocks.entry(sblock);
 
@@ -292,25 +570,6 @@ impl BitIndex {
 
         None
     }
-
-    pub fn select0_from_range(&self, subrank: u64, start: u64, end: u64) -> Option<u64> {
-        // todo this is a dumb implementation. we can actually do a much faster select by making sblock/block lookup ranged. for now this will work.
-        let rank_offset = if start == 0 { 0 } else { self.rank0(start - 1) };
-
-        let result = self.select0(rank_offset + subrank)?;
-
-        if result < start && start < end && subrank == 0 && self.get(start) {
-            Some(start)
-        } else if result < start || result >= end {
-            None
-        } else {
-            Some(result)
-        }
-    }
-
-    pub fn iter(&self) -> impl Iterator<Item = bool> {
-        self.array.iter()
-    }
 }
 
 pub async fn build_bitindex<
@@ -354,11 +613,144 @@ pub async fn build_bitindex<
     Ok(())
 }
 
+/// Calculate the blocks and superblocks of a bit index in memory.
+///
+/// This produces the same bytes as `build_bitindex`.
+pub fn build_bitindex_buf(bitarray: &BitArray) -> (Bytes, Bytes) {
+    let mut blocks_builder = LogArrayBufBuilder::new(64 - (SBLOCK_SIZE * 64).leading_zeros() as u8);
+    let mut sblocks_builder = LogArrayBufBuilder::new(64);
+
+    let mut sblock_rank = 0;
+    for chunk in bitarray.bits().chunks(SBLOCK_SIZE * 8) {
+        let block_ranks: Vec<u64> = chunk
+            .chunks(8)
+            .map(|block| BigEndian::read_u64(block).count_ones() as u64)
+            .collect();
+
+        let mut sblock_subrank = block_ranks.iter().sum();
+        sblock_rank += sblock_subrank;
+
+        for block_rank in block_ranks {
+            blocks_builder.push(sblock_subrank);
+            sblock_subrank -= block_rank;
+        }
+
+        sblocks_builder.push(sblock_rank);
+    }
+
+    (blocks_builder.finalize(), sblocks_builder.finalize())
+}
+
+/// How the bits of a `BitIndex` are stored when it is built.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BitIndexEncoding {
+    /// A plain bitarray with precomputed ranks.
+    #[default]
+    Plain,
+    /// An RRR compressed bit vector.
+    Rrr,
+    /// Whichever of the two is smaller, decided separately for every bit index.
+    Smallest,
+}
+
+/// Build a bit index in the given encoding from the contents of a bitarray file.
+///
+/// When the bits end up RRR compressed, empty logarrays are written
+/// for the blocks and superblocks.
+pub async fn build_bitindex_with_encoding<
+    W1: 'static + AsyncWrite + Unpin + Send,
+    W2: 'static + AsyncWrite + Unpin + Send,
+    W3: 'static + AsyncWrite + Unpin + Send,
+>(
+    bitarray: Vec<u8>,
+    encoding: BitIndexEncoding,
+    mut bits: W1,
+    mut blocks: W2,
+    mut sblocks: W3,
+) -> io::Result<()> {
+    let rrr = if encoding == BitIndexEncoding::Plain {
+        None
+    } else {
+        let array = BitArray::from_bits(Bytes::from(bitarray.clone()))?;
+        let mut builder = RrrFileBuilder::new(Vec::new());
+        builder.push_all(array.iter());
+        Some(builder.finalize().await?)
+    };
+
+    let plain = if encoding == BitIndexEncoding::Rrr {
+        None
+    } else {
+        let blocks_file = MemoryBackedStore::new();
+        let sblocks_file = MemoryBackedStore::new();
+        build_bitindex(
+            std::io::Cursor::new(bitarray.clone()),
+            blocks_file.open_write(),
+            sblocks_file.open_write(),
+        )
+        .await?;
+        Some((blocks_file.map().await?, sblocks_file.map().await?))
+    };
+
+    let use_rrr = match (&rrr, &plain) {
+        (Some(rrr), Some((blocks, sblocks))) => {
+            rrr.len() < bitarray.len() + blocks.len() + sblocks.len()
+        }
+        (rrr, _) => rrr.is_some(),
+    };
+
+    if use_rrr {
+        bits.write_all(&rrr.unwrap()).await?;
+        bits.flush().await?;
+        LogArrayFileBuilder::new(blocks, 1).finalize().await?;
+        LogArrayFileBuilder::new(sblocks, 1).finalize().await?;
+    } else {
+        let (blocks_map, sblocks_map) = plain.unwrap();
+        bits.write_all(&bitarray).await?;
+        bits.flush().await?;
+        blocks.write_all(&blocks_map).await?;
+        blocks.flush().await?;
+        sblocks.write_all(&sblocks_map).await?;
+        sblocks.flush().await?;
+    }
+
+    Ok(())
+}
+
+/// Stream the bits of a bit index file, whether it holds a plain bitarray or an RRR compressed bit vector.
+pub fn bitindex_stream_bits<F: 'static + FileLoad>(
+    f: F,
+) -> impl Stream<Item = io::Result<bool>> + Unpin + Send {
+    Box::pin(
+        async move {
+            let mut marker = [0; 8];
+            if f.size() >= 48 {
+                f.open_read_from(f.size() - 8)
+                    .read_exact(&mut marker)
+                    .await?;
+            }
+
+            if marker == RRR_MARKER {
+                let rrr = RrrBitVector::parse(f.map().await?)?;
+                Ok(Box::pin(stream::iter(rrr.iter().map(Ok)))
+                    as Pin<Box<dyn Stream<Item = io::Result<bool>> + Send>>)
+            } else {
+                Ok(Box::pin(bitarray_stream_bits(f))
+                    as Pin<Box<dyn Stream<Item = io::Result<bool>> + Send>>)
+            }
+        }
+        .map(|result| match result {
+            Ok(stream) => stream,
+            Err(e) => Box::pin(stream::iter(vec![Err(e)])),
+        })
+        .into_stream()
+        .flatten(),
+    )
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::storage::memory::*;
-    use crate::storage::*;
     use crate::structure::util::stream_iter_ok;
     use futures::executor::block_on;
 
@@ -389,7 +781,8 @@ mod tests {
             block_on(bits.map()).unwrap(),
             block_on(index_blocks.map()).unwrap(),
             block_on(index_sblocks.map()).unwrap(),
-        );
+        )
+        .unwrap();
 
         for i in 0..123456 {
             assert_eq!(i / 3 + 1, index.rank1(i));
@@ -423,7 +816,8 @@ mod tests {
             block_on(bits.map()).unwrap(),
             block_on(index_blocks.map()).unwrap(),
             block_on(index_sblocks.map()).unwrap(),
-        );
+        )
+        .unwrap();
 
         for i in 1..(123456 / 3) {
             assert_eq!((i - 1) * 3, index.select1(i).unwrap());
@@ -459,7 +853,8 @@ mod tests {
             block_on(bits.map()).unwrap(),
             block_on(index_blocks.map()).unwrap(),
             block_on(index_sblocks.map()).unwrap(),
-        );
+        )
+        .unwrap();
 
         assert_eq!(0, index.rank1_from_range(6, 6));
         assert_eq!(1, index.rank1_from_range(6, 7));
@@ -495,7 +890,8 @@ mod tests {
             block_on(bits.map()).unwrap(),
             block_on(index_blocks.map()).unwrap(),
             block_on(index_sblocks.map()).unwrap(),
-        );
+        )
+        .unwrap();
 
         assert_eq!(None, index.select1_from_range(0, 6, 6));
         assert_eq!(None, index.select1_from_range(0, 6, 7));
@@ -532,7 +928,8 @@ mod tests {
             block_on(bits.map()).unwrap(),
             block_on(index_blocks.map()).unwrap(),
             block_on(index_sblocks.map()).unwrap(),
-        );
+        )
+        .unwrap();
 
         for i in 0..123456 {
             assert_eq!(1 + i - (i / 3 + 1), index.rank0(i));
@@ -566,7 +963,8 @@ mod tests {
             block_on(bits.map()).unwrap(),
             block_on(index_blocks.map()).unwrap(),
             block_on(index_sblocks.map()).unwrap(),
-        );
+        )
+        .unwrap();
 
         for i in 1..=(123456 * 2 / 3) {
             assert_eq!(i + (i - 1) / 2, index.select0(i).unwrap());
@@ -602,7 +1000,8 @@ mod tests {
             block_on(bits.map()).unwrap(),
             block_on(index_blocks.map()).unwrap(),
             block_on(index_sblocks.map()).unwrap(),
-        );
+        )
+        .unwrap();
 
         assert_eq!(0, index.rank0_from_range(5, 5));
         assert_eq!(1, index.rank0_from_range(5, 6));
@@ -639,7 +1038,8 @@ mod tests {
             block_on(bits.map()).unwrap(),
             block_on(index_blocks.map()).unwrap(),
             block_on(index_sblocks.map()).unwrap(),
-        );
+        )
+        .unwrap();
 
         assert_eq!(None, index.select0_from_range(0, 6, 6));
         assert_eq!(Some(6), index.select0_from_range(0, 6, 7));
@@ -649,4 +1049,161 @@ mod tests {
         assert_eq!(Some(10), index.select0_from_range(4, 5, 11));
         assert_eq!(None, index.select0_from_range(123456, 5, 10));
     }
+
+    fn build_bitindex_in_memory(bits: &[bool], encoding: BitIndexEncoding) -> BitIndex {
+        let bits_file = MemoryBackedStore::new();
+        let blocks_file = MemoryBackedStore::new();
+        let sblocks_file = MemoryBackedStore::new();
+        let mut ba_builder = BitArrayFileBuilder::new(Vec::new());
+        block_on(async {
+            ba_builder
+                .push_all(stream_iter_ok(bits.iter().cloned()))
+                .await?;
+            let bitarray = ba_builder.finalize().await?;
+            build_bitindex_with_encoding(
+                bitarray,
+                encoding,
+                bits_file.open_write(),
+                blocks_file.open_write(),
+                sblocks_file.open_write(),
+            )
+            .await?;
+
+            Ok::<_, io::Error>(())
+        })
+        .unwrap();
+
+        BitIndex::from_maps(
+            block_on(bits_file.map()).unwrap(),
+            block_on(blocks_file.map()).unwrap(),
+            block_on(sblocks_file.map()).unwrap(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    pub fn rrr_bitindex_matches_plain() {
+        let contents: Vec<bool> = (0..10000).map(|n| n % 37 == 0 || n % 1000 < 3).collect();
+        let plain = build_bitindex_in_memory(&contents, BitIndexEncoding::Plain);
+        let rrr = build_bitindex_in_memory(&contents, BitIndexEncoding::Rrr);
+
+        assert!(!plain.is_rrr());
+        assert!(rrr.is_rrr());
+        assert!(rrr.size_in_bytes() < plain.size_in_bytes());
+        assert_eq!(plain.len(), rrr.len());
+        assert_eq!(contents, rrr.iter().collect::<Vec<_>>());
+
+        let ones = plain.rank1(9999);
+        let zeros = plain.rank0(9999);
+        for i in 0..10000 {
+            assert_eq!(plain.get(i), rrr.get(i));
+            assert_eq!(plain.rank1(i), rrr.rank1(i));
+            assert_eq!(plain.rank0(i), rrr.rank0(i));
+        }
+        for rank in 1..=ones {
+            assert_eq!(plain.select1(rank), rrr.select1(rank));
+        }
+        for rank in 1..=zeros {
+            assert_eq!(plain.select0(rank), rrr.select0(rank));
+        }
+        assert_eq!(
+            plain.select1_from_range(3, 1000, 2000),
+            rrr.select1_from_range(3, 1000, 2000)
+        );
+        assert_eq!(
+            plain.rank0_from_range(500, 5000),
+            rrr.rank0_from_range(500, 5000)
+        );
+    }
+
+    #[test]
+    pub fn smallest_encoding_is_chosen_per_bitindex() {
+        let sparse: Vec<bool> = (0..10000).map(|n| n % 500 == 0).collect();
+        let short = vec![true, false, false, true, true];
+
+        assert!(build_bitindex_in_memory(&sparse, BitIndexEncoding::Smallest).is_rrr());
+        assert!(!build_bitindex_in_memory(&short, BitIndexEncoding::Smallest).is_rrr());
+    }
+
+    #[test]
+    pub fn bitindex_from_bitarray_matches_build_bitindex() {
+        let contents: Vec<bool> = (0..5000).map(|n| n % 13 == 0 || n % 29 == 0).collect();
+        let mut builder = BitArrayBufBuilder::new();
+        builder.push_all(contents.clone());
+        let bits = builder.build();
+
+        let (blocks, sblocks) = build_bitindex_buf(&bits);
+        let blocks_file = MemoryBackedStore::new();
+        let sblocks_file = MemoryBackedStore::new();
+        let bits_file = MemoryBackedStore::new();
+        let mut file_builder = BitArrayFileBuilder::new(bits_file.open_write());
+        block_on(async {
+            file_builder
+                .push_all(stream_iter_ok(contents.clone()))
+                .await?;
+            file_builder.finalize().await?;
+            build_bitindex(
+                bits_file.open_read(),
+                blocks_file.open_write(),
+                sblocks_file.open_write(),
+            )
+            .await
+        })
+        .unwrap();
+        assert_eq!(block_on(blocks_file.map()).unwrap(), blocks);
+        assert_eq!(block_on(sblocks_file.map()).unwrap(), sblocks);
+
+        let index = BitIndex::from_bitarray(bits).unwrap();
+        assert_eq!(contents, index.iter().collect::<Vec<_>>());
+        assert_eq!(Some(13), index.select1(2));
+    }
+
+    #[test]
+    pub fn corrupt_bitindex_fails_to_parse() {
+        let mut builder = BitArrayBufBuilder::new();
+        builder.push_all((0..5000).map(|n| n % 7 == 0));
+        let bits = builder.finalize();
+        let (blocks, sblocks) = build_bitindex_buf(&BitArray::from_bits(bits.clone()).unwrap());
+
+        assert!(BitIndex::from_maps(bits.clone(), blocks.clone(), sblocks.clone()).is_ok());
+
+        let error = BitIndex::from_maps(
+            bits.slice(..bits.len() - 3),
+            blocks.clone(),
+            sblocks.clone(),
+        )
+        .err()
+        .unwrap();
+        assert!(matches!(error, BitIndexError::BitArray(_)));
+
+        let error = BitIndex::from_maps(bits.clone(), blocks.slice(3..), sblocks.clone())
+            .err()
+            .unwrap();
+        assert!(matches!(error, BitIndexError::LogArray(_)));
+
+        let mut short_builder = BitArrayBufBuilder::new();
+        short_builder.push_all((0..100).map(|n| n % 7 == 0));
+        let (short_blocks, short_sblocks) = build_bitindex_buf(&short_builder.build());
+        let error = BitIndex::from_maps(bits.clone(), short_blocks, sblocks.clone())
+            .err()
+            .unwrap();
+        assert!(matches!(error, BitIndexError::UnexpectedBlockCount(2, 79)));
+        let error = BitIndex::from_maps(bits.clone(), blocks.clone(), short_sblocks)
+            .err()
+            .unwrap();
+        assert!(matches!(error, BitIndexError::UnexpectedSblockCount(1, 2)));
+
+        let short_index = build_bitindex_in_memory(&[true; 100], BitIndexEncoding::Plain);
+        let mut select = Vec::new();
+        block_on(build_select_index(&short_index, 8, &mut select)).unwrap();
+        let error = BitIndex::from_maps(bits, blocks, sblocks)
+            .unwrap()
+            .with_select_map(Some(Bytes::from(select)))
+            .err()
+            .unwrap();
+        assert!(matches!(
+            error,
+            BitIndexError::UnexpectedSelectIndexLength(100, 5000)
+        ));
+    }
 }
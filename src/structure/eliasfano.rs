@@ -0,0 +1,513 @@
+//! Elias-Fano encoding of monotonic sequences.
+//!
+//! Every element is split up into a high and a low part. The low
+//! parts all have the same width, chosen from the amount of elements
+//! and the largest element, and are stored in a log array. The high
+//! parts are stored in a bit array, as a sequence of unary-coded
+//! gaps: for the element at index i with high part h, the bit at
+//! position h + i is set.
+//!
+//! This takes about 2 + log2(u/n) bits per element, where u is the
+//! largest element and n is the amount of elements. For sparse
+//! sequences such as the subject lists of child layers, this is much
+//! smaller than a log array, which needs log2(u) bits per element.
+//!
+//! An Elias-Fano sequence is stored as a single buffer, consisting of
+//! the low parts, the high part bits and the bit index over those
+//! bits, followed by 4 words:
+//!
+//! 1. the size in bytes of the low parts,
+//! 2. the size in bytes of the high part bits,
+//! 3. the size in bytes of the bit index blocks,
+//! 4. a control word, laid out like that of a log array, with a
+//!    32-bit length and the 8-bit width of the low parts, but with
+//!    the remaining 24 bits set to `ELIAS_FANO_MARKER`. Log arrays
+//!    leave those bits unused, which is how the two formats are told
+//!    apart.
+
+use byteorder::{BigEndian, ByteOrder};
+use bytes::Bytes;
+use std::convert::TryFrom;
+use std::io;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use super::bitarray::*;
+use super::bitindex::*;
+use super::logarray::*;
+use super::util;
+use crate::storage::memory::MemoryBackedStore;
+use crate::storage::*;
+
+/// Marks the control word of an Elias-Fano sequence.
+pub const ELIAS_FANO_MARKER: [u8; 3] = *b"EF1";
+
+/// Returns true if the given buffer holds an Elias-Fano sequence rather than a log array.
+pub fn is_elias_fano(buf: &[u8]) -> bool {
+    buf.len() >= 32 && buf[buf.len() - 3..] == ELIAS_FANO_MARKER
+}
+
+fn invalid_data<S: Into<String>>(message: S) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+/// Returns the width of the low parts for a sequence of the given length and largest element.
+fn calculate_low_width(len: usize, max: u64) -> u8 {
+    let ratio = if len == 0 { 0 } else { max / len as u64 };
+    if ratio < 2 {
+        // a log array can't have a width of 0
+        1
+    } else {
+        (63 - ratio.leading_zeros()) as u8
+    }
+}
+
+/// An Elias-Fano encoded monotonic sequence.
+#[derive(Clone)]
+pub struct EliasFano {
+    len: usize,
+    low_width: u8,
+    low: LogArray,
+    high: BitIndex,
+}
+
+impl EliasFano {
+    /// Construct an `EliasFano` by parsing a `Bytes` buffer.
+    pub fn parse(buf: Bytes) -> io::Result<EliasFano> {
+        if !is_elias_fano(&buf) {
+            return Err(invalid_data("not an Elias-Fano sequence"));
+        }
+
+        let trailer_start = buf.len() - 32;
+        let trailer = &buf[trailer_start..];
+        let low_size = BigEndian::read_u64(&trailer[0..8]) as usize;
+        let high_size = BigEndian::read_u64(&trailer[8..16]) as usize;
+        let blocks_size = BigEndian::read_u64(&trailer[16..24]) as usize;
+        let len = BigEndian::read_u32(&trailer[24..28]) as usize;
+        let low_width = trailer[28];
+
+        let high_start = low_size;
+        let blocks_start = high_start.checked_add(high_size);
+        let sblocks_start = blocks_start.and_then(|start| start.checked_add(blocks_size));
+        let (blocks_start, sblocks_start) = match (blocks_start, sblocks_start) {
+            (Some(blocks_start), Some(sblocks_start)) if sblocks_start <= trailer_start => {
+                (blocks_start, sblocks_start)
+            }
+            _ => return Err(invalid_data("Elias-Fano section sizes exceed the buffer")),
+        };
+
+        let low = LogArray::parse(buf.slice(..high_start))?;
+        let high_bits = BitArray::from_bits(buf.slice(high_start..blocks_start))?;
+        let blocks = LogArray::parse(buf.slice(blocks_start..sblocks_start))?;
+        let sblocks = LogArray::parse(buf.slice(sblocks_start..trailer_start))?;
+
+        if low.len() != len || low.width() != low_width {
+            return Err(invalid_data(format!(
+                "expected {} low parts of width {}, but found {} of width {}",
+                len,
+                low_width,
+                low.len(),
+                low.width()
+            )));
+        }
+        if blocks.len() != high_bits.len().div_ceil(64)
+            || sblocks.len() != blocks.len().div_ceil(SBLOCK_SIZE)
+        {
+            return Err(invalid_data("Elias-Fano bit index does not match its bits"));
+        }
+        let ones = if sblocks.is_empty() {
+            0
+        } else {
+            sblocks.entry(sblocks.len() - 1)
+        };
+        if ones != len as u64 {
+            return Err(invalid_data(format!(
+                "expected {} set high part bits, but found {}",
+                len, ones
+            )));
+        }
+        if high_bits.len() < len {
+            return Err(invalid_data(format!(
+                "expected at least {} high part bits, but found {}",
+                len,
+                high_bits.len()
+            )));
+        }
+
+        Ok(EliasFano {
+            len,
+            low_width,
+            low,
+            high: BitIndex::from_parts(high_bits, blocks, sblocks),
+        })
+    }
+
+    /// Returns the number of elements.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if there are no elements.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the size in bytes of the buffers backing this sequence.
+    pub fn size_in_bytes(&self) -> usize {
+        self.low.size_in_bytes() + self.high.size_in_bytes() + 32
+    }
+
+    /// Returns the element at the given index.
+    ///
+    /// Panics if `index` is >= the length of the sequence.
+    pub fn entry(&self, index: usize) -> u64 {
+        assert!(
+            index < self.len,
+            "expected index ({}) < length ({})",
+            index,
+            self.len
+        );
+
+        let position = self
+            .high
+            .select1(index as u64 + 1)
+            .expect("expected a high bit for every element");
+        let high = position - index as u64;
+
+        high << self.low_width | self.low.entry(index)
+    }
+
+    /// Returns the index of the first element that is not smaller than the given element.
+    ///
+    /// If all elements are smaller, this returns the length of the sequence.
+    pub fn nearest_index_of(&self, element: u64) -> usize {
+        if self.is_empty() {
+            return 0;
+        }
+
+        let high = element >> self.low_width;
+        let zero_count = (self.high.len() - self.len) as u64;
+        if high >= zero_count {
+            // the element is beyond the last bucket
+            return self.len;
+        }
+
+        // every bucket ends with a 0-bit, so the amount of elements
+        // in the buckets before this one is the amount of 1-bits
+        // before the end of the previous bucket.
+        let (mut index, mut position) = if high == 0 {
+            (0, 0)
+        } else {
+            let bucket_end = self.high.select0(high).unwrap();
+            ((bucket_end + 1 - high) as usize, bucket_end + 1)
+        };
+
+        let low = element & ((1 << self.low_width) - 1);
+        while self.high.get(position) {
+            if self.low.entry(index) >= low {
+                break;
+            }
+
+            index += 1;
+            position += 1;
+        }
+
+        index
+    }
+
+    /// Returns the index of the given element, if it is part of the sequence.
+    pub fn index_of(&self, element: u64) -> Option<usize> {
+        let index = self.nearest_index_of(element);
+        if index >= self.len || self.entry(index) != element {
+            None
+        } else {
+            Some(index)
+        }
+    }
+
+    pub fn iter(&self) -> EliasFanoIterator {
+        EliasFanoIterator {
+            sequence: self.clone(),
+            index: 0,
+            position: 0,
+        }
+    }
+}
+
+/// An iterator over the elements of an Elias-Fano sequence.
+///
+/// This scans the high part bits in order, rather than doing a select
+/// for every element.
+#[derive(Clone)]
+pub struct EliasFanoIterator {
+    sequence: EliasFano,
+    index: usize,
+    position: u64,
+}
+
+impl Iterator for EliasFanoIterator {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<u64> {
+        if self.index == self.sequence.len {
+            return None;
+        }
+
+        while !self.sequence.high.get(self.position) {
+            self.position += 1;
+        }
+
+        let high = self.position - self.index as u64;
+        let result = high << self.sequence.low_width | self.sequence.low.entry(self.index);
+        self.index += 1;
+        self.position += 1;
+
+        Some(result)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.sequence.len - self.index;
+
+        (remaining, Some(remaining))
+    }
+}
+
+/// Writes an Elias-Fano sequence directly to an AsyncWrite.
+///
+/// The amount of elements and the largest element have to be known
+/// up front, as they determine the width of the low parts.
+pub struct EliasFanoFileBuilder<W: AsyncWrite + Unpin> {
+    /// Destination of the sequence
+    file: W,
+    /// Amount of elements that will be pushed
+    len: usize,
+    /// Largest element that may be pushed
+    max: u64,
+    low_width: u8,
+    low: LogArrayFileBuilder<Vec<u8>>,
+    high: BitArrayFileBuilder<Vec<u8>>,
+    /// Amount of elements pushed so far
+    count: usize,
+    last: u64,
+    last_high: u64,
+}
+
+impl<W: AsyncWrite + Unpin> EliasFanoFileBuilder<W> {
+    pub fn new(file: W, len: usize, max: u64) -> EliasFanoFileBuilder<W> {
+        let low_width = calculate_low_width(len, max);
+
+        EliasFanoFileBuilder {
+            file,
+            len,
+            max,
+            low_width,
+            low: LogArrayFileBuilder::new(Vec::new(), low_width),
+            high: BitArrayFileBuilder::new(Vec::new()),
+            count: 0,
+            last: 0,
+            last_high: 0,
+        }
+    }
+
+    pub async fn push(&mut self, val: u64) -> io::Result<()> {
+        if self.count == self.len {
+            return Err(invalid_data(format!(
+                "expected no more than {} elements",
+                self.len
+            )));
+        }
+        if val > self.max {
+            return Err(invalid_data(format!(
+                "expected value ({}) <= maximum ({})",
+                val, self.max
+            )));
+        }
+        if self.count != 0 && val < self.last {
+            return Err(invalid_data(format!(
+                "not monotonic: expected predecessor ({}) <= successor ({})",
+                self.last, val
+            )));
+        }
+
+        let high = val >> self.low_width;
+        for _ in self.last_high..high {
+            self.high.push(false).await?;
+        }
+        self.high.push(true).await?;
+        self.low.push(val & ((1 << self.low_width) - 1)).await?;
+
+        self.count += 1;
+        self.last = val;
+        self.last_high = high;
+
+        Ok(())
+    }
+
+    pub async fn push_all<I: IntoIterator<Item = u64>>(&mut self, vals: I) -> io::Result<()> {
+        for val in vals {
+            self.push(val).await?;
+        }
+
+        Ok(())
+    }
+
+    pub async fn finalize(mut self) -> io::Result<W> {
+        if self.count != self.len {
+            return Err(invalid_data(format!(
+                "expected {} elements, but {} were pushed",
+                self.len, self.count
+            )));
+        }
+
+        // close off every bucket up to and including the one of the maximum
+        for _ in self.last_high..(self.max >> self.low_width) + 1 {
+            self.high.push(false).await?;
+        }
+
+        let low = self.low.finalize().await?;
+        let high = self.high.finalize().await?;
+
+        let blocks_file = MemoryBackedStore::new();
+        let sblocks_file = MemoryBackedStore::new();
+        build_bitindex(
+            io::Cursor::new(high.clone()),
+            blocks_file.open_write(),
+            sblocks_file.open_write(),
+        )
+        .await?;
+        let blocks = blocks_file.map().await?;
+        let sblocks = sblocks_file.map().await?;
+
+        self.file.write_all(&low).await?;
+        self.file.write_all(&high).await?;
+        self.file.write_all(&blocks).await?;
+        self.file.write_all(&sblocks).await?;
+
+        util::write_u64(&mut self.file, low.len() as u64).await?;
+        util::write_u64(&mut self.file, high.len() as u64).await?;
+        util::write_u64(&mut self.file, blocks.len() as u64).await?;
+
+        let len = self.len;
+        let len = u32::try_from(len)
+            .map_err(|_| invalid_data(format!("expected length ({}) to fit in 32 bits", len)))?;
+        let mut control = [0; 8];
+        BigEndian::write_u32(&mut control, len);
+        control[4] = self.low_width;
+        control[5..].copy_from_slice(&ELIAS_FANO_MARKER);
+        self.file.write_all(&control).await?;
+
+        self.file.flush().await?;
+
+        Ok(self.file)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::memory::*;
+    use futures::executor::block_on;
+
+    fn build_elias_fano(contents: &[u64]) -> EliasFano {
+        let file = MemoryBackedStore::new();
+        let max = contents.last().cloned().unwrap_or(0);
+        let mut builder = EliasFanoFileBuilder::new(file.open_write(), contents.len(), max);
+        block_on(async {
+            builder.push_all(contents.iter().cloned()).await?;
+            builder.finalize().await?;
+
+            Ok::<_, io::Error>(())
+        })
+        .unwrap();
+
+        EliasFano::parse(block_on(file.map()).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn build_and_access_elias_fano() {
+        let contents = vec![3, 4, 7, 13, 14, 15, 21, 43, 43, 100, 1000, 1001, 65536];
+        let sequence = build_elias_fano(&contents);
+
+        assert_eq!(contents.len(), sequence.len());
+        for (index, &element) in contents.iter().enumerate() {
+            assert_eq!(element, sequence.entry(index));
+        }
+        assert_eq!(contents, sequence.iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn elias_fano_index_of() {
+        let contents: Vec<u64> = (0..500).map(|i| i * i / 3 + 5).collect();
+        let sequence = build_elias_fano(&contents);
+
+        for element in 0..contents[contents.len() - 1] + 10 {
+            let expected = contents
+                .iter()
+                .position(|&e| e >= element)
+                .unwrap_or(contents.len());
+            assert_eq!(expected, sequence.nearest_index_of(element));
+            assert_eq!(
+                contents.iter().position(|&e| e == element),
+                sequence.index_of(element)
+            );
+        }
+    }
+
+    #[test]
+    fn dense_elias_fano() {
+        let contents: Vec<u64> = (1..=1000).collect();
+        let sequence = build_elias_fano(&contents);
+
+        assert_eq!(contents, sequence.iter().collect::<Vec<_>>());
+        assert_eq!(Some(499), sequence.index_of(500));
+        assert_eq!(None, sequence.index_of(0));
+        assert_eq!(None, sequence.index_of(1001));
+    }
+
+    #[test]
+    fn empty_elias_fano() {
+        let sequence = build_elias_fano(&[]);
+
+        assert!(sequence.is_empty());
+        assert_eq!(0, sequence.iter().count());
+        assert_eq!(0, sequence.nearest_index_of(5));
+        assert_eq!(None, sequence.index_of(0));
+    }
+
+    #[test]
+    fn sparse_elias_fano_is_smaller_than_logarray() {
+        let contents: Vec<u64> = (0..10000).map(|i| i * 1000 + (i % 7)).collect();
+        let sequence = build_elias_fano(&contents);
+
+        let logarray_file = MemoryBackedStore::new();
+        let mut builder = LogArrayFileBuilder::new(
+            logarray_file.open_write(),
+            util::calculate_width(contents[contents.len() - 1]),
+        );
+        block_on(async {
+            builder
+                .push_all(util::stream_iter_ok(contents.clone()))
+                .await?;
+            builder.finalize().await?;
+
+            Ok::<_, io::Error>(())
+        })
+        .unwrap();
+        let logarray = LogArray::parse(block_on(logarray_file.map()).unwrap()).unwrap();
+
+        assert_eq!(contents, sequence.iter().collect::<Vec<_>>());
+        assert!(sequence.size_in_bytes() < logarray.size_in_bytes());
+    }
+
+    #[test]
+    fn elias_fano_builder_rejects_bad_input() {
+        let file = MemoryBackedStore::new();
+        let mut builder = EliasFanoFileBuilder::new(file.open_write(), 3, 10);
+        block_on(async {
+            builder.push(5).await.unwrap();
+            assert!(builder.push(4).await.is_err());
+            assert!(builder.push(11).await.is_err());
+        });
+
+        assert!(block_on(builder.finalize()).is_err());
+    }
+}
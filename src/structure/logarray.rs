@@ -18,6 +18,10 @@
 //!       and
 //!    3. 24 unused bits.
 //!
+//! Elias-Fano sequences (see the `eliasfano` module) end in a control word with the same layout,
+//! but with the unused bits set to a marker, so that readers of monotonic sequences can tell the
+//! two formats apart.
+//!
 //! # Notes
 //!
 //! * All integers are stored in a standard big-endian encoding.
@@ -49,10 +53,12 @@
 //!
 //! * length: the number of elements in the log array
 
+use super::eliasfano::{is_elias_fano, EliasFano, EliasFanoIterator, ELIAS_FANO_MARKER};
 use super::util;
 use crate::storage::*;
 use byteorder::{BigEndian, ByteOrder};
-use bytes::{Bytes, BytesMut};
+use bytes::Bytes;
+use bytes05::BytesMut;
 use futures::future::FutureExt;
 use futures::stream::{self, Stream, StreamExt};
 use std::pin::Pin;
@@ -216,6 +222,13 @@ impl LogArray {
         self.len == 0
     }
 
+    /// Returns the size in bytes of the buffer backing this log array.
+    ///
+    /// For a slice, this is the size of the whole original buffer.
+    pub fn size_in_bytes(&self) -> usize {
+        self.input_buf.len()
+    }
+
     /// Returns the bit width.
     pub fn width(&self) -> u8 {
         self.width
@@ -434,6 +447,94 @@ impl<W: AsyncWrite + Unpin> LogArrayFileBuilder<W> {
     }
 }
 
+/// Build a log array in memory.
+///
+/// This produces the same bytes as `LogArrayFileBuilder`, but doesn't
+/// need a file or an async runtime.
+pub struct LogArrayBufBuilder {
+    /// Destination of the log array data
+    buf: Vec<u8>,
+    /// Bit width of an element
+    width: u8,
+    /// Storage for the next word to be written to the buffer
+    current: u64,
+    /// Bit offset in `current` for the msb of the next encoded element
+    offset: u8,
+    /// Number of elements written to the buffer
+    count: u32,
+}
+
+impl LogArrayBufBuilder {
+    pub fn new(width: u8) -> LogArrayBufBuilder {
+        LogArrayBufBuilder {
+            buf: Vec::new(),
+            width,
+            current: 0,
+            offset: 0,
+            count: 0,
+        }
+    }
+
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    /// Push a value onto the log array.
+    ///
+    /// Panics if the value does not fit in the width of this log array.
+    pub fn push(&mut self, val: u64) {
+        let leading_zeros = 64 - self.width;
+        assert!(
+            val.leading_zeros() >= u32::from(leading_zeros),
+            "expected value ({}) to fit in {} bits",
+            val,
+            self.width
+        );
+
+        self.count += 1;
+        self.current |= val << leading_zeros >> self.offset;
+        self.offset += self.width;
+
+        if self.offset >= 64 {
+            self.buf.extend_from_slice(&self.current.to_be_bytes());
+            self.offset -= 64;
+            self.current = if self.offset == 0 {
+                0
+            } else {
+                val << (64 - self.offset)
+            };
+        }
+    }
+
+    /// Push all values from an iterator.
+    ///
+    /// Panics if a value does not fit in the width of this log array.
+    pub fn push_all<I: IntoIterator<Item = u64>>(&mut self, vals: I) {
+        for val in vals {
+            self.push(val);
+        }
+    }
+
+    /// Finish the log array, returning its contents.
+    pub fn finalize(mut self) -> Bytes {
+        if u64::from(self.count) * u64::from(self.width) & 0b11_1111 != 0 {
+            self.buf.extend_from_slice(&self.current.to_be_bytes());
+        }
+
+        let mut control = [0; 8];
+        BigEndian::write_u32(&mut control, self.count);
+        control[4] = self.width;
+        self.buf.extend_from_slice(&control);
+
+        Bytes::from(self.buf)
+    }
+
+    /// Finish the log array and parse it.
+    pub fn build(self) -> LogArray {
+        LogArray::parse(self.finalize()).unwrap()
+    }
+}
+
 struct LogArrayDecoder {
     /// Storage for the most recent word read from the buffer
     current: u64,
@@ -586,8 +687,51 @@ pub fn logarray_stream_entries<F: 'static + FileLoad>(
     )
 }
 
+async fn monotonic_logarray_file_is_elias_fano<F: FileLoad>(f: F) -> io::Result<bool> {
+    LogArrayError::validate_input_buf_size(f.size())?;
+
+    let mut buf = [0; 8];
+    f.open_read_from(f.size() - 8).read_exact(&mut buf).await?;
+    Ok(buf[5..] == ELIAS_FANO_MARKER)
+}
+
+/// Stream the entries of a file holding either a log array or an Elias-Fano sequence.
+///
+/// Log arrays are decoded as they are read. Elias-Fano sequences are
+/// mapped into memory first.
+pub fn monotonic_logarray_stream_entries<F: 'static + FileLoad>(
+    f: F,
+) -> impl Stream<Item = io::Result<u64>> + Unpin + Send {
+    Box::pin(
+        async move {
+            if monotonic_logarray_file_is_elias_fano(f.clone()).await? {
+                let sequence = EliasFano::parse(f.map().await?)?;
+                Ok(Box::pin(stream::iter(sequence.iter().map(Ok)))
+                    as Pin<Box<dyn Stream<Item = io::Result<u64>> + Send>>)
+            } else {
+                Ok(Box::pin(logarray_stream_entries(f))
+                    as Pin<Box<dyn Stream<Item = io::Result<u64>> + Send>>)
+            }
+        }
+        .map(|result| match result {
+            Ok(stream) => stream,
+            Err(e) => Box::pin(stream::iter(vec![Err(e)])),
+        })
+        .into_stream()
+        .flatten(),
+    )
+}
+
+/// A monotonically increasing sequence, stored either as a plain log
+/// array or Elias-Fano encoded.
+#[derive(Clone)]
+pub struct MonotonicLogArray(MonotonicEncoding);
+
 #[derive(Clone)]
-pub struct MonotonicLogArray(LogArray);
+enum MonotonicEncoding {
+    LogArray(LogArray),
+    EliasFano(Box<EliasFano>),
+}
 
 impl MonotonicLogArray {
     pub fn from_logarray(logarray: LogArray) -> MonotonicLogArray {
@@ -607,23 +751,61 @@ impl MonotonicLogArray {
             }
         }
 
-        MonotonicLogArray(logarray)
+        MonotonicLogArray(MonotonicEncoding::LogArray(logarray))
+    }
+
+    pub fn from_elias_fano(sequence: EliasFano) -> MonotonicLogArray {
+        MonotonicLogArray(MonotonicEncoding::EliasFano(Box::new(sequence)))
+    }
+
+    /// Parse a buffer holding either a log array or an Elias-Fano sequence.
+    pub fn parse(buf: Bytes) -> io::Result<MonotonicLogArray> {
+        if is_elias_fano(&buf) {
+            Ok(MonotonicLogArray::from_elias_fano(EliasFano::parse(buf)?))
+        } else {
+            Ok(MonotonicLogArray::from_logarray(LogArray::parse(buf)?))
+        }
+    }
+
+    /// Returns true if this sequence is Elias-Fano encoded.
+    pub fn is_elias_fano(&self) -> bool {
+        matches!(self.0, MonotonicEncoding::EliasFano(_))
     }
 
     pub fn len(&self) -> usize {
-        self.0.len()
+        match &self.0 {
+            MonotonicEncoding::LogArray(logarray) => logarray.len(),
+            MonotonicEncoding::EliasFano(sequence) => sequence.len(),
+        }
     }
 
     pub fn is_empty(&self) -> bool {
-        self.0.is_empty()
+        self.len() == 0
+    }
+
+    pub fn size_in_bytes(&self) -> usize {
+        match &self.0 {
+            MonotonicEncoding::LogArray(logarray) => logarray.size_in_bytes(),
+            MonotonicEncoding::EliasFano(sequence) => sequence.size_in_bytes(),
+        }
     }
 
     pub fn entry(&self, index: usize) -> u64 {
-        self.0.entry(index)
+        match &self.0 {
+            MonotonicEncoding::LogArray(logarray) => logarray.entry(index),
+            MonotonicEncoding::EliasFano(sequence) => sequence.entry(index),
+        }
     }
 
-    pub fn iter(&self) -> LogArrayIterator {
-        self.0.iter()
+    pub fn iter(&self) -> MonotonicLogArrayIterator {
+        match &self.0 {
+            MonotonicEncoding::LogArray(logarray) => {
+                MonotonicLogArrayIterator::LogArray(logarray.iter())
+            }
+            MonotonicEncoding::EliasFano(sequence) => {
+                MonotonicLogArrayIterator::EliasFano(Box::new(sequence.iter()))
+            }
+        }
     }
 
     pub fn index_of(&self, element: u64) -> Option<usize> {
@@ -636,15 +818,20 @@ impl MonotonicLogArray {
     }
 
     pub fn nearest_index_of(&self, element: u64) -> usize {
-        if self.is_empty() {
+        let logarray = match &self.0 {
+            MonotonicEncoding::LogArray(logarray) => logarray,
+            MonotonicEncoding::EliasFano(sequence) => return sequence.nearest_index_of(element),
+        };
+
+        if logarray.is_empty() {
             return 0;
         }
 
         let mut min = 0;
-        let mut max = self.len() - 1;
+        let mut max = logarray.len() - 1;
         while min <= max {
             let mid = (min + max) / 2;
-            match element.cmp(&self.entry(mid)) {
+            match element.cmp(&logarray.entry(mid)) {
                 Ordering::Equal => return mid,
                 Ordering::Greater => min = mid + 1,
                 Ordering::Less => {
@@ -660,10 +847,36 @@ impl MonotonicLogArray {
     }
 }
 
+/// An iterator over the elements of a `MonotonicLogArray`.
+#[derive(Clone)]
+pub enum MonotonicLogArrayIterator {
+    LogArray(LogArrayIterator),
+    EliasFano(Box<EliasFanoIterator>),
+}
+
+impl Iterator for MonotonicLogArrayIterator {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<u64> {
+        match self {
+            MonotonicLogArrayIterator::LogArray(iter) => iter.next(),
+            MonotonicLogArrayIterator::EliasFano(iter) => iter.next(),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self {
+            MonotonicLogArrayIterator::LogArray(iter) => iter.size_hint(),
+            MonotonicLogArrayIterator::EliasFano(iter) => iter.size_hint(),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::storage::memory::*;
+    use crate::structure::eliasfano::EliasFanoFileBuilder;
     use crate::structure::util::stream_iter_ok;
     use futures::executor::block_on;
     use futures::stream::TryStreamExt;
@@ -1040,6 +1253,46 @@ mod tests {
         assert_eq!(expected, nearest);
     }
 
+    #[test]
+    fn monotonic_logarray_detects_elias_fano() {
+        let original = vec![3, 5, 6, 7, 10, 11, 15, 16, 18, 20, 25, 31, 1000];
+        let logarray_store = MemoryBackedStore::new();
+        let elias_fano_store = MemoryBackedStore::new();
+        let mut logarray_builder = LogArrayFileBuilder::new(logarray_store.open_write(), 10);
+        let mut elias_fano_builder =
+            EliasFanoFileBuilder::new(elias_fano_store.open_write(), original.len(), 1000);
+        block_on(async {
+            logarray_builder
+                .push_all(stream_iter_ok(original.clone()))
+                .await?;
+            logarray_builder.finalize().await?;
+            elias_fano_builder.push_all(original.clone()).await?;
+            elias_fano_builder.finalize().await?;
+
+            Ok::<_, io::Error>(())
+        })
+        .unwrap();
+
+        let logarray = MonotonicLogArray::parse(block_on(logarray_store.map()).unwrap()).unwrap();
+        let elias_fano =
+            MonotonicLogArray::parse(block_on(elias_fano_store.map()).unwrap()).unwrap();
+        assert!(!logarray.is_elias_fano());
+        assert!(elias_fano.is_elias_fano());
+
+        for monotonic in &[logarray, elias_fano] {
+            assert_eq!(original, monotonic.iter().collect::<Vec<_>>());
+            assert_eq!(Some(4), monotonic.index_of(10));
+            assert_eq!(12, monotonic.nearest_index_of(32));
+        }
+
+        for store in [logarray_store, elias_fano_store] {
+            let entries =
+                block_on(monotonic_logarray_stream_entries(store).try_collect::<Vec<u64>>())
+                    .unwrap();
+            assert_eq!(original, entries);
+        }
+    }
+
     #[test]
     fn writing_64_bits_of_data() {
         let store = MemoryBackedStore::new();
@@ -1059,4 +1312,33 @@ mod tests {
         assert_eq!(16, logarray.len());
         assert_eq!(4, logarray.width());
     }
+
+    #[test]
+    fn buf_builder_matches_file_builder() {
+        for &width in &[1, 5, 13, 64] {
+            let values: Vec<u64> = (0..100)
+                .map(|i| (i * 7919) % (1 << width.min(20)))
+                .collect();
+            let mut file_builder = LogArrayFileBuilder::new(Vec::new(), width);
+            block_on(file_builder.push_all(stream_iter_ok(values.clone()))).unwrap();
+            let expected = block_on(file_builder.finalize()).unwrap();
+
+            let mut builder = LogArrayBufBuilder::new(width);
+            builder.push_all(values.clone());
+            assert_eq!(100, builder.count());
+            let bytes = builder.finalize();
+            assert_eq!(expected, bytes.as_ref());
+
+            let mut builder = LogArrayBufBuilder::new(width);
+            builder.push_all(values.clone());
+            assert_eq!(values, builder.build().iter().collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "expected value (8) to fit in 3 bits")]
+    fn buf_builder_panics_on_wide_value() {
+        let mut builder = LogArrayBufBuilder::new(3);
+        builder.push(8);
+    }
 }
@@ -6,9 +6,13 @@ pub mod adjacencylist;
 pub mod bitarray;
 pub mod bitindex;
 pub mod bititer;
+pub mod eliasfano;
 pub mod logarray;
 //pub mod mapped_dict;
 pub mod pfc;
+pub mod rrr;
+pub mod selectindex;
+pub mod suffixarray;
 pub mod util;
 pub mod vbyte;
 pub mod wavelettree;
@@ -16,6 +20,10 @@ pub mod wavelettree;
 pub use adjacencylist::*;
 pub use bitarray::*;
 pub use bitindex::*;
+pub use eliasfano::*;
 pub use logarray::*;
 pub use pfc::*;
+pub use rrr::*;
+pub use selectindex::*;
+pub use suffixarray::*;
 pub use wavelettree::*;
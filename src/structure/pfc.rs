@@ -2,6 +2,7 @@
 
 use byteorder::{BigEndian, ByteOrder};
 use bytes::{Buf, Bytes, BytesMut};
+use bytes05::Buf as _;
 use futures::stream::{Stream, StreamExt};
 use std::cmp::{Ord, Ordering};
 use std::convert::TryInto;
@@ -9,9 +10,11 @@ use std::error::Error;
 use std::fmt::Display;
 use std::hash::{Hash, Hasher};
 use std::io;
+use std::ops::Range;
 use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
 use tokio_util::codec::{Decoder, FramedRead};
 
+use super::eliasfano::*;
 use super::logarray::*;
 use super::util::*;
 use super::vbyte;
@@ -21,6 +24,15 @@ use crate::storage::*;
 pub enum PfcError {
     InvalidCoding,
     NotEnoughData,
+    LogArray(LogArrayError),
+    /// The block offsets are Elias-Fano encoded, but could not be parsed.
+    EliasFano(io::Error),
+    /// The amount of block offsets (`.0`) does not match the amount expected for the header (`.1`).
+    UnexpectedBlockCount(usize, usize),
+    /// The block at the given index does not start inside the blocks buffer, after the block before it.
+    InvalidBlockOffset(usize),
+    /// The strings in the block at the given index are not correctly encoded.
+    InvalidBlockEncoding(usize),
 }
 
 impl Display for PfcError {
@@ -30,8 +42,8 @@ impl Display for PfcError {
 }
 
 impl From<LogArrayError> for PfcError {
-    fn from(_err: LogArrayError) -> PfcError {
-        PfcError::InvalidCoding
+    fn from(err: LogArrayError) -> PfcError {
+        PfcError::LogArray(err)
     }
 }
 
@@ -49,7 +61,45 @@ pub struct PfcBlock {
     n_strings: usize,
 }
 
-const BLOCK_SIZE: usize = 8;
+/// The amount of strings per block, unless another block size is chosen.
+///
+/// Dictionaries written before the block size became configurable
+/// always use this block size.
+pub const DEFAULT_BLOCK_SIZE: usize = 8;
+
+/// The largest block size that can be recorded in a dictionary.
+pub const MAX_BLOCK_SIZE: usize = 0xffff;
+
+/// The dictionary header is the last word of the blocks file. Its
+/// upper 16 bits hold the block size, and the rest holds the amount of
+/// strings. Older dictionaries only stored the amount of strings, so a
+/// block size of 0 means the default block size.
+const HEADER_BLOCK_SIZE_SHIFT: u32 = 48;
+
+/// Split a dictionary header word into the amount of strings and the block size.
+pub(crate) fn parse_header(header: u64) -> (u64, usize) {
+    let n_strings = header & ((1 << HEADER_BLOCK_SIZE_SHIFT) - 1);
+    let block_size = match (header >> HEADER_BLOCK_SIZE_SHIFT) as usize {
+        0 => DEFAULT_BLOCK_SIZE,
+        block_size => block_size,
+    };
+
+    (n_strings, block_size)
+}
+
+/// Build a dictionary header word from the amount of strings and the block size.
+///
+/// The default block size is stored as 0, so that dictionaries with
+/// the default block size can still be read by older versions.
+fn build_header(n_strings: usize, block_size: usize) -> u64 {
+    let stored_block_size = if block_size == DEFAULT_BLOCK_SIZE {
+        0
+    } else {
+        block_size as u64
+    };
+
+    n_strings as u64 | stored_block_size << HEADER_BLOCK_SIZE_SHIFT
+}
 
 pub struct PfcBlockEntryIterator {
     block: PfcBlock,
@@ -120,15 +170,58 @@ impl Iterator for PfcBlockIterator {
     }
 }
 
+/// Returns whether the given data starts with the given amount of
+/// correctly front-coded utf8 strings.
+fn is_valid_block(data: &[u8], n_strings: usize) -> bool {
+    let mut string: Vec<u8> = Vec::new();
+    let mut pos = 0;
+    for index in 0..n_strings {
+        if index != 0 {
+            let common = match vbyte::decode(&data[pos..]) {
+                Ok((common, common_len)) => {
+                    pos += common_len;
+                    common
+                }
+                Err(_) => return false,
+            };
+            if common > string.len() as u64 {
+                return false;
+            }
+            string.truncate(common as usize);
+        }
+
+        let postfix_len = match data[pos..].iter().position(|&b| b == 0) {
+            Some(postfix_len) => postfix_len,
+            None => return false,
+        };
+        string.extend_from_slice(&data[pos..pos + postfix_len]);
+        if std::str::from_utf8(&string).is_err() {
+            return false;
+        }
+        pos += postfix_len + 1;
+    }
+
+    true
+}
+
 impl PfcBlock {
-    pub fn parse(data: Bytes) -> Result<PfcBlock, PfcError> {
+    /// Parse a full block of a dictionary with the given block size.
+    pub fn parse(data: Bytes, block_size: usize) -> Result<PfcBlock, PfcError> {
+        if !data.contains(&0) {
+            return Err(PfcError::InvalidCoding);
+        }
+
         Ok(PfcBlock {
             encoded_strings: data,
-            n_strings: BLOCK_SIZE,
+            n_strings: block_size,
         })
     }
 
     pub fn parse_incomplete(data: Bytes, n_strings: usize) -> Result<PfcBlock, PfcError> {
+        if !data.contains(&0) {
+            return Err(PfcError::InvalidCoding);
+        }
+
         Ok(PfcBlock {
             encoded_strings: data,
             n_strings,
@@ -157,14 +250,14 @@ impl PfcBlock {
     fn entries(&self) -> PfcDictEntryIterator {
         PfcDictEntryIterator {
             block_iter: self.block_entries(),
-            parts: Vec::with_capacity(BLOCK_SIZE),
+            parts: Vec::with_capacity(self.n_strings),
         }
     }
 
     pub fn strings(&self) -> PfcBlockIterator {
         PfcBlockIterator {
             entry_iterator: self.block_entries(),
-            string: Vec::with_capacity(BLOCK_SIZE),
+            string: Vec::new(),
         }
     }
 
@@ -190,13 +283,14 @@ impl PfcBlock {
 
             // Having written down the prefixes, we now turn it into a list
             // of how much prefix we're interested in for every individual string.
-            // This is a simple matter of subtracting two adjacent entries.
-            for ix in 1..take_prefix_lengths.len() {
+            // This is a simple matter of subtracting two adjacent entries,
+            // going backwards so that each subtraction sees the original lengths.
+            for ix in (1..take_prefix_lengths.len()).rev() {
                 take_prefix_lengths[ix] -= take_prefix_lengths[ix - 1];
             }
 
             let (_, postfix) = &entries[index];
-            let mut result = Vec::with_capacity(BLOCK_SIZE);
+            let mut result = Vec::with_capacity(self.n_strings);
 
             for ((_, entry), take) in entries.iter().zip(take_prefix_lengths.iter()) {
                 result.push(entry.slice(..*take));
@@ -245,26 +339,16 @@ impl Iterator for PfcDictBlockIterator {
         if self.block_index >= self.dict.block_offsets.len() + 1 {
             return None;
         } else {
-            let block_offset = if self.block_index == 0 {
-                0
-            } else {
-                self.dict.block_offsets.entry(self.block_index - 1)
-            } as usize;
-            let remainder = self.dict.n_strings as usize - self.block_index * BLOCK_SIZE;
+            let remainder = self.dict.n_strings as usize - self.block_index * self.dict.block_size;
 
             if remainder == 0 {
                 return None;
             }
 
+            let block = self.dict.block(self.block_index);
             self.block_index += 1;
 
-            let mut block = self.dict.blocks.clone();
-            block.advance(block_offset);
-            if remainder >= BLOCK_SIZE {
-                Some(PfcBlock::parse(block).unwrap())
-            } else {
-                Some(PfcBlock::parse_incomplete(block, remainder).unwrap())
-            }
+            Some(block)
         }
     }
 }
@@ -373,45 +457,26 @@ impl PfcDictEntry {
 
     pub fn buf_eq<B: Buf>(&self, mut b: B) -> bool {
         if self.len() != b.remaining() {
-            false
-        } else if self.len() == 0 {
-            true
-        } else {
-            let mut it = self.parts.iter();
-            let mut part = it.next().unwrap();
-            loop {
-                let slice = b.bytes();
-
-                match part.len().cmp(&slice.len()) {
-                    Ordering::Less => {
-                        if part.as_ref() != &slice[..part.len()] {
-                            return false;
-                        }
-                    }
-                    Ordering::Equal => {
-                        if &part != &slice {
-                            return false;
-                        }
-
-                        if let Some(next) = it.next() {
-                            part = next;
-                        } else {
-                            // done!
-                            return true;
-                        }
-                    }
-                    Ordering::Greater => {
-                        if part.slice(..slice.len()) != slice {
-                            return false;
-                        }
+            return false;
+        }
 
-                        part = it.next().unwrap();
-                    }
+        // the parts and the chunks of the buffer don't line up, so
+        // compare as much as both have left each time.
+        for part in self.parts.iter() {
+            let mut part = part.as_ref();
+            while !part.is_empty() {
+                let slice = b.chunk();
+                let len = part.len().min(slice.len());
+                if part[..len] != slice[..len] {
+                    return false;
                 }
 
-                b.advance(part.len());
+                part = &part[len..];
+                b.advance(len);
             }
         }
+
+        true
     }
 }
 
@@ -509,23 +574,69 @@ impl PartialOrd for PfcDictEntry {
     }
 }
 
+/// Check that there is an offset for every block but the first, and
+/// that the blocks start inside the data, in order. The strings in the
+/// blocks are only checked as they are accessed through the `try_`
+/// methods of `PfcDict`, so that parsing doesn't have to read them.
+fn check_block_offsets<I: Iterator<Item = u64>>(
+    len: usize,
+    offsets: I,
+    block_count: usize,
+    data_end: usize,
+) -> Result<(), PfcError> {
+    if len != block_count.saturating_sub(1) {
+        return Err(PfcError::UnexpectedBlockCount(
+            len,
+            block_count.saturating_sub(1),
+        ));
+    }
+
+    let mut last_offset = 0;
+    for (index, offset) in offsets.enumerate() {
+        let offset = offset as usize;
+        if offset <= last_offset || offset >= data_end {
+            return Err(PfcError::InvalidBlockOffset(index + 1));
+        }
+        last_offset = offset;
+    }
+
+    Ok(())
+}
+
 #[derive(Clone)]
 pub struct PfcDict {
     n_strings: u64,
-    block_offsets: LogArray,
+    block_size: usize,
+    block_offsets: MonotonicLogArray,
     blocks: Bytes,
 }
 
 impl PfcDict {
     pub fn parse(blocks: Bytes, offsets: Bytes) -> Result<PfcDict, PfcError> {
-        let n_strings = BigEndian::read_u64(&blocks.as_ref()[blocks.as_ref().len() - 8..]);
-
-        let block_offsets = LogArray::parse(offsets)?;
+        if blocks.len() < 8 {
+            return Err(PfcError::NotEnoughData);
+        }
+        let data_end = blocks.len() - 8;
+        let (n_strings, block_size) = parse_header(BigEndian::read_u64(&blocks[data_end..]));
+
+        let block_count = (n_strings as usize).div_ceil(block_size);
+        // the offsets are checked before they're wrapped, as a log
+        // array that isn't increasing can't be used as a monotonic one
+        let block_offsets = if is_elias_fano(&offsets) {
+            let sequence = EliasFano::parse(offsets).map_err(PfcError::EliasFano)?;
+            check_block_offsets(sequence.len(), sequence.iter(), block_count, data_end)?;
+            MonotonicLogArray::from_elias_fano(sequence)
+        } else {
+            let logarray = LogArray::parse(offsets)?;
+            check_block_offsets(logarray.len(), logarray.iter(), block_count, data_end)?;
+            MonotonicLogArray::from_logarray(logarray)
+        };
 
         Ok(PfcDict {
-            n_strings: n_strings,
-            block_offsets: block_offsets,
-            blocks: blocks,
+            n_strings,
+            block_size,
+            block_offsets,
+            blocks,
         })
     }
 
@@ -533,47 +644,99 @@ impl PfcDict {
         self.n_strings as usize
     }
 
-    fn calculate_block_offset_index(&self, ix: usize) -> Option<(u64, usize)> {
-        if (ix as u64) < self.n_strings {
-            let block_index = ix / BLOCK_SIZE;
-            let block_offset = if block_index == 0 {
-                0
-            } else {
-                self.block_offsets.entry(block_index - 1)
-            };
+    /// Returns the amount of strings per block in this dictionary.
+    pub fn block_size(&self) -> usize {
+        self.block_size
+    }
 
-            let index_in_block = ix % BLOCK_SIZE;
-            Some((block_offset, index_in_block))
+    /// Returns the block with the given index.
+    fn block(&self, block_index: usize) -> PfcBlock {
+        let block_offset = if block_index == 0 {
+            0
+        } else {
+            self.block_offsets.entry(block_index - 1) as usize
+        };
+        let remainder = self.n_strings as usize - block_index * self.block_size;
+
+        let mut block = self.blocks.clone();
+        block.advance(block_offset);
+
+        PfcBlock {
+            encoded_strings: block,
+            n_strings: std::cmp::min(remainder, self.block_size),
+        }
+    }
+
+    /// Returns the block with the given index, after checking that its first `n_strings` strings are correctly encoded.
+    fn checked_block(&self, block_index: usize, n_strings: usize) -> Result<PfcBlock, PfcError> {
+        let block = self.block(block_index);
+        if is_valid_block(&block.encoded_strings, n_strings) {
+            Ok(block)
+        } else {
+            Err(PfcError::InvalidBlockEncoding(block_index))
+        }
+    }
+
+    /// Returns the size in bytes of the buffers backing this dictionary.
+    pub fn size_in_bytes(&self) -> usize {
+        self.blocks.len() + self.block_offsets.size_in_bytes()
+    }
+
+    fn calculate_block_index(&self, ix: usize) -> Option<(usize, usize)> {
+        if (ix as u64) < self.n_strings {
+            Some((ix / self.block_size, ix % self.block_size))
         } else {
             None
         }
     }
 
     pub fn entry(&self, ix: usize) -> Option<PfcDictEntry> {
-        if let Some((block_offset, index_in_block)) = self.calculate_block_offset_index(ix) {
-            let mut block_bytes = self.blocks.clone();
-            block_bytes.advance(block_offset as usize);
-
-            let block = PfcBlock::parse(block_bytes).unwrap();
-            block.entry(index_in_block)
+        if let Some((block_index, index_in_block)) = self.calculate_block_index(ix) {
+            self.block(block_index).entry(index_in_block)
         } else {
             None
         }
     }
 
     pub fn get(&self, ix: usize) -> Option<String> {
-        if let Some((block_offset, index_in_block)) = self.calculate_block_offset_index(ix) {
-            let mut block_bytes = self.blocks.clone();
-            block_bytes.advance(block_offset as usize);
-
-            let block = PfcBlock::parse(block_bytes).unwrap();
-            block.get(index_in_block)
+        if let Some((block_index, index_in_block)) = self.calculate_block_index(ix) {
+            self.block(block_index).get(index_in_block)
         } else {
             None
         }
     }
 
+    /// Like `entry`, but returns an error if the block holding the entry is not correctly encoded, instead of panicking.
+    pub fn try_entry(&self, ix: usize) -> Result<Option<PfcDictEntry>, PfcError> {
+        match self.calculate_block_index(ix) {
+            Some((block_index, index_in_block)) => Ok(self
+                .checked_block(block_index, index_in_block + 1)?
+                .entry(index_in_block)),
+            None => Ok(None),
+        }
+    }
+
+    /// Like `get`, but returns an error if the block holding the string is not correctly encoded, instead of panicking.
+    pub fn try_get(&self, ix: usize) -> Result<Option<String>, PfcError> {
+        match self.calculate_block_index(ix) {
+            Some((block_index, index_in_block)) => Ok(self
+                .checked_block(block_index, index_in_block + 1)?
+                .get(index_in_block)),
+            None => Ok(None),
+        }
+    }
+
     pub fn id(&self, s: &str) -> Option<u64> {
+        self.find_id(s, false)
+            .expect("lookups without checks don't fail")
+    }
+
+    /// Like `id`, but returns an error if the block that would hold the string is not correctly encoded, instead of panicking.
+    pub fn try_id(&self, s: &str) -> Result<Option<u64>, PfcError> {
+        self.find_id(s, true)
+    }
+
+    fn find_id(&self, s: &str, check: bool) -> Result<Option<u64>, PfcError> {
         let s_bytes = s.as_bytes();
         // let's binary search
         let mut min = 0;
@@ -589,7 +752,11 @@ impl PfcDict {
                 self.block_offsets.entry(mid - 1) as usize
             };
             let block_slice = &self.blocks.as_ref()[block_offset..]; // this is probably more than one block, but we're only interested in the first string anyway
-            let head_end = block_slice.iter().position(|&b| b == 0).unwrap();
+            let head_end = match block_slice.iter().position(|&b| b == 0) {
+                Some(head_end) => head_end,
+                None if check => return Err(PfcError::InvalidBlockEncoding(mid)),
+                None => return Ok(None),
+            };
             let head_slice = &block_slice[..head_end];
 
             match s_bytes.cmp(head_slice) {
@@ -597,41 +764,114 @@ impl PfcDict {
                     if mid == 0 {
                         // we checked the first block and determined that the string should be in the previous block, if it exists.
                         // but since this is the first block, the string doesn't exist.
-                        return None;
+                        return Ok(None);
                     }
                     max = mid - 1;
                 }
                 Ordering::Greater => min = mid + 1,
-                Ordering::Equal => return Some((mid * BLOCK_SIZE) as u64), // what luck! turns out the string we were looking for was the block head
+                Ordering::Equal => return Ok(Some((mid * self.block_size) as u64)), // what luck! turns out the string we were looking for was the block head
             }
         }
 
         let found = max;
 
         // we found the block the string should be part of.
-        let block_start = if found == 0 {
-            0
+        let block = if check {
+            self.checked_block(found, self.block(found).n_strings)?
         } else {
-            self.block_offsets.entry(found - 1) as usize
-        };
-        let remainder = self.n_strings as usize - (found * BLOCK_SIZE);
-        let mut block = self.blocks.clone();
-        block.advance(block_start);
-        let block = if remainder >= BLOCK_SIZE {
-            PfcBlock::parse(block).unwrap()
-        } else {
-            PfcBlock::parse_incomplete(block, remainder as usize).unwrap()
+            self.block(found)
         };
 
         let mut count = 0;
         for block_entry in block.entries() {
             if block_entry.buf_eq(s_bytes) {
-                return Some((found * BLOCK_SIZE + count) as u64);
+                return Ok(Some((found * self.block_size + count) as u64));
             }
             count += 1;
         }
 
-        None
+        Ok(None)
+    }
+
+    fn block_head(&self, block_index: usize) -> &[u8] {
+        let block_offset = if block_index == 0 {
+            0
+        } else {
+            self.block_offsets.entry(block_index - 1) as usize
+        };
+        let block_slice = &self.blocks.as_ref()[block_offset..];
+        let head_end = block_slice.iter().position(|&b| b == 0).unwrap();
+
+        &block_slice[..head_end]
+    }
+
+    /// Returns the id of the first string that is not smaller than the given bytes, or the length of this dictionary if there is none.
+    fn lower_bound(&self, bytes: &[u8]) -> u64 {
+        if self.n_strings == 0 {
+            return 0;
+        }
+
+        // find the first block whose head is not smaller than the given bytes.
+        let block_count = self.block_offsets.len() + 1;
+        let mut min = 0;
+        let mut max = block_count;
+        while min < max {
+            let mid = (min + max) / 2;
+            if self.block_head(mid) < bytes {
+                min = mid + 1;
+            } else {
+                max = mid;
+            }
+        }
+
+        if min == 0 {
+            return 0;
+        }
+
+        // the result is either in the block before that one, or it is the head of that block.
+        let found = min - 1;
+        let block = self.block(found);
+
+        let index_in_block = block
+            .strings()
+            .position(|s| s.as_bytes() >= bytes)
+            .unwrap_or(block.n_strings);
+
+        (found * self.block_size + index_in_block) as u64
+    }
+
+    /// Returns the ids of all strings `s` for which `lo <= s < hi`.
+    pub fn range(&self, lo: &str, hi: &str) -> Range<u64> {
+        let start = self.lower_bound(lo.as_bytes());
+        let end = self.lower_bound(hi.as_bytes());
+
+        start..std::cmp::max(start, end)
+    }
+
+    /// Returns the ids of all strings starting with the given prefix.
+    pub fn prefix_range(&self, prefix: &str) -> Range<u64> {
+        let start = self.lower_bound(prefix.as_bytes());
+
+        // the first string past the prefix range starts with the
+        // smallest byte sequence that is larger than every string
+        // with this prefix.
+        let mut upper = prefix.as_bytes().to_vec();
+        while let Some(&last) = upper.last() {
+            if last == 0xff {
+                upper.pop();
+            } else {
+                *upper.last_mut().unwrap() += 1;
+                break;
+            }
+        }
+
+        let end = if upper.is_empty() {
+            self.n_strings
+        } else {
+            self.lower_bound(&upper)
+        };
+
+        start..end
     }
 
     pub fn strings(&self) -> impl Iterator<Item = String> {
@@ -656,22 +896,57 @@ pub struct PfcDictFileBuilder<W: tokio::io::AsyncWrite + Unpin + Send> {
     count: usize,
     /// the size in bytes of the pfc data structure so far
     size: usize,
+    /// the amount of strings per block
+    block_size: usize,
+    /// whether the block offsets are written Elias-Fano encoded
+    elias_fano: bool,
     last: Option<Vec<u8>>,
     index: Vec<u64>,
 }
 
 impl<W: 'static + tokio::io::AsyncWrite + Unpin + Send> PfcDictFileBuilder<W> {
     pub fn new(pfc_blocks_file: W, pfc_block_offsets_file: W) -> PfcDictFileBuilder<W> {
+        Self::with_block_size(pfc_blocks_file, pfc_block_offsets_file, DEFAULT_BLOCK_SIZE)
+    }
+
+    /// Create a builder for a dictionary with the given amount of strings per block.
+    ///
+    /// Larger blocks compress long strings with shared prefixes
+    /// better, at the cost of decoding more strings per lookup.
+    ///
+    /// Panics if the block size is 0 or larger than `MAX_BLOCK_SIZE`.
+    pub fn with_block_size(
+        pfc_blocks_file: W,
+        pfc_block_offsets_file: W,
+        block_size: usize,
+    ) -> PfcDictFileBuilder<W> {
+        assert!(
+            block_size > 0 && block_size <= MAX_BLOCK_SIZE,
+            "invalid pfc block size {}",
+            block_size
+        );
+
         PfcDictFileBuilder {
             pfc_blocks_file,
             pfc_block_offsets_file,
             count: 0,
             size: 0,
+            block_size,
+            elias_fano: false,
             last: None,
             index: Vec::new(),
         }
     }
 
+    /// Set whether the block offsets are written Elias-Fano encoded.
+    ///
+    /// This makes the offsets of large dictionaries, which grow with
+    /// the size of the blocks file, a lot smaller.
+    pub fn with_elias_fano(mut self, elias_fano: bool) -> PfcDictFileBuilder<W> {
+        self.elias_fano = elias_fano;
+        self
+    }
+
     pub async fn add_entry(&mut self, e: &PfcDictEntry) -> io::Result<u64> {
         let bytes = e.to_bytes();
         self.add_bytes(&bytes).await
@@ -683,7 +958,7 @@ impl<W: 'static + tokio::io::AsyncWrite + Unpin + Send> PfcDictFileBuilder<W> {
     }
 
     pub async fn add_bytes(&mut self, bytes: &[u8]) -> io::Result<u64> {
-        if self.count % BLOCK_SIZE == 0 {
+        if self.count % self.block_size == 0 {
             if self.count != 0 {
                 // this is the start of a block, but not the start of the first block
                 // we need to store an index
@@ -733,36 +1008,152 @@ impl<W: 'static + tokio::io::AsyncWrite + Unpin + Send> PfcDictFileBuilder<W> {
 
     /// finish the data structure
     pub async fn finalize(mut self) -> io::Result<()> {
+        let header = build_header(self.count, self.block_size);
+        if self.elias_fano {
+            let max = self.index.last().cloned().unwrap_or(0);
+            let mut builder =
+                EliasFanoFileBuilder::new(self.pfc_block_offsets_file, self.index.len(), max);
+            builder.push_all(self.index).await?;
+            builder.finalize().await?;
+        } else {
+            let width = if self.index.is_empty() {
+                1
+            } else {
+                64 - self.index[self.index.len() - 1].leading_zeros()
+            };
+            let mut builder = LogArrayFileBuilder::new(self.pfc_block_offsets_file, width as u8);
+            builder.push_all(stream_iter_ok(self.index)).await?;
+            builder.finalize().await?;
+        }
+
+        write_padding(&mut self.pfc_blocks_file, self.size, 8).await?;
+        write_u64(&mut self.pfc_blocks_file, header).await?;
+        self.pfc_blocks_file.flush().await?;
+
+        Ok(())
+    }
+}
+
+/// Build a dictionary in memory.
+///
+/// This produces the same bytes as `PfcDictFileBuilder`, but doesn't
+/// need files or an async runtime.
+pub struct PfcDictBufBuilder {
+    /// the pfc blocks written so far
+    blocks: Vec<u8>,
+    /// the amount of strings in this dict so far
+    count: usize,
+    /// the amount of strings per block
+    block_size: usize,
+    last: Option<Vec<u8>>,
+    index: Vec<u64>,
+}
+
+impl Default for PfcDictBufBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PfcDictBufBuilder {
+    pub fn new() -> PfcDictBufBuilder {
+        Self::with_block_size(DEFAULT_BLOCK_SIZE)
+    }
+
+    /// Create a builder for a dictionary with the given amount of strings per block.
+    ///
+    /// Panics if the block size is 0 or larger than `MAX_BLOCK_SIZE`.
+    pub fn with_block_size(block_size: usize) -> PfcDictBufBuilder {
+        assert!(
+            block_size > 0 && block_size <= MAX_BLOCK_SIZE,
+            "invalid pfc block size {}",
+            block_size
+        );
+
+        PfcDictBufBuilder {
+            blocks: Vec::new(),
+            count: 0,
+            block_size,
+            last: None,
+            index: Vec::new(),
+        }
+    }
+
+    pub fn add_entry(&mut self, e: &PfcDictEntry) -> u64 {
+        self.add_bytes(&e.to_bytes())
+    }
+
+    pub fn add(&mut self, s: &str) -> u64 {
+        self.add_bytes(s.as_bytes())
+    }
+
+    pub fn add_bytes(&mut self, bytes: &[u8]) -> u64 {
+        if self.count % self.block_size == 0 {
+            if self.count != 0 {
+                // this is the start of a block, but not the start of the first block
+                // we need to store an index
+                self.index.push(self.blocks.len() as u64);
+            }
+            self.blocks.extend_from_slice(bytes);
+        } else {
+            let common = find_common_prefix(self.last.as_ref().unwrap(), bytes);
+            self.blocks.extend(vbyte::encode_vec(common as u64));
+            self.blocks.extend_from_slice(&bytes[common..]);
+        }
+        self.blocks.push(0);
+
+        self.count += 1;
+        self.last = Some(bytes.to_vec());
+
+        self.count as u64
+    }
+
+    pub fn add_all<'a, I: IntoIterator<Item = &'a str>>(&mut self, it: I) -> Vec<u64> {
+        it.into_iter().map(|s| self.add(s)).collect()
+    }
+
+    /// Finish the dictionary, returning the contents of its blocks and offsets.
+    pub fn finalize(mut self) -> DictionaryMaps {
         let width = if self.index.is_empty() {
             1
         } else {
             64 - self.index[self.index.len() - 1].leading_zeros()
         };
-        let mut builder = LogArrayFileBuilder::new(self.pfc_block_offsets_file, width as u8);
-        let count = self.count as u64;
+        let mut offsets = LogArrayBufBuilder::new(width as u8);
+        offsets.push_all(self.index);
 
-        builder.push_all(stream_iter_ok(self.index)).await?;
-        builder.finalize().await?;
+        let header = build_header(self.count, self.block_size);
+        let padding = (8 - self.blocks.len() % 8) % 8;
+        self.blocks.resize(self.blocks.len() + padding, 0);
+        self.blocks.extend_from_slice(&header.to_be_bytes());
 
-        write_padding(&mut self.pfc_blocks_file, self.size, 8).await?;
-        write_u64(&mut self.pfc_blocks_file, count).await?;
-        self.pfc_blocks_file.flush().await?;
+        DictionaryMaps {
+            blocks_map: Bytes::from(self.blocks),
+            offsets_map: offsets.finalize(),
+        }
+    }
 
-        Ok(())
+    /// Finish the dictionary and parse it.
+    pub fn build(self) -> PfcDict {
+        let maps = self.finalize();
+
+        PfcDict::parse(maps.blocks_map, maps.offsets_map).unwrap()
     }
 }
 
 struct PfcDecoder {
-    last: Option<BytesMut>,
+    last: Option<bytes05::BytesMut>,
     index: usize,
+    block_size: usize,
     done: bool,
 }
 
 impl PfcDecoder {
-    fn new() -> Self {
+    fn new(block_size: usize) -> Self {
         Self {
             last: None,
             index: 0,
+            block_size,
             done: false,
         }
     }
@@ -771,7 +1162,7 @@ impl PfcDecoder {
 impl Decoder for PfcDecoder {
     type Item = String;
     type Error = io::Error;
-    fn decode(&mut self, bytes: &mut BytesMut) -> Result<Option<String>, io::Error> {
+    fn decode(&mut self, bytes: &mut bytes05::BytesMut) -> Result<Option<String>, io::Error> {
         if self.done {
             bytes.clear();
             return Ok(None);
@@ -787,7 +1178,7 @@ impl Decoder for PfcDecoder {
 
         match pos {
             None => Ok(None),
-            Some(pos) => match self.index % 8 == 0 {
+            Some(pos) => match self.index % self.block_size == 0 {
                 true => {
                     // this is the start of a block. we expect a 0-delimited cstring
                     let b = bytes.split_to(pos);
@@ -798,14 +1189,14 @@ impl Decoder for PfcDecoder {
 
                     Ok(Some(s))
                 }
-                false => {
+                _ => {
                     // This is in the middle of some block. we expect a vbyte followed by some 0-delimited cstring
                     let last = self.last.as_ref().unwrap();
                     let (prefix_len, vbyte_len) = vbyte::decode(&bytes).expect("expected vbyte");
                     bytes.advance(vbyte_len);
                     let b = bytes.split_to(pos - vbyte_len);
                     bytes.advance(1);
-                    let mut full = BytesMut::with_capacity(prefix_len as usize + b.len());
+                    let mut full = bytes05::BytesMut::with_capacity(prefix_len as usize + b.len());
                     full.extend_from_slice(&last[..prefix_len as usize]);
                     full.extend_from_slice(&b);
 
@@ -820,25 +1211,57 @@ impl Decoder for PfcDecoder {
     }
 }
 
-pub async fn dict_file_get_count<F: 'static + FileLoad>(file: F) -> io::Result<u64> {
+async fn dict_file_get_header<F: 'static + FileLoad>(file: F) -> io::Result<(u64, usize)> {
     let mut result = vec![0; 8];
     file.open_read_from(file.size() - 8)
         .read_exact(&mut result)
         .await?;
-    Ok(BigEndian::read_u64(&result))
+    Ok(parse_header(BigEndian::read_u64(&result)))
 }
 
+pub async fn dict_file_get_count<F: 'static + FileLoad>(file: F) -> io::Result<u64> {
+    Ok(dict_file_get_header(file).await?.0)
+}
+
+/// Returns the amount of strings per block of the dictionary in the given file.
+pub async fn dict_file_get_block_size<F: 'static + FileLoad>(file: F) -> io::Result<usize> {
+    Ok(dict_file_get_header(file).await?.1)
+}
+
+/// Stream the strings of a dictionary with the default block size.
+///
+/// For dictionaries with another block size, use `dict_reader_to_stream_with_block_size`.
 pub fn dict_reader_to_stream<A: 'static + AsyncRead + Unpin + Send>(
     r: A,
 ) -> impl Stream<Item = io::Result<String>> + Unpin + Send {
-    FramedRead::new(r, PfcDecoder::new())
+    dict_reader_to_stream_with_block_size(r, DEFAULT_BLOCK_SIZE)
 }
 
+/// Stream the strings of a dictionary with the given block size.
+pub fn dict_reader_to_stream_with_block_size<A: 'static + AsyncRead + Unpin + Send>(
+    r: A,
+    block_size: usize,
+) -> impl Stream<Item = io::Result<String>> + Unpin + Send {
+    FramedRead::new(r, PfcDecoder::new(block_size))
+}
+
+/// Stream the strings of a dictionary with the default block size, along with their ids.
+///
+/// For dictionaries with another block size, use `dict_reader_to_indexed_stream_with_block_size`.
 pub fn dict_reader_to_indexed_stream<A: 'static + AsyncRead + Unpin + Send>(
     r: A,
     offset: u64,
 ) -> impl Stream<Item = io::Result<(u64, String)>> + Send {
-    let dict_stream = dict_reader_to_stream(r);
+    dict_reader_to_indexed_stream_with_block_size(r, offset, DEFAULT_BLOCK_SIZE)
+}
+
+/// Stream the strings of a dictionary with the given block size, along with their ids.
+pub fn dict_reader_to_indexed_stream_with_block_size<A: 'static + AsyncRead + Unpin + Send>(
+    r: A,
+    offset: u64,
+    block_size: usize,
+) -> impl Stream<Item = io::Result<(u64, String)>> + Send {
+    let dict_stream = dict_reader_to_stream_with_block_size(r, block_size);
 
     dict_stream.enumerate().map(move |(i, x)| match x {
         Ok(x) => Ok(((i + 1) as u64 + offset, x)),
@@ -854,7 +1277,13 @@ pub async fn merge_dictionaries<
     dictionaries: I,
     dict_files: DictionaryFiles<F>,
 ) -> io::Result<()> {
-    let iterators: Vec<_> = dictionaries.map(|d| d.entries()).collect();
+    let dictionaries: Vec<_> = dictionaries.collect();
+    // the merged dictionary keeps the block size of the first dictionary
+    let block_size = dictionaries
+        .first()
+        .map(|d| d.block_size())
+        .unwrap_or(DEFAULT_BLOCK_SIZE);
+    let iterators: Vec<_> = dictionaries.iter().map(|d| d.entries()).collect();
 
     let pick_fn = |vals: &[Option<&PfcDictEntry>]| {
         vals.iter()
@@ -866,9 +1295,10 @@ pub async fn merge_dictionaries<
 
     let sorted_iterator = sorted_iterator(iterators, pick_fn);
 
-    let mut builder = PfcDictFileBuilder::new(
+    let mut builder = PfcDictFileBuilder::with_block_size(
         dict_files.blocks_file.open_write(),
         dict_files.offsets_file.open_write(),
+        block_size,
     );
 
     builder.add_all_entries(sorted_iterator).await?;
@@ -1009,6 +1439,147 @@ mod tests {
         assert_eq!(None, dict.id("zzz"));
     }
 
+    #[test]
+    fn retrieve_id_of_strings_sharing_prefixes() {
+        let contents = vec![
+            "adopted",
+            "http://www.w3.org/2000/01/rdf-schema#domain",
+            "http://www.w3.org/2000/01/rdf-schema#range",
+            "http://www.w3.org/2000/01/rdf-schema#subClassOf",
+            "http://www.w3.org/2000/01/rdf-schema#subPropertyOf",
+            "http://www.w3.org/2000/01/rdf-schema#subPropertyOfAnother",
+        ];
+
+        let blocks = MemoryBackedStore::new();
+        let offsets = MemoryBackedStore::new();
+        let mut builder = PfcDictFileBuilder::new(blocks.open_write(), offsets.open_write());
+
+        block_on(async {
+            builder.add_all(contents.clone().into_iter()).await?;
+            builder.finalize().await?;
+
+            Ok::<_, io::Error>(())
+        })
+        .unwrap();
+
+        let dict = PfcDict::parse(
+            block_on(blocks.map()).unwrap(),
+            block_on(offsets.map()).unwrap(),
+        )
+        .unwrap();
+
+        for (id, string) in contents.iter().enumerate() {
+            assert_eq!(Some(id as u64), dict.id(string));
+        }
+        assert_eq!(None, dict.id("http://www.w3.org/2000/01/rdf-schema#sub"));
+        assert_eq!(None, dict.id("http://www.w3.org/2000/01/rdf-schema#rangeX"));
+    }
+
+    fn build_dict(contents: &[&str]) -> PfcDict {
+        build_dict_with_block_size(contents, DEFAULT_BLOCK_SIZE)
+    }
+
+    fn build_dict_with_block_size(contents: &[&str], block_size: usize) -> PfcDict {
+        build_dict_with_options(contents, block_size, false)
+    }
+
+    fn build_dict_with_options(contents: &[&str], block_size: usize, elias_fano: bool) -> PfcDict {
+        let blocks = MemoryBackedStore::new();
+        let offsets = MemoryBackedStore::new();
+        let mut builder = PfcDictFileBuilder::with_block_size(
+            blocks.open_write(),
+            offsets.open_write(),
+            block_size,
+        )
+        .with_elias_fano(elias_fano);
+
+        block_on(async {
+            for s in contents {
+                builder.add(s).await?;
+            }
+            builder.finalize().await?;
+
+            Ok::<_, io::Error>(())
+        })
+        .unwrap();
+
+        PfcDict::parse(
+            block_on(blocks.map()).unwrap(),
+            block_on(offsets.map()).unwrap(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn retrieve_prefix_range_from_dict() {
+        let contents = vec![
+            "aaaaa",
+            "aaaaaaaaaa",
+            "aaaabbbbbb",
+            "abcdefghijk",
+            "addeeerafa",
+            "arf",
+            "bapofsi",
+            "barf",
+            "berf",
+            "boo boo boo boo",
+            "bzwas baraf",
+            "dradsfadfvbbb",
+            "eadfpoicvu",
+            "eeeee ee e eee",
+            "faadsafdfaf sdfasdf",
+            "frumps framps fremps",
+            "gahh",
+            "hai hai hai",
+        ];
+        let dict = build_dict(&contents);
+
+        assert_eq!(0..18, dict.prefix_range(""));
+        assert_eq!(0..6, dict.prefix_range("a"));
+        assert_eq!(0..3, dict.prefix_range("aaaa"));
+        assert_eq!(1..2, dict.prefix_range("aaaaaa"));
+        assert_eq!(6..11, dict.prefix_range("b"));
+        assert_eq!(6..8, dict.prefix_range("ba"));
+        assert_eq!(8..9, dict.prefix_range("berf"));
+        assert_eq!(17..18, dict.prefix_range("hai"));
+        assert_eq!(11..11, dict.prefix_range("c"));
+        assert_eq!(18..18, dict.prefix_range("zzz"));
+
+        for prefix in &["a", "aa", "b", "bo", "d", "e", "ee", "f", "g", "h", "x"] {
+            let expected: Vec<u64> = (0..contents.len() as u64)
+                .filter(|&i| contents[i as usize].starts_with(prefix))
+                .collect();
+            let actual: Vec<u64> = dict.prefix_range(prefix).collect();
+            assert_eq!(expected, actual, "prefix {}", prefix);
+        }
+    }
+
+    #[test]
+    fn retrieve_range_from_dict() {
+        let contents = vec![
+            "aaaaa",
+            "aabbb",
+            "ccccc",
+            "ddddd",
+            "deasdfvv",
+            "deasdfvw",
+            "ee",
+            "eee",
+            "eeee",
+            "great scott",
+        ];
+        let dict = build_dict(&contents);
+
+        assert_eq!(0..10, dict.range("", "zzz"));
+        assert_eq!(2..6, dict.range("b", "e"));
+        assert_eq!(2..6, dict.range("ccccc", "ee"));
+        assert_eq!(6..9, dict.range("ee", "f"));
+        assert_eq!(9..10, dict.range("eeeee", "z"));
+        assert_eq!(10..10, dict.range("h", "z"));
+        assert_eq!(0..0, dict.range("", "a"));
+        assert_eq!(9..9, dict.range("f", "a"));
+    }
+
     #[test]
     fn retrieve_all_strings() {
         let contents = vec![
@@ -1216,4 +1787,225 @@ mod tests {
 
         assert_eq!(18, count);
     }
+
+    #[test]
+    fn dict_with_custom_block_size() {
+        let contents: Vec<String> = (0..20)
+            .map(|i| format!("http://example.com/thing/{:03}", i))
+            .collect();
+        let contents: Vec<&str> = contents.iter().map(|s| s.as_str()).collect();
+
+        for &block_size in &[1, 3, 8, 16, 32] {
+            let dict = build_dict_with_block_size(&contents, block_size);
+
+            assert_eq!(block_size, dict.block_size());
+            assert_eq!(20, dict.len());
+            for (ix, s) in contents.iter().enumerate() {
+                assert_eq!(Some(s.to_string()), dict.get(ix));
+                assert_eq!(Some(ix as u64), dict.id(s));
+            }
+            assert_eq!(None, dict.get(20));
+            assert_eq!(None, dict.id("http://example.com/thing/020"));
+            assert_eq!(contents, dict.strings().collect::<Vec<_>>());
+            assert_eq!(10..20, dict.prefix_range("http://example.com/thing/01"));
+        }
+    }
+
+    #[test]
+    fn dict_with_elias_fano_offsets() {
+        let contents: Vec<String> = (0..100)
+            .map(|i| format!("http://example.com/thing/{:03}", i))
+            .collect();
+        let contents: Vec<&str> = contents.iter().map(|s| s.as_str()).collect();
+
+        for &len in &[0, 1, 3, 100] {
+            let contents = &contents[..len];
+            let dict = build_dict_with_options(contents, 3, true);
+            let plain = build_dict_with_options(contents, 3, false);
+
+            assert!(dict.block_offsets.is_elias_fano());
+            assert_eq!(len, dict.len());
+            for (ix, s) in contents.iter().enumerate() {
+                assert_eq!(Some(s.to_string()), dict.get(ix));
+                assert_eq!(Some(ix as u64), dict.id(s));
+            }
+            assert_eq!(contents, dict.strings().collect::<Vec<_>>());
+            assert_eq!(plain.blocks, dict.blocks);
+        }
+    }
+
+    #[test]
+    fn stream_dict_with_custom_block_size() {
+        let contents = vec![
+            "aaaaa", "aabbb", "aabbc", "bbbbb", "bbbbc", "ccccc", "ccddd",
+        ];
+        let blocks = MemoryBackedStore::new();
+        let offsets = MemoryBackedStore::new();
+        let mut builder =
+            PfcDictFileBuilder::with_block_size(blocks.open_write(), offsets.open_write(), 3);
+        block_on(async {
+            builder.add_all(contents.clone().into_iter()).await?;
+            builder.finalize().await?;
+
+            Ok::<_, io::Error>(())
+        })
+        .unwrap();
+
+        let block_size = block_on(dict_file_get_block_size(blocks.clone())).unwrap();
+        assert_eq!(3, block_size);
+        assert_eq!(7, block_on(dict_file_get_count(blocks.clone())).unwrap());
+
+        let stream = dict_reader_to_stream_with_block_size(blocks.open_read(), block_size);
+        let result: Vec<String> = block_on(stream.try_collect()).unwrap();
+        assert_eq!(contents, result);
+
+        let stream =
+            dict_reader_to_indexed_stream_with_block_size(blocks.open_read(), 10, block_size);
+        let result: Vec<(u64, String)> = block_on(stream.try_collect()).unwrap();
+        let expected: Vec<(u64, String)> = contents
+            .iter()
+            .enumerate()
+            .map(|(i, s)| (i as u64 + 11, s.to_string()))
+            .collect();
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn parse_dict_without_block_size_in_header() {
+        let contents = vec![
+            "aaaaa", "aabbb", "ccccc", "ddddd", "eeeee", "fffff", "g", "h", "i",
+        ];
+        let blocks = MemoryBackedStore::new();
+        let offsets = MemoryBackedStore::new();
+        let mut builder = PfcDictFileBuilder::new(blocks.open_write(), offsets.open_write());
+        block_on(async {
+            builder.add_all(contents.clone().into_iter()).await?;
+            builder.finalize().await?;
+
+            Ok::<_, io::Error>(())
+        })
+        .unwrap();
+
+        // like older dictionaries, a dictionary with the default block
+        // size only stores the string count in its header
+        let data = block_on(blocks.map()).unwrap();
+        let len = data.len();
+        assert_eq!(contents.len() as u64, BigEndian::read_u64(&data[len - 8..]));
+
+        let dict = PfcDict::parse(data, block_on(offsets.map()).unwrap()).unwrap();
+        assert_eq!(DEFAULT_BLOCK_SIZE, dict.block_size());
+        assert_eq!(contents, dict.strings().collect::<Vec<_>>());
+        assert_eq!(Some(8), dict.id("i"));
+    }
+
+    #[test]
+    fn buf_builder_matches_file_builder() {
+        let strings = vec![
+            "aaaaa",
+            "aabbb",
+            "ab",
+            "bbbbb",
+            "bcdef",
+            "cdef",
+            "cdefg",
+            "cfff",
+            "cfgg",
+            "dddd",
+            "dddd\u{1}",
+            "eeee",
+        ];
+        for &block_size in &[1, 3, DEFAULT_BLOCK_SIZE] {
+            let blocks = MemoryBackedStore::new();
+            let offsets = MemoryBackedStore::new();
+            let mut file_builder = PfcDictFileBuilder::with_block_size(
+                blocks.open_write(),
+                offsets.open_write(),
+                block_size,
+            );
+            block_on(async {
+                file_builder.add_all(strings.clone().into_iter()).await?;
+                file_builder.finalize().await
+            })
+            .unwrap();
+
+            let mut builder = PfcDictBufBuilder::with_block_size(block_size);
+            assert_eq!(
+                (1..=12).collect::<Vec<u64>>(),
+                builder.add_all(strings.clone())
+            );
+            let maps = builder.finalize();
+            assert_eq!(block_on(blocks.map()).unwrap(), maps.blocks_map);
+            assert_eq!(block_on(offsets.map()).unwrap(), maps.offsets_map);
+
+            let mut builder = PfcDictBufBuilder::with_block_size(block_size);
+            builder.add_all(strings.clone());
+            let dict = builder.build();
+            assert_eq!(strings, dict.strings().collect::<Vec<_>>());
+            assert_eq!(Some(4), dict.id("bcdef"));
+        }
+    }
+
+    #[test]
+    fn corrupt_dict_fails_to_parse() {
+        let strings: Vec<String> = (0..12).map(|i| format!("string{:02}", i)).collect();
+        let mut builder = PfcDictBufBuilder::with_block_size(3);
+        builder.add_all(strings.iter().map(|s| s.as_str()));
+        let maps = builder.finalize();
+        let blocks = maps.blocks_map;
+        let offsets = maps.offsets_map;
+
+        assert!(PfcDict::parse(blocks.clone(), offsets.clone()).is_ok());
+
+        let error = PfcDict::parse(blocks.slice(..4), offsets.clone())
+            .err()
+            .unwrap();
+        assert!(matches!(error, PfcError::NotEnoughData));
+
+        let error = PfcDict::parse(blocks.clone(), offsets.slice(1..))
+            .err()
+            .unwrap();
+        assert!(matches!(error, PfcError::LogArray(_)));
+
+        let mut builder = LogArrayBufBuilder::new(8);
+        builder.push_all(vec![10, 20]);
+        let error = PfcDict::parse(blocks.clone(), builder.finalize())
+            .err()
+            .unwrap();
+        assert!(matches!(error, PfcError::UnexpectedBlockCount(2, 3)));
+
+        let mut builder = LogArrayBufBuilder::new(8);
+        builder.push_all(vec![10, 200, 20]);
+        let error = PfcDict::parse(blocks.clone(), builder.finalize())
+            .err()
+            .unwrap();
+        assert!(matches!(error, PfcError::InvalidBlockOffset(2)));
+
+        let mut builder = LogArrayBufBuilder::new(8);
+        builder.push_all(vec![20, 10, 30]);
+        let error = PfcDict::parse(blocks.clone(), builder.finalize())
+            .err()
+            .unwrap();
+        assert!(matches!(error, PfcError::InvalidBlockOffset(2)));
+
+        // the second string of the first block shares 7 bytes with the head
+        assert_eq!(&blocks[8..11], &[0, vbyte::encode_vec(7)[0], b'1']);
+        // the strings in a block are only checked when they are accessed
+        let mut data = blocks.to_vec();
+        data[9] = vbyte::encode_vec(100)[0];
+        let dict = PfcDict::parse(Bytes::from(data), offsets.clone()).unwrap();
+        assert_eq!("string00", dict.try_get(0).unwrap().unwrap());
+        let error = dict.try_get(1).err().unwrap();
+        assert!(matches!(error, PfcError::InvalidBlockEncoding(0)));
+        let error = dict.try_entry(1).err().unwrap();
+        assert!(matches!(error, PfcError::InvalidBlockEncoding(0)));
+        let error = dict.try_id("string01").err().unwrap();
+        assert!(matches!(error, PfcError::InvalidBlockEncoding(0)));
+        assert_eq!(Some(3), dict.try_id("string03").unwrap());
+
+        let mut data = blocks.to_vec();
+        data[2] ^= 0x80;
+        let dict = PfcDict::parse(Bytes::from(data), offsets).unwrap();
+        let error = dict.try_get(0).err().unwrap();
+        assert!(matches!(error, PfcError::InvalidBlockEncoding(0)));
+    }
 }
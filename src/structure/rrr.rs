@@ -0,0 +1,716 @@
+//! RRR compressed bit vectors.
+//!
+//! The bits are split up into blocks of 63 bits. Every block is
+//! stored as its class, which is the amount of 1-bits in it, and an
+//! offset, which is the index of this particular block among all
+//! blocks of the same class. Offsets take only as many bits as are
+//! needed to distinguish the blocks of their class, so blocks that
+//! are almost all 0s or all 1s take up very little space. Every 32
+//! blocks, the rank up to that point and the position of the next
+//! offset are sampled, so that rank and select don't have to decode
+//! everything that comes before.
+//!
+//! A compressed bit vector is stored as a single buffer, consisting of
+//! the classes, the offsets, the sampled ranks and the sampled offset
+//! positions, followed by 6 words:
+//!
+//! 1. the amount of bits,
+//! 2. the amount of 1-bits,
+//! 3. the size in bytes of the classes,
+//! 4. the size in bytes of the offsets,
+//! 5. the size in bytes of the sampled ranks,
+//! 6. `RRR_MARKER`.
+//!
+//! A plain bit array ends with a word holding its length instead,
+//! which is never equal to the marker. This is how the two formats
+//! are told apart.
+
+use byteorder::{BigEndian, ByteOrder};
+use bytes::Bytes;
+use std::io;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use super::logarray::*;
+use super::util;
+
+/// Marks the end of an RRR compressed bit vector.
+pub const RRR_MARKER: [u8; 8] = *b"RRRBITS1";
+
+/// The amount of bits in a block.
+const BLOCK_BITS: u64 = 63;
+/// The amount of blocks between samples.
+const SAMPLE_BLOCKS: usize = 32;
+/// The width of the logarray storing the block classes.
+const CLASS_WIDTH: u8 = 6;
+
+const fn calculate_binomials() -> [[u64; 64]; 64] {
+    let mut result = [[0; 64]; 64];
+    let mut n = 0;
+    while n < 64 {
+        result[n][0] = 1;
+        let mut k = 1;
+        while k <= n {
+            result[n][k] = result[n - 1][k - 1] + result[n - 1][k];
+            k += 1;
+        }
+        n += 1;
+    }
+
+    result
+}
+
+/// `BINOMIALS[n][k]` is n choose k.
+static BINOMIALS: [[u64; 64]; 64] = calculate_binomials();
+
+/// Returns the amount of bits needed for the offset of a block of the given class.
+fn offset_width(class: u64) -> u8 {
+    let count = BINOMIALS[BLOCK_BITS as usize][class as usize];
+
+    64 - (count - 1).leading_zeros() as u8
+}
+
+/// Returns the offset of the given block among all blocks of its class.
+///
+/// The first bit of a block is its most significant bit.
+fn encode_block(block: u64) -> u64 {
+    let mut remaining = block.count_ones() as usize;
+    let mut offset = 0;
+    for position in (0..BLOCK_BITS as usize).rev() {
+        if block & (1 << position) != 0 {
+            offset += BINOMIALS[position][remaining];
+            remaining -= 1;
+        }
+    }
+
+    offset
+}
+
+/// Returns the block of the given class with the given offset.
+fn decode_block(class: u64, mut offset: u64) -> u64 {
+    let mut remaining = class as usize;
+    let mut block = 0;
+    for position in (0..BLOCK_BITS as usize).rev() {
+        if remaining == 0 {
+            break;
+        }
+
+        let count = BINOMIALS[position][remaining];
+        if offset >= count {
+            block |= 1 << position;
+            offset -= count;
+            remaining -= 1;
+        }
+    }
+
+    block
+}
+
+/// Returns the position within a block of the bit with the given 1-based rank among the bits equal to `bit`.
+fn select_in_block(block: u64, bit: bool, rank: u64) -> u64 {
+    let mut tally = rank;
+    for position in 0..BLOCK_BITS {
+        if (block & (1 << (BLOCK_BITS - 1 - position)) != 0) == bit {
+            tally -= 1;
+            if tally == 0 {
+                return position;
+            }
+        }
+    }
+
+    unreachable!("rank not within block");
+}
+
+fn invalid_data<S: Into<String>>(message: S) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+/// Returns true if the given buffer holds an RRR compressed bit vector rather than a bit array.
+pub fn is_rrr(buf: &[u8]) -> bool {
+    buf.len() >= 48 && buf[buf.len() - 8..] == RRR_MARKER
+}
+
+/// An RRR compressed bit vector, supporting rank and select queries.
+#[derive(Clone)]
+pub struct RrrBitVector {
+    len: u64,
+    ones: u64,
+    size: usize,
+    classes: LogArray,
+    offsets: Bytes,
+    sampled_ranks: LogArray,
+    sampled_positions: LogArray,
+}
+
+impl RrrBitVector {
+    /// Construct an `RrrBitVector` by parsing a `Bytes` buffer.
+    pub fn parse(buf: Bytes) -> io::Result<RrrBitVector> {
+        if !is_rrr(&buf) {
+            return Err(invalid_data("not an RRR compressed bit vector"));
+        }
+
+        let size = buf.len();
+        let trailer_start = size - 48;
+        let trailer = &buf[trailer_start..];
+        let len = BigEndian::read_u64(&trailer[0..8]);
+        let ones = BigEndian::read_u64(&trailer[8..16]);
+        let classes_size = BigEndian::read_u64(&trailer[16..24]) as usize;
+        let offsets_size = BigEndian::read_u64(&trailer[24..32]) as usize;
+        let ranks_size = BigEndian::read_u64(&trailer[32..40]) as usize;
+
+        let offsets_start = classes_size;
+        let ranks_start = offsets_start.checked_add(offsets_size);
+        let positions_start = ranks_start.and_then(|start| start.checked_add(ranks_size));
+        let (ranks_start, positions_start) = match (ranks_start, positions_start) {
+            (Some(ranks_start), Some(positions_start)) if positions_start <= trailer_start => {
+                (ranks_start, positions_start)
+            }
+            _ => return Err(invalid_data("RRR section sizes exceed the buffer")),
+        };
+        if offsets_size % 8 != 0 {
+            return Err(invalid_data(format!(
+                "expected offsets size ({}) to be a multiple of 8",
+                offsets_size
+            )));
+        }
+
+        let classes = LogArray::parse(buf.slice(..offsets_start))?;
+        let offsets = buf.slice(offsets_start..ranks_start);
+        let sampled_ranks = LogArray::parse(buf.slice(ranks_start..positions_start))?;
+        let sampled_positions = LogArray::parse(buf.slice(positions_start..trailer_start))?;
+
+        let block_count = len.div_ceil(BLOCK_BITS);
+        let sample_count = (block_count as usize).div_ceil(SAMPLE_BLOCKS);
+        if classes.len() as u64 != block_count || classes.width() != CLASS_WIDTH {
+            return Err(invalid_data(format!(
+                "expected {} classes of width {} for {} bits, but found {} of width {}",
+                block_count,
+                CLASS_WIDTH,
+                len,
+                classes.len(),
+                classes.width()
+            )));
+        }
+        if sampled_ranks.len() != sample_count || sampled_positions.len() != sample_count {
+            return Err(invalid_data(format!(
+                "expected {} samples, but found {} ranks and {} positions",
+                sample_count,
+                sampled_ranks.len(),
+                sampled_positions.len()
+            )));
+        }
+        if ones > len {
+            return Err(invalid_data(format!(
+                "expected amount of 1-bits ({}) <= length ({})",
+                ones, len
+            )));
+        }
+
+        Ok(RrrBitVector {
+            len,
+            ones,
+            size,
+            classes,
+            offsets,
+            sampled_ranks,
+            sampled_positions,
+        })
+    }
+
+    /// Returns the amount of bits.
+    pub fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    /// Returns `true` if there are no bits.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the amount of 1-bits.
+    pub fn count_ones(&self) -> u64 {
+        self.ones
+    }
+
+    /// Returns the size in bytes of the buffer backing this bit vector.
+    pub fn size_in_bytes(&self) -> usize {
+        self.size
+    }
+
+    fn read_offset(&self, position: u64, width: u8) -> u64 {
+        if width == 0 {
+            return 0;
+        }
+
+        let word_index = (position / 64) as usize;
+        let shift = position % 64;
+        let first = BigEndian::read_u64(&self.offsets[word_index * 8..word_index * 8 + 8]);
+        let mut bits = first << shift;
+        if shift + width as u64 > 64 {
+            let second =
+                BigEndian::read_u64(&self.offsets[word_index * 8 + 8..word_index * 8 + 16]);
+            bits |= second >> (64 - shift);
+        }
+
+        bits >> (64 - width)
+    }
+
+    /// Returns the amount of 1-bits before the given block, and the block itself.
+    fn block(&self, block_index: usize) -> (u64, u64) {
+        let sample = block_index / SAMPLE_BLOCKS;
+        let mut rank = self.sampled_ranks.entry(sample);
+        let mut position = self.sampled_positions.entry(sample);
+        for preceding in sample * SAMPLE_BLOCKS..block_index {
+            let class = self.classes.entry(preceding);
+            rank += class;
+            position += offset_width(class) as u64;
+        }
+
+        let class = self.classes.entry(block_index);
+        let offset = self.read_offset(position, offset_width(class));
+
+        (rank, decode_block(class, offset))
+    }
+
+    /// Returns the bit at the given index.
+    pub fn get(&self, index: u64) -> bool {
+        assert!(
+            index < self.len,
+            "expected index ({}) < length ({})",
+            index,
+            self.len
+        );
+        let (_, block) = self.block((index / BLOCK_BITS) as usize);
+
+        block & (1 << (BLOCK_BITS - 1 - index % BLOCK_BITS)) != 0
+    }
+
+    /// Returns the amount of 1-bits in the bit vector up to and including the given index.
+    pub fn rank1(&self, index: u64) -> u64 {
+        let (rank, block) = self.block((index / BLOCK_BITS) as usize);
+
+        rank + (block >> (BLOCK_BITS - 1 - index % BLOCK_BITS)).count_ones() as u64
+    }
+
+    /// Returns the amount of 0-bits in the bit vector up to and including the given index.
+    pub fn rank0(&self, index: u64) -> u64 {
+        1 + index - self.rank1(index)
+    }
+
+    /// Returns the index of the bit equal to `bit` with the given 1-based rank.
+    fn select(&self, bit: bool, rank: u64) -> Option<u64> {
+        let total = if bit { self.ones } else { self.len - self.ones };
+        if rank == 0 || rank > total {
+            return None;
+        }
+
+        // amount of bits equal to `bit` before the given sample or block
+        let count_before = |blocks: u64, ones: u64| {
+            if bit {
+                ones
+            } else {
+                blocks * BLOCK_BITS - ones
+            }
+        };
+
+        // find the last sample that starts before the requested bit
+        let mut start = 0;
+        let mut end = self.sampled_ranks.len() - 1;
+        while start < end {
+            let mid = (start + end).div_ceil(2);
+            let before = count_before((mid * SAMPLE_BLOCKS) as u64, self.sampled_ranks.entry(mid));
+            if before < rank {
+                start = mid;
+            } else {
+                end = mid - 1;
+            }
+        }
+
+        let mut block_index = start * SAMPLE_BLOCKS;
+        let mut ones = self.sampled_ranks.entry(start);
+        loop {
+            let class = self.classes.entry(block_index);
+            let before = count_before(block_index as u64, ones);
+            let in_block = if bit { class } else { BLOCK_BITS - class };
+            if before + in_block >= rank {
+                let (_, block) = self.block(block_index);
+                let position =
+                    block_index as u64 * BLOCK_BITS + select_in_block(block, bit, rank - before);
+
+                return Some(position);
+            }
+
+            ones += class;
+            block_index += 1;
+        }
+    }
+
+    /// Returns the index of the 1-bit with the given rank.
+    pub fn select1(&self, rank: u64) -> Option<u64> {
+        self.select(true, rank)
+    }
+
+    /// Returns the index of the 0-bit with the given rank.
+    pub fn select0(&self, rank: u64) -> Option<u64> {
+        self.select(false, rank)
+    }
+
+    pub fn iter(&self) -> RrrBitVectorIterator {
+        RrrBitVectorIterator {
+            vector: self.clone(),
+            index: 0,
+            position: 0,
+            block: 0,
+        }
+    }
+
+    /// Check that the samples agree with the classes, and that every offset is valid for its class.
+    pub fn check_consistency(&self) -> Result<(), String> {
+        let mut rank = 0;
+        let mut position = 0;
+        for block_index in 0..self.classes.len() {
+            if block_index % SAMPLE_BLOCKS == 0 {
+                let sample = block_index / SAMPLE_BLOCKS;
+                if self.sampled_ranks.entry(sample) != rank {
+                    return Err(format!(
+                        "sample {} has rank {}, expected {}",
+                        sample,
+                        self.sampled_ranks.entry(sample),
+                        rank
+                    ));
+                }
+                if self.sampled_positions.entry(sample) != position {
+                    return Err(format!(
+                        "sample {} has offset position {}, expected {}",
+                        sample,
+                        self.sampled_positions.entry(sample),
+                        position
+                    ));
+                }
+            }
+
+            let class = self.classes.entry(block_index);
+            if class > BLOCK_BITS {
+                return Err(format!("block {} has invalid class {}", block_index, class));
+            }
+            let width = offset_width(class);
+            if position + width as u64 > self.offsets.len() as u64 * 8 {
+                return Err(format!(
+                    "offset of block {} lies beyond the end of the offsets",
+                    block_index
+                ));
+            }
+            let offset = self.read_offset(position, width);
+            if offset >= BINOMIALS[BLOCK_BITS as usize][class as usize] {
+                return Err(format!(
+                    "block {} has offset {}, which is too large for class {}",
+                    block_index, offset, class
+                ));
+            }
+
+            rank += class;
+            position += width as u64;
+        }
+
+        if rank != self.ones {
+            return Err(format!(
+                "blocks contain {} 1-bits, but {} were expected",
+                rank, self.ones
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// An iterator over the bits of an RRR compressed bit vector.
+///
+/// This decodes every block just once.
+#[derive(Clone)]
+pub struct RrrBitVectorIterator {
+    vector: RrrBitVector,
+    index: u64,
+    position: u64,
+    block: u64,
+}
+
+impl Iterator for RrrBitVectorIterator {
+    type Item = bool;
+
+    fn next(&mut self) -> Option<bool> {
+        if self.index == self.vector.len {
+            return None;
+        }
+
+        let in_block = self.index % BLOCK_BITS;
+        if in_block == 0 {
+            let block_index = (self.index / BLOCK_BITS) as usize;
+            let class = self.vector.classes.entry(block_index);
+            let width = offset_width(class);
+            let offset = self.vector.read_offset(self.position, width);
+            self.block = decode_block(class, offset);
+            self.position += width as u64;
+        }
+
+        self.index += 1;
+
+        Some(self.block & (1 << (BLOCK_BITS - 1 - in_block)) != 0)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = (self.vector.len - self.index) as usize;
+
+        (remaining, Some(remaining))
+    }
+}
+
+/// Writes an RRR compressed bit vector to an AsyncWrite.
+///
+/// The bit vector is kept in memory until it is finalized, as the
+/// sections can only be written out once they're all complete.
+pub struct RrrFileBuilder<W: AsyncWrite + Unpin> {
+    /// Destination of the bit vector
+    file: W,
+    /// Amount of bits pushed so far
+    len: u64,
+    /// Amount of 1-bits pushed so far
+    ones: u64,
+    /// The block that is currently being filled
+    block: u64,
+    classes: Vec<u64>,
+    offsets: Vec<u64>,
+    offsets_len: u64,
+    sampled_ranks: Vec<u64>,
+    sampled_positions: Vec<u64>,
+}
+
+impl<W: AsyncWrite + Unpin> RrrFileBuilder<W> {
+    pub fn new(file: W) -> RrrFileBuilder<W> {
+        RrrFileBuilder {
+            file,
+            len: 0,
+            ones: 0,
+            block: 0,
+            classes: Vec::new(),
+            offsets: Vec::new(),
+            offsets_len: 0,
+            sampled_ranks: Vec::new(),
+            sampled_positions: Vec::new(),
+        }
+    }
+
+    /// Returns the amount of bits pushed so far.
+    pub fn count(&self) -> u64 {
+        self.len
+    }
+
+    pub fn push(&mut self, bit: bool) {
+        let in_block = self.len % BLOCK_BITS;
+        if bit {
+            self.block |= 1 << (BLOCK_BITS - 1 - in_block);
+        }
+
+        self.len += 1;
+        if in_block == BLOCK_BITS - 1 {
+            self.flush_block();
+        }
+    }
+
+    pub fn push_all<I: IntoIterator<Item = bool>>(&mut self, bits: I) {
+        for bit in bits {
+            self.push(bit);
+        }
+    }
+
+    fn flush_block(&mut self) {
+        if self.classes.len() % SAMPLE_BLOCKS == 0 {
+            self.sampled_ranks.push(self.ones);
+            self.sampled_positions.push(self.offsets_len);
+        }
+
+        let class = self.block.count_ones() as u64;
+        self.classes.push(class);
+        self.ones += class;
+
+        let width = offset_width(class) as u64;
+        if width != 0 {
+            let offset = encode_block(self.block);
+            let shift = self.offsets_len % 64;
+            if shift == 0 {
+                self.offsets.push(0);
+            }
+            let last = self.offsets.len() - 1;
+            if shift + width <= 64 {
+                self.offsets[last] |= offset << (64 - shift - width);
+            } else {
+                let overflow = shift + width - 64;
+                self.offsets[last] |= offset >> overflow;
+                self.offsets.push(offset << (64 - overflow));
+            }
+            self.offsets_len += width;
+        }
+
+        self.block = 0;
+    }
+
+    pub async fn finalize(mut self) -> io::Result<W> {
+        if self.len % BLOCK_BITS != 0 {
+            self.flush_block();
+        }
+
+        let mut classes = LogArrayFileBuilder::new(Vec::new(), CLASS_WIDTH);
+        classes
+            .push_all(util::stream_iter_ok(self.classes.iter().cloned()))
+            .await?;
+        let classes = classes.finalize().await?;
+
+        let mut offsets = vec![0; self.offsets.len() * 8];
+        for (index, &word) in self.offsets.iter().enumerate() {
+            BigEndian::write_u64(&mut offsets[index * 8..], word);
+        }
+
+        let mut ranks =
+            LogArrayFileBuilder::new(Vec::new(), util::calculate_width(self.ones).max(1));
+        ranks
+            .push_all(util::stream_iter_ok(self.sampled_ranks.iter().cloned()))
+            .await?;
+        let ranks = ranks.finalize().await?;
+
+        let mut positions =
+            LogArrayFileBuilder::new(Vec::new(), util::calculate_width(self.offsets_len).max(1));
+        positions
+            .push_all(util::stream_iter_ok(self.sampled_positions.iter().cloned()))
+            .await?;
+        let positions = positions.finalize().await?;
+
+        self.file.write_all(&classes).await?;
+        self.file.write_all(&offsets).await?;
+        self.file.write_all(&ranks).await?;
+        self.file.write_all(&positions).await?;
+
+        util::write_u64(&mut self.file, self.len).await?;
+        util::write_u64(&mut self.file, self.ones).await?;
+        util::write_u64(&mut self.file, classes.len() as u64).await?;
+        util::write_u64(&mut self.file, offsets.len() as u64).await?;
+        util::write_u64(&mut self.file, ranks.len() as u64).await?;
+        self.file.write_all(&RRR_MARKER).await?;
+
+        self.file.flush().await?;
+
+        Ok(self.file)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+
+    fn build_rrr(bits: &[bool]) -> RrrBitVector {
+        let mut builder = RrrFileBuilder::new(Vec::new());
+        builder.push_all(bits.iter().cloned());
+        let buf = block_on(builder.finalize()).unwrap();
+
+        RrrBitVector::parse(Bytes::from(buf)).unwrap()
+    }
+
+    fn example_bits(len: usize) -> Vec<bool> {
+        // a mix of sparse, dense and random-looking stretches
+        (0..len)
+            .map(|i| match (i / 500) % 3 {
+                0 => i % 97 == 0,
+                1 => i % 89 != 0,
+                _ => (i * 7919) % 13 < 5,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn encode_and_decode_blocks() {
+        let blocks: [u64; 6] = [
+            0,
+            1,
+            1 << 62,
+            (1 << 63) - 1,
+            0x2aaa_aaaa_aaaa_aaaa,
+            0x1234_5678_9abc_def0 & ((1 << 63) - 1),
+        ];
+        for &block in blocks.iter() {
+            let class = block.count_ones() as u64;
+            let offset = encode_block(block);
+            assert!(offset < BINOMIALS[63][class as usize]);
+            assert_eq!(block, decode_block(class, offset));
+        }
+    }
+
+    #[test]
+    fn rrr_get_and_iter() {
+        let bits = example_bits(5000);
+        let rrr = build_rrr(&bits);
+
+        assert_eq!(bits.len(), rrr.len());
+        for (index, &bit) in bits.iter().enumerate() {
+            assert_eq!(bit, rrr.get(index as u64));
+        }
+        assert_eq!(bits, rrr.iter().collect::<Vec<_>>());
+        assert_eq!(Ok(()), rrr.check_consistency());
+    }
+
+    #[test]
+    fn rrr_rank_and_select() {
+        let bits = example_bits(5000);
+        let rrr = build_rrr(&bits);
+
+        let mut ones = 0;
+        let mut zeros = 0;
+        for (index, &bit) in bits.iter().enumerate() {
+            if bit {
+                ones += 1;
+                assert_eq!(Some(index as u64), rrr.select1(ones));
+            } else {
+                zeros += 1;
+                assert_eq!(Some(index as u64), rrr.select0(zeros));
+            }
+            assert_eq!(ones, rrr.rank1(index as u64));
+            assert_eq!(zeros, rrr.rank0(index as u64));
+        }
+
+        assert_eq!(ones, rrr.count_ones());
+        assert_eq!(None, rrr.select1(0));
+        assert_eq!(None, rrr.select1(ones + 1));
+        assert_eq!(None, rrr.select0(zeros + 1));
+    }
+
+    #[test]
+    fn sparse_rrr_is_small() {
+        let bits: Vec<bool> = (0..100_000).map(|i| i % 1000 == 0).collect();
+        let rrr = build_rrr(&bits);
+
+        // a plain bit array would take 12.5KB
+        assert!(rrr.size_in_bytes() < 2000);
+        assert_eq!(100, rrr.count_ones());
+        assert_eq!(Some(99000), rrr.select1(100));
+    }
+
+    #[test]
+    fn uniform_rrr() {
+        for &bit in [false, true].iter() {
+            let bits = vec![bit; 3000];
+            let rrr = build_rrr(&bits);
+
+            assert_eq!(bits, rrr.iter().collect::<Vec<_>>());
+            assert_eq!(if bit { 3000 } else { 0 }, rrr.rank1(2999));
+            assert_eq!(Some(1234), rrr.select(bit, 1235));
+            assert_eq!(Ok(()), rrr.check_consistency());
+        }
+    }
+
+    #[test]
+    fn empty_rrr() {
+        let rrr = build_rrr(&[]);
+
+        assert!(rrr.is_empty());
+        assert_eq!(0, rrr.iter().count());
+        assert_eq!(None, rrr.select1(1));
+        assert_eq!(None, rrr.select0(1));
+    }
+}
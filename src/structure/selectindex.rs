@@ -0,0 +1,307 @@
+//! Sampled select index for bit indexes.
+//!
+//! Select on a plain `BitIndex` binary searches over all superblocks
+//! before it can look at the blocks of the right one. A select index
+//! stores the position of every k-th 1-bit and every k-th 0-bit, so
+//! that this search only has to consider the superblocks between two
+//! samples.
+//!
+//! A select index is stored as a single buffer, consisting of the
+//! sampled 1-bit positions and the sampled 0-bit positions, followed
+//! by 3 words:
+//!
+//! 1. the amount of bits in the indexed bit array,
+//! 2. the sample rate,
+//! 3. the size in bytes of the sampled 1-bit positions.
+//!
+//! Select indexes are stored in their own optional file next to the
+//! bit index files. Readers that don't know about them never open
+//! that file.
+
+use byteorder::{BigEndian, ByteOrder};
+use bytes::Bytes;
+use std::io;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use super::bitindex::BitIndex;
+use super::logarray::*;
+use super::util;
+
+fn invalid_data<S: Into<String>>(message: S) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+/// The positions of every k-th 1-bit and 0-bit of a bit array.
+#[derive(Clone)]
+pub struct SelectIndex {
+    len: u64,
+    sample_rate: u64,
+    size: usize,
+    ones: LogArray,
+    zeros: LogArray,
+}
+
+impl SelectIndex {
+    /// Construct a `SelectIndex` by parsing a `Bytes` buffer.
+    pub fn parse(buf: Bytes) -> io::Result<SelectIndex> {
+        if buf.len() < 24 {
+            return Err(invalid_data(format!(
+                "expected select index size ({}) >= 24",
+                buf.len()
+            )));
+        }
+
+        let trailer_start = buf.len() - 24;
+        let trailer = &buf[trailer_start..];
+        let len = BigEndian::read_u64(&trailer[0..8]);
+        let sample_rate = BigEndian::read_u64(&trailer[8..16]);
+        let ones_size = BigEndian::read_u64(&trailer[16..24]) as usize;
+        if sample_rate == 0 {
+            return Err(invalid_data("expected a sample rate > 0"));
+        }
+        if ones_size > trailer_start {
+            return Err(invalid_data(format!(
+                "expected sampled 1-bits size ({}) <= {}",
+                ones_size, trailer_start
+            )));
+        }
+
+        let ones = LogArray::parse(buf.slice(..ones_size))?;
+        let zeros = LogArray::parse(buf.slice(ones_size..trailer_start))?;
+        if (ones.len() + zeros.len()) as u64 > len / sample_rate {
+            return Err(invalid_data(format!(
+                "expected at most {} samples for {} bits, but found {}",
+                len / sample_rate,
+                len,
+                ones.len() + zeros.len()
+            )));
+        }
+
+        Ok(SelectIndex {
+            len,
+            sample_rate,
+            size: buf.len(),
+            ones,
+            zeros,
+        })
+    }
+
+    /// Returns the amount of bits in the indexed bit array.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns true if the indexed bit array is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn sample_rate(&self) -> u64 {
+        self.sample_rate
+    }
+
+    /// Returns the size in bytes of the buffer backing this select index.
+    pub fn size_in_bytes(&self) -> usize {
+        self.size
+    }
+
+    /// Returns the bounds on the position of the 1-bit with the given rank.
+    ///
+    /// The 1-bit is at or after the first position, and at or before
+    /// the second. If the second is `None`, it is bounded by the end of
+    /// the bit array.
+    pub fn ones_bounds(&self, rank: u64) -> (u64, Option<u64>) {
+        bounds(&self.ones, self.sample_rate, rank)
+    }
+
+    /// Returns the bounds on the position of the 0-bit with the given rank.
+    ///
+    /// See `ones_bounds`.
+    pub fn zeros_bounds(&self, rank: u64) -> (u64, Option<u64>) {
+        bounds(&self.zeros, self.sample_rate, rank)
+    }
+
+    /// Returns the sampled 1-bit positions.
+    pub fn ones(&self) -> &LogArray {
+        &self.ones
+    }
+
+    /// Returns the sampled 0-bit positions.
+    pub fn zeros(&self) -> &LogArray {
+        &self.zeros
+    }
+}
+
+fn bounds(samples: &LogArray, sample_rate: u64, rank: u64) -> (u64, Option<u64>) {
+    if rank == 0 {
+        return (0, None);
+    }
+
+    // sample i holds the position of the bit with rank (i+1)*sample_rate
+    let next = ((rank - 1) / sample_rate) as usize;
+    let start = if next == 0 {
+        0
+    } else {
+        samples.entry(next.min(samples.len()) - 1)
+    };
+    let end = if next < samples.len() {
+        Some(samples.entry(next))
+    } else {
+        None
+    };
+
+    (start, end)
+}
+
+/// Build a select index for the given bit index, sampling every `sample_rate`-th 1-bit and 0-bit.
+pub async fn build_select_index<W: AsyncWrite + Unpin>(
+    index: &BitIndex,
+    sample_rate: u64,
+    mut destination: W,
+) -> io::Result<()> {
+    assert!(sample_rate > 0, "select index sample rate should be > 0");
+    let len = index.len() as u64;
+    let width = util::calculate_width(len).max(1);
+
+    let mut ones = Vec::new();
+    let mut zeros = Vec::new();
+    let mut ones_count: u64 = 0;
+    let mut zeros_count: u64 = 0;
+    for (position, bit) in index.iter().enumerate() {
+        if bit {
+            ones_count += 1;
+            if ones_count % sample_rate == 0 {
+                ones.push(position as u64);
+            }
+        } else {
+            zeros_count += 1;
+            if zeros_count % sample_rate == 0 {
+                zeros.push(position as u64);
+            }
+        }
+    }
+
+    let mut ones_builder = LogArrayFileBuilder::new(Vec::new(), width);
+    ones_builder.push_all(util::stream_iter_ok(ones)).await?;
+    let ones = ones_builder.finalize().await?;
+
+    let mut zeros_builder = LogArrayFileBuilder::new(Vec::new(), width);
+    zeros_builder.push_all(util::stream_iter_ok(zeros)).await?;
+    let zeros = zeros_builder.finalize().await?;
+
+    destination.write_all(&ones).await?;
+    destination.write_all(&zeros).await?;
+    util::write_u64(&mut destination, len).await?;
+    util::write_u64(&mut destination, sample_rate).await?;
+    util::write_u64(&mut destination, ones.len() as u64).await?;
+    destination.flush().await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::memory::*;
+    use crate::storage::*;
+    use crate::structure::bitarray::*;
+    use crate::structure::bitindex::*;
+    use futures::executor::block_on;
+
+    fn bitindex_from_bits(bits: &[bool]) -> BitIndex {
+        let bits_file = MemoryBackedStore::new();
+        let blocks_file = MemoryBackedStore::new();
+        let sblocks_file = MemoryBackedStore::new();
+        let mut builder = BitArrayFileBuilder::new(bits_file.open_write());
+        block_on(async {
+            builder
+                .push_all(util::stream_iter_ok(bits.to_vec()))
+                .await?;
+            builder.finalize().await?;
+            build_bitindex(
+                bits_file.open_read(),
+                blocks_file.open_write(),
+                sblocks_file.open_write(),
+            )
+            .await
+        })
+        .unwrap();
+
+        BitIndex::from_maps(
+            block_on(bits_file.map()).unwrap(),
+            block_on(blocks_file.map()).unwrap(),
+            block_on(sblocks_file.map()).unwrap(),
+        )
+        .unwrap()
+    }
+
+    fn build_select(index: &BitIndex, sample_rate: u64) -> SelectIndex {
+        let mut buf = Vec::new();
+        block_on(build_select_index(index, sample_rate, &mut buf)).unwrap();
+
+        SelectIndex::parse(Bytes::from(buf)).unwrap()
+    }
+
+    #[test]
+    fn samples_every_kth_bit() {
+        let bits: Vec<bool> = (0..1000).map(|i| i % 3 == 0).collect();
+        let index = bitindex_from_bits(&bits);
+        let select = build_select(&index, 10);
+
+        assert_eq!(1000, select.len());
+        assert_eq!(10, select.sample_rate());
+        assert_eq!(33, select.ones().len());
+        assert_eq!(66, select.zeros().len());
+        assert_eq!(27, select.ones().entry(0));
+        assert_eq!(14, select.zeros().entry(0));
+
+        assert_eq!((0, Some(27)), select.ones_bounds(10));
+        assert_eq!((27, Some(57)), select.ones_bounds(11));
+        assert_eq!((987, None), select.ones_bounds(334));
+        assert_eq!((987, None), select.ones_bounds(1000));
+    }
+
+    #[test]
+    fn select_with_index_matches_select_without() {
+        let bits: Vec<bool> = (0..100_000)
+            .map(|i| ((i / 7000) % 2 == 0 && i % 5 == 0) || i % 61 == 0)
+            .collect();
+        let index = bitindex_from_bits(&bits);
+        let select = build_select(&index, 64);
+        let sampled = index.clone().with_select_index(select);
+
+        assert!(sampled.select_index().is_some());
+        let ones = index.rank1(99_999);
+        let zeros = index.rank0(99_999);
+        for rank in 1..=ones + 2 {
+            assert_eq!(index.select1(rank), sampled.select1(rank));
+        }
+        for rank in 1..=zeros + 2 {
+            assert_eq!(index.select0(rank), sampled.select0(rank));
+        }
+    }
+
+    #[test]
+    fn empty_select_index() {
+        let index = bitindex_from_bits(&[]);
+        let select = build_select(&index, 16);
+
+        assert!(select.is_empty());
+        assert_eq!((0, None), select.ones_bounds(1));
+    }
+
+    #[test]
+    fn corrupt_select_index_fails_to_parse() {
+        let bits: Vec<bool> = (0..1000).map(|i| i % 2 == 0).collect();
+        let index = bitindex_from_bits(&bits);
+        let mut buf = Vec::new();
+        block_on(build_select_index(&index, 4, &mut buf)).unwrap();
+
+        assert!(SelectIndex::parse(Bytes::from(buf[..16].to_vec())).is_err());
+
+        // a sample rate of 0
+        let rate_start = buf.len() - 16;
+        buf[rate_start..rate_start + 8].copy_from_slice(&[0; 8]);
+        assert!(SelectIndex::parse(Bytes::from(buf)).is_err());
+    }
+}
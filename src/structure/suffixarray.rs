@@ -0,0 +1,429 @@
+//! A suffix array over a list of strings, for substring search.
+//!
+//! The strings are stored in a single text, each string preceded by a
+//! nul byte. The suffix array lists every position in that text,
+//! ordered by the suffix starting at that position. All occurrences
+//! of a pattern are then adjacent in the suffix array, and can be
+//! found with a binary search. Since every string is preceded by a
+//! nul byte, searching for a nul byte followed by a pattern finds
+//! all strings starting with that pattern.
+//!
+//! To go from a position in the text back to a string, the start
+//! position of every string is stored as well.
+
+use bytes::Bytes;
+use futures::io;
+use regex::Regex;
+use std::cmp::Ordering;
+use std::ops::Range;
+use tokio::io::AsyncWriteExt;
+
+use super::logarray::*;
+use super::util;
+use crate::storage::*;
+
+/// A suffix array over a list of strings.
+#[derive(Clone)]
+pub struct SuffixArray {
+    text: Bytes,
+    suffixes: LogArray,
+    starts: MonotonicLogArray,
+}
+
+impl SuffixArray {
+    pub fn parse(text: Bytes, suffixes: Bytes, starts: Bytes) -> io::Result<SuffixArray> {
+        let suffixes = LogArray::parse(suffixes)?;
+        let starts = MonotonicLogArray::from_logarray(LogArray::parse(starts)?);
+
+        if suffixes.len() != text.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "suffix array length does not match text length",
+            ));
+        }
+
+        Ok(SuffixArray {
+            text,
+            suffixes,
+            starts,
+        })
+    }
+
+    /// Returns the amount of strings in this suffix array.
+    pub fn len(&self) -> usize {
+        self.starts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the size in bytes of the buffers backing this suffix array.
+    pub fn size_in_bytes(&self) -> usize {
+        self.text.len() + self.suffixes.size_in_bytes() + self.starts.size_in_bytes()
+    }
+
+    /// Returns the string with the given index.
+    pub fn get(&self, index: usize) -> Option<&[u8]> {
+        if index >= self.len() {
+            return None;
+        }
+
+        let start = self.starts.entry(index) as usize + 1;
+        let end = if index + 1 == self.len() {
+            self.text.len()
+        } else {
+            self.starts.entry(index + 1) as usize
+        };
+
+        Some(&self.text[start..end])
+    }
+
+    /// Returns the index of the string that contains the given text position.
+    fn string_at(&self, position: u64) -> u64 {
+        // find the last string start that is not past the position
+        let mut min = 0;
+        let mut max = self.len();
+        while min < max {
+            let mid = (min + max) / 2;
+            if self.starts.entry(mid) <= position {
+                min = mid + 1;
+            } else {
+                max = mid;
+            }
+        }
+
+        (min - 1) as u64
+    }
+
+    fn compare_suffix(&self, index: usize, pattern: &[u8]) -> Ordering {
+        let position = self.suffixes.entry(index) as usize;
+        let end = std::cmp::min(self.text.len(), position + pattern.len());
+
+        self.text[position..end].cmp(pattern)
+    }
+
+    /// Returns the range in the suffix array of all suffixes starting with the given pattern.
+    fn suffix_range(&self, pattern: &[u8]) -> Range<usize> {
+        let mut min = 0;
+        let mut max = self.suffixes.len();
+        while min < max {
+            let mid = (min + max) / 2;
+            if self.compare_suffix(mid, pattern) == Ordering::Less {
+                min = mid + 1;
+            } else {
+                max = mid;
+            }
+        }
+        let start = min;
+
+        max = self.suffixes.len();
+        while min < max {
+            let mid = (min + max) / 2;
+            if self.compare_suffix(mid, pattern) == Ordering::Equal {
+                min = mid + 1;
+            } else {
+                max = mid;
+            }
+        }
+
+        start..min
+    }
+
+    fn strings_at_suffixes(&self, range: Range<usize>) -> Vec<u64> {
+        let mut result: Vec<u64> = range
+            .map(|i| self.string_at(self.suffixes.entry(i)))
+            .collect();
+        result.sort_unstable();
+        result.dedup();
+
+        result
+    }
+
+    /// Returns the indexes of all strings containing the given pattern, in order.
+    pub fn substring(&self, pattern: &str) -> Vec<u64> {
+        if pattern.is_empty() {
+            return (0..self.len() as u64).collect();
+        }
+
+        self.strings_at_suffixes(self.suffix_range(pattern.as_bytes()))
+    }
+
+    /// Returns the indexes of all strings starting with the given prefix, in order.
+    pub fn prefix(&self, prefix: &str) -> Vec<u64> {
+        let mut pattern = Vec::with_capacity(prefix.len() + 1);
+        pattern.push(0);
+        pattern.extend_from_slice(prefix.as_bytes());
+
+        self.strings_at_suffixes(self.suffix_range(&pattern))
+    }
+
+    /// Returns the indexes of all strings matching the given pattern, in order.
+    ///
+    /// For regular expressions with a literal prefix, only strings
+    /// starting with that prefix are checked. Otherwise, every string
+    /// is checked.
+    pub fn search(&self, pattern: &SearchPattern) -> Vec<u64> {
+        match pattern {
+            SearchPattern::Substring(substring) => self.substring(substring),
+            SearchPattern::Regex { regex, prefix } => {
+                let candidates: Box<dyn Iterator<Item = u64>> = if prefix.is_empty() {
+                    Box::new(0..self.len() as u64)
+                } else {
+                    Box::new(self.prefix(prefix).into_iter())
+                };
+
+                candidates
+                    .filter(|&index| {
+                        let s = self.get(index as usize).unwrap();
+                        std::str::from_utf8(s)
+                            .map(|s| regex.is_match(s))
+                            .unwrap_or(false)
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+/// A pattern to search for in a full-text index.
+#[derive(Clone, Debug)]
+pub enum SearchPattern {
+    /// Match all strings containing this string.
+    Substring(String),
+    /// Match all strings matching this regular expression, anchored at the start of the string.
+    Regex { regex: Regex, prefix: String },
+}
+
+impl SearchPattern {
+    /// A pattern matching all strings that contain the given string.
+    pub fn substring<S: Into<String>>(substring: S) -> SearchPattern {
+        SearchPattern::Substring(substring.into())
+    }
+
+    /// A pattern matching all strings that match the given regular expression.
+    ///
+    /// The expression is anchored at the start of the string, as if it started with `^`.
+    pub fn regex(pattern: &str) -> Result<SearchPattern, regex::Error> {
+        Ok(SearchPattern::Regex {
+            regex: Regex::new(&format!("^(?:{})", pattern))?,
+            prefix: regex_literal_prefix(pattern),
+        })
+    }
+
+    /// Returns true if the given string matches this pattern.
+    pub fn matches(&self, s: &str) -> bool {
+        match self {
+            SearchPattern::Substring(substring) => s.contains(substring.as_str()),
+            SearchPattern::Regex { regex, .. } => regex.is_match(s),
+        }
+    }
+}
+
+/// Returns a literal string that every match of the given regular expression starts with.
+///
+/// This is conservative. Anything that is not a plain literal ends
+/// the prefix, and patterns with alternations have no prefix at all.
+fn regex_literal_prefix(pattern: &str) -> String {
+    if pattern.contains('|') {
+        return String::new();
+    }
+
+    let mut result = String::new();
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        let literal = match c {
+            '\\' => match chars.next() {
+                Some(escaped) if escaped.is_ascii_punctuation() => escaped,
+                _ => break,
+            },
+            '.' | '+' | '*' | '?' | '(' | ')' | '[' | ']' | '{' | '}' | '^' | '$' => break,
+            c => c,
+        };
+
+        let last_len = result.len();
+        result.push(literal);
+
+        if let Some('*') | Some('?') | Some('{') = chars.peek() {
+            // the last literal is optional or repeated, so it is not part of the prefix
+            result.truncate(last_len);
+            break;
+        }
+    }
+
+    result
+}
+
+/// Calculate the suffix array of the given text by prefix doubling.
+fn calculate_suffixes(text: &[u8]) -> Vec<u64> {
+    let n = text.len();
+    let mut suffixes: Vec<usize> = (0..n).collect();
+    let mut rank: Vec<usize> = text.iter().map(|&b| b as usize).collect();
+    let mut new_rank = vec![0; n];
+    let mut k = 1;
+    while k < n {
+        let key = |i: usize| (rank[i], if i + k < n { rank[i + k] + 1 } else { 0 });
+        suffixes.sort_unstable_by_key(|&i| key(i));
+
+        new_rank[suffixes[0]] = 0;
+        for j in 1..n {
+            new_rank[suffixes[j]] =
+                new_rank[suffixes[j - 1]] + (key(suffixes[j - 1]) != key(suffixes[j])) as usize;
+        }
+        std::mem::swap(&mut rank, &mut new_rank);
+
+        if rank[suffixes[n - 1]] == n - 1 {
+            // all suffixes are distinct
+            break;
+        }
+        k *= 2;
+    }
+
+    suffixes.into_iter().map(|i| i as u64).collect()
+}
+
+/// Build a suffix array over the given strings, writing it to the given files.
+pub async fn build_suffix_array<
+    'a,
+    F: 'static + FileLoad + FileStore,
+    I: Iterator<Item = &'a str>,
+>(
+    strings: I,
+    text_file: F,
+    suffixes_file: F,
+    starts_file: F,
+) -> io::Result<()> {
+    let mut text = Vec::new();
+    let mut starts = Vec::new();
+    for s in strings {
+        starts.push(text.len() as u64);
+        text.push(0);
+        text.extend_from_slice(s.as_bytes());
+    }
+    let suffixes = calculate_suffixes(&text);
+
+    let width = std::cmp::max(1, util::calculate_width(text.len() as u64));
+
+    let mut text_writer = text_file.open_write();
+    text_writer.write_all(&text).await?;
+    text_writer.flush().await?;
+
+    let mut suffixes_builder = LogArrayFileBuilder::new(suffixes_file.open_write(), width);
+    for suffix in suffixes {
+        suffixes_builder.push(suffix).await?;
+    }
+    suffixes_builder.finalize().await?;
+
+    let mut starts_builder = LogArrayFileBuilder::new(starts_file.open_write(), width);
+    for start in starts {
+        starts_builder.push(start).await?;
+    }
+    starts_builder.finalize().await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::memory::*;
+    use futures::executor::block_on;
+
+    fn build(strings: &[&str]) -> SuffixArray {
+        let text_file = MemoryBackedStore::new();
+        let suffixes_file = MemoryBackedStore::new();
+        let starts_file = MemoryBackedStore::new();
+        block_on(build_suffix_array(
+            strings.iter().copied(),
+            text_file.clone(),
+            suffixes_file.clone(),
+            starts_file.clone(),
+        ))
+        .unwrap();
+
+        SuffixArray::parse(
+            block_on(text_file.map()).unwrap(),
+            block_on(suffixes_file.map()).unwrap(),
+            block_on(starts_file.map()).unwrap(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn suffixes_are_sorted() {
+        let text = b"\0banana\0bandana\0ananas";
+        let suffixes = calculate_suffixes(text);
+        assert_eq!(text.len(), suffixes.len());
+        for pair in suffixes.windows(2) {
+            assert!(text[pair[0] as usize..] < text[pair[1] as usize..]);
+        }
+    }
+
+    #[test]
+    fn substring_search() {
+        let strings = vec![
+            "http://example.com/Person/alice",
+            "http://example.com/Person/bob",
+            "http://example.com/Pet/rex",
+            "rex the dog",
+            "the quick brown fox",
+        ];
+        let index = build(&strings);
+
+        assert_eq!(5, index.len());
+        assert_eq!(Some(&b"rex the dog"[..]), index.get(3));
+        assert_eq!(None, index.get(5));
+
+        assert_eq!(vec![2, 3], index.substring("rex"));
+        assert_eq!(vec![0, 1], index.substring("Person"));
+        assert_eq!(vec![3, 4], index.substring("the"));
+        assert_eq!(vec![0, 1, 2], index.substring("example.com/P"));
+        assert_eq!(Vec::<u64>::new(), index.substring("cat"));
+        assert_eq!(vec![0, 1, 2, 3, 4], index.substring(""));
+
+        assert_eq!(vec![3], index.prefix("rex"));
+        assert_eq!(vec![0, 1, 2], index.prefix("http://"));
+        assert_eq!(Vec::<u64>::new(), index.prefix("dog"));
+    }
+
+    #[test]
+    fn regex_search() {
+        let strings = vec!["abc", "abd", "abcd", "xabc", "zzz"];
+        let index = build(&strings);
+
+        let search = |pattern| index.search(&SearchPattern::regex(pattern).unwrap());
+        assert_eq!(vec![0, 2], search("abc"));
+        assert_eq!(vec![0, 1], search("ab[cd]$"));
+        assert_eq!(vec![3], search(".abc"));
+        assert_eq!(vec![0, 1, 2, 4], search("ab|z+"));
+        assert!(SearchPattern::regex("(").is_err());
+
+        let pattern = SearchPattern::substring("bc");
+        assert_eq!(vec![0, 2, 3], index.search(&pattern));
+        assert!(pattern.matches("abcd"));
+        assert!(!pattern.matches("abd"));
+    }
+
+    #[test]
+    fn literal_prefixes() {
+        assert_eq!("abc", regex_literal_prefix("abc"));
+        assert_eq!("ab", regex_literal_prefix("abc*"));
+        assert_eq!("ab", regex_literal_prefix("abc?d"));
+        assert_eq!("abc", regex_literal_prefix("abc+"));
+        assert_eq!("http://ex.com/", regex_literal_prefix(r"http://ex\.com/.*"));
+        assert_eq!("", regex_literal_prefix("a|b"));
+        assert_eq!("", regex_literal_prefix("(?i)abc"));
+        assert_eq!("a", regex_literal_prefix(r"a\d"));
+    }
+
+    #[test]
+    fn empty_suffix_array() {
+        let index = build(&[]);
+        assert!(index.is_empty());
+        assert_eq!(Vec::<u64>::new(), index.substring("a"));
+        assert_eq!(
+            Vec::<u64>::new(),
+            index.search(&SearchPattern::regex("a").unwrap())
+        );
+    }
+}
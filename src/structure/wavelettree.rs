@@ -7,7 +7,12 @@ use super::util;
 use crate::storage::*;
 use tokio::prelude::*;
 
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
 use std::convert::TryInto;
+use std::error;
+use std::fmt;
+use std::ops::Range;
 
 /// A wavelet tree, encoding a u64 array for fast lookup of number positions.
 ///
@@ -83,7 +88,113 @@ impl WaveletLookup {
     }
 }
 
+/// A range of positions within a single node of a wavelet tree.
+///
+/// Every layer of the wavelet tree is split up into nodes, each of
+/// which holds the entries within a part of the alphabet. Positions
+/// are relative to the start of the layer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct WaveletRange {
+    layer: u8,
+    node_start: u64,
+    node_end: u64,
+    start: u64,
+    end: u64,
+    alphabet_start: u64,
+    alphabet_end: u64,
+}
+
+impl WaveletRange {
+    fn len(&self) -> usize {
+        (self.end - self.start) as usize
+    }
+
+    fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    /// Split this range into the corresponding ranges of the two child nodes.
+    fn children(&self, tree: &WaveletTree) -> (WaveletRange, WaveletRange) {
+        let offset = self.layer as u64 * tree.len() as u64;
+        let node_start = offset + self.node_start;
+        let zeros_before_start = tree.bits.rank0_from_range(node_start, offset + self.start);
+        let zeros_before_end = tree.bits.rank0_from_range(node_start, offset + self.end);
+        let node_zeros = tree
+            .bits
+            .rank0_from_range(node_start, offset + self.node_end);
+        let alphabet_half = (self.alphabet_start + self.alphabet_end) / 2;
+
+        let left = WaveletRange {
+            layer: self.layer + 1,
+            node_start: self.node_start,
+            node_end: self.node_start + node_zeros,
+            start: self.node_start + zeros_before_start,
+            end: self.node_start + zeros_before_end,
+            alphabet_start: self.alphabet_start,
+            alphabet_end: alphabet_half,
+        };
+        let right = WaveletRange {
+            layer: self.layer + 1,
+            node_start: left.node_end,
+            node_end: self.node_end,
+            start: left.node_end + (self.start - self.node_start - zeros_before_start),
+            end: left.node_end + (self.end - self.node_start - zeros_before_end),
+            alphabet_start: alphabet_half,
+            alphabet_end: self.alphabet_end,
+        };
+
+        (left, right)
+    }
+}
+
+/// An error that occurred while parsing a wavelet tree.
+#[derive(Debug)]
+pub enum WaveletTreeError {
+    BitIndex(BitIndexError),
+    /// The amount of bits (`.0`) is not a multiple of the amount of layers (`.1`).
+    UnexpectedLength(usize, u8),
+}
+
+impl fmt::Display for WaveletTreeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use WaveletTreeError::*;
+        match self {
+            BitIndex(err) => write!(f, "invalid wavelet tree bits: {}", err),
+            UnexpectedLength(len, num_layers) => write!(
+                f,
+                "expected wavelet tree length ({}) to be a multiple of {} layers",
+                len, num_layers
+            ),
+        }
+    }
+}
+
+impl error::Error for WaveletTreeError {}
+
+impl From<BitIndexError> for WaveletTreeError {
+    fn from(err: BitIndexError) -> WaveletTreeError {
+        WaveletTreeError::BitIndex(err)
+    }
+}
+
+impl From<WaveletTreeError> for io::Error {
+    fn from(err: WaveletTreeError) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, err)
+    }
+}
+
 impl WaveletTree {
+    /// Construct a wavelet tree by parsing the buffers of its bit index.
+    pub fn from_maps(maps: BitIndexMaps, num_layers: u8) -> Result<WaveletTree, WaveletTreeError> {
+        let bits = BitIndex::from_maps(maps.bits_map, maps.blocks_map, maps.sblocks_map)?
+            .with_select_map(maps.select_map)?;
+        if num_layers != 0 && bits.len() % num_layers as usize != 0 {
+            return Err(WaveletTreeError::UnexpectedLength(bits.len(), num_layers));
+        }
+
+        Ok(WaveletTree { bits, num_layers })
+    }
+
     /// Construct a wavelet tree from a bitindex and a layer count.
     pub fn from_parts(bits: BitIndex, num_layers: u8) -> WaveletTree {
         if num_layers != 0 && bits.len() % num_layers as usize != 0 {
@@ -93,6 +204,13 @@ impl WaveletTree {
         WaveletTree { bits, num_layers }
     }
 
+    /// Build a wavelet tree in memory from the given values, which all have to fit in `width` bits.
+    pub fn from_values<I: IntoIterator<Item = u64>>(width: u8, source: I) -> WaveletTree {
+        let maps = build_wavelet_tree_buf(width, source);
+
+        WaveletTree::from_maps(maps, width).expect("wavelet tree built in memory should be valid")
+    }
+
     /// Returns the length of the encoded array.
     pub fn len(&self) -> usize {
         if self.num_layers == 0 {
@@ -107,6 +225,11 @@ impl WaveletTree {
         self.num_layers as usize
     }
 
+    /// Returns the size in bytes of the buffers backing this wavelet tree.
+    pub fn size_in_bytes(&self) -> usize {
+        self.bits.size_in_bytes()
+    }
+
     /// Decode the wavelet tree to the original u64 sequence. This returns an iterator.
     pub fn decode(&self) -> impl Iterator<Item = u64> {
         let owned = self.clone();
@@ -196,6 +319,134 @@ impl WaveletTree {
     pub fn lookup_one(&self, entry: u64) -> Option<u64> {
         self.lookup(entry).map(|l| l.entry(0))
     }
+
+    /// Returns the root range for the given positions, clamped to the length of this tree.
+    fn root_range(&self, positions: Range<usize>) -> WaveletRange {
+        let len = self.len() as u64;
+        let end = std::cmp::min(positions.end as u64, len);
+        let start = std::cmp::min(positions.start as u64, end);
+
+        WaveletRange {
+            layer: 0,
+            node_start: 0,
+            node_end: len,
+            start,
+            end,
+            alphabet_start: 0,
+            alphabet_end: 2_u64.pow(self.num_layers as u32),
+        }
+    }
+
+    /// Returns the amount of entries within the given positions whose value is within the given value range.
+    pub fn range_count(&self, positions: Range<usize>, values: Range<u64>) -> usize {
+        let mut result = 0;
+        let mut stack = vec![self.root_range(positions)];
+        while let Some(range) = stack.pop() {
+            if range.is_empty()
+                || range.alphabet_end <= values.start
+                || range.alphabet_start >= values.end
+            {
+                continue;
+            }
+
+            if range.alphabet_start >= values.start && range.alphabet_end <= values.end {
+                result += range.len();
+            } else {
+                let (left, right) = range.children(self);
+                stack.push(left);
+                stack.push(right);
+            }
+        }
+
+        result
+    }
+
+    /// Returns the k'th smallest value within the given positions, counting from 0.
+    ///
+    /// Returns None if there are no more than k entries within the given positions.
+    pub fn kth_smallest(&self, positions: Range<usize>, k: usize) -> Option<u64> {
+        let mut range = self.root_range(positions);
+        if k >= range.len() {
+            return None;
+        }
+
+        let mut k = k;
+        while range.layer < self.num_layers {
+            let (left, right) = range.children(self);
+            if k < left.len() {
+                range = left;
+            } else {
+                k -= left.len();
+                range = right;
+            }
+        }
+
+        Some(range.alphabet_start)
+    }
+
+    /// Returns all distinct values within the given positions, in order, together with how often they occur.
+    pub fn range_distinct(&self, positions: Range<usize>) -> Vec<(u64, usize)> {
+        let mut result = Vec::new();
+        let mut stack = vec![self.root_range(positions)];
+        while let Some(range) = stack.pop() {
+            if range.is_empty() {
+                continue;
+            }
+
+            if range.layer == self.num_layers {
+                result.push((range.alphabet_start, range.len()));
+            } else {
+                let (left, right) = range.children(self);
+                // right goes on the stack first, so that values come out in order
+                stack.push(right);
+                stack.push(left);
+            }
+        }
+
+        result
+    }
+
+    /// Returns the k most frequent values within the given positions, together with how often they occur.
+    ///
+    /// The result is ordered from most to least frequent. Values that
+    /// occur equally often are ordered from small to large.
+    pub fn top_k(&self, positions: Range<usize>, k: usize) -> Vec<(u64, usize)> {
+        let mut result = Vec::with_capacity(k);
+        // Nodes are visited from largest to smallest. Since a node is
+        // never smaller than any of its children, every leaf that
+        // comes out of the heap is at least as frequent as all the
+        // values that are still in it.
+        let mut heap = BinaryHeap::new();
+        let root = self.root_range(positions);
+        heap.push((root.len(), Reverse(root.alphabet_start), root.layer, root));
+        while result.len() < k {
+            let range = match heap.pop() {
+                Some((_, _, _, range)) => range,
+                None => break,
+            };
+            if range.is_empty() {
+                continue;
+            }
+
+            if range.layer == self.num_layers {
+                result.push((range.alphabet_start, range.len()));
+            } else {
+                let (left, right) = range.children(self);
+                for child in [left, right].iter() {
+                    if !child.is_empty() {
+                        heap.push((
+                            child.len(),
+                            Reverse(child.alphabet_start),
+                            child.layer,
+                            *child,
+                        ));
+                    }
+                }
+            }
+        }
+
+        result
+    }
 }
 
 #[derive(Debug)]
@@ -270,11 +521,12 @@ pub async fn build_wavelet_tree_from_iter<
 >(
     width: u8,
     source: I,
+    encoding: BitIndexEncoding,
     destination_bits: F,
     destination_blocks: F,
     destination_sblocks: F,
 ) -> io::Result<()> {
-    let mut bits = BitArrayFileBuilder::new(destination_bits.open_write());
+    let mut bits = BitArrayFileBuilder::new(Vec::new());
     let mut fragments = create_fragments(width);
 
     for num in source {
@@ -284,10 +536,12 @@ pub async fn build_wavelet_tree_from_iter<
     let iter = fragments.into_iter().flat_map(|f| f.into_iter());
 
     bits.push_all(util::stream_iter_ok(iter)).await?;
-    bits.finalize().await?;
+    let bits = bits.finalize().await?;
 
-    build_bitindex(
-        destination_bits.open_read(),
+    build_bitindex_with_encoding(
+        bits,
+        encoding,
+        destination_bits.open_write(),
         destination_blocks.open_write(),
         destination_sblocks.open_write(),
     )
@@ -296,12 +550,37 @@ pub async fn build_wavelet_tree_from_iter<
     Ok(())
 }
 
+/// Build a wavelet tree with a plain bit index in memory, returning the contents of its files.
+///
+/// This produces the same bytes as `build_wavelet_tree_from_iter`
+/// with a plain encoding, but doesn't need files or an async runtime.
+pub fn build_wavelet_tree_buf<I: IntoIterator<Item = u64>>(width: u8, source: I) -> BitIndexMaps {
+    let mut fragments = create_fragments(width);
+    for num in source {
+        push_to_fragments(num, width, &mut fragments);
+    }
+
+    let mut bits = BitArrayBufBuilder::new();
+    bits.push_all(fragments.into_iter().flat_map(|f| f.into_iter()));
+    let bits_map = bits.finalize();
+    let (blocks_map, sblocks_map) =
+        build_bitindex_buf(&BitArray::from_bits(bits_map.clone()).unwrap());
+
+    BitIndexMaps {
+        bits_map,
+        blocks_map,
+        sblocks_map,
+        select_map: None,
+    }
+}
+
 /// Build a wavelet tree from a file storing a logarray.
 pub async fn build_wavelet_tree_from_logarray<
     FLoad: 'static + FileLoad,
     F: 'static + FileLoad + FileStore,
 >(
     source: FLoad,
+    encoding: BitIndexEncoding,
     destination_bits: F,
     destination_blocks: F,
     destination_sblocks: F,
@@ -312,6 +591,7 @@ pub async fn build_wavelet_tree_from_logarray<
     build_wavelet_tree_from_iter(
         logarray.width(),
         logarray.iter(),
+        encoding,
         destination_bits,
         destination_blocks,
         destination_sblocks,
@@ -340,6 +620,7 @@ mod tests {
         block_on(build_wavelet_tree_from_iter(
             5,
             contents_closure.into_iter(),
+            BitIndexEncoding::Plain,
             wavelet_bits_file.clone(),
             wavelet_blocks_file.clone(),
             wavelet_sblocks_file.clone(),
@@ -350,7 +631,8 @@ mod tests {
         let wavelet_blocks = block_on(wavelet_blocks_file.map()).unwrap();
         let wavelet_sblocks = block_on(wavelet_sblocks_file.map()).unwrap();
 
-        let wavelet_bitindex = BitIndex::from_maps(wavelet_bits, wavelet_blocks, wavelet_sblocks);
+        let wavelet_bitindex =
+            BitIndex::from_maps(wavelet_bits, wavelet_blocks, wavelet_sblocks).unwrap();
         let wavelet_tree = WaveletTree::from_parts(wavelet_bitindex, 5);
 
         assert_eq!(contents_len, wavelet_tree.len());
@@ -380,6 +662,7 @@ mod tests {
 
         block_on(build_wavelet_tree_from_logarray(
             logarray_file,
+            BitIndexEncoding::Plain,
             wavelet_bits_file.clone(),
             wavelet_blocks_file.clone(),
             wavelet_sblocks_file.clone(),
@@ -390,7 +673,8 @@ mod tests {
         let wavelet_blocks = block_on(wavelet_blocks_file.map()).unwrap();
         let wavelet_sblocks = block_on(wavelet_sblocks_file.map()).unwrap();
 
-        let wavelet_bitindex = BitIndex::from_maps(wavelet_bits, wavelet_blocks, wavelet_sblocks);
+        let wavelet_bitindex =
+            BitIndex::from_maps(wavelet_bits, wavelet_blocks, wavelet_sblocks).unwrap();
         let wavelet_tree = WaveletTree::from_parts(wavelet_bitindex, 5);
 
         assert_eq!(contents_len, wavelet_tree.len());
@@ -410,6 +694,7 @@ mod tests {
         block_on(build_wavelet_tree_from_iter(
             4,
             contents_closure.into_iter(),
+            BitIndexEncoding::Plain,
             wavelet_bits_file.clone(),
             wavelet_blocks_file.clone(),
             wavelet_sblocks_file.clone(),
@@ -420,7 +705,8 @@ mod tests {
         let wavelet_blocks = block_on(wavelet_blocks_file.map()).unwrap();
         let wavelet_sblocks = block_on(wavelet_sblocks_file.map()).unwrap();
 
-        let wavelet_bitindex = BitIndex::from_maps(wavelet_bits, wavelet_blocks, wavelet_sblocks);
+        let wavelet_bitindex =
+            BitIndex::from_maps(wavelet_bits, wavelet_blocks, wavelet_sblocks).unwrap();
         let wavelet_tree = WaveletTree::from_parts(wavelet_bitindex, 4);
 
         let slice = wavelet_tree.lookup(8).unwrap();
@@ -445,6 +731,7 @@ mod tests {
         block_on(build_wavelet_tree_from_iter(
             4,
             contents_closure.into_iter(),
+            BitIndexEncoding::Plain,
             wavelet_bits_file.clone(),
             wavelet_blocks_file.clone(),
             wavelet_sblocks_file.clone(),
@@ -455,7 +742,8 @@ mod tests {
         let wavelet_blocks = block_on(wavelet_blocks_file.map()).unwrap();
         let wavelet_sblocks = block_on(wavelet_sblocks_file.map()).unwrap();
 
-        let wavelet_bitindex = BitIndex::from_maps(wavelet_bits, wavelet_blocks, wavelet_sblocks);
+        let wavelet_bitindex =
+            BitIndex::from_maps(wavelet_bits, wavelet_blocks, wavelet_sblocks).unwrap();
         let wavelet_tree = WaveletTree::from_parts(wavelet_bitindex, 4);
 
         assert!(wavelet_tree.lookup(3).is_none());
@@ -473,6 +761,7 @@ mod tests {
         block_on(build_wavelet_tree_from_iter(
             4,
             contents_closure.into_iter(),
+            BitIndexEncoding::Plain,
             wavelet_bits_file.clone(),
             wavelet_blocks_file.clone(),
             wavelet_sblocks_file.clone(),
@@ -483,7 +772,8 @@ mod tests {
         let wavelet_blocks = block_on(wavelet_blocks_file.map()).unwrap();
         let wavelet_sblocks = block_on(wavelet_sblocks_file.map()).unwrap();
 
-        let wavelet_bitindex = BitIndex::from_maps(wavelet_bits, wavelet_blocks, wavelet_sblocks);
+        let wavelet_bitindex =
+            BitIndex::from_maps(wavelet_bits, wavelet_blocks, wavelet_sblocks).unwrap();
         let wavelet_tree = WaveletTree::from_parts(wavelet_bitindex, 4);
 
         assert!(wavelet_tree.lookup(100).is_none());
@@ -501,6 +791,7 @@ mod tests {
         block_on(build_wavelet_tree_from_iter(
             4,
             contents_closure.into_iter(),
+            BitIndexEncoding::Plain,
             wavelet_bits_file.clone(),
             wavelet_blocks_file.clone(),
             wavelet_sblocks_file.clone(),
@@ -511,7 +802,8 @@ mod tests {
         let wavelet_blocks = block_on(wavelet_blocks_file.map()).unwrap();
         let wavelet_sblocks = block_on(wavelet_sblocks_file.map()).unwrap();
 
-        let wavelet_bitindex = BitIndex::from_maps(wavelet_bits, wavelet_blocks, wavelet_sblocks);
+        let wavelet_bitindex =
+            BitIndex::from_maps(wavelet_bits, wavelet_blocks, wavelet_sblocks).unwrap();
         let wavelet_tree = WaveletTree::from_parts(wavelet_bitindex, 4);
 
         assert_eq!(
@@ -534,6 +826,7 @@ mod tests {
         block_on(build_wavelet_tree_from_iter(
             4,
             contents_closure.into_iter(),
+            BitIndexEncoding::Plain,
             wavelet_bits_file.clone(),
             wavelet_blocks_file.clone(),
             wavelet_sblocks_file.clone(),
@@ -544,7 +837,8 @@ mod tests {
         let wavelet_blocks = block_on(wavelet_blocks_file.map()).unwrap();
         let wavelet_sblocks = block_on(wavelet_sblocks_file.map()).unwrap();
 
-        let wavelet_bitindex = BitIndex::from_maps(wavelet_bits, wavelet_blocks, wavelet_sblocks);
+        let wavelet_bitindex =
+            BitIndex::from_maps(wavelet_bits, wavelet_blocks, wavelet_sblocks).unwrap();
         let wavelet_tree = WaveletTree::from_parts(wavelet_bitindex, 4);
 
         assert_eq!(Some(3), wavelet_tree.lookup_one(1));
@@ -555,4 +849,146 @@ mod tests {
         assert_eq!(Some(7), wavelet_tree.lookup_one(7));
         assert_eq!(Some(4), wavelet_tree.lookup_one(8));
     }
+
+    fn build_wavelet_tree(contents: &[u64], width: u8) -> WaveletTree {
+        let wavelet_bits_file = MemoryBackedStore::new();
+        let wavelet_blocks_file = MemoryBackedStore::new();
+        let wavelet_sblocks_file = MemoryBackedStore::new();
+
+        block_on(build_wavelet_tree_from_iter(
+            width,
+            contents.iter().copied(),
+            BitIndexEncoding::Plain,
+            wavelet_bits_file.clone(),
+            wavelet_blocks_file.clone(),
+            wavelet_sblocks_file.clone(),
+        ))
+        .unwrap();
+
+        let wavelet_bits = block_on(wavelet_bits_file.map()).unwrap();
+        let wavelet_blocks = block_on(wavelet_blocks_file.map()).unwrap();
+        let wavelet_sblocks = block_on(wavelet_sblocks_file.map()).unwrap();
+
+        let wavelet_bitindex =
+            BitIndex::from_maps(wavelet_bits, wavelet_blocks, wavelet_sblocks).unwrap();
+        WaveletTree::from_parts(wavelet_bitindex, width)
+    }
+
+    #[test]
+    fn wavelet_tree_range_count() {
+        let contents = vec![8, 3, 8, 8, 1, 2, 3, 2, 8, 9, 3, 3, 6, 7, 0, 4, 8, 7, 3];
+        let wavelet_tree = build_wavelet_tree(&contents, 4);
+
+        for start in 0..contents.len() {
+            for end in start..contents.len() + 1 {
+                for (lo, hi) in &[(0, 16), (3, 4), (2, 8), (8, 10), (5, 5), (10, 16)] {
+                    let expected = contents[start..end]
+                        .iter()
+                        .filter(|&&v| v >= *lo && v < *hi)
+                        .count();
+                    assert_eq!(expected, wavelet_tree.range_count(start..end, *lo..*hi));
+                }
+            }
+        }
+
+        // positions beyond the end of the tree are ignored
+        assert_eq!(8, wavelet_tree.range_count(10..100, 0..8));
+    }
+
+    #[test]
+    fn wavelet_tree_kth_smallest() {
+        let contents = vec![8, 3, 8, 8, 1, 2, 3, 2, 8, 9, 3, 3, 6, 7, 0, 4, 8, 7, 3];
+        let wavelet_tree = build_wavelet_tree(&contents, 4);
+
+        for start in 0..contents.len() {
+            for end in start..contents.len() + 1 {
+                let mut sorted = contents[start..end].to_vec();
+                sorted.sort();
+                for (k, &value) in sorted.iter().enumerate() {
+                    assert_eq!(Some(value), wavelet_tree.kth_smallest(start..end, k));
+                }
+                assert_eq!(None, wavelet_tree.kth_smallest(start..end, sorted.len()));
+            }
+        }
+    }
+
+    #[test]
+    fn wavelet_tree_range_distinct() {
+        let contents = vec![8, 3, 8, 8, 1, 2, 3, 2, 8, 9, 3, 3, 6, 7, 0, 4, 8, 7, 3];
+        let wavelet_tree = build_wavelet_tree(&contents, 4);
+
+        assert_eq!(
+            vec![
+                (0, 1),
+                (1, 1),
+                (2, 2),
+                (3, 5),
+                (4, 1),
+                (6, 1),
+                (7, 2),
+                (8, 5),
+                (9, 1)
+            ],
+            wavelet_tree.range_distinct(0..contents.len())
+        );
+        assert_eq!(
+            vec![(2, 1), (3, 2), (6, 1), (8, 1), (9, 1)],
+            wavelet_tree.range_distinct(7..13)
+        );
+        assert!(wavelet_tree.range_distinct(5..5).is_empty());
+    }
+
+    #[test]
+    fn wavelet_tree_top_k() {
+        let contents = vec![8, 3, 8, 8, 1, 2, 3, 2, 8, 9, 3, 3, 6, 7, 0, 4, 8, 7, 3];
+        let wavelet_tree = build_wavelet_tree(&contents, 4);
+
+        assert_eq!(
+            vec![(3, 5), (8, 5), (2, 2), (7, 2)],
+            wavelet_tree.top_k(0..contents.len(), 4)
+        );
+        assert_eq!(vec![(3, 2)], wavelet_tree.top_k(7..13, 1));
+        assert_eq!(
+            vec![(3, 2), (2, 1), (6, 1), (8, 1), (9, 1)],
+            wavelet_tree.top_k(7..13, 10)
+        );
+        assert!(wavelet_tree.top_k(0..contents.len(), 0).is_empty());
+        assert!(wavelet_tree.top_k(3..3, 5).is_empty());
+    }
+
+    #[test]
+    fn empty_wavelet_tree_queries() {
+        let wavelet_tree = build_wavelet_tree(&[], 0);
+
+        assert_eq!(0, wavelet_tree.range_count(0..10, 0..10));
+        assert_eq!(None, wavelet_tree.kth_smallest(0..10, 0));
+        assert!(wavelet_tree.range_distinct(0..10).is_empty());
+        assert!(wavelet_tree.top_k(0..10, 3).is_empty());
+    }
+
+    #[test]
+    fn build_wavelet_tree_in_memory() {
+        let contents = vec![21, 1, 30, 13, 23, 21, 3, 0, 21, 21, 12, 11];
+        let bits_file = MemoryBackedStore::new();
+        let blocks_file = MemoryBackedStore::new();
+        let sblocks_file = MemoryBackedStore::new();
+        block_on(build_wavelet_tree_from_iter(
+            5,
+            contents.clone().into_iter(),
+            BitIndexEncoding::Plain,
+            bits_file.clone(),
+            blocks_file.clone(),
+            sblocks_file.clone(),
+        ))
+        .unwrap();
+
+        let maps = build_wavelet_tree_buf(5, contents.clone());
+        assert_eq!(block_on(bits_file.map()).unwrap(), maps.bits_map);
+        assert_eq!(block_on(blocks_file.map()).unwrap(), maps.blocks_map);
+        assert_eq!(block_on(sblocks_file.map()).unwrap(), maps.sblocks_map);
+
+        let wavelet_tree = WaveletTree::from_values(5, contents.clone());
+        assert_eq!(contents, wavelet_tree.decode().collect::<Vec<_>>());
+        assert_eq!(4, wavelet_tree.lookup(21).unwrap().len());
+    }
 }
@@ -0,0 +1,517 @@
+//! Validation of layers against shapes.
+//!
+//! A `Validator` reads SHACL-style shape definitions from one layer,
+//! and validates other layers against them. Each node shape selects
+//! its focus nodes through `sh:targetClass`, `sh:targetNode` and
+//! `sh:targetSubjectsOf`, and constrains the focus node itself and
+//! the values of its properties, given through `sh:property` and
+//! `sh:path`. The supported constraints are `sh:minCount`,
+//! `sh:maxCount`, `sh:datatype`, `sh:nodeKind` and `sh:class`. Class
+//! membership takes `rdfs:subClassOf` into account.
+//!
+//! Values in a layer are plain strings, so a datatype constraint
+//! checks the lexical form of a value. `xsd:integer`, `xsd:decimal`,
+//! `xsd:double` and `xsd:boolean` values have to parse as such, and
+//! any value conforms to other datatypes.
+//!
+//! Validating a layer results in a `ValidationReport`, listing every
+//! violation along with its focus node and path. A layer whose parent
+//! is known to be valid can be validated incrementally, by only
+//! validating the subjects that the layer adds or removes triples for.
+mod shapes;
+
+use self::shapes::{objects, read_shapes, subjects, Shape};
+use crate::layer::{Layer, ObjectType};
+use crate::reasoning::vocabulary::{RDFS_SUB_CLASS_OF, RDF_TYPE};
+use std::collections::{BTreeSet, HashSet};
+use std::{error, fmt, io};
+
+/// The IRIs of the SHACL and XSD terms the validator knows about.
+pub mod vocabulary {
+    pub const SH_NODE_SHAPE: &str = "http://www.w3.org/ns/shacl#NodeShape";
+    pub const SH_TARGET_CLASS: &str = "http://www.w3.org/ns/shacl#targetClass";
+    pub const SH_TARGET_NODE: &str = "http://www.w3.org/ns/shacl#targetNode";
+    pub const SH_TARGET_SUBJECTS_OF: &str = "http://www.w3.org/ns/shacl#targetSubjectsOf";
+    pub const SH_PROPERTY: &str = "http://www.w3.org/ns/shacl#property";
+    pub const SH_PATH: &str = "http://www.w3.org/ns/shacl#path";
+    pub const SH_MIN_COUNT: &str = "http://www.w3.org/ns/shacl#minCount";
+    pub const SH_MAX_COUNT: &str = "http://www.w3.org/ns/shacl#maxCount";
+    pub const SH_DATATYPE: &str = "http://www.w3.org/ns/shacl#datatype";
+    pub const SH_NODE_KIND: &str = "http://www.w3.org/ns/shacl#nodeKind";
+    pub const SH_CLASS: &str = "http://www.w3.org/ns/shacl#class";
+    pub const SH_IRI: &str = "http://www.w3.org/ns/shacl#IRI";
+    pub const SH_BLANK_NODE: &str = "http://www.w3.org/ns/shacl#BlankNode";
+    pub const SH_BLANK_NODE_OR_IRI: &str = "http://www.w3.org/ns/shacl#BlankNodeOrIRI";
+    pub const SH_LITERAL: &str = "http://www.w3.org/ns/shacl#Literal";
+    pub const SH_IRI_OR_LITERAL: &str = "http://www.w3.org/ns/shacl#IRIOrLiteral";
+    pub const SH_BLANK_NODE_OR_LITERAL: &str = "http://www.w3.org/ns/shacl#BlankNodeOrLiteral";
+    pub const XSD_STRING: &str = "http://www.w3.org/2001/XMLSchema#string";
+    pub const XSD_INTEGER: &str = "http://www.w3.org/2001/XMLSchema#integer";
+    pub const XSD_DECIMAL: &str = "http://www.w3.org/2001/XMLSchema#decimal";
+    pub const XSD_DOUBLE: &str = "http://www.w3.org/2001/XMLSchema#double";
+    pub const XSD_BOOLEAN: &str = "http://www.w3.org/2001/XMLSchema#boolean";
+}
+
+use self::vocabulary::*;
+
+/// Whether an object is a node or a value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum NodeKind {
+    /// An IRI or blank node, which may also appear as a subject.
+    Node,
+    /// A literal.
+    Value,
+}
+
+/// A constraint of a shape.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Constraint {
+    /// There are at least this many values.
+    MinCount(usize),
+    /// There are at most this many values.
+    MaxCount(usize),
+    /// Every value is a value of the given datatype.
+    Datatype(String),
+    /// Every value is of the given kind.
+    NodeKind(NodeKind),
+    /// Every value is a node that is an instance of the given class.
+    Class(String),
+}
+
+/// A focus node that does not satisfy a constraint.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Violation {
+    /// The node that was validated.
+    pub focus_node: String,
+    /// The shape the constraint belongs to.
+    pub shape: String,
+    /// The predicate the constraint applies to, or None if it applies to the focus node itself.
+    pub path: Option<String>,
+    /// The constraint that is not satisfied.
+    pub constraint: Constraint,
+    /// The offending value, or None for a count constraint.
+    pub value: Option<ObjectType>,
+}
+
+/// The result of validating a layer.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ValidationReport {
+    violations: Vec<Violation>,
+}
+
+impl ValidationReport {
+    /// Returns true if no constraint was violated.
+    pub fn conforms(&self) -> bool {
+        self.violations.is_empty()
+    }
+
+    /// Returns the violations, ordered by focus node.
+    pub fn violations(&self) -> &[Violation] {
+        &self.violations
+    }
+}
+
+/// An error in the definition of a shape.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ShapeError {
+    /// The shape (`.0`) has an object (`.2`) for the predicate (`.1`) that is not allowed there.
+    Invalid(String, String, String),
+    /// The property shape does not have exactly one path.
+    MissingPath(String),
+}
+
+impl fmt::Display for ShapeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use ShapeError::*;
+        match self {
+            Invalid(shape, predicate, object) => write!(
+                f,
+                "shape {} has an invalid object {} for {}",
+                shape, object, predicate
+            ),
+            MissingPath(shape) => write!(f, "property shape {} needs exactly one path", shape),
+        }
+    }
+}
+
+impl error::Error for ShapeError {}
+
+impl From<ShapeError> for io::Error {
+    fn from(err: ShapeError) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, err)
+    }
+}
+
+/// Returns true if the value has the lexical form of the given datatype.
+fn has_datatype(value: &str, datatype: &str) -> bool {
+    match datatype {
+        XSD_INTEGER => value.parse::<i64>().is_ok(),
+        XSD_DECIMAL | XSD_DOUBLE => value.parse::<f64>().is_ok(),
+        XSD_BOOLEAN => matches!(value, "true" | "false" | "1" | "0"),
+        _ => true,
+    }
+}
+
+/// Returns true if the node has the given class or one of its subclasses as a type.
+fn is_instance(layer: &dyn Layer, node: &str, class: &str) -> bool {
+    let mut pending: Vec<String> = objects(layer, node, RDF_TYPE)
+        .into_iter()
+        .filter_map(|object| match object {
+            ObjectType::Node(class) => Some(class),
+            ObjectType::Value(_) => None,
+        })
+        .collect();
+    let mut visited = HashSet::new();
+    while let Some(current) = pending.pop() {
+        if current == class {
+            return true;
+        }
+        if visited.insert(current.clone()) {
+            for object in objects(layer, &current, RDFS_SUB_CLASS_OF) {
+                if let ObjectType::Node(super_class) = object {
+                    pending.push(super_class);
+                }
+            }
+        }
+    }
+
+    false
+}
+
+/// Returns all instances of the given class or one of its subclasses.
+fn instances(layer: &dyn Layer, class: &str) -> BTreeSet<String> {
+    let mut classes = HashSet::new();
+    let mut pending = vec![class.to_owned()];
+    let mut result = BTreeSet::new();
+    while let Some(current) = pending.pop() {
+        if classes.insert(current.clone()) {
+            result.extend(subjects(layer, RDF_TYPE, Some(&current)));
+            pending.extend(subjects(layer, RDFS_SUB_CLASS_OF, Some(&current)));
+        }
+    }
+
+    result
+}
+
+fn satisfies(layer: &dyn Layer, constraint: &Constraint, value: &ObjectType) -> bool {
+    match (constraint, value) {
+        (Constraint::Datatype(datatype), ObjectType::Value(value)) => has_datatype(value, datatype),
+        (Constraint::Datatype(_), ObjectType::Node(_)) => false,
+        (Constraint::NodeKind(NodeKind::Node), value) => matches!(value, ObjectType::Node(_)),
+        (Constraint::NodeKind(NodeKind::Value), value) => matches!(value, ObjectType::Value(_)),
+        (Constraint::Class(class), ObjectType::Node(node)) => is_instance(layer, node, class),
+        (Constraint::Class(_), ObjectType::Value(_)) => false,
+        (Constraint::MinCount(_), _) | (Constraint::MaxCount(_), _) => true,
+    }
+}
+
+/// Validates layers against a set of shapes.
+#[derive(Clone, Debug)]
+pub struct Validator {
+    shapes: Vec<Shape>,
+}
+
+impl Validator {
+    /// Construct a validator for the shapes defined in the given layer.
+    pub fn from_layer(layer: &dyn Layer) -> Result<Validator, ShapeError> {
+        Ok(Validator {
+            shapes: read_shapes(layer)?,
+        })
+    }
+
+    /// Returns the names of the node shapes of this validator.
+    pub fn shapes(&self) -> impl Iterator<Item = &str> {
+        self.shapes.iter().map(|shape| shape.name.as_str())
+    }
+
+    /// Validate every focus node in the given layer.
+    pub fn validate(&self, layer: &dyn Layer) -> ValidationReport {
+        let mut violations = Vec::new();
+        for shape in self.shapes.iter() {
+            let mut focus_nodes: BTreeSet<String> = shape.target_nodes.iter().cloned().collect();
+            for class in shape.target_classes.iter() {
+                focus_nodes.extend(instances(layer, class));
+            }
+            for predicate in shape.target_subjects_of.iter() {
+                focus_nodes.extend(subjects(layer, predicate, None));
+            }
+
+            for focus_node in focus_nodes {
+                validate_node(layer, shape, &focus_node, &mut violations);
+            }
+        }
+
+        violations.sort();
+        ValidationReport { violations }
+    }
+
+    /// Validate only the focus nodes whose triples are changed by the given layer.
+    ///
+    /// This considers the subjects that the layer adds or removes
+    /// triples for. If the parent of the layer is valid, the layer is
+    /// valid if this reports no violations, unless a change affects
+    /// the validity of other nodes, like the removal of the type of a
+    /// node that is the value of an `sh:class` constraint.
+    pub fn validate_changes(&self, layer: &dyn Layer) -> ValidationReport {
+        let touched: BTreeSet<String> = layer
+            .subject_additions()
+            .chain(layer.subject_removals())
+            .filter_map(|lookup| layer.id_subject(lookup.subject()))
+            .collect();
+
+        let mut violations = Vec::new();
+        for focus_node in touched {
+            for shape in self.shapes.iter() {
+                if is_target(layer, shape, &focus_node) {
+                    validate_node(layer, shape, &focus_node, &mut violations);
+                }
+            }
+        }
+
+        violations.sort();
+        ValidationReport { violations }
+    }
+}
+
+/// Returns true if the node is a focus node of the shape.
+fn is_target(layer: &dyn Layer, shape: &Shape, node: &str) -> bool {
+    shape.target_nodes.iter().any(|target| target == node)
+        || shape
+            .target_classes
+            .iter()
+            .any(|class| is_instance(layer, node, class))
+        || shape
+            .target_subjects_of
+            .iter()
+            .any(|predicate| !objects(layer, node, predicate).is_empty())
+}
+
+fn validate_node(
+    layer: &dyn Layer,
+    shape: &Shape,
+    focus_node: &str,
+    violations: &mut Vec<Violation>,
+) {
+    let violation =
+        |path: Option<&str>, constraint: &Constraint, value: Option<&ObjectType>| Violation {
+            focus_node: focus_node.to_owned(),
+            shape: shape.name.clone(),
+            path: path.map(|path| path.to_owned()),
+            constraint: constraint.clone(),
+            value: value.cloned(),
+        };
+
+    let node = ObjectType::Node(focus_node.to_owned());
+    for constraint in shape.constraints.iter() {
+        if !satisfies(layer, constraint, &node) {
+            violations.push(violation(None, constraint, Some(&node)));
+        }
+    }
+
+    for property in shape.properties.iter() {
+        let values = objects(layer, focus_node, &property.path);
+        let path = Some(property.path.as_str());
+        for constraint in property.constraints.iter() {
+            match constraint {
+                Constraint::MinCount(min) if values.len() < *min => {
+                    violations.push(violation(path, constraint, None))
+                }
+                Constraint::MaxCount(max) if values.len() > *max => {
+                    violations.push(violation(path, constraint, None))
+                }
+                _ => {
+                    for value in values.iter() {
+                        if !satisfies(layer, constraint, value) {
+                            violations.push(violation(path, constraint, Some(value)));
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layer::StringTriple;
+    use crate::store::sync::*;
+
+    const SHAPE: &str = "PersonShape";
+
+    fn shapes_layer(store: &SyncStore, extra: &[StringTriple]) -> SyncStoreLayer {
+        let builder = store.create_base_layer().unwrap();
+        let triples = vec![
+            StringTriple::new_node(SHAPE, RDF_TYPE, SH_NODE_SHAPE),
+            StringTriple::new_node(SHAPE, SH_TARGET_CLASS, "Person"),
+            StringTriple::new_node(SHAPE, SH_PROPERTY, "name"),
+            StringTriple::new_node("name", SH_PATH, "hasName"),
+            StringTriple::new_value("name", SH_MIN_COUNT, "1"),
+            StringTriple::new_value("name", SH_MAX_COUNT, "1"),
+            StringTriple::new_node("name", SH_DATATYPE, XSD_STRING),
+            StringTriple::new_node(SHAPE, SH_PROPERTY, "age"),
+            StringTriple::new_node("age", SH_PATH, "hasAge"),
+            StringTriple::new_node("age", SH_DATATYPE, XSD_INTEGER),
+            StringTriple::new_node(SHAPE, SH_PROPERTY, "knows"),
+            StringTriple::new_node("knows", SH_PATH, "knows"),
+            StringTriple::new_node("knows", SH_NODE_KIND, SH_IRI),
+            StringTriple::new_node("knows", SH_CLASS, "Person"),
+        ];
+        for triple in triples.into_iter().chain(extra.iter().cloned()) {
+            builder.add_string_triple(triple).unwrap();
+        }
+
+        builder.commit().unwrap()
+    }
+
+    fn data_layer(store: &SyncStore) -> SyncStoreLayer {
+        let builder = store.create_base_layer().unwrap();
+        let triples = vec![
+            StringTriple::new_node("Employee", RDFS_SUB_CLASS_OF, "Person"),
+            StringTriple::new_node("alice", RDF_TYPE, "Person"),
+            StringTriple::new_value("alice", "hasName", "Alice"),
+            StringTriple::new_value("alice", "hasAge", "30"),
+            StringTriple::new_node("alice", "knows", "bob"),
+            StringTriple::new_node("bob", RDF_TYPE, "Employee"),
+            StringTriple::new_value("bob", "hasAge", "old"),
+            StringTriple::new_node("carol", RDF_TYPE, "Person"),
+            StringTriple::new_value("carol", "hasName", "Carol"),
+            StringTriple::new_value("carol", "hasName", "Caroline"),
+            StringTriple::new_value("carol", "knows", "dave"),
+            StringTriple::new_node("carol", "knows", "rex"),
+            StringTriple::new_value("rex", "hasName", "Rex"),
+        ];
+        for triple in triples {
+            builder.add_string_triple(triple).unwrap();
+        }
+
+        builder.commit().unwrap()
+    }
+
+    fn violation(
+        focus_node: &str,
+        path: &str,
+        constraint: Constraint,
+        value: Option<ObjectType>,
+    ) -> Violation {
+        Violation {
+            focus_node: focus_node.to_owned(),
+            shape: SHAPE.to_owned(),
+            path: Some(path.to_owned()),
+            constraint,
+            value,
+        }
+    }
+
+    #[test]
+    fn validate_layer_against_shapes() {
+        let store = open_sync_memory_store();
+        let validator = Validator::from_layer(&shapes_layer(&store, &[])).unwrap();
+        assert_eq!(vec![SHAPE], validator.shapes().collect::<Vec<_>>());
+
+        let report = validator.validate(&data_layer(&store));
+        assert!(!report.conforms());
+
+        let person = || Constraint::Class("Person".to_owned());
+        let expected = vec![
+            violation(
+                "bob",
+                "hasAge",
+                Constraint::Datatype(XSD_INTEGER.to_owned()),
+                Some(ObjectType::Value("old".to_owned())),
+            ),
+            violation("bob", "hasName", Constraint::MinCount(1), None),
+            violation("carol", "hasName", Constraint::MaxCount(1), None),
+            violation(
+                "carol",
+                "knows",
+                Constraint::NodeKind(NodeKind::Node),
+                Some(ObjectType::Value("dave".to_owned())),
+            ),
+            violation(
+                "carol",
+                "knows",
+                person(),
+                Some(ObjectType::Node("rex".to_owned())),
+            ),
+            violation(
+                "carol",
+                "knows",
+                person(),
+                Some(ObjectType::Value("dave".to_owned())),
+            ),
+        ];
+        assert_eq!(expected, report.violations());
+    }
+
+    #[test]
+    fn validate_changes_only() {
+        let store = open_sync_memory_store();
+        let validator = Validator::from_layer(&shapes_layer(&store, &[])).unwrap();
+        let base = data_layer(&store);
+
+        let builder = base.open_write().unwrap();
+        builder
+            .add_string_triple(StringTriple::new_value("bob", "hasName", "Bob"))
+            .unwrap();
+        builder
+            .remove_string_triple(StringTriple::new_value("alice", "hasName", "Alice"))
+            .unwrap();
+        let child = builder.commit().unwrap();
+
+        let report = validator.validate_changes(&child);
+        let focus_nodes: BTreeSet<_> = report
+            .violations()
+            .iter()
+            .map(|v| v.focus_node.as_str())
+            .collect();
+        assert_eq!(
+            vec!["alice", "bob"],
+            focus_nodes.into_iter().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![
+                violation("alice", "hasName", Constraint::MinCount(1), None),
+                violation(
+                    "bob",
+                    "hasAge",
+                    Constraint::Datatype(XSD_INTEGER.to_owned()),
+                    Some(ObjectType::Value("old".to_owned()))
+                ),
+            ],
+            report.violations()
+        );
+
+        // the incremental report agrees with a full validation on the changed nodes
+        let full: Vec<_> = validator
+            .validate(&child)
+            .violations()
+            .iter()
+            .filter(|v| v.focus_node != "carol")
+            .cloned()
+            .collect();
+        assert_eq!(full, report.violations());
+    }
+
+    #[test]
+    fn reject_invalid_shapes() {
+        let store = open_sync_memory_store();
+        let invalid_count = shapes_layer(
+            &store,
+            &[StringTriple::new_value("age", SH_MAX_COUNT, "many")],
+        );
+        assert_eq!(
+            ShapeError::Invalid("age".to_owned(), SH_MAX_COUNT.to_owned(), "many".to_owned()),
+            Validator::from_layer(&invalid_count).unwrap_err()
+        );
+
+        let missing_path = shapes_layer(
+            &store,
+            &[StringTriple::new_node(SHAPE, SH_PROPERTY, "email")],
+        );
+        assert_eq!(
+            ShapeError::MissingPath("email".to_owned()),
+            Validator::from_layer(&missing_path).unwrap_err()
+        );
+    }
+}
@@ -0,0 +1,173 @@
+//! Reading shape definitions from a layer.
+use super::vocabulary::*;
+use super::{Constraint, NodeKind, ShapeError};
+use crate::layer::{Layer, ObjectType};
+use crate::reasoning::vocabulary::RDF_TYPE;
+use std::collections::BTreeSet;
+
+/// A node shape, with the nodes it targets and the constraints on them.
+#[derive(Clone, Debug)]
+pub(super) struct Shape {
+    pub(super) name: String,
+    pub(super) target_classes: Vec<String>,
+    pub(super) target_nodes: Vec<String>,
+    pub(super) target_subjects_of: Vec<String>,
+    /// Constraints on the focus node itself.
+    pub(super) constraints: Vec<Constraint>,
+    pub(super) properties: Vec<PropertyShape>,
+}
+
+/// The constraints on the values of a predicate.
+#[derive(Clone, Debug)]
+pub(super) struct PropertyShape {
+    pub(super) path: String,
+    pub(super) constraints: Vec<Constraint>,
+}
+
+/// Returns the objects of the triples with the given subject and predicate.
+pub(super) fn objects(layer: &dyn Layer, subject: &str, predicate: &str) -> Vec<ObjectType> {
+    let (subject, predicate) = match (layer.subject_id(subject), layer.predicate_id(predicate)) {
+        (Some(s), Some(p)) => (s, p),
+        _ => return Vec::new(),
+    };
+
+    layer
+        .triples_sp(subject, predicate)
+        .filter_map(|t| layer.id_object(t.object))
+        .collect()
+}
+
+/// Returns the subjects of the triples with the given predicate, and optionally a given node object.
+pub(super) fn subjects(
+    layer: &dyn Layer,
+    predicate: &str,
+    object: Option<&str>,
+) -> BTreeSet<String> {
+    let predicate = match layer.predicate_id(predicate) {
+        Some(p) => p,
+        None => return BTreeSet::new(),
+    };
+    let triples = match object {
+        Some(object) => match layer.object_node_id(object) {
+            Some(o) => layer.triples_o(o),
+            None => return BTreeSet::new(),
+        },
+        None => layer.triples_p(predicate),
+    };
+
+    triples
+        .filter(|t| t.predicate == predicate)
+        .filter_map(|t| layer.id_subject(t.subject))
+        .collect()
+}
+
+fn node(shape: &str, predicate: &str, object: ObjectType) -> Result<String, ShapeError> {
+    match object {
+        ObjectType::Node(node) => Ok(node),
+        ObjectType::Value(value) => Err(ShapeError::Invalid(
+            shape.to_owned(),
+            predicate.to_owned(),
+            value,
+        )),
+    }
+}
+
+fn nodes(layer: &dyn Layer, shape: &str, predicate: &str) -> Result<Vec<String>, ShapeError> {
+    objects(layer, shape, predicate)
+        .into_iter()
+        .map(|object| node(shape, predicate, object))
+        .collect()
+}
+
+fn count(shape: &str, predicate: &str, object: ObjectType) -> Result<usize, ShapeError> {
+    match object {
+        ObjectType::Value(value) => value
+            .trim()
+            .parse()
+            .map_err(|_| ShapeError::Invalid(shape.to_owned(), predicate.to_owned(), value)),
+        ObjectType::Node(node) => Err(ShapeError::Invalid(
+            shape.to_owned(),
+            predicate.to_owned(),
+            node,
+        )),
+    }
+}
+
+/// Read the constraints that apply to every value node of a shape.
+fn value_constraints(layer: &dyn Layer, shape: &str) -> Result<Vec<Constraint>, ShapeError> {
+    let mut constraints = Vec::new();
+    for datatype in nodes(layer, shape, SH_DATATYPE)? {
+        constraints.push(Constraint::Datatype(datatype));
+    }
+    for kind in nodes(layer, shape, SH_NODE_KIND)? {
+        match kind.as_str() {
+            SH_IRI | SH_BLANK_NODE | SH_BLANK_NODE_OR_IRI => {
+                constraints.push(Constraint::NodeKind(NodeKind::Node))
+            }
+            SH_LITERAL => constraints.push(Constraint::NodeKind(NodeKind::Value)),
+            // every object is either a node or a value
+            SH_IRI_OR_LITERAL | SH_BLANK_NODE_OR_LITERAL => {}
+            _ => {
+                return Err(ShapeError::Invalid(
+                    shape.to_owned(),
+                    SH_NODE_KIND.to_owned(),
+                    kind,
+                ))
+            }
+        }
+    }
+    for class in nodes(layer, shape, SH_CLASS)? {
+        constraints.push(Constraint::Class(class));
+    }
+
+    Ok(constraints)
+}
+
+fn property_shape(layer: &dyn Layer, name: &str) -> Result<PropertyShape, ShapeError> {
+    let mut paths = nodes(layer, name, SH_PATH)?;
+    if paths.len() != 1 {
+        return Err(ShapeError::MissingPath(name.to_owned()));
+    }
+    let path = paths.pop().unwrap();
+
+    let mut constraints = Vec::new();
+    for object in objects(layer, name, SH_MIN_COUNT) {
+        constraints.push(Constraint::MinCount(count(name, SH_MIN_COUNT, object)?));
+    }
+    for object in objects(layer, name, SH_MAX_COUNT) {
+        constraints.push(Constraint::MaxCount(count(name, SH_MAX_COUNT, object)?));
+    }
+    constraints.extend(value_constraints(layer, name)?);
+
+    Ok(PropertyShape { path, constraints })
+}
+
+/// Read all node shapes from the given layer.
+///
+/// A node shape is a node that is declared to be a `sh:NodeShape`,
+/// or that has a target.
+pub(super) fn read_shapes(layer: &dyn Layer) -> Result<Vec<Shape>, ShapeError> {
+    let mut names = subjects(layer, RDF_TYPE, Some(SH_NODE_SHAPE));
+    for target in [SH_TARGET_CLASS, SH_TARGET_NODE, SH_TARGET_SUBJECTS_OF].iter() {
+        names.extend(subjects(layer, target, None));
+    }
+
+    names
+        .into_iter()
+        .map(|name| {
+            let properties = nodes(layer, &name, SH_PROPERTY)?
+                .iter()
+                .map(|property| property_shape(layer, property))
+                .collect::<Result<_, _>>()?;
+
+            Ok(Shape {
+                target_classes: nodes(layer, &name, SH_TARGET_CLASS)?,
+                target_nodes: nodes(layer, &name, SH_TARGET_NODE)?,
+                target_subjects_of: nodes(layer, &name, SH_TARGET_SUBJECTS_OF)?,
+                constraints: value_constraints(layer, &name)?,
+                properties,
+                name,
+            })
+        })
+        .collect()
+}